@@ -0,0 +1,12 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use onetagger_ai::bench::{run_pipeline, synthetic_buffer};
+use onetagger_ai::AIConfig;
+
+fn pipeline(c: &mut Criterion) {
+    let config = AIConfig::default();
+    let samples = synthetic_buffer(44100, 30.0);
+    c.bench_function("pipeline 30s", |b| b.iter(|| run_pipeline(black_box(&samples), 44100, &config).unwrap()));
+}
+
+criterion_group!(benches, pipeline);
+criterion_main!(benches);
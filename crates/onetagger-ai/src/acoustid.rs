@@ -0,0 +1,241 @@
+//! AcoustID Module
+//!
+//! Identifies recordings by looking up Chromaprint fingerprints in the AcoustID database (MusicBrainz recording ids).
+//! The duplicate detection fingerprints (`crate::fingerprint`) use a different algorithm and can't be looked up,
+//! files are fingerprinted with Chromaprint (`fingerprinting` feature) instead
+
+use anyhow::{Error, Result};
+use serde::{Serialize, Deserialize};
+use std::path::Path;
+use crate::config::AcoustIdConfig;
+
+/// Fingerprint algorithm id sent in the compressed fingerprint header (Chromaprint's default)
+const ALGORITHM: u8 = 1;
+/// Seconds of audio fingerprinted by `chromaprint`, same as `fpcalc`
+#[cfg(feature = "fingerprinting")]
+const CHROMAPRINT_SECS: f32 = 120.0;
+/// Bit positions differences from this value are stored in the exception stream
+const NORMAL_BITS_MAX: u8 = 7;
+
+/// Best AcoustID result for a fingerprint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AcoustIdMatch {
+    /// AcoustID track id
+    pub acoustid: String,
+    /// Match score (0-1)
+    pub score: f32,
+    /// MusicBrainz id of the first recording
+    pub recording_id: String,
+    /// Recordings linked to the AcoustID track
+    pub recordings: Vec<RecordingCandidate>,
+}
+
+/// MusicBrainz recording linked to an AcoustID track
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordingCandidate {
+    pub id: String,
+    pub title: Option<String>,
+    pub artists: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LookupResponse {
+    status: String,
+    #[serde(default)]
+    results: Vec<LookupResult>,
+    error: Option<LookupError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LookupError {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct LookupResult {
+    id: String,
+    score: f32,
+    #[serde(default)]
+    recordings: Vec<Recording>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Recording {
+    id: String,
+    title: Option<String>,
+    #[serde(default)]
+    artists: Vec<Artist>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Artist {
+    name: String,
+}
+
+/// Raw Chromaprint fingerprint of the beginning of a file and the fingerprinted duration (seconds)
+#[cfg(feature = "fingerprinting")]
+pub fn chromaprint(path: &Path) -> Result<(Vec<u32>, f32)> {
+    let (samples, sample_rate) = crate::features::FeatureExtractor::new()
+        .with_window(Some(CHROMAPRINT_SECS), 0.0)
+        .decode_samples(path)?;
+    let pcm: Vec<u8> = samples.iter()
+        .flat_map(|s| ((s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes())
+        .collect();
+    let mut context = chromaprint::Chromaprint::new();
+    // `feed` passes the slice length as sample count, while Chromaprint reads that many 16-bit samples
+    if !context.start(sample_rate as i32, 1) || !context.feed(&pcm[..samples.len()]) || !context.finish() {
+        return Err(anyhow!("Chromaprint failed on {}", path.display()));
+    }
+    let codes = context.raw_fingerprint().ok_or_else(|| anyhow!("Chromaprint returned no fingerprint for {}", path.display()))?;
+    Ok((codes.into_iter().map(|c| c as u32).collect(), samples.len() as f32 / sample_rate as f32))
+}
+
+#[cfg(not(feature = "fingerprinting"))]
+pub fn chromaprint(path: &Path) -> Result<(Vec<u32>, f32)> {
+    Err(anyhow!("Can't fingerprint {}: Chromaprint support not enabled, build with the `fingerprinting` feature", path.display()))
+}
+
+/// Look up raw Chromaprint fingerprint (`chromaprint`, `fpcalc -raw`) of a track `duration` seconds long, `None` if no result has a recording
+pub async fn lookup(http_client: &reqwest::Client, config: &AcoustIdConfig, fingerprint: &[u32], duration: f32) -> Result<Option<AcoustIdMatch>, Error> {
+    let api_key = config.api_key.as_deref().filter(|k| !k.trim().is_empty())
+        .ok_or_else(|| anyhow!("AcoustID API key not configured"))?;
+    let form = [
+        ("client", api_key.to_string()),
+        ("meta", "recordings".to_string()),
+        ("duration", format!("{:.0}", duration)),
+        ("fingerprint", compress(fingerprint)),
+    ];
+    let response: LookupResponse = http_client.post(&config.endpoint)
+        .form(&form)
+        .send().await?
+        .json().await?;
+    if response.status != "ok" {
+        return Err(anyhow!("AcoustID lookup failed: {}", response.error.map(|e| e.message).unwrap_or(response.status)));
+    }
+    Ok(best_match(response.results))
+}
+
+/// Highest scoring result with at least one recording
+fn best_match(mut results: Vec<LookupResult>) -> Option<AcoustIdMatch> {
+    results.retain(|r| !r.recordings.is_empty());
+    let result = results.into_iter().max_by(|a, b| a.score.total_cmp(&b.score))?;
+    let recordings: Vec<RecordingCandidate> = result.recordings.into_iter()
+        .map(|r| RecordingCandidate { id: r.id, title: r.title, artists: r.artists.into_iter().map(|a| a.name).collect() })
+        .collect();
+    Some(AcoustIdMatch {
+        acoustid: result.id,
+        score: result.score,
+        recording_id: recordings[0].id.clone(),
+        recordings,
+    })
+}
+
+/// Compressed, URL-safe base64 fingerprint in the Chromaprint format
+pub fn compress(codes: &[u32]) -> String {
+    base64_url(&compress_bytes(codes))
+}
+
+/// Header (algorithm, 24-bit length) and positions of the bits changed from the previous code,
+/// split into a 3-bit and an exceptional 5-bit stream
+fn compress_bytes(codes: &[u32]) -> Vec<u8> {
+    let mut bits = Vec::new();
+    let mut previous = 0;
+    for code in codes {
+        let (mut x, mut bit, mut last_bit) = (code ^ previous, 1u8, 0u8);
+        while x != 0 {
+            if x & 1 != 0 {
+                bits.push(bit - last_bit);
+                last_bit = bit;
+            }
+            x >>= 1;
+            bit += 1;
+        }
+        bits.push(0);
+        previous = *code;
+    }
+
+    let mut output = vec![ALGORITHM, (codes.len() >> 16) as u8, (codes.len() >> 8) as u8, codes.len() as u8];
+    output.extend(pack(bits.iter().map(|b| (*b).min(NORMAL_BITS_MAX)), 3));
+    output.extend(pack(bits.iter().filter(|b| **b >= NORMAL_BITS_MAX).map(|b| b - NORMAL_BITS_MAX), 5));
+    output
+}
+
+/// Pack values of `width` bits, least significant bit first
+fn pack(values: impl Iterator<Item = u8>, width: u32) -> Vec<u8> {
+    let (mut output, mut buffer, mut used) = (Vec::new(), 0u32, 0);
+    for value in values {
+        buffer |= (value as u32 & ((1 << width) - 1)) << used;
+        used += width;
+        while used >= 8 {
+            output.push(buffer as u8);
+            buffer >>= 8;
+            used -= 8;
+        }
+    }
+    if used > 0 {
+        output.push(buffer as u8);
+    }
+    output
+}
+
+/// URL-safe base64 without padding
+fn base64_url(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+    let mut output = String::with_capacity(data.len() * 4 / 3 + 3);
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..=chunk.len() {
+            output.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{MockServer, MockResponse};
+
+    #[test]
+    fn test_compress() {
+        assert_eq!(base64_url(b"Man"), "TWFu");
+        assert_eq!(base64_url(b"Ma"), "TWE");
+        assert_eq!(pack([1, 2, 3, 4, 5, 6, 7, 0].into_iter(), 3), vec![0xd1, 0x58, 0x1f]);
+        // Chromaprint compressor test vectors
+        assert_eq!(compress_bytes(&[1]), vec![1, 0, 0, 1, 1]);
+        assert_eq!(compress_bytes(&[7]), vec![1, 0, 0, 1, 0x49, 0]);
+        assert_eq!(compress_bytes(&[1 << 6]), vec![1, 0, 0, 1, 7, 0]);
+        assert_eq!(compress(&[1]), "AQAAAQE");
+    }
+
+    #[tokio::test]
+    async fn test_lookup() {
+        let server = MockServer::start(vec![MockResponse::new(200, r#"{"status": "ok", "results": [
+            {"id": "empty", "score": 0.99},
+            {"id": "9ff43b6a-4f16-427c-93c2-92307ca505e0", "score": 0.94, "recordings": [
+                {"id": "cd2e7c47-16f5-46c6-a37c-a1eb7bf599ff", "title": "Strobe", "artists": [{"id": "x", "name": "deadmau5"}]},
+                {"id": "b1a9c0e9-d987-4042-ae91-78d6a3267d69"}
+            ]}
+        ]}"#)]).await;
+        let config = AcoustIdConfig { api_key: Some("key".to_string()), endpoint: server.url.clone() };
+        let fingerprint = [1, 2, 3];
+
+        let result = lookup(&reqwest::Client::new(), &config, &fingerprint, 634.6).await.unwrap().unwrap();
+        assert_eq!(result.acoustid, "9ff43b6a-4f16-427c-93c2-92307ca505e0");
+        assert_eq!(result.recording_id, "cd2e7c47-16f5-46c6-a37c-a1eb7bf599ff");
+        assert!((result.score - 0.94).abs() < 1e-6);
+        assert_eq!(result.recordings[0].artists, vec!["deadmau5"]);
+        assert_eq!(result.recordings.len(), 2);
+
+        let request = &server.requests()[0];
+        assert!(request.contains("client=key"));
+        assert!(request.contains("duration=635"));
+        assert!(request.contains(&format!("fingerprint={}", compress(&fingerprint))));
+
+        // Missing key
+        let config = AcoustIdConfig { api_key: None, ..config };
+        assert!(lookup(&reqwest::Client::new(), &config, &fingerprint, 60.0).await.is_err());
+    }
+}
@@ -6,81 +6,568 @@
 //! - Groq
 //! - Together AI
 //! - OpenAI
+//! - Ollama (local)
 
 use anyhow::{Error, Result};
+use futures_util::stream::{self, Stream};
 use serde::{Serialize, Deserialize};
+use std::collections::VecDeque;
+use std::future::Future;
 use std::time::Duration;
-use crate::config::{APIConfig, APIProvider};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::Semaphore;
+use std::collections::HashMap;
+use parking_lot::Mutex;
+use crate::cache::{Cache, ResponseCache};
+use crate::config::{APIConfig, APIProvider, AzureConfig, ConfigError};
+use crate::error::AiError;
+
+/// Non-success HTTP response from an API provider
+#[derive(Debug, Clone)]
+pub struct HttpError {
+    pub status: u16,
+    pub retry_after: Option<Duration>,
+    pub body: String,
+}
+
+impl HttpError {
+    /// Is this a temporary failure worth retrying
+    pub fn is_transient(&self) -> bool {
+        matches!(self.status, 429 | 500 | 502 | 503 | 504)
+    }
+}
+
+impl std::fmt::Display for HttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "API error ({}): {}", self.status, self.body)
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+/// Parse `Retry-After` header, either seconds or an HTTP date
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    // Dates in the past mean retry right away
+    Some((date.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().unwrap_or(Duration::ZERO))
+}
+
+lazy_static! {
+    /// Permits for requests in flight by provider and limit, shared by all clients
+    static ref IN_FLIGHT: Mutex<HashMap<(APIProvider, usize), Arc<Semaphore>>> = Mutex::new(HashMap::new());
+}
+
+/// Tags the mock client picks from
+#[cfg(any(test, feature = "mock-provider"))]
+const MOCK_TAGS: [&str; 8] = ["hypnotic", "peak-time", "warehouse", "melodic", "driving", "deep", "groovy", "euphoric"];
+
+/// Canned suggestion answer (`Description: ...` / `Tags: ...`) of the mock client, the same prompt always gets the same answer
+#[cfg(any(test, feature = "mock-provider"))]
+fn mock_response(prompt: &str) -> String {
+    use sha2::{Digest, Sha256};
+    let hash = Sha256::digest(prompt.as_bytes());
+    let mut tags: Vec<&str> = vec![];
+    for byte in hash.iter() {
+        let tag = MOCK_TAGS[*byte as usize % MOCK_TAGS.len()];
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+        if tags.len() == 3 {
+            break;
+        }
+    }
+    format!("Description: Mock description {:02x}{:02x}{:02x}{:02x}.\nTags: {}", hash[0], hash[1], hash[2], hash[3], tags.join(", "))
+}
+
+/// Approximate token count of text (~4 characters per token)
+pub fn estimate_tokens(text: &str) -> u64 {
+    (text.chars().count() as u64).div_ceil(4)
+}
+
+/// Shared counter of tokens used by live API calls
+#[derive(Debug, Clone, Default)]
+pub struct TokenCounter(Arc<AtomicU64>);
+
+impl TokenCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add used tokens
+    pub fn add(&self, tokens: u64) {
+        self.0.fetch_add(tokens, Ordering::SeqCst);
+    }
+
+    /// Total tokens used
+    pub fn total(&self) -> u64 {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Token usage reported by the provider
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageStats {
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Estimated cost in USD, `None` if no used model has a price in `APIConfig::model_prices`
+    pub cost_usd: Option<f64>,
+}
+
+impl UsageStats {
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+/// Cache lookups of a cache-only client
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct CacheLookups {
+    pub hits: usize,
+    pub misses: usize,
+}
 
 /// API client for LLM inference
 pub struct APIClient {
     config: APIConfig,
     http_client: reqwest::Client,
+    cache: Option<ResponseCache>,
+    tokens: TokenCounter,
+    /// Clients of `APIConfig::fallback_providers`
+    fallbacks: Vec<APIClient>,
+    /// Reported usage by model (shared with fallbacks)
+    usage: Arc<Mutex<HashMap<String, UsageStats>>>,
+    /// Only answer from cache, counting lookups (dry runs)
+    cache_only: Option<Arc<Mutex<CacheLookups>>>,
+    /// Permits for requests in flight (`APIConfig::max_concurrent_requests`), shared by clients of the provider
+    in_flight: Arc<Semaphore>,
+    /// Answers prompts instead of the provider (`APIClient::mock`)
+    responder: Option<fn(&str) -> String>,
 }
 
 impl APIClient {
-    /// Create a new API client
+    /// Create a new API client, the API key can also come from environment (see `APIConfig::resolved_api_key`)
     pub fn new(config: APIConfig) -> Result<Self> {
-        let http_client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(30))
-            .build()?;
+        Self::new_with_client(config, Self::default_http_client()?)
+    }
+
+    /// Create API client using a shared HTTP client, so connections are reused between clients
+    pub fn new_with_client(mut config: APIConfig, http_client: reqwest::Client) -> Result<Self> {
+        config.api_key = config.resolved_api_key();
+        let usage = Arc::new(Mutex::new(HashMap::new()));
+        let fallbacks = config.fallback_providers.iter()
+            .map(|fallback| APIClient::new_with_client(config.fallback_config(fallback), http_client.clone()).map(|c| APIClient { usage: usage.clone(), ..c }))
+            .collect::<Result<Vec<_>>>()?;
+        let permits = match config.max_concurrent_requests {
+            0 => Semaphore::MAX_PERMITS,
+            n => n,
+        };
+        let in_flight = IN_FLIGHT.lock().entry((config.provider, permits))
+            .or_insert_with(|| Arc::new(Semaphore::new(permits)))
+            .clone();
 
         Ok(Self {
+            in_flight,
             config,
             http_client,
+            cache: None,
+            tokens: TokenCounter::new(),
+            fallbacks,
+            usage,
+            cache_only: None,
+            responder: None,
         })
     }
 
-    /// Generate text using the configured API
-    pub async fn generate(&self, prompt: &str) -> Result<String> {
-        info!("Calling {} API", self.config.provider.display_name());
-        debug!("Prompt: {}", prompt);
+    /// Offline client answering every prompt with a canned suggestion derived from the prompt, for tests
+    #[cfg(any(test, feature = "mock-provider"))]
+    pub fn mock(config: APIConfig) -> Result<Self> {
+        Ok(Self { responder: Some(mock_response), ..Self::new(config)? })
+    }
+
+    /// HTTP client with the default timeout
+    pub fn default_http_client() -> Result<reqwest::Client> {
+        Ok(reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()?)
+    }
+
+    /// Count used tokens into a shared counter
+    pub fn with_token_counter(mut self, tokens: TokenCounter) -> Self {
+        self.tokens = tokens;
+        self
+    }
 
-        let response = match self.config.provider {
-            APIProvider::Gemini => self.call_gemini(prompt).await?,
-            APIProvider::OpenRouter => self.call_openrouter(prompt).await?,
-            APIProvider::Groq => self.call_groq(prompt).await?,
-            APIProvider::TogetherAI => self.call_together(prompt).await?,
-            APIProvider::OpenAI => self.call_openai(prompt).await?,
-            APIProvider::Custom => self.call_custom(prompt).await?,
+    /// Tokens used by live (not cached) requests of this client
+    pub fn tokens_used(&self) -> u64 {
+        self.tokens.total()
+    }
+
+    /// Token usage reported by the providers, with cost estimate
+    pub fn usage(&self) -> UsageStats {
+        let usage = self.usage.lock();
+        let mut total = UsageStats::default();
+        for (model, stats) in usage.iter() {
+            total.requests += stats.requests;
+            total.prompt_tokens += stats.prompt_tokens;
+            total.completion_tokens += stats.completion_tokens;
+            if let Some(price) = self.config.model_prices.get(model) {
+                let cost = (stats.prompt_tokens as f64 * price.prompt + stats.completion_tokens as f64 * price.completion) / 1_000_000.0;
+                total.cost_usd = Some(total.cost_usd.unwrap_or(0.0) + cost);
+            }
+        }
+        total
+    }
+
+    /// Add reported usage of a request
+    fn record_usage(&self, model: &str, prompt_tokens: u64, completion_tokens: u64) {
+        let mut usage = self.usage.lock();
+        let stats = usage.entry(model.to_string()).or_default();
+        stats.requests += 1;
+        stats.prompt_tokens += prompt_tokens;
+        stats.completion_tokens += completion_tokens;
+    }
+
+    /// Cache responses in the given directory (if enabled in config)
+    pub fn with_cache_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.cache = match (self.config.enable_cache, dir) {
+            (true, Some(dir)) => Some(ResponseCache::new(dir.join("api"), Duration::from_secs(self.config.cache_ttl))),
+            _ => None,
         };
+        self
+    }
 
-        debug!("Response: {}", response);
+    /// Cache responses in custom storage (if enabled in config)
+    pub fn with_cache(mut self, cache: Box<dyn Cache>) -> Self {
+        self.cache = self.config.enable_cache.then(|| ResponseCache::with_backend(cache, Duration::from_secs(self.config.cache_ttl)));
+        self
+    }
+
+    /// Never call the provider, uncached prompts fail. Lookups are counted into `lookups`
+    pub(crate) fn cache_only(mut self, lookups: Arc<Mutex<CacheLookups>>) -> Self {
+        self.cache_only = Some(lookups);
+        self
+    }
+
+    /// Remove all cached responses
+    pub fn clear_cache(&self) -> Result<()> {
+        if let Some(cache) = &self.cache {
+            cache.clear()?;
+        }
+        Ok(())
+    }
+
+    /// Cache key for prompt with provider and model of `config`
+    fn cache_key(config: &APIConfig, prompt: &str) -> String {
+        let provider = format!("{:?}", config.provider);
+        let mut parts = vec![provider.as_str(), config.model_name().unwrap_or("default")];
+        // Responses depend on the system prompt too
+        if let Some(system) = &config.system_prompt {
+            parts.push(system);
+        }
+        parts.push(prompt);
+        ResponseCache::key(&parts)
+    }
+
+    /// Generate text using the configured API (or cache), retrying transient failures
+    pub async fn generate(&self, prompt: &str) -> Result<String, AiError> {
+        let cache_key = Self::cache_key(&self.config, prompt);
+        let cached = self.cache.as_ref().and_then(|c| c.get(&cache_key));
+        if let Some(lookups) = &self.cache_only {
+            let mut lookups = lookups.lock();
+            return match cached {
+                Some(response) => { lookups.hits += 1; Ok(response) },
+                None => { lookups.misses += 1; Err(anyhow!("Response not cached").into()) },
+            };
+        }
+        if let Some(response) = cached {
+            debug!("Using cached response");
+            return Ok(response);
+        }
+
+        let mut response = self.generate_live(prompt).await;
+        // Cache under the provider and model which answered
+        let mut answered = &self.config;
+        for fallback in &self.fallbacks {
+            match response {
+                Ok(_) => break,
+                Err(e) => {
+                    warn!("{} API failed: {}. Falling back to {}", self.config.provider.display_name(), e, fallback.config.provider.display_name());
+                    response = fallback.generate_live(prompt).await;
+                    if response.is_ok() {
+                        info!("Response from fallback provider {}", fallback.config.provider.display_name());
+                        answered = &fallback.config;
+                    }
+                }
+            }
+        }
+        let response = response?;
+        self.store_response(answered, prompt, &response);
         Ok(response)
     }
 
-    /// Call Google Gemini API
-    async fn call_gemini(&self, prompt: &str) -> Result<String> {
+    /// Generate text yielding chunks as they arrive, for providers supporting streaming (Gemini, OpenAI-compatible).
+    /// Cached responses and other providers yield the whole response as a single chunk, fallback providers aren't used
+    pub fn generate_stream<'a>(&'a self, prompt: &'a str) -> impl Stream<Item = Result<String, AiError>> + 'a {
+        stream::unfold(StreamState::Start, move |state| async move {
+            match state {
+                StreamState::Start => {
+                    let cached = self.cache.as_ref().and_then(|c| c.get(&Self::cache_key(&self.config, prompt))).is_some();
+                    if cached || self.cache_only.is_some() || self.responder.is_some() || self.config.provider == APIProvider::Ollama {
+                        return Some((self.generate(prompt).await, StreamState::Done));
+                    }
+                    info!("Streaming from {} API", self.config.provider.display_name());
+                    debug!("Prompt: {}", prompt);
+                    match self.with_retries(|| self.start_stream(prompt)).await {
+                        Ok(response) => self.next_stream_chunk(prompt, Box::new(StreamReader::new(response))).await,
+                        Err(e) => Some((Err(e.into()), StreamState::Done)),
+                    }
+                },
+                StreamState::Receiving(reader) => self.next_stream_chunk(prompt, reader).await,
+                StreamState::Done => None,
+            }
+        })
+    }
+
+    /// Next text chunk of a streaming response, the full response is cached once the stream ends
+    async fn next_stream_chunk(&self, prompt: &str, mut reader: Box<StreamReader>) -> Option<(Result<String, AiError>, StreamState)> {
+        loop {
+            if let Some(chunk) = reader.pending.pop_front() {
+                return Some((Ok(chunk), StreamState::Receiving(reader)));
+            }
+            if reader.finished {
+                self.store_response(&self.config, prompt, &reader.text);
+                return None;
+            }
+            match reader.response.chunk().await {
+                Ok(Some(bytes)) => reader.buffer.extend_from_slice(&bytes),
+                // Last line might not be terminated
+                Ok(None) => {
+                    reader.finished = true;
+                    reader.buffer.push(b'\n');
+                },
+                Err(e) => return Some((Err(AiError::Other(e.into())), StreamState::Done)),
+            }
+            // Events of complete lines
+            while let Some(end) = reader.buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = reader.buffer.drain(..=end).collect();
+                match self.stream_event_text(String::from_utf8_lossy(&line).trim()) {
+                    Ok(Some(text)) if !text.is_empty() => {
+                        reader.text.push_str(&text);
+                        reader.pending.push_back(text);
+                    },
+                    Ok(_) => {},
+                    Err(e) => return Some((Err(e.into()), StreamState::Done)),
+                }
+            }
+        }
+    }
+
+    /// Text of a server-sent event line, None for other lines
+    fn stream_event_text(&self, line: &str) -> Result<Option<String>> {
+        let data = match line.strip_prefix("data:").map(|d| d.trim()) {
+            Some("[DONE]") | None => return Ok(None),
+            Some(data) => data,
+        };
+        let json: serde_json::Value = serde_json::from_str(data)?;
+        if let Some(error) = json.get("error") {
+            return Err(anyhow!("Streaming error: {}", error));
+        }
+        let text = match self.config.provider {
+            APIProvider::Gemini => &json["candidates"][0]["content"]["parts"][0]["text"],
+            _ => &json["choices"][0]["delta"]["content"],
+        };
+        Ok(text.as_str().map(String::from))
+    }
+
+    /// Count tokens and cache a live response from provider of `answered`
+    fn store_response(&self, answered: &APIConfig, prompt: &str, response: &str) {
+        debug!("Response: {}", response);
+        self.tokens.add(estimate_tokens(prompt) + estimate_tokens(response));
+        if let Some(cache) = &self.cache {
+            if let Err(e) = cache.put(&Self::cache_key(answered, prompt), response) {
+                warn!("Failed caching API response: {}", e);
+            }
+        }
+    }
+
+    /// Call the configured provider, retrying transient failures
+    async fn generate_live(&self, prompt: &str) -> Result<String> {
+        info!("Calling {} API", self.config.provider.display_name());
+        debug!("Prompt: {}", prompt);
+        self.with_retries(|| self.call_provider(prompt)).await
+    }
+
+    /// Run request, retrying transient failures.
+    /// Each attempt holds a concurrency permit, which is released while waiting for the retry
+    async fn with_retries<T, F, Fut>(&self, mut request: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempt = 0;
+        loop {
+            let permit = self.in_flight.acquire().await?;
+            let result = request().await;
+            drop(permit);
+            match result {
+                Ok(response) => return Ok(response),
+                Err(e) => {
+                    let delay = match e.downcast_ref::<HttpError>() {
+                        Some(http) if http.is_transient() && attempt < self.config.retry.max_retries => {
+                            self.config.retry.delay(attempt, http.retry_after)
+                        },
+                        _ => None,
+                    };
+                    let Some(delay) = delay else { return Err(e) };
+                    warn!("{} API request failed: {}. Retrying in {:?}", self.config.provider.display_name(), e, delay);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// Single request to the configured provider
+    async fn call_provider(&self, prompt: &str) -> Result<String> {
+        if let Some(responder) = &self.responder {
+            return Ok(responder(prompt));
+        }
+        match self.config.provider {
+            APIProvider::Gemini => self.call_gemini(prompt).await,
+            APIProvider::Ollama => self.call_ollama(prompt).await,
+            _ => {
+                let (endpoint, model) = self.openai_target()?;
+                self.call_openai_compatible(&endpoint, prompt, model).await
+            },
+        }
+    }
+
+    /// Start a streaming request to the configured provider
+    async fn start_stream(&self, prompt: &str) -> Result<reqwest::Response> {
+        if self.config.provider == APIProvider::Gemini {
+            let api_key = self.config.api_key.as_ref()
+                .ok_or(ConfigError::MissingApiKey(self.config.provider))?;
+            let endpoint = self.gemini_endpoint().replace(":generateContent", ":streamGenerateContent");
+            let url = format!("{}?alt=sse&key={}", endpoint, api_key);
+            return self.send(self.http_client.post(&url).json(&self.gemini_body(prompt))).await;
+        }
+
+        let (endpoint, model) = self.openai_target()?;
         let api_key = self.config.api_key.as_ref()
-            .ok_or_else(|| anyhow!("Gemini API key not set. Get one free at: https://aistudio.google.com/app/apikey"))?;
+            .ok_or(ConfigError::MissingApiKey(self.config.provider))?;
+        let mut request_body = self.openai_body(prompt, model);
+        request_body["stream"] = serde_json::json!(true);
+        self.send(self.openai_auth(self.http_client.post(&endpoint), api_key).json(&request_body)).await
+    }
 
-        let endpoint = self.config.endpoint.as_deref()
-            .unwrap_or_else(|| self.config.provider.default_endpoint());
+    /// Endpoint and default model of OpenAI-compatible providers
+    fn openai_target(&self) -> Result<(String, &'static str)> {
+        let endpoint = self.config.provider.default_endpoint().to_string();
+        match self.config.provider {
+            APIProvider::OpenRouter => Ok((endpoint, "openchat/openchat-7b:free")),  // Free model
+            APIProvider::Groq => Ok((endpoint, "llama-3.2-3b-preview")),  // Free, fast Llama
+            APIProvider::TogetherAI => Ok((endpoint, "meta-llama/Meta-Llama-3.1-8B-Instruct-Turbo")),
+            APIProvider::OpenAI => Ok((endpoint, "gpt-3.5-turbo")),
+            APIProvider::AzureOpenAI => Ok((self.azure_url()?, "")),
+            APIProvider::Custom => match self.config.endpoint.as_deref() {
+                Some(endpoint) if AzureConfig::is_azure_endpoint(endpoint) => Ok((self.azure_url()?, "")),
+                Some(endpoint) => Ok((endpoint.to_string(), "")),
+                None => Err(anyhow!("Custom endpoint not configured")),
+            },
+            provider => Err(anyhow!("{} isn't OpenAI-compatible", provider.display_name())),
+        }
+    }
 
-        let url = format!("{}?key={}", endpoint, api_key);
+    /// Is the Azure OpenAI URL layout and auth used
+    fn is_azure(&self) -> bool {
+        match self.config.provider {
+            APIProvider::AzureOpenAI => true,
+            APIProvider::Custom => self.config.endpoint.as_deref().map(AzureConfig::is_azure_endpoint).unwrap_or(false),
+            _ => false,
+        }
+    }
 
-        let request_body = serde_json::json!({
-            "contents": [{
-                "parts": [{"text": prompt}]
-            }],
-            "generationConfig": {
-                "temperature": 0.7,
-                "maxOutputTokens": 256,
+    /// Chat completions URL of the Azure deployment (deployment in path, `api-version` query).
+    /// Endpoint is either the resource (`https://<resource>.openai.azure.com`) or already the full deployment URL
+    fn azure_url(&self) -> Result<String> {
+        let endpoint = self.config.endpoint.as_deref()
+            .ok_or_else(|| anyhow!("Azure OpenAI endpoint not configured"))?
+            .trim_end_matches('/');
+        let azure = &self.config.azure;
+        let mut url = match endpoint.contains("/openai/deployments/") {
+            true => endpoint.to_string(),
+            false => {
+                let deployment = azure.deployment.as_deref().or(self.config.model_name())
+                    .ok_or_else(|| anyhow!("Azure OpenAI deployment not configured"))?;
+                format!("{}/openai/deployments/{}/chat/completions", endpoint, deployment)
             }
-        });
+        };
+        if !url.contains("api-version=") {
+            url.push(if url.contains('?') { '&' } else { '?' });
+            url.push_str(&format!("api-version={}", azure.api_version));
+        }
+        Ok(url)
+    }
 
-        let response = self.http_client
-            .post(&url)
-            .json(&request_body)
-            .send()
-            .await?;
+    /// Add the provider's auth header
+    fn openai_auth(&self, request: reqwest::RequestBuilder, api_key: &str) -> reqwest::RequestBuilder {
+        match self.is_azure() {
+            true => request.header("api-key", api_key),
+            false => request.header("Authorization", format!("Bearer {}", api_key)),
+        }
+    }
 
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(anyhow!("Gemini API error: {}", error_text));
+    /// Gemini endpoint, the model is part of the URL
+    fn gemini_endpoint(&self) -> String {
+        match (&self.config.endpoint, self.config.model_name()) {
+            (Some(endpoint), _) => endpoint.to_string(),
+            (None, Some(model)) => format!("https://generativelanguage.googleapis.com/v1beta/models/{}:generateContent", model),
+            (None, None) => self.config.provider.default_endpoint().to_string(),
         }
+    }
+
+    /// Send request and convert non-success responses into `HttpError`
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let retry_after = response.headers().get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_retry_after);
+        let body = response.text().await.unwrap_or_default();
+        Err(HttpError { status: status.as_u16(), retry_after, body }.into())
+    }
+
+    /// Call Google Gemini API
+    async fn call_gemini(&self, prompt: &str) -> Result<String> {
+        let api_key = self.config.api_key.as_ref()
+            .ok_or(ConfigError::MissingApiKey(self.config.provider))?;
+
+        let url = format!("{}?key={}", self.gemini_endpoint(), api_key);
+        let request_body = self.gemini_body(prompt);
+
+        let response = self.send(
+            self.http_client
+                .post(&url)
+                .json(&request_body)
+        ).await?;
 
         let json: GeminiResponse = response.json().await?;
+        if let Some(usage) = &json.usage_metadata {
+            let model = self.config.model_name().unwrap_or(GEMINI_DEFAULT_MODEL);
+            self.record_usage(model, usage.prompt_token_count, usage.candidates_token_count);
+        }
 
         json.candidates.first()
             .and_then(|c| c.content.parts.first())
@@ -88,81 +575,91 @@ impl APIClient {
             .ok_or_else(|| anyhow!("No response from Gemini"))
     }
 
-    /// Call OpenRouter API (OpenAI-compatible)
-    async fn call_openrouter(&self, prompt: &str) -> Result<String> {
-        self.call_openai_compatible(
-            self.config.provider.default_endpoint(),
-            prompt,
-            "openchat/openchat-7b:free",  // Free model
-        ).await
-    }
+    /// Call local Ollama server (non-streaming)
+    async fn call_ollama(&self, prompt: &str) -> Result<String> {
+        let endpoint = self.config.endpoint.as_deref()
+            .unwrap_or_else(|| self.config.provider.default_endpoint());
 
-    /// Call Groq API (OpenAI-compatible)
-    async fn call_groq(&self, prompt: &str) -> Result<String> {
-        self.call_openai_compatible(
-            self.config.provider.default_endpoint(),
-            prompt,
-            "llama-3.2-3b-preview",  // Free, fast Llama
-        ).await
-    }
+        let mut request_body = serde_json::json!({
+            "model": self.config.model_name().unwrap_or("llama3.2"),
+            "prompt": prompt,
+            "stream": false,
+            "options": {
+                "temperature": self.config.model.temperature,
+                "num_predict": self.config.model.max_tokens,
+            }
+        });
+        if let Some(system) = &self.config.system_prompt {
+            request_body["system"] = serde_json::json!(system);
+        }
 
-    /// Call Together AI API (OpenAI-compatible)
-    async fn call_together(&self, prompt: &str) -> Result<String> {
-        self.call_openai_compatible(
-            self.config.provider.default_endpoint(),
-            prompt,
-            "meta-llama/Meta-Llama-3.1-8B-Instruct-Turbo",
-        ).await
-    }
+        let response = self.send(
+            self.http_client
+                .post(endpoint)
+                .json(&request_body)
+        ).await?;
 
-    /// Call OpenAI API
-    async fn call_openai(&self, prompt: &str) -> Result<String> {
-        self.call_openai_compatible(
-            self.config.provider.default_endpoint(),
-            prompt,
-            "gpt-3.5-turbo",
-        ).await
+        let json: OllamaResponse = response.json().await?;
+        if let (Some(prompt_tokens), Some(completion_tokens)) = (json.prompt_eval_count, json.eval_count) {
+            self.record_usage(request_body["model"].as_str().unwrap_or_default(), prompt_tokens, completion_tokens);
+        }
+        Ok(json.response)
     }
 
-    /// Call custom endpoint
-    async fn call_custom(&self, prompt: &str) -> Result<String> {
-        let endpoint = self.config.endpoint.as_ref()
-            .ok_or_else(|| anyhow!("Custom endpoint not configured"))?;
+    /// Gemini request body
+    fn gemini_body(&self, prompt: &str) -> serde_json::Value {
+        let mut body = serde_json::json!({
+            "contents": [{
+                "parts": [{"text": prompt}]
+            }],
+            "generationConfig": {
+                "temperature": self.config.model.temperature,
+                "maxOutputTokens": self.config.model.max_tokens,
+            }
+        });
+        if let Some(system) = &self.config.system_prompt {
+            body["systemInstruction"] = serde_json::json!({ "parts": [{"text": system}] });
+        }
+        if let Some(threshold) = self.config.safety_threshold {
+            body["safetySettings"] = GEMINI_HARM_CATEGORIES.iter()
+                .map(|category| serde_json::json!({ "category": category, "threshold": threshold.gemini_value() }))
+                .collect();
+        }
+        body
+    }
 
-        self.call_openai_compatible(endpoint, prompt, "").await
+    /// OpenAI-compatible request body, configured model (`APIConfig::model_name`) overrides the default one
+    fn openai_body(&self, prompt: &str, default_model: &str) -> serde_json::Value {
+        let mut messages = vec![];
+        if let Some(system) = &self.config.system_prompt {
+            messages.push(serde_json::json!({ "role": "system", "content": system }));
+        }
+        messages.push(serde_json::json!({ "role": "user", "content": prompt }));
+        serde_json::json!({
+            "model": self.config.model_name().unwrap_or(default_model),
+            "messages": messages,
+            "temperature": self.config.model.temperature,
+            "max_tokens": self.config.model.max_tokens,
+        })
     }
 
     /// Helper for OpenAI-compatible APIs
     async fn call_openai_compatible(&self, endpoint: &str, prompt: &str, model: &str) -> Result<String> {
         let api_key = self.config.api_key.as_ref()
-            .ok_or_else(|| anyhow!("API key not set"))?;
-
-        let request_body = serde_json::json!({
-            "model": model,
-            "messages": [
-                {
-                    "role": "user",
-                    "content": prompt
-                }
-            ],
-            "temperature": 0.7,
-            "max_tokens": 256,
-        });
+            .ok_or(ConfigError::MissingApiKey(self.config.provider))?;
 
-        let response = self.http_client
-            .post(endpoint)
-            .header("Authorization", format!("Bearer {}", api_key))
-            .header("Content-Type", "application/json")
-            .json(&request_body)
-            .send()
-            .await?;
+        let request_body = self.openai_body(prompt, model);
 
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(anyhow!("API error: {}", error_text));
-        }
+        let response = self.send(
+            self.openai_auth(self.http_client.post(endpoint), api_key)
+                .header("Content-Type", "application/json")
+                .json(&request_body)
+        ).await?;
 
         let json: OpenAIResponse = response.json().await?;
+        if let Some(usage) = &json.usage {
+            self.record_usage(request_body["model"].as_str().unwrap_or(model), usage.prompt_tokens, usage.completion_tokens);
+        }
 
         json.choices.first()
             .map(|c| c.message.content.clone())
@@ -170,10 +667,58 @@ impl APIClient {
     }
 }
 
+/// Progress of `APIClient::generate_stream`
+enum StreamState {
+    Start,
+    Receiving(Box<StreamReader>),
+    Done,
+}
+
+/// Reads server-sent events of a streaming response
+struct StreamReader {
+    response: reqwest::Response,
+    /// Bytes of the unfinished line
+    buffer: Vec<u8>,
+    /// Parsed chunks not yielded yet
+    pending: VecDeque<String>,
+    /// Full text so far
+    text: String,
+    finished: bool,
+}
+
+impl StreamReader {
+    fn new(response: reqwest::Response) -> Self {
+        Self { response, buffer: Vec::new(), pending: VecDeque::new(), text: String::new(), finished: false }
+    }
+}
+
+/// Harm categories configured by `APIConfig::safety_threshold`
+const GEMINI_HARM_CATEGORIES: [&str; 4] = [
+    "HARM_CATEGORY_HARASSMENT",
+    "HARM_CATEGORY_HATE_SPEECH",
+    "HARM_CATEGORY_SEXUALLY_EXPLICIT",
+    "HARM_CATEGORY_DANGEROUS_CONTENT",
+];
+
+/// Model of the default Gemini endpoint
+const GEMINI_DEFAULT_MODEL: &str = "gemini-2.0-flash-exp";
+
 /// Gemini API response structure
 #[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct GeminiResponse {
     candidates: Vec<GeminiCandidate>,
+    #[serde(default)]
+    usage_metadata: Option<GeminiUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiUsage {
+    #[serde(default)]
+    prompt_token_count: u64,
+    #[serde(default)]
+    candidates_token_count: u64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -195,6 +740,16 @@ struct GeminiPart {
 #[derive(Debug, Deserialize)]
 struct OpenAIResponse {
     choices: Vec<OpenAIChoice>,
+    #[serde(default)]
+    usage: Option<OpenAIUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIUsage {
+    #[serde(default)]
+    prompt_tokens: u64,
+    #[serde(default)]
+    completion_tokens: u64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -207,9 +762,21 @@ struct OpenAIMessage {
     content: String,
 }
 
+/// Ollama non-streaming response
+#[derive(Debug, Deserialize)]
+struct OllamaResponse {
+    response: String,
+    #[serde(default)]
+    prompt_eval_count: Option<u64>,
+    #[serde(default)]
+    eval_count: Option<u64>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::{FallbackProvider, ModelConfig};
+    use crate::test_utils::{MockServer, MockResponse, gemini_response};
 
     #[test]
     fn test_api_client_creation() {
@@ -224,4 +791,342 @@ mod tests {
         assert!(APIProvider::Gemini.default_endpoint().contains("googleapis.com"));
         assert!(APIProvider::Groq.default_endpoint().contains("groq.com"));
     }
+
+    #[test]
+    fn test_model_config_request_body() {
+        let mut config = APIConfig::default();
+        config.model = ModelConfig {
+            model_name: Some("llama-3.3-70b-versatile".to_string()),
+            temperature: 0.2,
+            max_tokens: 512,
+        };
+        let client = APIClient::new(config).unwrap();
+
+        let body = client.openai_body("prompt", "llama-3.2-3b-preview");
+        assert_eq!(body["model"], "llama-3.3-70b-versatile");
+        assert_eq!(body["max_tokens"], 512);
+        assert!((body["temperature"].as_f64().unwrap() - 0.2).abs() < 1e-6);
+
+        let body = client.gemini_body("prompt");
+        assert_eq!(body["generationConfig"]["maxOutputTokens"], 512);
+        assert!((body["generationConfig"]["temperature"].as_f64().unwrap() - 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_system_prompt_and_safety_settings() {
+        let client = APIClient::new(APIConfig::default()).unwrap();
+        let body = client.gemini_body("prompt");
+        assert!(body.get("systemInstruction").is_none());
+        assert!(body.get("safetySettings").is_none());
+
+        let mut config = APIConfig::default();
+        config.system_prompt = Some("You are a music tagger".to_string());
+        config.safety_threshold = Some(crate::config::SafetyThreshold::BlockOnlyHigh);
+        let client = APIClient::new(config).unwrap();
+        let body = client.gemini_body("prompt");
+        assert_eq!(body["systemInstruction"]["parts"][0]["text"], "You are a music tagger");
+        assert_eq!(body["contents"][0]["parts"][0]["text"], "prompt");
+        assert_eq!(body["safetySettings"].as_array().unwrap().len(), 4);
+        assert_eq!(body["safetySettings"][0]["threshold"], "BLOCK_ONLY_HIGH");
+
+        let body = client.openai_body("prompt", "gpt-3.5-turbo");
+        assert_eq!(body["messages"][0]["role"], "system");
+        assert_eq!(body["messages"][1]["content"], "prompt");
+    }
+
+    #[test]
+    fn test_default_model_used_without_override() {
+        let client = APIClient::new(APIConfig::default()).unwrap();
+        let body = client.openai_body("prompt", "gpt-3.5-turbo");
+        assert_eq!(body["model"], "gpt-3.5-turbo");
+        assert_eq!(body["max_tokens"], 256);
+    }
+
+    /// Gemini config pointing to a mock server
+    fn mock_config(url: &str) -> APIConfig {
+        let mut config = APIConfig::default();
+        config.api_key = Some("test".to_string());
+        config.endpoint = Some(url.to_string());
+        config.retry.base_delay_ms = 1;
+        config
+    }
+
+    #[tokio::test]
+    async fn test_generate_stream() {
+        use futures_util::StreamExt;
+        let chunks = ["Deep, ", "hypnotic ", "techno"];
+
+        // OpenAI-compatible
+        let body: String = chunks.iter()
+            .map(|c| format!("data: {}\n\n", serde_json::json!({ "choices": [{ "delta": { "content": c } }] })))
+            .chain(["data: [DONE]\n\n".to_string()])
+            .collect();
+        let server = MockServer::start(vec![MockResponse::new(200, body)]).await;
+        let mut config = mock_config(&server.url);
+        config.provider = APIProvider::Custom;
+        let client = APIClient::new(config).unwrap();
+        let streamed: Vec<String> = client.generate_stream("describe").map(|c| c.unwrap()).collect().await;
+        assert_eq!(streamed, chunks);
+        assert_eq!(streamed.concat(), "Deep, hypnotic techno");
+        assert!(server.requests()[0].contains("\"stream\":true"));
+
+        // Gemini, last event without trailing newline
+        let body: String = chunks.iter()
+            .map(|c| format!("data: {}\r\n\r\n", gemini_response(c)))
+            .collect::<String>().trim_end().to_string();
+        let server = MockServer::start(vec![MockResponse::new(200, body)]).await;
+        let client = APIClient::new(mock_config(&server.url)).unwrap();
+        let streamed: Vec<String> = client.generate_stream("describe").map(|c| c.unwrap()).collect().await;
+        assert_eq!(streamed, chunks);
+        assert!(server.requests()[0].contains("alt=sse"));
+        assert!(client.tokens_used() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_azure_request() {
+        let server = MockServer::start(vec![MockResponse::new(200, r#"{"choices": [{"message": {"content": "techno"}}]}"#)]).await;
+        let mut config = mock_config(&format!("{}/", server.url));
+        config.provider = APIProvider::AzureOpenAI;
+        config.azure.deployment = Some("tagger-gpt".to_string());
+        config.azure.api_version = "2024-10-21".to_string();
+        let client = APIClient::new(config).unwrap();
+        assert_eq!(client.generate("prompt").await.unwrap(), "techno");
+
+        let request = server.requests()[0].to_lowercase();
+        assert!(request.starts_with("post /openai/deployments/tagger-gpt/chat/completions?api-version=2024-10-21 "));
+        assert!(request.contains("\r\napi-key: test\r\n"));
+        assert!(!request.contains("authorization"));
+
+        // Full deployment URL as custom endpoint
+        let mut config = APIConfig::default();
+        config.provider = APIProvider::Custom;
+        config.endpoint = Some("https://res.openai.azure.com/openai/deployments/gpt/chat/completions".to_string());
+        let client = APIClient::new(config).unwrap();
+        assert!(client.is_azure());
+        assert_eq!(client.openai_target().unwrap().0, "https://res.openai.azure.com/openai/deployments/gpt/chat/completions?api-version=2024-06-01");
+    }
+
+    #[tokio::test]
+    async fn test_max_concurrent_requests() {
+        let mut config = mock_config("http://localhost");
+        config.max_concurrent_requests = 3;
+        // Limit holds across clients (tagger threads)
+        let clients = [APIClient::new(config.clone()).unwrap(), APIClient::new(config).unwrap()];
+        let (in_flight, max) = (&AtomicU64::new(0), &AtomicU64::new(0));
+
+        let tasks = (0..10).map(|i| clients[i % 2].with_retries(move || async move {
+            let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            max.fetch_max(current, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+            Ok(())
+        }));
+        for result in futures_util::future::join_all(tasks).await {
+            result.unwrap();
+        }
+        assert_eq!(max.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_fallback_provider() {
+        let primary = MockServer::start(vec![MockResponse::new(500, "down")]).await;
+        let fallback = MockServer::start(vec![MockResponse::new(200, gemini_response("fallback, answer"))]).await;
+
+        let mut config = mock_config(&primary.url);
+        config.retry.max_retries = 1;
+        // Fallback uses the provider's default model
+        config.model.model_name = Some("primary-model".to_string());
+        config.fallback_providers = vec![
+            // Unreachable
+            FallbackProvider { provider: APIProvider::Ollama, api_key: None, endpoint: Some("http://127.0.0.1:1/api/generate".to_string()) },
+            FallbackProvider { provider: APIProvider::Gemini, api_key: Some("fallback".to_string()), endpoint: Some(fallback.url.clone()) },
+        ];
+        let dir = tempfile::tempdir().unwrap();
+        let client = APIClient::new(config).unwrap().with_cache_dir(Some(dir.path().to_owned()));
+        assert_eq!(client.generate("test").await.unwrap(), "fallback, answer");
+        assert_eq!(primary.hits(), 2);
+        assert_eq!(fallback.hits(), 1);
+        assert!(fallback.requests()[0].contains("key=fallback"));
+
+        // Cached as the fallback's answer, not the primary's
+        let cache = client.cache.as_ref().unwrap();
+        assert!(cache.get(&APIClient::cache_key(&client.config, "test")).is_none());
+        assert_eq!(cache.get(&APIClient::cache_key(&client.fallbacks[1].config, "test")).unwrap(), "fallback, answer");
+    }
+
+    #[tokio::test]
+    async fn test_usage_stats() {
+        let gemini = serde_json::json!({
+            "candidates": [{ "content": { "parts": [{ "text": "techno" }] } }],
+            "usageMetadata": { "promptTokenCount": 100, "candidatesTokenCount": 20, "totalTokenCount": 120 }
+        }).to_string();
+        let openai = serde_json::json!({
+            "choices": [{ "message": { "content": "house" } }],
+            "usage": { "prompt_tokens": 1000, "completion_tokens": 500, "total_tokens": 1500 }
+        }).to_string();
+        let gemini_server = MockServer::start(vec![MockResponse::new(200, gemini)]).await;
+        let openai_server = MockServer::start(vec![MockResponse::new(200, openai)]).await;
+
+        let client = APIClient::new(mock_config(&gemini_server.url)).unwrap();
+        client.generate("a").await.unwrap();
+        client.generate("b").await.unwrap();
+        let usage = client.usage();
+        assert_eq!(usage, UsageStats { requests: 2, prompt_tokens: 200, completion_tokens: 40, cost_usd: None });
+
+        let mut config = mock_config(&openai_server.url);
+        config.provider = APIProvider::Custom;
+        config.model.model_name = Some("gpt-4o-mini".to_string());
+        config.model_prices.insert("gpt-4o-mini".to_string(), crate::config::ModelPrice { prompt: 0.15, completion: 0.6 });
+        let client = APIClient::new(config).unwrap();
+        client.generate("a").await.unwrap();
+        let usage = client.usage();
+        assert_eq!(usage.total_tokens(), 1500);
+        assert!((usage.cost_usd.unwrap() - 0.00045).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_provider_models() {
+        let mut config = APIConfig { provider: APIProvider::Groq, api_key: Some("key".to_string()), ..Default::default() };
+        config.models.insert(APIProvider::Groq, "llama-3.3-70b-versatile".to_string());
+        config.models.insert(APIProvider::OpenAI, "gpt-4o".to_string());
+        // Round trips with provider names as keys
+        let json = serde_json::to_value(&config).unwrap();
+        assert_eq!(json["models"]["groq"], "llama-3.3-70b-versatile");
+        let config: APIConfig = serde_json::from_value(json).unwrap();
+
+        let client = APIClient::new(config.clone()).unwrap();
+        let (_, default_model) = client.openai_target().unwrap();
+        assert_eq!(client.openai_body("prompt", default_model)["model"], "llama-3.3-70b-versatile");
+
+        // Unset providers keep their default
+        let client = APIClient::new(APIConfig { provider: APIProvider::TogetherAI, ..config }).unwrap();
+        let (_, default_model) = client.openai_target().unwrap();
+        assert_eq!(client.openai_body("prompt", default_model)["model"], "meta-llama/Meta-Llama-3.1-8B-Instruct-Turbo");
+    }
+
+    #[tokio::test]
+    async fn test_shared_http_client() {
+        let server1 = MockServer::start(vec![MockResponse::new(200, gemini_response("first"))]).await;
+        let server2 = MockServer::start(vec![MockResponse::new(200, gemini_response("second"))]).await;
+        let http_client = APIClient::default_http_client().unwrap();
+
+        let client1 = APIClient::new_with_client(mock_config(&server1.url), http_client.clone()).unwrap();
+        let client2 = APIClient::new_with_client(mock_config(&server2.url), http_client).unwrap();
+        assert_eq!(client1.generate("test").await.unwrap(), "first");
+        assert_eq!(client2.generate("test").await.unwrap(), "second");
+        assert_eq!(client1.generate("again").await.unwrap(), "first");
+        assert_eq!(server1.hits(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_retry_on_rate_limit() {
+        let server = MockServer::start(vec![
+            MockResponse::new(429, "rate limited").header("Retry-After", "0"),
+            MockResponse::new(503, "unavailable"),
+            MockResponse::new(200, gemini_response("techno, dark")),
+        ]).await;
+
+        let client = APIClient::new(mock_config(&server.url)).unwrap();
+        let response = client.generate("test").await.unwrap();
+        assert_eq!(response, "techno, dark");
+        assert_eq!(server.hits(), 3);
+    }
+
+    #[test]
+    fn test_ollama_response() {
+        let json: OllamaResponse = serde_json::from_str(r#"{"model":"llama3.2","created_at":"2024-01-01T00:00:00Z","response":"hypnotic, warehouse","done":true}"#).unwrap();
+        assert_eq!(json.response, "hypnotic, warehouse");
+    }
+
+    #[tokio::test]
+    async fn test_ollama_provider() {
+        let server = MockServer::start(vec![
+            MockResponse::new(200, r#"{"model":"mistral","response":"hypnotic, warehouse","done":true}"#)
+        ]).await;
+        let mut config = APIConfig::default();
+        config.provider = APIProvider::Ollama;
+        config.endpoint = Some(format!("{}/api/generate", server.url));
+        config.model.model_name = Some("mistral".to_string());
+
+        let client = APIClient::new(config).unwrap();
+        assert_eq!(client.generate("test").await.unwrap(), "hypnotic, warehouse");
+        let request = &server.requests()[0];
+        assert!(request.contains(r#""stream":false"#));
+        assert!(request.contains(r#""model":"mistral""#));
+    }
+
+    #[tokio::test]
+    async fn test_response_cache() {
+        let server = MockServer::start(vec![MockResponse::new(200, gemini_response("techno, dark"))]).await;
+        let dir = tempfile::tempdir().unwrap();
+        let client = APIClient::new(mock_config(&server.url)).unwrap()
+            .with_cache_dir(Some(dir.path().to_owned()));
+
+        assert_eq!(client.generate("same prompt").await.unwrap(), "techno, dark");
+        assert_eq!(client.generate("same prompt").await.unwrap(), "techno, dark");
+        assert_eq!(server.hits(), 1);
+        // Cache hits are free
+        assert_eq!(client.tokens_used(), estimate_tokens("same prompt") + estimate_tokens("techno, dark"));
+
+        client.clear_cache().unwrap();
+        client.generate("same prompt").await.unwrap();
+        assert_eq!(server.hits(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_no_retry_on_permanent_error() {
+        let server = MockServer::start(vec![
+            MockResponse::new(401, "unauthorized"),
+            MockResponse::new(200, gemini_response("techno")),
+        ]).await;
+
+        let client = APIClient::new(mock_config(&server.url)).unwrap();
+        let error = client.generate("test").await.unwrap_err();
+        assert!(matches!(error, AiError::ProviderError { status: 401, .. }));
+        assert_eq!(server.hits(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_missing_api_key_error() {
+        let mut config = APIConfig::default();
+        config.provider = APIProvider::Groq;
+        config.api_key = None;
+        let client = APIClient::new(config).unwrap();
+        match client.generate("test").await {
+            Err(AiError::MissingApiKey(provider)) => assert_eq!(provider, APIProvider::Groq),
+            r => panic!("Expected missing API key, got {:?}", r),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_error() {
+        let server = MockServer::start(vec![MockResponse::new(429, "slow down").header("Retry-After", "0")]).await;
+        let mut config = mock_config(&server.url);
+        config.retry.max_retries = 1;
+        let client = APIClient::new(config).unwrap();
+        match client.generate("test").await {
+            Err(AiError::RateLimited { retry_after }) => assert_eq!(retry_after, Some(Duration::ZERO)),
+            r => panic!("Expected rate limit, got {:?}", r),
+        }
+        assert_eq!(server.hits(), 2);
+
+        // Retry-After longer than the maximum delay, not retried
+        let server = MockServer::start(vec![MockResponse::new(429, "come back later").header("Retry-After", "3600")]).await;
+        let client = APIClient::new(mock_config(&server.url)).unwrap();
+        match client.generate("test").await {
+            Err(AiError::RateLimited { retry_after }) => assert_eq!(retry_after, Some(Duration::from_secs(3600))),
+            r => panic!("Expected rate limit, got {:?}", r),
+        }
+        assert_eq!(server.hits(), 1);
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after(" 120 "), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), Some(Duration::ZERO));
+        let later = (chrono::Utc::now() + chrono::Duration::seconds(60)).to_rfc2822();
+        let delay = parse_retry_after(&later).unwrap();
+        assert!(delay > Duration::from_secs(55) && delay <= Duration::from_secs(60), "{:?}", delay);
+        assert_eq!(parse_retry_after("soon"), None);
+    }
 }
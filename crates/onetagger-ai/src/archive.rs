@@ -0,0 +1,121 @@
+//! Archive Module
+//!
+//! Extracts audio stored inside zip archives (e.g. promo packs) for analysis
+
+use anyhow::Result;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// Audio entry extracted from an archive into a temporary file, removed on drop
+pub struct ArchiveEntry {
+    pub archive: PathBuf,
+    pub entry: String,
+    /// Path of the extracted temporary file
+    pub path: PathBuf,
+    _temp_dir: TempDir,
+}
+
+/// Is the path a zip archive (by extension)
+pub fn is_archive(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.eq_ignore_ascii_case("zip"))
+        .unwrap_or(false)
+}
+
+/// Is the archive entry a supported audio file
+pub fn is_audio_entry(entry: &str) -> bool {
+    Path::new(entry).extension()
+        .and_then(|e| e.to_str())
+        .map(|e| onetagger_tag::EXTENSIONS.contains(&e.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Split a path like `promos/pack.zip/folder/track.mp3` into archive path and entry name
+pub fn split_archive_path(path: &Path) -> Option<(PathBuf, String)> {
+    for archive in path.ancestors().skip(1) {
+        if is_archive(archive) && archive.is_file() {
+            let entry = path.strip_prefix(archive).ok()?
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+                .join("/");
+            return Some((archive.to_owned(), entry));
+        }
+    }
+    None
+}
+
+/// List all supported audio entries in the archive (including nested directories)
+pub fn list_audio_entries(archive: &Path) -> Result<Vec<String>> {
+    let zip = zip::ZipArchive::new(File::open(archive)?)?;
+    Ok(zip.file_names()
+        .filter(|name| !name.ends_with('/') && is_audio_entry(name))
+        .map(String::from)
+        .collect())
+}
+
+/// Extract an audio entry into a temporary file
+pub fn extract_entry(archive: &Path, entry: &str) -> Result<ArchiveEntry> {
+    let entry = entry.replace('\\', "/");
+    if !is_audio_entry(&entry) {
+        return Err(anyhow!("Unsupported archive entry: {}", entry));
+    }
+
+    let mut zip = zip::ZipArchive::new(File::open(archive)?)?;
+    let mut file = zip.by_name(&entry)
+        .map_err(|e| anyhow!("Entry {} not found in {}: {}", entry, archive.display(), e))?;
+
+    // Keep the original filename so the format can be detected by extension
+    let filename = Path::new(&entry).file_name().ok_or(anyhow!("Invalid archive entry: {}", entry))?;
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join(filename);
+    std::io::copy(&mut file, &mut File::create(&path)?)?;
+    debug!("Extracted {} from {} to {}", entry, archive.display(), path.display());
+
+    Ok(ArchiveEntry {
+        archive: archive.to_owned(),
+        entry,
+        path,
+        _temp_dir: temp_dir,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_split_archive_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("pack.zip");
+        File::create(&archive).unwrap();
+
+        let (a, entry) = split_archive_path(&archive.join("promos").join("track.mp3")).unwrap();
+        assert_eq!(a, archive);
+        assert_eq!(entry, "promos/track.mp3");
+        assert!(split_archive_path(&dir.path().join("track.mp3")).is_none());
+    }
+
+    #[test]
+    fn test_extract_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("pack.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&archive).unwrap());
+        zip.start_file("promos/nested/track.wav", zip::write::SimpleFileOptions::default()).unwrap();
+        zip.write_all(b"RIFF").unwrap();
+        zip.start_file("promos/cover.jpg", zip::write::SimpleFileOptions::default()).unwrap();
+        zip.finish().unwrap();
+
+        assert_eq!(list_audio_entries(&archive).unwrap(), vec!["promos/nested/track.wav".to_string()]);
+        assert!(extract_entry(&archive, "promos/cover.jpg").is_err());
+
+        let extracted = extract_entry(&archive, "promos/nested/track.wav").unwrap();
+        let path = extracted.path.clone();
+        assert_eq!(std::fs::read(&path).unwrap(), b"RIFF");
+        drop(extracted);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,246 @@
+//! Batch Module
+//!
+//! Analyzes multiple tracks sharing one API client and a per-run LLM token budget
+
+use anyhow::Result;
+use serde::{Serialize, Deserialize};
+use std::path::{Path, PathBuf};
+use crate::api::{APIClient, TokenCounter};
+use crate::config::AIConfig;
+use crate::duplicates::{DuplicateDetector, FINGERPRINT_CACHE_KIND};
+use crate::embeddings::EmbeddingGenerator;
+use crate::features::{FeatureExtractor, UnsupportedAudio};
+use crate::fingerprint::Fingerprint;
+use crate::AIAnalysisResult;
+
+/// Summary of a batch run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchSummary {
+    /// Number of analyzed tracks
+    pub analyzed: usize,
+    /// Tracks which skipped the LLM because the token budget was exhausted
+    pub llm_skipped: usize,
+    /// Tokens used by live LLM calls
+    pub tokens_used: u64,
+    /// Tracks skipped because of DRM / unsupported codec
+    pub unsupported: usize,
+    /// Non-fatal problems (e.g. skipped files)
+    pub warnings: Vec<String>,
+}
+
+/// Work a batch would do, see `analyze_batch_estimate`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanEstimate {
+    pub files: usize,
+    /// Files with cached audio features, the rest needs decoding
+    pub cached_features: usize,
+    /// All expected LLM calls
+    pub llm_calls: usize,
+    /// LLM calls answered from the response cache
+    pub cached_calls: usize,
+    /// LLM calls hitting the provider
+    pub live_calls: usize,
+    /// Time the live calls take at the configured rate limit (seconds)
+    pub eta_secs: u64,
+}
+
+/// Analyzes tracks one by one, tracking LLM token usage across the run
+pub struct BatchAnalyzer {
+    config: AIConfig,
+    client: Option<APIClient>,
+    tokens: TokenCounter,
+    summary: BatchSummary,
+}
+
+impl BatchAnalyzer {
+    pub fn new(config: AIConfig) -> Result<Self> {
+        let tokens = TokenCounter::new();
+        let client = crate::llm_client(&config)?.map(|c| c.with_token_counter(tokens.clone()));
+        Ok(Self { config, client, tokens, summary: BatchSummary::default() })
+    }
+
+    /// Has the run used up `max_tokens_per_run`
+    pub fn budget_exhausted(&self) -> bool {
+        self.config.max_tokens_per_run.map(|max| self.tokens.total() >= max).unwrap_or(false)
+    }
+
+    /// Analyze next track, uses rule-based tags only once the budget is exhausted
+    pub async fn analyze(&mut self, path: &Path) -> Result<AIAnalysisResult> {
+        let exhausted = self.budget_exhausted();
+        let client = match &self.client {
+            Some(_) if exhausted => {
+                if self.summary.llm_skipped == 0 {
+                    warn!("LLM token budget of {} tokens per run exhausted, remaining tracks will use rule-based tags only",
+                        self.config.max_tokens_per_run.unwrap_or_default());
+                }
+                self.summary.llm_skipped += 1;
+                None
+            },
+            client => client.as_ref(),
+        };
+
+        let result = crate::analyze_path(path, &self.config, client, None, None).await;
+        self.summary.tokens_used = self.tokens.total();
+        match &result {
+            Err(e) if e.is::<UnsupportedAudio>() => {
+                warn!("Skipping: {}", e);
+                self.summary.unsupported += 1;
+                self.summary.warnings.push(format!("Skipped: {}", e));
+            },
+            _ => self.summary.analyzed += 1,
+        }
+        result
+    }
+
+    /// Current run statistics
+    pub fn summary(&self) -> &BatchSummary {
+        &self.summary
+    }
+
+    /// Finish the run and log summary
+    pub fn finish(self) -> BatchSummary {
+        info!("Batch analysis finished: {} tracks, {} LLM tokens used", self.summary.analyzed, self.summary.tokens_used);
+        if self.summary.unsupported > 0 {
+            warn!("{} tracks skipped due to DRM / unsupported codec", self.summary.unsupported);
+        }
+        if self.summary.llm_skipped > 0 {
+            warn!("{} of {} tracks skipped the LLM due to the token budget", self.summary.llm_skipped, self.summary.analyzed);
+        }
+        self.summary
+    }
+}
+
+/// Cache kind of audio embeddings
+pub(crate) const EMBEDDING_CACHE_KIND: &str = "audio-embedding";
+
+/// Statistics of a prewarm run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrewarmStats {
+    pub files: usize,
+    /// Files which were already fully cached
+    pub cached: usize,
+    /// Files with newly computed features / embeddings
+    pub computed: usize,
+    pub failed: usize,
+}
+
+/// Precompute and cache features, embeddings and fingerprints for files without calling the LLM or writing tags
+pub fn prewarm(paths: &[PathBuf], config: &AIConfig) -> PrewarmStats {
+    let mut stats = PrewarmStats { files: paths.len(), ..Default::default() };
+    let cache = match crate::feature_cache(config) {
+        Some(cache) => cache,
+        None => {
+            warn!("No cache directory configured, nothing to prewarm");
+            stats.failed = paths.len();
+            return stats;
+        }
+    };
+    let extractor = FeatureExtractor::from_config(config);
+    let generator = EmbeddingGenerator::new();
+    let detector = DuplicateDetector::new(config.duplicate_threshold).with_cache(Some(cache.clone()));
+    let kind = extractor.cache_kind();
+
+    for path in paths {
+        if cache.get::<crate::AudioFeatures>(path, &kind).is_some()
+            && cache.get::<Vec<f32>>(path, EMBEDDING_CACHE_KIND).is_some()
+            && cache.get::<Fingerprint>(path, FINGERPRINT_CACHE_KIND).is_some() {
+            stats.cached += 1;
+            continue;
+        }
+        let result = extractor.extract_cached(path, Some(&cache))
+            .and_then(|features| generator.generate_audio_embedding(&features))
+            .and_then(|embedding| cache.put(path, EMBEDDING_CACHE_KIND, &embedding))
+            .and_then(|_| detector.fingerprint(path).map(|_| ()));
+        match result {
+            Ok(_) => stats.computed += 1,
+            Err(e) => {
+                warn!("Prewarm failed for {}: {}", path.display(), e);
+                stats.failed += 1;
+            }
+        }
+    }
+    info!("Prewarm finished: {} computed, {} already cached, {} failed", stats.computed, stats.cached, stats.failed);
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{gemini_response, llm_config, write_sine_wav, MockResponse, MockServer};
+
+    #[tokio::test]
+    async fn test_token_budget_skips_llm() {
+        let server = MockServer::start(vec![MockResponse::new(200, gemini_response("hypnotic, warehouse"))]).await;
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut config = llm_config(&server);
+        config.max_tokens_per_run = Some(1);
+
+        let mut analyzer = BatchAnalyzer::new(config).unwrap();
+        let mut results = vec![];
+        for i in 0..3 {
+            let path = dir.path().join(format!("{}.wav", i));
+            write_sine_wav(&path, 440.0, 0.5);
+            results.push(analyzer.analyze(&path).await.unwrap());
+        }
+        let summary = analyzer.finish();
+
+        assert_eq!(server.hits(), 1);
+        assert_eq!(results[0].llm_suggestions, vec!["hypnotic", "warehouse"]);
+        assert!(results[1].llm_suggestions.is_empty());
+        assert!(results[2].llm_suggestions.is_empty());
+        assert_eq!(summary.analyzed, 3);
+        assert_eq!(summary.llm_skipped, 2);
+        assert!(summary.tokens_used >= 1);
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_reported_as_warning() {
+        let dir = tempfile::tempdir().unwrap();
+        let good = dir.path().join("good.wav");
+        write_sine_wav(&good, 440.0, 0.5);
+        let drm = dir.path().join("purchase.m4p");
+        std::fs::write(&drm, b"\x00\x00\x00\x20ftypM4P ").unwrap();
+
+        let mut config = AIConfig::default();
+        config.cache_dir = None;
+        config.api_config.api_key = None;
+        let mut analyzer = BatchAnalyzer::new(config).unwrap();
+        assert!(analyzer.analyze(&good).await.is_ok());
+        assert!(analyzer.analyze(&drm).await.is_err());
+        let summary = analyzer.finish();
+
+        assert_eq!(summary.analyzed, 1);
+        assert_eq!(summary.unsupported, 1);
+        assert!(summary.warnings[0].contains("FairPlay"));
+    }
+
+    #[test]
+    fn test_prewarm() {
+        let dir = tempfile::tempdir().unwrap();
+        let track = dir.path().join("track.wav");
+        write_sine_wav(&track, 440.0, 1.0);
+        let original = std::fs::read(&track).unwrap();
+
+        let mut config = AIConfig::default();
+        config.cache_dir = Some(dir.path().join("cache"));
+        let paths = vec![track.clone(), dir.path().join("missing.wav")];
+
+        let stats = prewarm(&paths, &config);
+        assert_eq!((stats.computed, stats.cached, stats.failed), (1, 0, 1));
+        let cache = crate::feature_cache(&config).unwrap();
+        let kind = FeatureExtractor::from_config(&config).cache_kind();
+        assert!(cache.get::<crate::AudioFeatures>(&track, &kind).is_some());
+        assert!(cache.get::<Vec<f32>>(&track, EMBEDDING_CACHE_KIND).is_some());
+        assert!(cache.get::<Fingerprint>(&track, FINGERPRINT_CACHE_KIND).is_some());
+
+        // No tags written
+        assert_eq!(std::fs::read(&track).unwrap(), original);
+
+        let stats = prewarm(&paths, &config);
+        assert_eq!((stats.computed, stats.cached), (0, 1));
+    }
+}
@@ -0,0 +1,285 @@
+//! Response Cache Module
+//!
+//! Cache for API responses and extracted features, so re-analyzing a library doesn't repeat work.
+//! Storage is pluggable (`Cache`), files are used by default
+
+use anyhow::Result;
+use parking_lot::Mutex;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Key-value storage backend of the caches
+pub trait Cache: Debug + Send + Sync {
+    /// Get value if stored and not expired
+    fn get(&self, key: &str) -> Option<Vec<u8>>;
+    /// Store value, expiring after `ttl`
+    fn put(&self, key: &str, data: &[u8], ttl: Duration) -> Result<()>;
+    /// Remove all values
+    fn clear(&self) -> Result<()>;
+}
+
+/// Cache storing one file per key, prefixed with the expiry UNIX timestamp
+#[derive(Debug, Clone)]
+pub struct FileCache {
+    dir: PathBuf,
+}
+
+impl FileCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.cache", key))
+    }
+}
+
+impl Cache for FileCache {
+    fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let mut data = std::fs::read(self.path(key)).ok()?;
+        let expires = u64::from_le_bytes(data.get(..8)?.try_into().ok()?);
+        if now() >= expires {
+            return None;
+        }
+        Some(data.split_off(8))
+    }
+
+    fn put(&self, key: &str, data: &[u8], ttl: Duration) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let mut file = now().saturating_add(ttl.as_secs()).to_le_bytes().to_vec();
+        file.extend_from_slice(data);
+        std::fs::write(self.path(key), file)?;
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<()> {
+        if self.dir.exists() {
+            std::fs::remove_dir_all(&self.dir)?;
+        }
+        Ok(())
+    }
+}
+
+/// Cached value and expiry (`None` = never)
+type MemoryEntry = (Vec<u8>, Option<Instant>);
+
+/// Cache kept in memory for the lifetime of the process
+#[derive(Debug, Default)]
+pub struct InMemoryCache {
+    entries: Mutex<HashMap<String, MemoryEntry>>,
+}
+
+impl InMemoryCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Cache for InMemoryCache {
+    fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let mut entries = self.entries.lock();
+        match entries.get(key)? {
+            (_, Some(expires)) if Instant::now() >= *expires => {
+                entries.remove(key);
+                None
+            },
+            (data, _) => Some(data.clone()),
+        }
+    }
+
+    fn put(&self, key: &str, data: &[u8], ttl: Duration) -> Result<()> {
+        self.entries.lock().insert(key.to_string(), (data.to_vec(), Instant::now().checked_add(ttl)));
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<()> {
+        self.entries.lock().clear();
+        Ok(())
+    }
+}
+
+/// Cache of API responses
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    backend: Arc<dyn Cache>,
+    ttl: Duration,
+}
+
+/// Single cached response
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    /// UNIX timestamp in seconds
+    created: u64,
+    response: String,
+}
+
+impl ResponseCache {
+    /// Cache storing files in `dir`
+    pub fn new(dir: impl Into<PathBuf>, ttl: Duration) -> Self {
+        Self::with_backend(Box::new(FileCache::new(dir)), ttl)
+    }
+
+    /// Cache using custom storage
+    pub fn with_backend(backend: Box<dyn Cache>, ttl: Duration) -> Self {
+        Self { backend: backend.into(), ttl }
+    }
+
+    /// Generate cache key from request parameters
+    pub fn key(parts: &[&str]) -> String {
+        let mut hasher = Sha256::new();
+        for part in parts {
+            hasher.update(part.as_bytes());
+            // Separator so ("ab", "c") != ("a", "bc")
+            hasher.update([0u8]);
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Get response if cached and younger than TTL
+    pub fn get(&self, key: &str) -> Option<String> {
+        let data = self.backend.get(key)?;
+        let entry: CacheEntry = serde_json::from_slice(&data).ok()?;
+        let age = now().saturating_sub(entry.created);
+        match age < self.ttl.as_secs() {
+            true => Some(entry.response),
+            false => None,
+        }
+    }
+
+    /// Store response
+    pub fn put(&self, key: &str, response: &str) -> Result<()> {
+        let entry = CacheEntry { created: now(), response: response.to_string() };
+        self.backend.put(key, &serde_json::to_vec(&entry)?, self.ttl)
+    }
+
+    /// Remove all cached responses
+    pub fn clear(&self) -> Result<()> {
+        self.backend.clear()
+    }
+}
+
+/// Cache of per-file analysis data (features, embeddings), invalidated when the file changes
+#[derive(Debug, Clone)]
+pub struct FeatureCache {
+    cache: ResponseCache,
+}
+
+impl FeatureCache {
+    /// Cache storing files in `dir`
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self::with_backend(Box::new(FileCache::new(dir)))
+    }
+
+    /// Cache using custom storage
+    pub fn with_backend(backend: Box<dyn Cache>) -> Self {
+        // Entries don't expire, the key changes with the file
+        Self { cache: ResponseCache::with_backend(backend, Duration::MAX) }
+    }
+
+    /// Key from path, size, modification time and kind of data (including settings it depends on)
+    fn file_key(path: &Path, kind: &str) -> Option<String> {
+        let metadata = std::fs::metadata(path).ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+        Some(ResponseCache::key(&[&path.to_string_lossy(), &metadata.len().to_string(), &modified.to_string(), kind]))
+    }
+
+    /// Get cached value for file
+    pub fn get<T: DeserializeOwned>(&self, path: &Path, kind: &str) -> Option<T> {
+        let data = self.cache.get(&Self::file_key(path, kind)?)?;
+        serde_json::from_str(&data).ok()
+    }
+
+    /// Store value for file
+    pub fn put<T: Serialize>(&self, path: &Path, kind: &str, value: &T) -> Result<()> {
+        let key = Self::file_key(path, kind).ok_or(anyhow!("Can't read metadata of {}", path.display()))?;
+        self.cache.put(&key, &serde_json::to_string(value)?)
+    }
+
+    /// Remove all cached data
+    pub fn clear(&self) -> Result<()> {
+        self.cache.clear()
+    }
+}
+
+/// Current UNIX timestamp in seconds
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_ttl() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = ResponseCache::key(&["gemini", "default", "prompt"]);
+        assert_ne!(key, ResponseCache::key(&["gemini", "default", "other prompt"]));
+
+        let cache = ResponseCache::new(dir.path(), Duration::from_secs(60));
+        assert!(cache.get(&key).is_none());
+        cache.put(&key, "techno").unwrap();
+        assert_eq!(cache.get(&key).unwrap(), "techno");
+
+        // Expired
+        let expired = ResponseCache::new(dir.path(), Duration::ZERO);
+        assert!(expired.get(&key).is_none());
+
+        cache.clear().unwrap();
+        assert!(cache.get(&key).is_none());
+    }
+
+    #[test]
+    fn test_cache_backends() {
+        let dir = tempfile::tempdir().unwrap();
+        let backends: Vec<Box<dyn Cache>> = vec![Box::new(FileCache::new(dir.path())), Box::new(InMemoryCache::new())];
+        for cache in backends {
+            assert!(cache.get("key").is_none());
+            cache.put("key", b"data", Duration::from_secs(60)).unwrap();
+            cache.put("forever", b"kept", Duration::MAX).unwrap();
+            assert_eq!(cache.get("key").unwrap(), b"data");
+            assert_eq!(cache.get("forever").unwrap(), b"kept");
+            cache.put("expired", b"old", Duration::ZERO).unwrap();
+            assert!(cache.get("expired").is_none());
+            cache.clear().unwrap();
+            assert!(cache.get("key").is_none());
+        }
+
+        // Response cache on top of memory
+        let cache = ResponseCache::with_backend(Box::new(InMemoryCache::new()), Duration::from_secs(60));
+        cache.put("prompt", "techno").unwrap();
+        assert_eq!(cache.get("prompt").unwrap(), "techno");
+    }
+
+    #[test]
+    fn test_in_memory_expiry() {
+        let cache = InMemoryCache::new();
+        cache.put("key", b"data", Duration::from_millis(20)).unwrap();
+        assert_eq!(cache.get("key").unwrap(), b"data");
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(cache.get("key").is_none());
+        assert!(cache.entries.lock().is_empty());
+    }
+
+    #[test]
+    fn test_feature_cache_invalidation() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("track.wav");
+        std::fs::write(&file, b"audio").unwrap();
+
+        let cache = FeatureCache::new(dir.path().join("features"));
+        cache.put(&file, "bpm", &128.0f32).unwrap();
+        assert_eq!(cache.get::<f32>(&file, "bpm"), Some(128.0));
+        assert!(cache.get::<f32>(&file, "key").is_none());
+
+        // File changed
+        std::fs::write(&file, b"different audio").unwrap();
+        assert!(cache.get::<f32>(&file, "bpm").is_none());
+    }
+}
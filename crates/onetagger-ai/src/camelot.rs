@@ -0,0 +1,184 @@
+//! Camelot Module
+//!
+//! Camelot wheel notation of musical keys and harmonic mixing compatibility
+
+use std::fmt;
+
+/// Standard key names by Camelot number (1-12), minor keys
+const MINOR_KEYS: [&str; 12] = ["Abm", "Ebm", "Bbm", "Fm", "Cm", "Gm", "Dm", "Am", "Em", "Bm", "F#m", "C#m"];
+/// Standard key names by Camelot number (1-12), major keys
+const MAJOR_KEYS: [&str; 12] = ["B", "F#", "Db", "Ab", "Eb", "Bb", "F", "C", "G", "D", "A", "E"];
+
+/// Position on the Camelot wheel (`8A` = A minor)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Camelot {
+    /// 1-12
+    pub number: u8,
+    /// `A` = minor, `B` = major
+    pub minor: bool,
+}
+
+impl Camelot {
+    /// Valid for numbers 1-12
+    pub fn new(number: u8, minor: bool) -> Option<Self> {
+        (1..=12).contains(&number).then_some(Self { number, minor })
+    }
+
+    /// Steps around the wheel (0-6), switching between minor and major counts as one more step
+    pub fn distance(&self, other: &Camelot) -> u8 {
+        let distance = (self.number as i8 - other.number as i8).rem_euclid(12) as u8;
+        distance.min(12 - distance) + (self.minor != other.minor) as u8
+    }
+}
+
+impl fmt::Display for Camelot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.number, if self.minor { 'A' } else { 'B' })
+    }
+}
+
+/// Parse key in any common notation (`Am`, `A minor`, `F#`, `Gb major`, `8A`)
+pub fn to_camelot(key: &str) -> Option<Camelot> {
+    let key = key.trim();
+
+    // Camelot
+    if let Some(letter) = key.chars().last().filter(|c| matches!(c.to_ascii_uppercase(), 'A' | 'B')) {
+        if let Ok(number) = key[..key.len() - 1].parse::<u8>() {
+            return Camelot::new(number, letter.eq_ignore_ascii_case(&'A'));
+        }
+    }
+
+    let mut chars = key.chars();
+    let mut pitch: i32 = match chars.next()?.to_ascii_uppercase() {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+    let mut rest = chars.as_str();
+    match rest.chars().next() {
+        Some(c @ ('#' | '♯')) => { pitch += 1; rest = &rest[c.len_utf8()..]; },
+        Some(c @ ('b' | '♭')) => { pitch -= 1; rest = &rest[c.len_utf8()..]; },
+        _ => {},
+    }
+    let minor = match rest.trim() {
+        "" | "M" => false,
+        r => match r.to_lowercase().as_str() {
+            "maj" | "major" => false,
+            "m" | "min" | "minor" => true,
+            _ => return None,
+        }
+    };
+
+    // Relative major shares the number (A minor = C major)
+    let major_pitch = if minor { pitch + 3 } else { pitch };
+    // Each step clockwise is a fifth up, C major = 8B
+    let number = ((major_pitch * 7).rem_euclid(12) + 7) % 12 + 1;
+    Camelot::new(number as u8, minor)
+}
+
+/// Standard key name (`Am`, `F#`) of Camelot position
+pub fn from_camelot(camelot: Camelot) -> &'static str {
+    let index = (camelot.number as usize).clamp(1, 12) - 1;
+    match camelot.minor {
+        true => MINOR_KEYS[index],
+        false => MAJOR_KEYS[index],
+    }
+}
+
+/// `Camelot::distance` of keys in any notation, None if either can't be parsed
+pub fn key_distance(a: &str, b: &str) -> Option<u8> {
+    Some(to_camelot(a)?.distance(&to_camelot(b)?))
+}
+
+/// Whether keys mix harmonically: same key, adjacent number, relative major / minor or energy boost (±2)
+pub fn compatible(a: Camelot, b: Camelot) -> bool {
+    match a.minor == b.minor {
+        true => a.distance(&b) <= 2,
+        false => a.number == b.number,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camelot(key: &str) -> Camelot {
+        to_camelot(key).unwrap_or_else(|| panic!("{} not parsed", key))
+    }
+
+    #[test]
+    fn test_notations() {
+        assert_eq!(camelot("Am").to_string(), "8A");
+        assert_eq!(camelot("A minor").to_string(), "8A");
+        assert_eq!(camelot("a min").to_string(), "8A");
+        assert_eq!(camelot("8A").to_string(), "8A");
+        assert_eq!(camelot("8a").to_string(), "8A");
+        assert_eq!(camelot("C").to_string(), "8B");
+        assert_eq!(camelot("C major").to_string(), "8B");
+        assert_eq!(camelot("F#").to_string(), "2B");
+        assert_eq!(camelot("Bbm").to_string(), "3A");
+        assert_eq!(camelot("E♭").to_string(), "5B");
+        assert_eq!(camelot("12B").to_string(), "12B");
+        assert!(to_camelot("13A").is_none());
+        assert!(to_camelot("0B").is_none());
+        assert!(to_camelot("H").is_none());
+        assert!(to_camelot("A dorian").is_none());
+        assert!(to_camelot("").is_none());
+    }
+
+    #[test]
+    fn test_distance() {
+        assert_eq!(key_distance("8A", "8A"), Some(0));
+        assert_eq!(key_distance("12A", "1A"), Some(1));
+        assert_eq!(key_distance("Am", "C"), Some(1));
+        assert_eq!(key_distance("8A", "10A"), Some(2));
+        assert_eq!(key_distance("8A", "2B"), Some(7));
+        assert_eq!(key_distance("8A", "unknown"), None);
+    }
+
+    #[test]
+    fn test_enharmonic_equivalents() {
+        for (a, b) in [("F#", "Gb"), ("C#m", "Dbm"), ("A#m", "Bbm"), ("G#", "Ab"), ("D#m", "Ebm"), ("Cb", "B"), ("E#", "F"), ("B#m", "Cm"), ("Fb", "E")] {
+            assert_eq!(camelot(a), camelot(b), "{} != {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for number in 1..=12 {
+            for minor in [true, false] {
+                let position = Camelot::new(number, minor).unwrap();
+                assert_eq!(camelot(from_camelot(position)), position);
+                assert_eq!(camelot(&position.to_string()), position);
+            }
+        }
+        assert_eq!(from_camelot(camelot("8A")), "Am");
+        assert_eq!(from_camelot(camelot("Gb")), "F#");
+    }
+
+    #[test]
+    fn test_compatible() {
+        // Same key
+        assert!(compatible(camelot("8A"), camelot("Am")));
+        // Adjacent number, wrapping around
+        assert!(compatible(camelot("8A"), camelot("9A")));
+        assert!(compatible(camelot("8A"), camelot("7A")));
+        assert!(compatible(camelot("12B"), camelot("1B")));
+        // Relative major / minor
+        assert!(compatible(camelot("Am"), camelot("C")));
+        assert!(compatible(camelot("8B"), camelot("8A")));
+        // Energy boost
+        assert!(compatible(camelot("8A"), camelot("10A")));
+        assert!(compatible(camelot("1A"), camelot("11A")));
+
+        assert!(!compatible(camelot("8A"), camelot("11A")));
+        assert!(!compatible(camelot("8A"), camelot("9B")));
+        assert!(!compatible(camelot("8A"), camelot("7B")));
+        assert!(!compatible(camelot("C"), camelot("F#")));
+    }
+}
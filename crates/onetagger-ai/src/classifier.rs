@@ -4,64 +4,523 @@
 //! - Genre/style detection
 //! - Mood detection
 //! - Energy analysis
+//! - Vocal / instrumental detection
 
 use anyhow::{Error, Result};
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use parking_lot::Mutex;
+use crate::api::APIClient;
 use crate::config::AIConfig;
-use crate::features::AudioFeatures;
+use crate::features::{AudioFeatures, RMS_FRAME_SECS};
 use crate::TagWithConfidence;
 
+#[cfg(feature = "onnx")]
+use tract_onnx::prelude::*;
+
 /// Genre/Style classifier
 pub struct GenreClassifier {
     confidence_threshold: f32,
+    rules: GenreRules,
+    model: Option<GenreModel>,
+    /// User's genre taxonomy, LLM results are clamped to it (any genre if empty)
+    taxonomy: Vec<String>,
 }
 
 impl GenreClassifier {
     pub fn new(config: &AIConfig) -> Result<Self> {
-        Ok(Self {
-            confidence_threshold: config.confidence_threshold,
-        })
+        let rules = match &config.genre_rules {
+            Some(path) => GenreRules::load(path).unwrap_or_else(|e| {
+                warn!("Failed loading genre rules from {}: {}. Using built-in rules.", path.display(), e);
+                GenreRules::default()
+            }),
+            None => GenreRules::default(),
+        };
+        let classifier = Self {
+            confidence_threshold: config.genre_threshold(),
+            rules,
+            model: None,
+            taxonomy: config.custom_tags.genres.clone(),
+        };
+        match &config.genre_model {
+            Some(model) => match classifier.with_model(&model.model, &model.labels) {
+                Ok(classifier) => Ok(classifier),
+                Err(e) => {
+                    warn!("Failed loading genre model: {}. Using rule-based classification.", e);
+                    Self::new(&AIConfig { genre_model: None, ..config.clone() })
+                }
+            },
+            None => Ok(classifier),
+        }
+    }
+
+    /// Use custom rules instead of the built-in ones
+    pub fn with_rules(mut self, rules: GenreRules) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// Use ONNX model (with labels file, one label per output) instead of rules
+    pub fn with_model(mut self, model: &Path, labels: &Path) -> Result<Self> {
+        self.model = Some(GenreModel::load(model, labels)?);
+        Ok(self)
     }
 
     /// Classify genre from audio features
     pub fn classify(&self, features: &AudioFeatures) -> Result<Vec<TagWithConfidence>> {
-        let mut genres = Vec::new();
+        let genres = match &self.model {
+            Some(model) => model.predict(features).unwrap_or_else(|e| {
+                warn!("Genre model inference failed: {}. Using rule-based classification.", e);
+                self.classify_rules(features)
+            }),
+            None => self.classify_rules(features),
+        };
+
+        // Filter by confidence
+        Ok(genres.into_iter()
+            .filter(|g| g.confidence >= self.confidence_threshold)
+            .collect())
+    }
 
-        // Rule-based classification based on BPM and features
-        // TODO: Replace with ONNX model inference
+    /// Ask the LLM for genres, falls back to `classify` if the request fails
+    pub async fn classify_with_llm(&self, client: &APIClient, features: &AudioFeatures) -> Result<Vec<TagWithConfidence>> {
+        let response = match client.generate(&self.llm_prompt(features)).await {
+            Ok(response) => response,
+            Err(e) => {
+                warn!("LLM genre classification failed: {}. Using rule-based classification.", e);
+                return self.classify(features);
+            }
+        };
+        match self.parse_llm_genres(&response) {
+            Ok(genres) => Ok(genres.into_iter().filter(|g| g.confidence >= self.confidence_threshold).collect()),
+            Err(e) => {
+                warn!("Invalid LLM genre response: {}. Using rule-based classification.", e);
+                self.classify(features)
+            }
+        }
+    }
 
+    /// Prompt asking for genres in JSON
+    fn llm_prompt(&self, features: &AudioFeatures) -> String {
+        let mut prompt = String::from("You are a DJ assistant classifying the genre of an electronic music track from its audio characteristics.\n\nAudio Features:\n");
         if let Some(bpm) = features.bpm {
-            // Techno range
-            if (120.0..=135.0).contains(&bpm) && features.spectral_centroid > 1200.0 {
-                genres.push(TagWithConfidence::new("techno", 0.85));
-
-                // Sub-genres based on features
-                if features.rms_energy > 0.75 {
-                    genres.push(TagWithConfidence::new("peak-time-techno", 0.80));
-                } else if features.rms_energy < 0.5 {
-                    genres.push(TagWithConfidence::new("minimal-techno", 0.75));
-                }
+            prompt.push_str(&format!("- BPM: {:.1}\n", bpm));
+        }
+        if let Some(key) = &features.key {
+            prompt.push_str(&format!("- Key: {}\n", key));
+        }
+        prompt.push_str(&format!("- Spectral centroid: {:.0} Hz\n- RMS energy: {:.2}\n- Onset strength: {:.2}\n",
+            features.spectral_centroid, features.rms_energy, features.onset_strength));
+
+        if !self.taxonomy.is_empty() {
+            prompt.push_str(&format!("\nOnly use genres from this list: {}\n", self.taxonomy.join(", ")));
+        }
+        prompt.push_str("\nAnswer only with a JSON array of up to 3 genres, e.g. [{\"genre\": \"techno\", \"confidence\": 0.9}]\n");
+        prompt
+    }
+
+    /// Parse JSON genre list, clamped to the taxonomy
+    fn parse_llm_genres(&self, response: &str) -> Result<Vec<TagWithConfidence>> {
+        #[derive(Deserialize)]
+        struct LlmGenre {
+            genre: String,
+            confidence: f32,
+        }
+
+        // Strip markdown fences / surrounding text
+        let start = response.find('[').ok_or(anyhow!("No JSON array in response"))?;
+        let end = response.rfind(']').ok_or(anyhow!("No JSON array in response"))?;
+        let genres: Vec<LlmGenre> = serde_json::from_str(response.get(start..=end).unwrap_or_default())?;
+
+        Ok(genres.into_iter().filter_map(|g| {
+            let genre = match self.taxonomy.is_empty() {
+                true => g.genre.trim().to_lowercase(),
+                // Use taxonomy spelling
+                false => self.taxonomy.iter().find(|t| t.eq_ignore_ascii_case(g.genre.trim()))?.to_string(),
+            };
+            Some(TagWithConfidence::new(genre, g.confidence).with_source(crate::DetectionSource::Llm))
+        }).collect())
+    }
+
+    /// Rule-based classification based on BPM and features
+    fn classify_rules(&self, features: &AudioFeatures) -> Vec<TagWithConfidence> {
+        let mut genres = Vec::new();
+        for rule in &self.rules.rules {
+            rule.evaluate(features, &mut genres);
+        }
+        genres
+    }
+}
+
+/// Data-driven genre rules
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenreRules {
+    pub rules: Vec<GenreRule>,
+}
+
+impl GenreRules {
+    /// Load rules from JSON or TOML (by extension) file
+    pub fn load(path: &Path) -> Result<GenreRules> {
+        let data = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()).as_deref() {
+            Some("toml") => Ok(toml::from_str(&data)?),
+            _ => Ok(serde_json::from_str(&data)?),
+        }
+    }
+
+    /// Parent of every subgenre rule
+    pub fn parents(&self) -> HashMap<String, String> {
+        fn collect(rule: &GenreRule, parents: &mut HashMap<String, String>) {
+            for sub in &rule.subgenres {
+                parents.insert(sub.genre.clone(), rule.genre.clone());
+                collect(sub, parents);
             }
+        }
+        let mut parents = HashMap::new();
+        self.rules.iter().for_each(|rule| collect(rule, &mut parents));
+        parents
+    }
+}
 
-            // House range
-            if (118.0..=128.0).contains(&bpm) && features.zero_crossing_rate < 0.4 {
-                genres.push(TagWithConfidence::new("house", 0.82));
+impl Default for GenreRules {
+    fn default() -> Self {
+        use FeatureCondition as C;
+        Self {
+            rules: vec![
+                GenreRule::new("techno", 120.0, 135.0, 0.85, vec![C::above(Feature::SpectralCentroid, 1200.0)])
+                    .with_subgenre(GenreRule::new("peak-time-techno", 120.0, 135.0, 0.80, vec![C::above(Feature::RmsEnergy, 0.75)]))
+                    .with_subgenre(GenreRule::new("minimal-techno", 120.0, 135.0, 0.75, vec![C::below(Feature::RmsEnergy, 0.5)])),
+                GenreRule::new("house", 118.0, 128.0, 0.82, vec![C::below(Feature::ZeroCrossingRate, 0.4)])
+                    .with_subgenre(GenreRule::new("deep-house", 118.0, 128.0, 0.78, vec![C::below(Feature::SpectralCentroid, 1000.0)])),
+                GenreRule::new("progressive", 128.0, 140.0, 0.80, vec![C::above(Feature::OnsetStrength, 0.6)]),
+            ]
+        }
+    }
+}
+
+/// Sub-genre to parent genre map, so "peak-time-techno" is also found under "techno"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct GenreHierarchy {
+    pub enabled: bool,
+    /// Parent by genre (case-insensitive), subgenres of the built-in rules by default
+    pub parents: HashMap<String, String>,
+    /// Confidence of an implied parent relative to its child
+    pub parent_confidence: f32,
+}
 
-                if features.spectral_centroid < 1000.0 {
-                    genres.push(TagWithConfidence::new("deep-house", 0.78));
+impl GenreHierarchy {
+    /// Parent of genre
+    pub fn parent(&self, genre: &str) -> Option<&str> {
+        self.parents.iter().find(|(child, _)| child.eq_ignore_ascii_case(genre)).map(|(_, parent)| parent.as_str())
+    }
+
+    /// Add parents (and their parents) of detected genres which aren't detected already
+    pub fn expand(&self, genres: Vec<TagWithConfidence>) -> Vec<TagWithConfidence> {
+        if !self.enabled {
+            return genres;
+        }
+        let mut output = genres.clone();
+        for genre in &genres {
+            let mut confidence = genre.confidence;
+            let mut current = genre.tag.as_str();
+            // Depth limit guards against cycles in user config
+            for _ in 0..self.parents.len() {
+                let Some(parent) = self.parent(current) else { break };
+                confidence *= self.parent_confidence;
+                if !output.iter().any(|t| t.tag.eq_ignore_ascii_case(parent)) {
+                    output.push(TagWithConfidence { tag: parent.to_string(), confidence, source: genre.source, collection: None });
                 }
+                current = parent;
             }
+        }
+        output
+    }
+}
+
+impl Default for GenreHierarchy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            parents: GenreRules::default().parents(),
+            parent_confidence: 0.8,
+        }
+    }
+}
+
+/// Single genre rule, all conditions have to match
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenreRule {
+    pub genre: String,
+    /// Required BPM range (inclusive), rule is skipped when BPM is unknown
+    #[serde(default)]
+    pub bpm: Option<BpmRange>,
+    #[serde(default)]
+    pub conditions: Vec<FeatureCondition>,
+    /// Confidence of the detection (0.0-1.0)
+    pub confidence: f32,
+    /// Rules evaluated only if this one matched
+    #[serde(default)]
+    pub subgenres: Vec<GenreRule>,
+}
+
+impl GenreRule {
+    pub fn new(genre: &str, bpm_min: f32, bpm_max: f32, confidence: f32, conditions: Vec<FeatureCondition>) -> Self {
+        Self {
+            genre: genre.to_string(),
+            bpm: Some(BpmRange { min: bpm_min, max: bpm_max }),
+            conditions,
+            confidence,
+            subgenres: vec![],
+        }
+    }
+
+    pub fn with_subgenre(mut self, rule: GenreRule) -> Self {
+        self.subgenres.push(rule);
+        self
+    }
 
-            // Trance/Progressive
-            if (128.0..=140.0).contains(&bpm) && features.onset_strength > 0.6 {
-                genres.push(TagWithConfidence::new("progressive", 0.80));
+    /// Does the rule match the features
+    pub fn matches(&self, features: &AudioFeatures) -> bool {
+        if let Some(range) = &self.bpm {
+            match features.bpm {
+                Some(bpm) if (range.min..=range.max).contains(&bpm) => {},
+                _ => return false,
             }
         }
+        self.conditions.iter().all(|c| c.matches(features))
+    }
 
-        // Filter by confidence
-        Ok(genres.into_iter()
-            .filter(|g| g.confidence >= self.confidence_threshold)
+    /// Add this genre (and matching subgenres) if it matches
+    fn evaluate(&self, features: &AudioFeatures, output: &mut Vec<TagWithConfidence>) {
+        if !self.matches(features) {
+            return;
+        }
+        output.push(TagWithConfidence::new(&self.genre, self.confidence));
+        for rule in &self.subgenres {
+            rule.evaluate(features, output);
+        }
+    }
+}
+
+/// Inclusive BPM range
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BpmRange {
+    pub min: f32,
+    pub max: f32,
+}
+
+/// Condition on an audio feature
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeatureCondition {
+    pub feature: Feature,
+    /// Feature has to be strictly above this value
+    #[serde(default)]
+    pub above: Option<f32>,
+    /// Feature has to be strictly below this value
+    #[serde(default)]
+    pub below: Option<f32>,
+}
+
+impl FeatureCondition {
+    pub fn above(feature: Feature, value: f32) -> Self {
+        Self { feature, above: Some(value), below: None }
+    }
+
+    pub fn below(feature: Feature, value: f32) -> Self {
+        Self { feature, above: None, below: Some(value) }
+    }
+
+    pub fn matches(&self, features: &AudioFeatures) -> bool {
+        let value = self.feature.value(features);
+        self.above.map(|a| value > a).unwrap_or(true) && self.below.map(|b| value < b).unwrap_or(true)
+    }
+}
+
+/// Audio feature usable in rules
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Feature {
+    SpectralCentroid,
+    SpectralRolloff,
+    SpectralFlux,
+    ZeroCrossingRate,
+    RmsEnergy,
+    OnsetStrength,
+    TempoStability,
+}
+
+impl Feature {
+    pub fn value(&self, features: &AudioFeatures) -> f32 {
+        match self {
+            Feature::SpectralCentroid => features.spectral_centroid,
+            Feature::SpectralRolloff => features.spectral_rolloff,
+            Feature::SpectralFlux => features.spectral_flux,
+            Feature::ZeroCrossingRate => features.zero_crossing_rate,
+            Feature::RmsEnergy => features.rms_energy,
+            Feature::OnsetStrength => features.onset_strength,
+            Feature::TempoStability => features.tempo_stability,
+        }
+    }
+}
+
+/// ONNX genre model taking `[1, 18]` feature vector (see `GenreModel::input`) and outputting per-label probabilities
+pub struct GenreModel {
+    labels: Vec<String>,
+    #[cfg(feature = "onnx")]
+    plan: TypedRunnableModel<TypedModel>,
+}
+
+impl GenreModel {
+    /// Model input size
+    pub const INPUT_SIZE: usize = 18;
+
+    /// Load model and labels file
+    pub fn load(model: &Path, labels: &Path) -> Result<GenreModel> {
+        let labels: Vec<String> = std::fs::read_to_string(labels)?
+            .lines()
+            .map(|l| l.trim().to_string())
+            .filter(|l| !l.is_empty())
+            .collect();
+        if labels.is_empty() {
+            return Err(anyhow!("Labels file is empty"));
+        }
+        Self::load_model(model, labels)
+    }
+
+    #[cfg(feature = "onnx")]
+    fn load_model(model: &Path, labels: Vec<String>) -> Result<GenreModel> {
+        let plan = tract_onnx::onnx()
+            .model_for_path(model)?
+            .with_input_fact(0, f32::fact([1, Self::INPUT_SIZE]).into())?
+            .into_optimized()?
+            .into_runnable()?;
+        info!("Loaded genre model {} with {} labels", model.display(), labels.len());
+        Ok(GenreModel { labels, plan })
+    }
+
+    #[cfg(not(feature = "onnx"))]
+    fn load_model(model: &Path, _labels: Vec<String>) -> Result<GenreModel> {
+        Err(anyhow!("Can't load {}: ONNX support not enabled, build with the `onnx` feature", model.display()))
+    }
+
+    /// Feature vector: 13 MFCCs, spectral centroid, rolloff, flux, zero crossing rate, RMS energy
+    pub fn input(features: &AudioFeatures) -> Vec<f32> {
+        let mut input: Vec<f32> = features.mfccs.iter().cloned().chain(std::iter::repeat(0.0)).take(13).collect();
+        input.extend([
+            features.spectral_centroid,
+            features.spectral_rolloff,
+            features.spectral_flux,
+            features.zero_crossing_rate,
+            features.rms_energy,
+        ]);
+        input
+    }
+
+    /// Genre probabilities mapped to labels
+    #[cfg(feature = "onnx")]
+    pub fn predict(&self, features: &AudioFeatures) -> Result<Vec<TagWithConfidence>> {
+        let input = tract_ndarray::Array2::from_shape_vec((1, Self::INPUT_SIZE), Self::input(features))?;
+        let output = self.plan.run(tvec!(Tensor::from(input).into()))?;
+        let probabilities: Vec<f32> = output[0].to_array_view::<f32>()?.iter().cloned().collect();
+        if probabilities.len() != self.labels.len() {
+            return Err(anyhow!("Model has {} outputs but {} labels", probabilities.len(), self.labels.len()));
+        }
+        Ok(self.labels.iter().zip(probabilities)
+            .map(|(label, p)| TagWithConfidence::new(label, p).with_source(crate::DetectionSource::Onnx))
             .collect())
     }
+
+    #[cfg(not(feature = "onnx"))]
+    pub fn predict(&self, _features: &AudioFeatures) -> Result<Vec<TagWithConfidence>> {
+        Err(anyhow!("ONNX support not enabled ({} labels)", self.labels.len()))
+    }
+}
+
+/// Vocal presence / probability from which a track has vocals
+const VOCAL_THRESHOLD: f32 = 0.3;
+
+lazy_static! {
+    /// Loaded vocal models by path (None if loading failed), shared between detectors
+    static ref VOCAL_MODELS: Mutex<HashMap<PathBuf, Option<Arc<VocalModel>>>> = Mutex::new(HashMap::new());
+}
+
+/// Vocal / instrumental detector
+pub struct VocalDetector {
+    model: Option<Arc<VocalModel>>,
+}
+
+impl VocalDetector {
+    /// The model is loaded once per path
+    pub fn new(config: &AIConfig) -> Result<Self> {
+        let model = config.vocal_model.as_ref().and_then(|path| VOCAL_MODELS.lock()
+            .entry(path.clone())
+            .or_insert_with(|| VocalModel::load(path).map(Arc::new).map_err(|e| {
+                warn!("Failed loading vocal model: {}. Using vocal presence heuristic.", e);
+            }).ok())
+            .clone());
+        Ok(Self { model })
+    }
+
+    /// Whether the track has no vocals, None if unknown (placeholder features)
+    pub fn is_instrumental(&self, features: &AudioFeatures) -> Option<bool> {
+        if features.is_placeholder {
+            return None;
+        }
+        let vocals = match &self.model {
+            Some(model) => match model.predict(features) {
+                Ok(probability) => probability,
+                Err(e) => {
+                    warn!("Vocal model inference failed: {}. Using vocal presence heuristic.", e);
+                    features.vocal_presence?
+                }
+            },
+            None => features.vocal_presence?,
+        };
+        Some(vocals < VOCAL_THRESHOLD)
+    }
+}
+
+/// ONNX vocal detection model taking the `GenreModel` feature vector and outputting the probability of vocals
+pub struct VocalModel {
+    #[cfg(feature = "onnx")]
+    plan: TypedRunnableModel<TypedModel>,
+}
+
+impl VocalModel {
+    #[cfg(feature = "onnx")]
+    pub fn load(model: &Path) -> Result<VocalModel> {
+        let plan = tract_onnx::onnx()
+            .model_for_path(model)?
+            .with_input_fact(0, f32::fact([1, GenreModel::INPUT_SIZE]).into())?
+            .into_optimized()?
+            .into_runnable()?;
+        info!("Loaded vocal model {}", model.display());
+        Ok(VocalModel { plan })
+    }
+
+    #[cfg(not(feature = "onnx"))]
+    pub fn load(model: &Path) -> Result<VocalModel> {
+        Err(anyhow!("Can't load {}: ONNX support not enabled, build with the `onnx` feature", model.display()))
+    }
+
+    /// Probability of vocals (0-1)
+    #[cfg(feature = "onnx")]
+    pub fn predict(&self, features: &AudioFeatures) -> Result<f32> {
+        let input = tract_ndarray::Array2::from_shape_vec((1, GenreModel::INPUT_SIZE), GenreModel::input(features))?;
+        let output = self.plan.run(tvec!(Tensor::from(input).into()))?;
+        output[0].to_array_view::<f32>()?.iter().next().cloned().ok_or(anyhow!("Vocal model has no output"))
+    }
+
+    #[cfg(not(feature = "onnx"))]
+    pub fn predict(&self, _features: &AudioFeatures) -> Result<f32> {
+        Err(anyhow!("ONNX support not enabled"))
+    }
 }
 
 /// Mood detector
@@ -72,7 +531,7 @@ pub struct MoodDetector {
 impl MoodDetector {
     pub fn new(config: &AIConfig) -> Result<Self> {
         Ok(Self {
-            confidence_threshold: config.confidence_threshold,
+            confidence_threshold: config.mood_threshold(),
         })
     }
 
@@ -80,19 +539,18 @@ impl MoodDetector {
     pub fn detect(&self, features: &AudioFeatures) -> Result<Vec<TagWithConfidence>> {
         let mut moods = Vec::new();
 
-        // Rule-based mood detection
-        // TODO: Replace with ML model
-
-        // Dark vs Uplifting (based on key and spectral features)
-        if let Some(ref key) = features.key {
-            if key.contains('m') || key.contains("min") {
-                moods.push(TagWithConfidence::new("dark", 0.75));
-                moods.push(TagWithConfidence::new("melancholic", 0.70));
-            } else {
-                moods.push(TagWithConfidence::new("uplifting", 0.72));
-                moods.push(TagWithConfidence::new("euphoric", 0.68));
-            }
-        }
+        // Map valence/arousal quadrant to moods, stronger further from the neutral center
+        let point = self.mood_point(features);
+        let distance = ((point.valence - 0.5).hypot(point.arousal - 0.5) / 0.5).min(1.0);
+        let confidence = 0.6 + 0.35 * distance;
+        let (primary, secondary) = match (point.valence >= 0.5, point.arousal >= 0.5) {
+            (true, true) => ("euphoric", "uplifting"),
+            (false, true) => ("dark", "tense"),
+            (false, false) => ("melancholic", "moody"),
+            (true, false) => ("dreamy", "chill"),
+        };
+        moods.push(TagWithConfidence::new(primary, confidence));
+        moods.push(TagWithConfidence::new(secondary, confidence * 0.95));
 
         // Energy-based moods
         if features.rms_energy > 0.7 {
@@ -112,29 +570,70 @@ impl MoodDetector {
             .filter(|m| m.confidence >= self.confidence_threshold)
             .collect())
     }
+
+    /// Position of the track on the valence/arousal plane
+    pub fn mood_point(&self, features: &AudioFeatures) -> MoodPoint {
+        // Valence from mode and spectral brightness
+        let mode = match is_minor(features) {
+            Some(true) => 0.2,
+            Some(false) => 0.8,
+            None => 0.5,
+        };
+        let brightness = ((features.spectral_centroid - 500.0) / 3000.0).clamp(0.0, 1.0);
+        let valence = 0.6 * mode + 0.4 * brightness;
+
+        // Arousal from loudness and rhythmic activity
+        let arousal = 0.6 * features.rms_energy.clamp(0.0, 1.0) + 0.4 * features.onset_strength.clamp(0.0, 1.0);
+
+        MoodPoint { valence, arousal }
+    }
+}
+
+/// Whether the detected key is minor, `None` if unknown
+fn is_minor(features: &AudioFeatures) -> Option<bool> {
+    if let Some(camelot) = &features.camelot {
+        return Some(camelot.ends_with('A'));
+    }
+    let key = features.key.as_ref()?.trim().to_lowercase();
+    Some(key.contains("min") || key.ends_with('m'))
+}
+
+/// Valence (negative - positive) and arousal (calm - excited), both 0-1
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MoodPoint {
+    pub valence: f32,
+    pub arousal: f32,
 }
 
+/// Loudness mapped to energy level 0 (LUFS)
+const LUFS_ENERGY_MIN: f32 = -40.0;
+/// Loudness mapped to energy level 100 (LUFS)
+const LUFS_ENERGY_MAX: f32 = -5.0;
+
+/// Tempo most suited for dancing (BPM)
+const DANCE_TEMPO: f32 = 124.0;
+/// Width of the dance tempo preference (octaves)
+const DANCE_TEMPO_WIDTH: f32 = 0.3;
+
 /// Energy analyzer
-pub struct EnergyAnalyzer {}
+pub struct EnergyAnalyzer {
+    use_lufs: bool,
+}
 
 impl EnergyAnalyzer {
-    pub fn new(_config: &AIConfig) -> Result<Self> {
-        Ok(Self {})
+    pub fn new(config: &AIConfig) -> Result<Self> {
+        Ok(Self { use_lufs: config.lufs_energy })
     }
 
     /// Analyze energy levels
     pub fn analyze(&self, features: &AudioFeatures) -> Result<EnergyAnalysis> {
-        // Calculate energy level (0-100)
-        let energy_level = (features.rms_energy * 100.0).clamp(0.0, 100.0);
-
-        // Danceability (based on tempo, onset strength)
-        let danceability = if let Some(bpm) = features.bpm {
-            let bpm_score = if (118.0..=135.0).contains(&bpm) { 0.9 } else { 0.5 };
-            let onset_score = features.onset_strength;
-            ((bpm_score + onset_score) / 2.0 * 100.0).clamp(0.0, 100.0)
-        } else {
-            50.0
-        };
+        // Calculate energy level (0-100), from loudness if measured
+        let energy_level = match features.lufs.filter(|_| self.use_lufs) {
+            Some(lufs) => (lufs - LUFS_ENERGY_MIN) / (LUFS_ENERGY_MAX - LUFS_ENERGY_MIN) * 100.0,
+            None => features.rms_energy * 100.0,
+        }.clamp(0.0, 100.0);
+
+        let danceability = Self::danceability(features);
 
         // Aggression (based on spectral features)
         let aggression = ((features.spectral_flux + features.zero_crossing_rate) / 2.0 * 100.0)
@@ -146,6 +645,115 @@ impl EnergyAnalyzer {
             aggression,
         })
     }
+
+    /// Danceability (0-100) from beat grid alignment, beat consistency and closeness to `DANCE_TEMPO`.
+    /// Without rhythm measurements (placeholders, older cache entries) from tempo range and onset strength
+    fn danceability(features: &AudioFeatures) -> f32 {
+        let Some(rhythm) = features.rhythm.as_ref().filter(|_| !features.is_placeholder) else {
+            return match features.bpm {
+                Some(bpm) => {
+                    let bpm_score = if (118.0..=135.0).contains(&bpm) { 0.9 } else { 0.5 };
+                    ((bpm_score + features.onset_strength) / 2.0 * 100.0).clamp(0.0, 100.0)
+                },
+                None => 50.0,
+            };
+        };
+
+        // Tempo from the beat grid if not detected
+        let beats = &features.beat_positions;
+        let bpm = features.bpm.or_else(|| (beats.len() >= 2)
+            .then(|| 60.0 * (beats.len() - 1) as f32 / (beats[beats.len() - 1] - beats[0]))
+        ).filter(|bpm| bpm.is_finite() && *bpm > 0.0);
+        let tempo_score = bpm.map(|bpm| (-0.5 * ((bpm / DANCE_TEMPO).log2() / DANCE_TEMPO_WIDTH).powi(2)).exp()).unwrap_or(0.5);
+        ((0.5 * rhythm.beat_alignment + 0.3 * rhythm.beat_consistency + 0.2 * tempo_score) * 100.0).clamp(0.0, 100.0)
+    }
+
+    /// Energy curve over time, `frame_samples` are RMS values of `RMS_FRAME_SECS` frames (see `AudioFeatures::rms_frames`)
+    pub fn analyze_segments(&self, features: &AudioFeatures, frame_samples: &[f32]) -> Option<EnergyTimeline> {
+        if features.is_placeholder || frame_samples.is_empty() {
+            return None;
+        }
+
+        // Combine frames into segments (RMS of RMS), same 0-100 scale as energy_level
+        let frames_per_segment = ((ENERGY_SEGMENT_SECS / RMS_FRAME_SECS).round() as usize).max(1);
+        let energy: Vec<f32> = frame_samples.chunks(frames_per_segment)
+            .map(|c| ((c.iter().map(|f| f * f).sum::<f32>() / c.len() as f32).sqrt() * 100.0).clamp(0.0, 100.0))
+            .collect();
+
+        // Local maxima (drops), plateaus count once at their start
+        let peaks = (0..energy.len())
+            .filter(|&i| {
+                let previous = i.checked_sub(1).map(|p| energy[p]).unwrap_or(f32::MIN);
+                let next = energy.get(i + 1).copied().unwrap_or(f32::MIN);
+                energy.len() > 1 && energy[i] > previous && energy[i] >= next
+            })
+            .map(|i| i as f32 * ENERGY_SEGMENT_SECS)
+            .collect();
+
+        Some(EnergyTimeline { segment_secs: ENERGY_SEGMENT_SECS, energy, peaks })
+    }
+
+    /// Mix-in (first sustained energy) and mix-out (end of the last sustained energy before the outro) cues
+    /// in seconds from the start of the analyzed audio. None without a clear rise / drop (e.g. ambient fades)
+    pub fn cue_points(&self, features: &AudioFeatures) -> (Option<f32>, Option<f32>) {
+        let frames = &features.rms_frames;
+        if features.is_placeholder || frames.is_empty() {
+            return (None, None);
+        }
+
+        // Body of the track = 75th percentile energy
+        let mut sorted = frames.clone();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let body = sorted[(sorted.len() - 1) * 3 / 4];
+        if body <= 0.0 {
+            return (None, None);
+        }
+
+        let threshold = body * CUE_THRESHOLD;
+        let sustain = ((CUE_SUSTAIN_SECS / RMS_FRAME_SECS).round() as usize).max(1);
+        let mix_in = sustained_onset(frames, threshold, sustain).map(|i| i as f32 * RMS_FRAME_SECS);
+        let reversed: Vec<f32> = frames.iter().rev().copied().collect();
+        let mix_out = sustained_onset(&reversed, threshold, sustain).map(|i| (frames.len() - i) as f32 * RMS_FRAME_SECS);
+        (mix_in, mix_out)
+    }
+}
+
+/// Sustained energy shorter than this isn't a cue (seconds)
+const CUE_SUSTAIN_SECS: f32 = 8.0;
+/// Fraction of the body energy counted as sustained
+const CUE_THRESHOLD: f32 = 0.6;
+/// Length of the audio before the sustained energy compared against it (seconds)
+const CUE_ONSET_SECS: f32 = 4.0;
+/// Min ratio of the sustained energy to the energy before it for a clear onset
+const CUE_ONSET_RISE: f32 = 2.0;
+
+/// Index of the first frame of `sustain` frames above threshold, if energy rises into it clearly
+fn sustained_onset(frames: &[f32], threshold: f32, sustain: usize) -> Option<usize> {
+    let start = (0..=frames.len().checked_sub(sustain)?)
+        .find(|&i| frames[i..i + sustain].iter().all(|f| *f >= threshold))?;
+    // Starts straight in
+    if start == 0 {
+        return Some(0);
+    }
+    let before = &frames[start.saturating_sub(((CUE_ONSET_SECS / RMS_FRAME_SECS).round() as usize).max(1))..start];
+    let before = before.iter().sum::<f32>() / before.len() as f32;
+    let region = frames[start..start + sustain].iter().sum::<f32>() / sustain as f32;
+    (region >= before * CUE_ONSET_RISE).then_some(start)
+}
+
+/// Length of a single energy timeline segment (seconds)
+pub const ENERGY_SEGMENT_SECS: f32 = 4.0;
+
+/// Energy level per segment of the track
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnergyTimeline {
+    /// Length of each segment (seconds)
+    pub segment_secs: f32,
+    /// Energy (0-100) of each segment
+    pub energy: Vec<f32>,
+    /// Start of segments which are local energy maxima (seconds from the start of the analyzed audio)
+    pub peaks: Vec<f32>,
 }
 
 /// Energy analysis result
@@ -160,6 +768,54 @@ pub struct EnergyAnalysis {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_genre_hierarchy() {
+        let hierarchy = GenreHierarchy::default();
+        assert_eq!(hierarchy.parent("Deep-House"), Some("house"));
+        assert_eq!(hierarchy.parent("house"), None);
+
+        // Existing parent isn't duplicated
+        let genres = hierarchy.expand(vec![TagWithConfidence::new("peak-time-techno", 0.9), TagWithConfidence::new("minimal-techno", 0.8), TagWithConfidence::new("Techno", 0.5)]);
+        assert_eq!(genres.len(), 3);
+
+        let mut hierarchy = GenreHierarchy::default();
+        hierarchy.parents.insert("house".to_string(), "electronic".to_string());
+        let genres = hierarchy.expand(vec![TagWithConfidence::new("deep-house", 1.0)]);
+        let tags: Vec<_> = genres.iter().map(|t| (t.tag.as_str(), t.confidence)).collect();
+        assert_eq!(tags[0], ("deep-house", 1.0));
+        assert_eq!(tags[1].0, "house");
+        assert!((tags[1].1 - 0.8).abs() < 1e-6);
+        assert_eq!(tags[2].0, "electronic");
+        assert!((tags[2].1 - 0.64).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_vocal_model_loaded_once() {
+        let mut config = AIConfig::default();
+        config.vocal_model = Some(PathBuf::from("/nonexistent/vocal.onnx"));
+        assert!(VocalDetector::new(&config).unwrap().model.is_none());
+        // Failed load is remembered instead of retried for every track
+        assert!(VOCAL_MODELS.lock().get(config.vocal_model.as_ref().unwrap()).unwrap().is_none());
+        assert!(VocalDetector::new(&config).unwrap().model.is_none());
+    }
+
+    #[test]
+    fn test_instrumental_detection() {
+        use crate::features::FeatureExtractor;
+        use crate::test_utils::{vocal_samples, drum_samples};
+
+        let detector = VocalDetector::new(&AIConfig::default()).unwrap();
+        let extractor = FeatureExtractor::new();
+        let vocal = extractor.extract_from_samples(&vocal_samples(3.0), 44100).unwrap();
+        let instrumental = extractor.extract_from_samples(&drum_samples(3.0), 44100).unwrap();
+        assert_eq!(detector.is_instrumental(&vocal), Some(false));
+        assert_eq!(detector.is_instrumental(&instrumental), Some(true));
+
+        let placeholder = AudioFeatures { is_placeholder: true, ..vocal };
+        assert_eq!(detector.is_instrumental(&placeholder), None);
+        assert_eq!(detector.is_instrumental(&AudioFeatures::default()), None);
+    }
+
     #[test]
     fn test_genre_classification() {
         let config = AIConfig::default();
@@ -174,6 +830,197 @@ mod tests {
         assert!(!genres.is_empty());
     }
 
+    #[test]
+    fn test_custom_genre_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rules.toml");
+        std::fs::write(&path, r#"
+            [[rules]]
+            genre = "drum-and-bass"
+            bpm = { min = 160, max = 180 }
+            confidence = 0.9
+            conditions = [{ feature = "rms-energy", above = 0.5 }]
+
+            [[rules.subgenres]]
+            genre = "liquid-dnb"
+            confidence = 0.8
+            conditions = [{ feature = "spectral-centroid", below = 1500 }]
+        "#).unwrap();
+
+        let mut features = AudioFeatures::default();
+        features.bpm = Some(174.0);
+        features.rms_energy = 0.8;
+        features.spectral_centroid = 2000.0;
+
+        // Built-in rules don't know DnB
+        let config = AIConfig::default();
+        assert!(GenreClassifier::new(&config).unwrap().classify(&features).unwrap().is_empty());
+
+        let config = AIConfig { genre_rules: Some(path), ..AIConfig::default() };
+        let genres = GenreClassifier::new(&config).unwrap().classify(&features).unwrap();
+        assert_eq!(genres.len(), 1);
+        assert_eq!(genres[0].tag, "drum-and-bass");
+        assert_eq!(genres[0].confidence, 0.9);
+
+        // JSON
+        let rules: GenreRules = serde_json::from_str(r#"{"rules": [{"genre": "dubstep", "bpm": {"min": 138, "max": 142}, "confidence": 0.85}]}"#).unwrap();
+        features.bpm = Some(140.0);
+        let genres = GenreClassifier::new(&AIConfig::default()).unwrap().with_rules(rules).classify(&features).unwrap();
+        assert_eq!(genres[0].tag, "dubstep");
+    }
+
+    #[tokio::test]
+    async fn test_llm_genre_classification() {
+        use crate::test_utils::{gemini_response, llm_config, MockResponse, MockServer};
+
+        let response = "```json\n[{\"genre\": \"Techno\", \"confidence\": 0.9}, {\"genre\": \"polka\", \"confidence\": 0.95}, {\"genre\": \"house\", \"confidence\": 1.4}]\n```";
+        let server = MockServer::start(vec![MockResponse::new(200, gemini_response(response))]).await;
+        let mut config = llm_config(&server);
+        config.custom_tags.genres = vec!["techno".to_string(), "house".to_string()];
+        let client = APIClient::new(config.api_config.clone()).unwrap();
+
+        let mut features = AudioFeatures::default();
+        features.bpm = Some(128.0);
+        let classifier = GenreClassifier::new(&config).unwrap();
+        let genres = classifier.classify_with_llm(&client, &features).await.unwrap();
+        assert_eq!(genres.len(), 2);
+        assert_eq!((genres[0].tag.as_str(), genres[0].confidence), ("techno", 0.9));
+        assert_eq!((genres[1].tag.as_str(), genres[1].confidence), ("house", 1.0));
+        assert!(genres.iter().all(|g| g.source == crate::DetectionSource::Llm));
+        assert!(server.requests()[0].contains("techno, house"));
+    }
+
+    #[tokio::test]
+    async fn test_llm_genre_fallback() {
+        use crate::test_utils::{llm_config, MockResponse, MockServer};
+
+        let server = MockServer::start(vec![MockResponse::new(401, "unauthorized")]).await;
+        let config = llm_config(&server);
+        let client = APIClient::new(config.api_config.clone()).unwrap();
+
+        let mut features = AudioFeatures::default();
+        features.bpm = Some(128.0);
+        features.spectral_centroid = 1500.0;
+        features.rms_energy = 0.8;
+        let genres = GenreClassifier::new(&config).unwrap().classify_with_llm(&client, &features).await.unwrap();
+        assert_eq!(genres[0].tag, "techno");
+        assert_eq!(genres[0].source, crate::DetectionSource::Rule);
+    }
+
+    #[test]
+    fn test_model_input() {
+        let features = AudioFeatures::default();
+        assert_eq!(GenreModel::input(&features).len(), GenreModel::INPUT_SIZE);
+    }
+
+    #[cfg(not(feature = "onnx"))]
+    #[test]
+    fn test_model_requires_feature() {
+        let dir = tempfile::tempdir().unwrap();
+        let labels = dir.path().join("labels.txt");
+        std::fs::write(&labels, "house\ntechno\n").unwrap();
+        let config = AIConfig::default();
+        let error = GenreClassifier::new(&config).unwrap().with_model(&dir.path().join("genre.onnx"), &labels).err().unwrap();
+        assert!(error.to_string().contains("onnx"));
+    }
+
+    /// Minimal protobuf encoding for building a test ONNX model
+    #[cfg(feature = "onnx")]
+    mod proto {
+        pub fn varint(mut value: u64, out: &mut Vec<u8>) {
+            while value >= 0x80 {
+                out.push((value as u8) | 0x80);
+                value >>= 7;
+            }
+            out.push(value as u8);
+        }
+
+        pub fn int(field: u64, value: u64, out: &mut Vec<u8>) {
+            varint(field << 3, out);
+            varint(value, out);
+        }
+
+        pub fn bytes(field: u64, value: &[u8], out: &mut Vec<u8>) {
+            varint(field << 3 | 2, out);
+            varint(value.len() as u64, out);
+            out.extend_from_slice(value);
+        }
+
+        /// ValueInfoProto of float tensor
+        pub fn value_info(name: &str, dims: &[u64]) -> Vec<u8> {
+            let mut shape = vec![];
+            for dim in dims {
+                let mut d = vec![];
+                int(1, *dim, &mut d);
+                bytes(1, &d, &mut shape);
+            }
+            let mut tensor = vec![];
+            int(1, 1, &mut tensor);
+            bytes(2, &shape, &mut tensor);
+            let mut type_proto = vec![];
+            bytes(1, &tensor, &mut type_proto);
+            let mut out = vec![];
+            bytes(1, name.as_bytes(), &mut out);
+            bytes(2, &type_proto, &mut out);
+            out
+        }
+
+        pub fn node(inputs: &[&str], output: &str, op: &str) -> Vec<u8> {
+            let mut out = vec![];
+            for input in inputs {
+                bytes(1, input.as_bytes(), &mut out);
+            }
+            bytes(2, output.as_bytes(), &mut out);
+            bytes(4, op.as_bytes(), &mut out);
+            out
+        }
+    }
+
+    #[cfg(feature = "onnx")]
+    #[test]
+    fn test_onnx_model() {
+        // input [1, 18] x W [18, 3] -> Softmax, only RMS energy (index 17) drives the 3rd label
+        let mut weights = vec![0.0f32; 18 * 3];
+        weights[17 * 3 + 2] = 10.0;
+        let mut initializer = vec![];
+        proto::int(1, 18, &mut initializer);
+        proto::int(1, 3, &mut initializer);
+        proto::int(2, 1, &mut initializer);
+        proto::bytes(8, b"W", &mut initializer);
+        proto::bytes(9, &weights.iter().flat_map(|w| w.to_le_bytes()).collect::<Vec<u8>>(), &mut initializer);
+
+        let mut graph = vec![];
+        proto::bytes(1, &proto::node(&["input", "W"], "logits", "MatMul"), &mut graph);
+        proto::bytes(1, &proto::node(&["logits"], "output", "Softmax"), &mut graph);
+        proto::bytes(2, b"genres", &mut graph);
+        proto::bytes(5, &initializer, &mut graph);
+        proto::bytes(11, &proto::value_info("input", &[1, 18]), &mut graph);
+        proto::bytes(12, &proto::value_info("output", &[1, 3]), &mut graph);
+
+        let mut opset = vec![];
+        proto::int(2, 13, &mut opset);
+        let mut model = vec![];
+        proto::int(1, 7, &mut model);
+        proto::bytes(7, &graph, &mut model);
+        proto::bytes(8, &opset, &mut model);
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("genre.onnx"), model).unwrap();
+        std::fs::write(dir.path().join("labels.txt"), "house\ntechno\ndrum-and-bass\n").unwrap();
+
+        let classifier = GenreClassifier::new(&AIConfig::default()).unwrap()
+            .with_model(&dir.path().join("genre.onnx"), &dir.path().join("labels.txt"))
+            .unwrap();
+        let mut features = AudioFeatures::default();
+        features.bpm = Some(128.0);
+        features.rms_energy = 1.0;
+        let genres = classifier.classify(&features).unwrap();
+        assert_eq!(genres.len(), 1);
+        assert_eq!(genres[0].tag, "drum-and-bass");
+        assert_eq!(genres[0].source, crate::DetectionSource::Onnx);
+        assert!(genres[0].confidence > 0.99);
+    }
+
     #[test]
     fn test_mood_detection() {
         let config = AIConfig::default();
@@ -186,4 +1033,111 @@ mod tests {
         let moods = detector.detect(&features).unwrap();
         assert!(!moods.is_empty());
     }
+
+    #[test]
+    fn test_energy_timeline_ramp() {
+        // 16s sine with amplitude ramping up
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ramp.wav");
+        let samples: Vec<f32> = (0..44100 * 16)
+            .map(|i| (i as f32 * 440.0 * 2.0 * std::f32::consts::PI / 44100.0).sin() * (0.05 + 0.85 * i as f32 / (44100.0 * 16.0)))
+            .collect();
+        crate::test_utils::write_wav(&path, &samples, 44100);
+
+        let features = crate::FeatureExtractor::new().extract(&path).unwrap();
+        let analyzer = EnergyAnalyzer::new(&AIConfig::default()).unwrap();
+        let timeline = analyzer.analyze_segments(&features, &features.rms_frames).unwrap();
+        assert_eq!(timeline.energy.len(), 4);
+        assert!(timeline.energy.windows(2).all(|w| w[1] > w[0]));
+        assert_eq!(timeline.peaks, vec![12.0]);
+    }
+
+    #[test]
+    fn test_cue_points() {
+        // 8s quiet intro, 24s loud body, 6s quiet outro
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cues.wav");
+        let samples: Vec<f32> = (0..22050 * 38)
+            .map(|i| {
+                let amplitude = match i / 22050 { 8..=31 => 0.8, _ => 0.02 };
+                (i as f32 * 440.0 * 2.0 * std::f32::consts::PI / 22050.0).sin() * amplitude
+            })
+            .collect();
+        crate::test_utils::write_wav(&path, &samples, 22050);
+
+        let features = crate::FeatureExtractor::new().extract(&path).unwrap();
+        let analyzer = EnergyAnalyzer::new(&AIConfig::default()).unwrap();
+        let (mix_in, mix_out) = analyzer.cue_points(&features);
+        assert!((mix_in.unwrap() - 8.0).abs() <= 1.0);
+        assert!((mix_out.unwrap() - 32.0).abs() <= 1.0);
+
+        // Ambient swell without a clear onset
+        let mut features = AudioFeatures::default();
+        features.rms_frames = (0..60).map(|i| 0.01 + i as f32 / 100.0).collect();
+        assert_eq!(analyzer.cue_points(&features).0, None);
+    }
+
+    #[test]
+    fn test_lufs_energy() {
+        let features = AudioFeatures { rms_energy: 0.1, lufs: Some(-8.0), ..Default::default() };
+        let rms = EnergyAnalyzer::new(&AIConfig::default()).unwrap().analyze(&features).unwrap();
+        assert!((rms.energy_level - 10.0).abs() < 1e-4);
+
+        let config = AIConfig { lufs_energy: true, ..Default::default() };
+        let lufs = EnergyAnalyzer::new(&config).unwrap().analyze(&features).unwrap();
+        assert!(lufs.energy_level > 90.0);
+        let unmeasured = AudioFeatures { lufs: None, ..features };
+        assert_eq!(EnergyAnalyzer::new(&config).unwrap().analyze(&unmeasured).unwrap().energy_level, rms.energy_level);
+    }
+
+    #[test]
+    fn test_danceability_regularity() {
+        use crate::features::FeatureExtractor;
+        let danceability = |times: &[f32], seconds: f32| {
+            let mut features = AudioFeatures { bpm: Some(124.0), ..Default::default() };
+            FeatureExtractor::new().analyze_samples(&crate::test_utils::click_samples(times, seconds), 22050, &mut features);
+            EnergyAnalyzer::new(&AIConfig::default()).unwrap().analyze(&features).unwrap().danceability
+        };
+
+        // Four on the floor vs rubato at the same BPM
+        let four: Vec<f32> = (0..20).map(|i| i as f32 * 60.0 / 124.0).collect();
+        let rubato: Vec<f32> = [0.2, 0.8, 0.3, 1.0, 0.25, 0.6].iter().cycle().take(18)
+            .scan(0.0, |time, interval| { *time += interval; Some(*time) })
+            .collect();
+        let (four, rubato) = (danceability(&four, 10.5), danceability(&rubato, 11.0));
+        assert!(four > rubato + 30.0, "{} <= {} + 30", four, rubato);
+        assert!(four <= 100.0 && rubato >= 0.0);
+    }
+
+    #[test]
+    fn test_mood_euphoric() {
+        let detector = MoodDetector::new(&AIConfig::default()).unwrap();
+        let mut features = AudioFeatures::default();
+        features.key = Some("C".to_string());
+        features.rms_energy = 0.9;
+        features.onset_strength = 0.9;
+        features.spectral_centroid = 3000.0;
+
+        let point = detector.mood_point(&features);
+        assert!(point.valence > 0.7 && point.arousal > 0.7);
+        let moods = detector.detect(&features).unwrap();
+        assert!(moods.iter().any(|m| m.tag == "euphoric"));
+        assert!(!moods.iter().any(|m| m.tag == "melancholic"));
+    }
+
+    #[test]
+    fn test_mood_melancholic() {
+        let detector = MoodDetector::new(&AIConfig::default()).unwrap();
+        let mut features = AudioFeatures::default();
+        features.key = Some("Am".to_string());
+        features.rms_energy = 0.2;
+        features.onset_strength = 0.2;
+        features.spectral_centroid = 800.0;
+
+        let point = detector.mood_point(&features);
+        assert!(point.valence < 0.3 && point.arousal < 0.3);
+        let moods = detector.detect(&features).unwrap();
+        assert!(moods.iter().any(|m| m.tag == "melancholic"));
+        assert!(!moods.iter().any(|m| m.tag == "euphoric"));
+    }
 }
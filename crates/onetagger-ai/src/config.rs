@@ -2,13 +2,18 @@
 //!
 //! Defines all configuration structures for AI features using FREE Cloud APIs
 
+use onetagger_tag::FrameName;
+use crate::DetectionSource;
+use crate::classifier::GenreHierarchy;
 use serde::{Serialize, Deserialize};
-use std::path::PathBuf;
+use anyhow::Result;
+use std::path::{Path, PathBuf};
 use std::collections::HashMap;
+use std::time::Duration;
 
 /// Main AI configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", default)]
 pub struct AIConfig {
     /// Enable AI features globally
     pub enabled: bool,
@@ -19,6 +24,9 @@ pub struct AIConfig {
     /// API configuration (Gemini, OpenRouter, etc.)
     pub api_config: APIConfig,
 
+    /// Use the LLM (suggestions, descriptions, lyrics), only rule-based analysis when disabled
+    pub llm_enabled: bool,
+
     /// Feature toggles
     pub enable_genre_classification: bool,
     pub enable_mood_detection: bool,
@@ -30,25 +38,256 @@ pub struct AIConfig {
     /// Confidence threshold for accepting AI predictions (0.0-1.0)
     pub confidence_threshold: f32,
 
+    /// Per-task thresholds, `confidence_threshold` is used when not set
+    pub genre_threshold: Option<f32>,
+    pub mood_threshold: Option<f32>,
+    pub custom_tag_threshold: Option<f32>,
+
     /// Duplicate detection similarity threshold (0.0-1.0)
     pub duplicate_threshold: f32,
 
     /// Quality control strictness (0.0-1.0)
     pub quality_strictness: f32,
 
+    /// Report genres outside the custom genre taxonomy during quality control
+    #[serde(default)]
+    pub validate_genre_taxonomy: bool,
+
+    /// Let the LLM classify genres (clamped to the custom genre list) instead of rules
+    #[serde(default)]
+    pub llm_genre_classification: bool,
+
+    /// Add moods, themes and explicit flag extracted from lyrics (tags) by the LLM, instrumental tracks are skipped
+    #[serde(default)]
+    pub lyrics_themes: bool,
+
+    /// Custom genre rules file (JSON or TOML), built-in rules are used when not set
+    #[serde(default)]
+    pub genre_rules: Option<PathBuf>,
+
+    /// Parents implied by detected sub-genres
+    pub genre_hierarchy: GenreHierarchy,
+
+    /// ONNX genre model, rule-based classification is used when not set
+    #[serde(default)]
+    pub genre_model: Option<GenreModelConfig>,
+
+    /// Directory with sentence-transformer text embedding model (`model.onnx` + `vocab.txt`), default location when not set
+    #[serde(default)]
+    pub text_model: Option<PathBuf>,
+
+    /// ONNX vocal detection model, instrumental tracks are detected from the vocal presence heuristic when not set
+    #[serde(default)]
+    pub vocal_model: Option<PathBuf>,
+
+    /// Trust in each detection source when merging tags
+    #[serde(default)]
+    pub source_weights: SourceWeights,
+
+    /// How confidences of the same tag from multiple detections are combined
+    pub merge_strategy: MergeStrategy,
+
+    /// Weights of tag categories in the overall confidence
+    pub confidence_weights: ConfidenceWeights,
+
+    /// Scale energy level by integrated loudness (LUFS) instead of RMS
+    pub lufs_energy: bool,
+
+    /// Style of the LLM-generated description
+    pub description_style: DescriptionStyle,
+
+    /// Prompt for LLM tag suggestions instead of the built-in DJ one, with `{bpm}`, `{key}`, `{genres}`, `{moods}`, `{energy}`
+    /// and `{custom_genres}` placeholders. Answer format instructions are appended
+    #[serde(default)]
+    pub prompt_template: Option<String>,
+
+    /// Language of the LLM-generated description as BCP-47 tag (`de`, `pt-BR`), English when not set
+    #[serde(default)]
+    pub description_language: Option<String>,
+
     /// Advanced options
     pub multi_label_classification: bool,
     pub max_tags_per_track: usize,
     pub prefer_ai_over_platform: bool,
 
+    /// Protect trusted existing tags from being overwritten by AI guesses
+    pub overwrite_guard: OverwriteGuard,
+
+    /// Write the energy level as a 1-5 rating
+    pub energy_rating: EnergyRating,
+
+    /// Additional frames the key is written into, each in its own notation
+    #[serde(default)]
+    pub key_frames: Vec<KeyFrame>,
+
     /// Performance options
     pub batch_size: usize,
     pub max_threads: usize,
 
+    /// Analyze only the first N seconds of audio (None = whole track)
+    #[serde(default)]
+    pub analysis_window_secs: Option<f32>,
+
+    /// Seconds skipped at the start before the analysis window (intro)
+    #[serde(default)]
+    pub analysis_offset_secs: f32,
+
+    /// Separate harmonic and percussive components before analysis (key from harmonic, rhythm from percussive). Slow
+    pub hpss: bool,
+
+    /// `analyze_track` returns the result from a `<audio>.ai.json` sidecar newer than the audio file instead of analyzing it again
+    pub reuse_sidecars: bool,
+
+    /// Max LLM tokens per batch run, remaining tracks use rule-based tags only (None = unlimited)
+    #[serde(default)]
+    pub max_tokens_per_run: Option<u64>,
+
     /// Cache directory
     pub cache_dir: Option<PathBuf>,
 }
 
+impl AIConfig {
+    /// Default config tuned for a use case, fields can be overridden afterwards
+    pub fn preset(preset: Preset) -> AIConfig {
+        let mut config = AIConfig::default();
+        match preset {
+            Preset::TechnoDj => {
+                config.custom_tags.genres = [
+                    "techno", "peak-time-techno", "minimal-techno", "melodic-techno", "hard-techno",
+                    "house", "deep-house", "tech-house", "progressive-house", "minimal",
+                ].iter().map(|g| g.to_string()).collect();
+                config.genre_threshold = Some(0.8);
+                config.llm_genre_classification = true;
+                config.max_tags_per_track = 3;
+                config.energy_rating.enabled = true;
+                config.description_style = DescriptionStyle::SetNotes;
+            },
+            Preset::OpenFormat => {
+                config.custom_tags.genres = [
+                    "pop", "hip-hop", "r&b", "rock", "latin", "reggaeton", "afrobeats", "dancehall",
+                    "house", "edm", "disco", "funk", "soul", "country",
+                ].iter().map(|g| g.to_string()).collect();
+                config.mood_threshold = Some(0.6);
+                config.lyrics_themes = true;
+                config.max_tags_per_track = 5;
+            },
+            Preset::FastScan => {
+                config.llm_enabled = false;
+                config.analysis_offset_secs = 30.0;
+                config.analysis_window_secs = Some(30.0);
+                config.enable_quality_control = false;
+            },
+        }
+        config
+    }
+
+    /// Load config from JSON or TOML file (by extension), missing fields use defaults
+    pub fn load(path: &Path) -> Result<AIConfig> {
+        let data = std::fs::read_to_string(path)?;
+        match is_toml(path) {
+            true => Ok(toml::from_str(&data)?),
+            false => Ok(serde_json::from_str(&data)?),
+        }
+    }
+
+    /// Save config as JSON or TOML (by extension)
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let data = match is_toml(path) {
+            true => toml::to_string_pretty(self)?,
+            false => serde_json::to_string_pretty(self)?,
+        };
+        std::fs::write(path, data)?;
+        Ok(())
+    }
+}
+
+impl AIConfig {
+    /// Confidence threshold of genres
+    pub fn genre_threshold(&self) -> f32 {
+        self.genre_threshold.unwrap_or(self.confidence_threshold)
+    }
+
+    /// Confidence threshold of moods
+    pub fn mood_threshold(&self) -> f32 {
+        self.mood_threshold.unwrap_or(self.confidence_threshold)
+    }
+
+    /// Confidence threshold of tags mapped into custom collections
+    pub fn custom_tag_threshold(&self) -> f32 {
+        self.custom_tag_threshold.unwrap_or(self.confidence_threshold)
+    }
+
+    /// Check value ranges and that an API key is available, returns all problems found
+    pub fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        self.validate_with_env(process_env)
+    }
+
+    /// `validate` with API keys looked up in `env` instead of the process environment
+    pub(crate) fn validate_with_env(&self, env: impl Fn(&str) -> Option<String>) -> Result<(), Vec<ConfigError>> {
+        let mut errors = vec![];
+        let thresholds = [
+            ("confidenceThreshold", Some(self.confidence_threshold)),
+            ("genreThreshold", self.genre_threshold),
+            ("moodThreshold", self.mood_threshold),
+            ("customTagThreshold", self.custom_tag_threshold),
+            ("duplicateThreshold", Some(self.duplicate_threshold)),
+            ("qualityStrictness", Some(self.quality_strictness)),
+            ("overwriteGuard.existingTrust", Some(self.overwrite_guard.existing_trust)),
+        ];
+        for (field, value) in thresholds.into_iter().filter_map(|(f, v)| v.map(|v| (f, v))) {
+            if !(0.0..=1.0).contains(&value) {
+                errors.push(ConfigError::OutOfRange { field, value });
+            }
+        }
+        let counts = [
+            ("maxTagsPerTrack", self.max_tags_per_track),
+            ("batchSize", self.batch_size),
+            ("maxThreads", self.max_threads),
+        ];
+        for (field, value) in counts {
+            if value == 0 {
+                errors.push(ConfigError::Zero { field });
+            }
+        }
+        if self.enabled && self.llm_enabled && !self.api_config.is_configured_with_env(env) {
+            errors.push(ConfigError::MissingApiKey(self.api_config.provider));
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+}
+
+/// Invalid `AIConfig` value
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    /// Value has to be within 0.0-1.0
+    OutOfRange { field: &'static str, value: f32 },
+    /// Value has to be greater than 0
+    Zero { field: &'static str },
+    /// Provider requires an API key but none is configured
+    MissingApiKey(APIProvider),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::OutOfRange { field, value } => write!(f, "{} must be between 0 and 1, got {}", field, value),
+            ConfigError::Zero { field } => write!(f, "{} must be greater than 0", field),
+            ConfigError::MissingApiKey(provider) => write!(f, "{} requires an API key, set it in settings or the {} environment variable (get one at {})",
+                provider.display_name(), provider.api_key_env().unwrap_or(API_KEY_ENV), crate::get_api_key_url(provider)),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+fn is_toml(path: &Path) -> bool {
+    path.extension().and_then(|e| e.to_str()).map(|e| e.eq_ignore_ascii_case("toml")).unwrap_or(false)
+}
+
 impl Default for AIConfig {
     fn default() -> Self {
         let base_path = onetagger_shared::Settings::get_folder()
@@ -58,6 +297,7 @@ impl Default for AIConfig {
             enabled: true,
             custom_tags: CustomTagConfig::default(),
             api_config: APIConfig::default(),
+            llm_enabled: true,
             enable_genre_classification: true,
             enable_mood_detection: true,
             enable_energy_analysis: true,
@@ -65,21 +305,85 @@ impl Default for AIConfig {
             enable_quality_control: true,
             enable_smart_playlists: false,
             confidence_threshold: 0.7,
+            genre_threshold: None,
+            mood_threshold: None,
+            custom_tag_threshold: None,
             duplicate_threshold: 0.85,
             quality_strictness: 0.6,
+            validate_genre_taxonomy: false,
+            llm_genre_classification: false,
+            lyrics_themes: false,
+            lufs_energy: false,
+            genre_rules: None,
+            genre_hierarchy: GenreHierarchy::default(),
+            genre_model: None,
+            text_model: None,
+            vocal_model: None,
+            source_weights: SourceWeights::default(),
+            merge_strategy: MergeStrategy::Combine,
+            confidence_weights: ConfidenceWeights::default(),
+            description_style: DescriptionStyle::OneLiner,
+            prompt_template: None,
+            description_language: None,
             multi_label_classification: true,
             max_tags_per_track: 5,
             prefer_ai_over_platform: false,
+            overwrite_guard: OverwriteGuard::default(),
+            energy_rating: EnergyRating::default(),
+            key_frames: Vec::new(),
             batch_size: 32,
             max_threads: num_cpus::get(),
+            analysis_window_secs: None,
+            analysis_offset_secs: 0.0,
+            hpss: false,
+            reuse_sidecars: false,
+            max_tokens_per_run: None,
             cache_dir: Some(base_path.join("cache")),
         }
     }
 }
 
+/// Named `AIConfig` presets
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preset {
+    /// Techno / house taxonomy, strict genres, energy rating and set notes
+    TechnoDj,
+    /// Broad mainstream genres, lyrics themes
+    OpenFormat,
+    /// Short analysis window without LLM calls
+    FastScan,
+}
+
+/// Style of the LLM-generated track description
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DescriptionStyle {
+    /// Don't generate a description (saves tokens)
+    None,
+    /// Single terse sentence
+    OneLiner,
+    /// Short notes for DJ set preparation
+    SetNotes,
+    /// Promotional blurb
+    Marketing,
+}
+
+impl DescriptionStyle {
+    /// Prompt instruction for this style, `None` if no description should be generated
+    pub fn instruction(&self) -> Option<&'static str> {
+        match self {
+            Self::None => None,
+            Self::OneLiner => Some("a single terse sentence (max 15 words) describing the track"),
+            Self::SetNotes => Some("2-3 short DJ set notes: where the track fits in a set, mixing tips and energy"),
+            Self::Marketing => Some("a short, catchy promotional blurb (2 sentences) for a record store"),
+        }
+    }
+}
+
 /// Custom tag collections defined by the user
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", default)]
 pub struct CustomTagConfig {
     /// Custom genre taxonomy
     pub genres: Vec<String>,
@@ -92,6 +396,39 @@ pub struct CustomTagConfig {
 
     /// Additional custom collections (name -> tags)
     pub custom_collections: HashMap<String, Vec<String>>,
+
+    /// Frame to write matched tags of a collection into (collection name -> frame)
+    /// Built-in collections are named `genres`, `moods` and `vibes`
+    #[serde(default)]
+    pub frames: HashMap<String, FrameName>,
+
+    /// Tag field to write matched tags of a collection into (collection name -> field), takes priority over `frames`
+    #[serde(default)]
+    pub tag_mapping: HashMap<String, TagTarget>,
+
+    /// Min similarity (0.0-1.0) of an LLM suggestion to snap onto a collection tag, 1.0 = exact matches only
+    pub fuzzy_threshold: f32,
+}
+
+impl CustomTagConfig {
+    /// All collections as (name, tags), built-in first, custom ones sorted by name
+    pub fn collections(&self) -> Vec<(&str, &Vec<String>)> {
+        let mut custom: Vec<(&str, &Vec<String>)> = self.custom_collections.iter()
+            .map(|(name, tags)| (name.as_str(), tags))
+            .collect();
+        custom.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut collections = vec![("genres", &self.genres), ("moods", &self.moods), ("vibes", &self.vibes)];
+        collections.extend(custom);
+        collections
+    }
+
+    /// Name of the first collection containing the tag (case-insensitive)
+    pub fn collection_for(&self, tag: &str) -> Option<&str> {
+        self.collections().into_iter()
+            .find(|(_, tags)| tags.iter().any(|t| t.eq_ignore_ascii_case(tag)))
+            .map(|(name, _)| name)
+    }
 }
 
 impl Default for CustomTagConfig {
@@ -126,13 +463,232 @@ impl Default for CustomTagConfig {
                 "after-hours".to_string(),
             ],
             custom_collections: HashMap::new(),
+            frames: HashMap::new(),
+            tag_mapping: HashMap::new(),
+            fuzzy_threshold: 0.85,
         }
     }
 }
 
-/// API Configuration - which FREE cloud API to use
+/// Tag field a collection can be mapped to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TagTarget {
+    Comment,
+    Grouping,
+    Mood,
+    Label,
+    /// Any other frame, written as multiple values
+    Frame(FrameName),
+}
+
+impl TagTarget {
+    pub fn frame(&self) -> FrameName {
+        match self {
+            TagTarget::Comment => FrameName::new("COMM", "COMMENT", "©cmt"),
+            TagTarget::Grouping => FrameName::new("TIT1", "GROUPING", "©grp"),
+            TagTarget::Mood => FrameName::new("TMOO", "MOOD", "com.apple.iTunes:MOOD"),
+            TagTarget::Label => FrameName::new("TPUB", "PUBLISHER", "com.apple.iTunes:LABEL"),
+            TagTarget::Frame(frame) => frame.clone(),
+        }
+    }
+
+    /// Tags as the values of this field: free text fields get them joined,
+    /// single value fields only the first (most confident) one
+    pub fn values(&self, tags: Vec<String>) -> Vec<String> {
+        match self {
+            TagTarget::Comment | TagTarget::Grouping => vec![tags.join(", ")],
+            TagTarget::Mood | TagTarget::Label => tags.into_iter().take(1).collect(),
+            TagTarget::Frame(_) => tags,
+        }
+    }
+}
+
+/// ONNX genre model files
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenreModelConfig {
+    /// Path to the `.onnx` model
+    pub model: PathBuf,
+    /// Text file with one genre label per model output
+    pub labels: PathBuf,
+}
+
+/// Multipliers (0.0-1.0) applied to detection confidences by source
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SourceWeights {
+    pub rule_weight: f32,
+    pub llm_weight: f32,
+    pub onnx_weight: f32,
+    pub lyrics_weight: f32,
+}
+
+impl SourceWeights {
+    /// Weight of detection source
+    pub fn weight(&self, source: DetectionSource) -> f32 {
+        match source {
+            DetectionSource::Rule => self.rule_weight,
+            DetectionSource::Llm => self.llm_weight,
+            DetectionSource::Onnx => self.onnx_weight,
+            DetectionSource::Lyrics => self.lyrics_weight,
+        }
+    }
+}
+
+impl Default for SourceWeights {
+    fn default() -> Self {
+        Self {
+            rule_weight: 1.0,
+            llm_weight: 1.0,
+            onnx_weight: 1.0,
+            lyrics_weight: 1.0,
+        }
+    }
+}
+
+/// Weights of the overall confidence (weighted mean of tag confidences)
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ConfidenceWeights {
+    pub genre_weight: f32,
+    pub mood_weight: f32,
+    pub custom_tag_weight: f32,
+    /// Weight of the feature extraction confidence (tempo stability of measured audio), 0 to ignore
+    pub feature_weight: f32,
+}
+
+impl Default for ConfidenceWeights {
+    fn default() -> Self {
+        Self {
+            genre_weight: 2.0,
+            mood_weight: 1.0,
+            custom_tag_weight: 1.0,
+            feature_weight: 0.0,
+        }
+    }
+}
+
+/// Combining confidences of duplicate tags
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum MergeStrategy {
+    /// Independent evidence, agreeing detections boost each other
+    #[default]
+    Combine,
+    /// Highest confidence
+    Max,
+    /// Average confidence
+    Mean,
+}
+
+/// Notation of a written key
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeyNotation {
+    /// `Am`, `F#`
+    Standard,
+    /// `8A`, `2B`
+    Camelot,
+}
+
+/// Frame the key is additionally written into
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
+pub struct KeyFrame {
+    pub frame: FrameName,
+    pub notation: KeyNotation,
+}
+
+impl KeyFrame {
+    pub fn new(frame: FrameName, notation: KeyNotation) -> Self {
+        Self { frame, notation }
+    }
+
+    /// The standard key frame of each format (TKEY, INITIALKEY, initialkey)
+    pub fn key(notation: KeyNotation) -> Self {
+        Self::new(FrameName::new("TKEY", "INITIALKEY", "com.apple.iTunes:initialkey"), notation)
+    }
+}
+
+/// Values which are treated as missing when found in existing tags
+const PLACEHOLDER_VALUES: [&str; 8] = ["unknown", "none", "n/a", "na", "-", "0", "other", "untitled"];
+
+/// Guard which keeps existing genre/key/BPM unless the AI is confident enough,
+/// judged by the top genre's confidence, key profile correlation and tempo stability
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OverwriteGuard {
+    /// Enable the guard
+    pub enabled: bool,
+
+    /// Assumed confidence of pre-existing tags (0.0-1.0)
+    pub existing_trust: f32,
+
+    /// How much the AI confidence has to exceed `existing_trust` by
+    pub margin: f32,
+}
+
+impl OverwriteGuard {
+    /// Should the existing value be replaced by an AI value with given confidence
+    pub fn allows_overwrite(&self, existing: &[String], confidence: f32) -> bool {
+        if !self.enabled || existing.iter().all(|v| Self::is_placeholder(v)) {
+            return true;
+        }
+        confidence > self.existing_trust + self.margin
+    }
+
+    /// Is the value empty or a known placeholder
+    pub fn is_placeholder(value: &str) -> bool {
+        let value = value.trim().to_lowercase();
+        value.is_empty() || PLACEHOLDER_VALUES.contains(&value.as_str())
+    }
+}
+
+impl Default for OverwriteGuard {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            existing_trust: 0.9,
+            margin: 0.05,
+        }
+    }
+}
+
+/// Energy level (0-100) written into the rating frame (POPM / RATING)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct EnergyRating {
+    /// Write the rating
+    pub enabled: bool,
+
+    /// Write the raw `AI_ENERGY` frame too
+    pub keep_raw: bool,
+
+    /// Highest energy of ratings 1-4, anything above the last is 5
+    pub thresholds: [f32; 4],
+}
+
+impl EnergyRating {
+    /// Rating (1-5) of energy level
+    pub fn rating(&self, energy: f32) -> u8 {
+        1 + self.thresholds.iter().filter(|t| energy > **t).count() as u8
+    }
+}
+
+impl Default for EnergyRating {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            keep_raw: true,
+            thresholds: [20.0, 40.0, 60.0, 80.0],
+        }
+    }
+}
+
+/// API Configuration - which FREE cloud API to use
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
 pub struct APIConfig {
     /// Which API provider to use
     pub provider: APIProvider,
@@ -151,6 +707,172 @@ pub struct APIConfig {
 
     /// Rate limiting (requests per minute)
     pub rate_limit: u32,
+
+    /// Max requests in flight at once per provider (0 = unlimited)
+    pub max_concurrent_requests: usize,
+
+    /// Retry transient failures (429, 5xx)
+    pub retry: RetryConfig,
+
+    /// Model, temperature and response length
+    pub model: ModelConfig,
+
+    /// Model by provider, overrides `ModelConfig::model_name` (fallback providers included)
+    pub models: HashMap<APIProvider, String>,
+
+    /// Providers tried in order when the primary one fails
+    pub fallback_providers: Vec<FallbackProvider>,
+
+    /// Prices by model name, used to estimate cost of the used tokens
+    pub model_prices: HashMap<String, ModelPrice>,
+
+    /// Instructions sent separately from the per-track prompt (system message)
+    pub system_prompt: Option<String>,
+
+    /// Gemini safety filter threshold for all harm categories, provider default when not set
+    pub safety_threshold: Option<SafetyThreshold>,
+
+    /// Azure OpenAI deployment, used with an Azure resource as `endpoint`
+    pub azure: AzureConfig,
+
+    /// AcoustID lookups of Chromaprint fingerprints (`DuplicateDetector::identify`)
+    pub acoustid: AcoustIdConfig,
+}
+
+/// AcoustID web service settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AcoustIdConfig {
+    /// Application API key (https://acoustid.org/new-application)
+    pub api_key: Option<String>,
+    /// Lookup endpoint
+    pub endpoint: String,
+}
+
+impl Default for AcoustIdConfig {
+    fn default() -> Self {
+        Self {
+            api_key: None,
+            endpoint: "https://api.acoustid.org/v2/lookup".to_string(),
+        }
+    }
+}
+
+/// Azure OpenAI deployment settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AzureConfig {
+    /// Deployment name, `ModelConfig::model_name` is used when not set
+    pub deployment: Option<String>,
+    /// `api-version` query parameter
+    pub api_version: String,
+}
+
+impl AzureConfig {
+    /// Is the endpoint hosted by Azure OpenAI
+    pub fn is_azure_endpoint(endpoint: &str) -> bool {
+        endpoint.contains(".openai.azure.com")
+    }
+}
+
+impl Default for AzureConfig {
+    fn default() -> Self {
+        Self {
+            deployment: None,
+            api_version: "2024-06-01".to_string(),
+        }
+    }
+}
+
+/// Gemini safety filter threshold
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SafetyThreshold {
+    BlockNone,
+    BlockOnlyHigh,
+    BlockMediumAndAbove,
+    BlockLowAndAbove,
+}
+
+impl SafetyThreshold {
+    /// Gemini API value
+    pub fn gemini_value(&self) -> &'static str {
+        match self {
+            Self::BlockNone => "BLOCK_NONE",
+            Self::BlockOnlyHigh => "BLOCK_ONLY_HIGH",
+            Self::BlockMediumAndAbove => "BLOCK_MEDIUM_AND_ABOVE",
+            Self::BlockLowAndAbove => "BLOCK_LOW_AND_ABOVE",
+        }
+    }
+}
+
+/// Price of a model in USD per million tokens
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ModelPrice {
+    pub prompt: f64,
+    pub completion: f64,
+}
+
+/// Provider used when the previous ones fail
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FallbackProvider {
+    pub provider: APIProvider,
+    /// API key of this provider (the provider's environment variable is used when not set)
+    pub api_key: Option<String>,
+    /// Custom endpoint (Ollama / custom provider / Azure OpenAI resource)
+    #[serde(default)]
+    pub endpoint: Option<String>,
+}
+
+/// Environment variable with an API key for any provider
+pub const API_KEY_ENV: &str = "ONETAGGER_AI_API_KEY";
+
+/// Environment variable lookup
+pub(crate) fn process_env(name: &str) -> Option<String> {
+    std::env::var(name).ok()
+}
+
+impl APIConfig {
+    /// Is enough configured to make API calls
+    pub fn is_configured(&self) -> bool {
+        self.is_configured_with_env(process_env)
+    }
+
+    /// `is_configured` with `env` instead of the process environment
+    pub(crate) fn is_configured_with_env(&self, env: impl Fn(&str) -> Option<String>) -> bool {
+        !self.provider.requires_api_key() || self.resolve_api_key(env).is_some()
+    }
+
+    /// API key from config, falling back to the provider's environment variable and then `ONETAGGER_AI_API_KEY`
+    pub fn resolved_api_key(&self) -> Option<String> {
+        self.resolve_api_key(process_env)
+    }
+
+    /// Model of the configured provider, None for the provider's default
+    pub fn model_name(&self) -> Option<&str> {
+        self.models.get(&self.provider).or(self.model.model_name.as_ref()).map(String::as_str)
+    }
+
+    /// Config of a fallback provider, model name is provider specific so `models` or the provider's default is used
+    pub fn fallback_config(&self, fallback: &FallbackProvider) -> APIConfig {
+        let mut config = self.clone();
+        config.provider = fallback.provider;
+        config.api_key = fallback.api_key.clone();
+        config.endpoint = fallback.endpoint.clone();
+        config.model.model_name = None;
+        config.fallback_providers = vec![];
+        config
+    }
+
+    /// `resolved_api_key` with `env` instead of the process environment
+    pub(crate) fn resolve_api_key(&self, env: impl Fn(&str) -> Option<String>) -> Option<String> {
+        let non_empty = |key: String| Some(key).filter(|k| !k.trim().is_empty());
+        self.api_key.clone().and_then(non_empty)
+            .or_else(|| self.provider.api_key_env().and_then(&env).and_then(non_empty))
+            .or_else(|| env(API_KEY_ENV).and_then(non_empty))
+    }
 }
 
 impl Default for APIConfig {
@@ -162,12 +884,59 @@ impl Default for APIConfig {
             enable_cache: true,
             cache_ttl: 7 * 24 * 60 * 60,  // 7 days
             rate_limit: 15,  // Gemini free tier: 15 RPM
+            max_concurrent_requests: 4,
+            retry: RetryConfig::default(),
+            model: ModelConfig::default(),
+            models: HashMap::new(),
+            fallback_providers: Vec::new(),
+            model_prices: HashMap::new(),
+            system_prompt: None,
+            safety_threshold: None,
+            azure: AzureConfig::default(),
+            acoustid: AcoustIdConfig::default(),
+        }
+    }
+}
+
+/// Exponential backoff configuration for transient API failures
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RetryConfig {
+    /// Max retries after the initial attempt
+    pub max_retries: u32,
+
+    /// Delay before the first retry, doubled for each next one
+    pub base_delay_ms: u64,
+
+    /// Upper bound for the backoff delay, longer `Retry-After` requests aren't retried
+    pub max_delay_ms: u64,
+}
+
+impl RetryConfig {
+    /// Delay before retry number `attempt` (0 based), capped at `max_delay_ms`.
+    /// `Retry-After` takes precedence and is honored in full, None (don't retry) if it's longer than `max_delay_ms`
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        let max_delay = Duration::from_millis(self.max_delay_ms);
+        if let Some(retry_after) = retry_after {
+            return (retry_after <= max_delay).then_some(retry_after);
+        }
+        let ms = self.base_delay_ms.saturating_mul(1u64 << attempt.min(16));
+        Some(Duration::from_millis(ms).min(max_delay))
+    }
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay_ms: 1000,
+            max_delay_ms: 30_000,
         }
     }
 }
 
 /// Available FREE API providers
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "kebab-case")]
 pub enum APIProvider {
     /// Google Gemini (RECOMMENDED)
@@ -180,6 +949,10 @@ pub enum APIProvider {
     TogetherAI,
     /// OpenAI (requires paid API key)
     OpenAI,
+    /// Azure OpenAI deployment (enterprise)
+    AzureOpenAI,
+    /// Ollama local server (offline, no API key)
+    Ollama,
     /// Custom endpoint
     Custom,
 }
@@ -193,6 +966,9 @@ impl APIProvider {
             Self::Groq => "https://api.groq.com/openai/v1/chat/completions",
             Self::TogetherAI => "https://api.together.xyz/v1/chat/completions",
             Self::OpenAI => "https://api.openai.com/v1/chat/completions",
+            // Resource specific (https://<resource>.openai.azure.com)
+            Self::AzureOpenAI => "",
+            Self::Ollama => "http://localhost:11434/api/generate",
             Self::Custom => "",
         }
     }
@@ -205,6 +981,8 @@ impl APIProvider {
             Self::Groq => "Groq (Ultra-Fast, FREE)",
             Self::TogetherAI => "Together AI (FREE Credits)",
             Self::OpenAI => "OpenAI (Paid)",
+            Self::AzureOpenAI => "Azure OpenAI (Paid)",
+            Self::Ollama => "Ollama (Local, Offline)",
             Self::Custom => "Custom Endpoint",
         }
     }
@@ -217,6 +995,8 @@ impl APIProvider {
             Self::Groq => "30 RPM, 14,400/day, free",
             Self::TogetherAI => "$25 free credits/month",
             Self::OpenAI => "Requires paid API key",
+            Self::AzureOpenAI => "Depends on your Azure deployment",
+            Self::Ollama => "Unlimited, runs on your machine",
             Self::Custom => "Depends on provider",
         }
     }
@@ -226,15 +1006,28 @@ impl APIProvider {
         matches!(self, Self::Gemini)
     }
 
+    /// Environment variable the API key is read from when not configured
+    pub fn api_key_env(&self) -> Option<&'static str> {
+        match self {
+            Self::Gemini => Some("GEMINI_API_KEY"),
+            Self::OpenRouter => Some("OPENROUTER_API_KEY"),
+            Self::Groq => Some("GROQ_API_KEY"),
+            Self::TogetherAI => Some("TOGETHER_API_KEY"),
+            Self::OpenAI => Some("OPENAI_API_KEY"),
+            Self::AzureOpenAI => Some("AZURE_OPENAI_API_KEY"),
+            Self::Ollama | Self::Custom => None,
+        }
+    }
+
     /// Does this provider require an API key?
     pub fn requires_api_key(&self) -> bool {
-        !matches!(self, Self::Custom)
+        !matches!(self, Self::Custom | Self::Ollama)
     }
 }
 
 /// Model configuration (simplified for API-based approach)
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", default)]
 pub struct ModelConfig {
     /// Specific model name (optional)
     pub model_name: Option<String>,
@@ -258,7 +1051,7 @@ impl Default for ModelConfig {
 
 /// Playlist generation configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", default)]
 pub struct PlaylistConfig {
     /// Desired playlist duration in minutes
     pub duration_minutes: usize,
@@ -286,6 +1079,14 @@ pub struct PlaylistConfig {
 
     /// Max BPM difference between consecutive tracks
     pub max_bpm_difference: f32,
+
+    /// Minimum number of tracks between two tracks of the same artist
+    #[serde(default = "default_min_artist_gap")]
+    pub min_artist_gap: usize,
+}
+
+fn default_min_artist_gap() -> usize {
+    3
 }
 
 impl Default for PlaylistConfig {
@@ -300,6 +1101,7 @@ impl Default for PlaylistConfig {
             genre_consistency: 0.7,
             allow_bpm_changes: true,
             max_bpm_difference: 10.0,
+            min_artist_gap: default_min_artist_gap(),
         }
     }
 }
@@ -332,6 +1134,55 @@ mod tests {
         assert!(config.enable_genre_classification);
     }
 
+    #[test]
+    fn test_presets() {
+        let config = AIConfig::preset(Preset::FastScan);
+        assert!(!config.llm_enabled);
+        assert_eq!(config.analysis_window_secs, Some(30.0));
+        assert!(crate::llm_client(&config).unwrap().is_none());
+        // No API key needed
+        assert!(config.validate().is_ok());
+
+        let config = AIConfig::preset(Preset::TechnoDj);
+        assert!(config.llm_enabled);
+        assert!(config.custom_tags.genres.contains(&"peak-time-techno".to_string()));
+        assert_eq!(config.genre_threshold(), 0.8);
+        assert!(config.energy_rating.enabled);
+
+        let config = AIConfig::preset(Preset::OpenFormat);
+        assert!(config.custom_tags.genres.contains(&"hip-hop".to_string()));
+        assert_eq!(config.mood_threshold(), 0.6);
+        assert!(config.lyrics_themes);
+    }
+
+    #[test]
+    fn test_partial_config() {
+        let config: AIConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config.max_tags_per_track, AIConfig::default().max_tags_per_track);
+        serde_json::from_str::<APIConfig>("{}").unwrap();
+        serde_json::from_str::<CustomTagConfig>("{}").unwrap();
+        serde_json::from_str::<PlaylistConfig>("{}").unwrap();
+
+        // Config saved before the newest fields existed
+        let config: AIConfig = serde_json::from_str(r#"{
+            "enabled": false,
+            "confidenceThreshold": 0.8,
+            "apiConfig": { "provider": "groq", "apiKey": "key", "rateLimit": 10 },
+            "customTags": { "genres": ["techno"] },
+            "sourceWeights": { "ruleWeight": 0.5 }
+        }"#).unwrap();
+        assert!(!config.enabled);
+        assert_eq!(config.confidence_threshold, 0.8);
+        assert_eq!(config.api_config.provider, APIProvider::Groq);
+        assert_eq!(config.api_config.retry.max_retries, RetryConfig::default().max_retries);
+        assert!(config.api_config.fallback_providers.is_empty());
+        assert!(config.api_config.system_prompt.is_none());
+        assert_eq!(config.custom_tags.genres, vec!["techno".to_string()]);
+        assert!(config.custom_tags.tag_mapping.is_empty());
+        assert_eq!((config.source_weights.rule_weight, config.source_weights.lyrics_weight), (0.5, 1.0));
+        assert!(!config.lyrics_themes);
+    }
+
     #[test]
     fn test_api_providers() {
         let gemini = APIProvider::Gemini;
@@ -339,6 +1190,10 @@ mod tests {
         assert!(gemini.requires_api_key());
         assert!(gemini.default_endpoint().contains("googleapis.com"));
         assert_eq!(gemini.display_name(), "Google Gemini 2.0 Flash (FREE)");
+
+        let ollama = APIProvider::Ollama;
+        assert!(!ollama.requires_api_key());
+        assert_eq!(ollama.default_endpoint(), "http://localhost:11434/api/generate");
     }
 
     #[test]
@@ -348,4 +1203,131 @@ mod tests {
         assert!(!custom.moods.is_empty());
         assert!(custom.genres.contains(&"deep-techno".to_string()));
     }
+
+    #[test]
+    fn test_collection_for() {
+        let mut custom = CustomTagConfig::default();
+        custom.custom_collections.insert("venues".to_string(), vec!["Rooftop".to_string()]);
+        assert_eq!(custom.collection_for("Deep-Techno"), Some("genres"));
+        assert_eq!(custom.collection_for("dark"), Some("moods"));
+        assert_eq!(custom.collection_for("rooftop"), Some("venues"));
+        assert_eq!(custom.collection_for("polka"), None);
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let retry = RetryConfig { max_retries: 5, base_delay_ms: 100, max_delay_ms: 1000 };
+        assert_eq!(retry.delay(0, None), Some(Duration::from_millis(100)));
+        assert_eq!(retry.delay(2, None), Some(Duration::from_millis(400)));
+        assert_eq!(retry.delay(10, None), Some(Duration::from_millis(1000)));
+        assert_eq!(retry.delay(0, Some(Duration::from_millis(300))), Some(Duration::from_millis(300)));
+        // Server wants a longer break than allowed
+        assert_eq!(retry.delay(0, Some(Duration::from_secs(3600))), None);
+    }
+
+    #[test]
+    fn test_overwrite_guard() {
+        assert!(OverwriteGuard::default().allows_overwrite(&["Techno".to_string()], 0.1));
+        let guard = OverwriteGuard { enabled: true, ..Default::default() };
+        assert!(guard.allows_overwrite(&[], 0.1));
+        assert!(guard.allows_overwrite(&[" Unknown ".to_string()], 0.1));
+        assert!(!guard.allows_overwrite(&["Techno".to_string()], 0.9));
+        assert!(guard.allows_overwrite(&["Techno".to_string()], 0.99));
+    }
+
+    #[test]
+    fn test_energy_rating() {
+        let rating = EnergyRating::default();
+        assert_eq!(rating.rating(10.0), 1);
+        assert_eq!(rating.rating(50.0), 3);
+        assert_eq!(rating.rating(95.0), 5);
+        let rating = EnergyRating { thresholds: [60.0, 70.0, 80.0, 90.0], ..Default::default() };
+        assert_eq!(rating.rating(50.0), 1);
+    }
+
+    #[test]
+    fn test_config_load_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = AIConfig::default();
+        config.confidence_threshold = 0.55;
+        config.api_config.provider = APIProvider::Groq;
+        config.api_config.api_key = Some("key".to_string());
+        config.custom_tags.genres = vec!["Techouse".to_string()];
+        config.custom_tags.frames.insert("vibes".to_string(), FrameName::same("VIBE"));
+        config.custom_tags.tag_mapping.insert("moods".to_string(), TagTarget::Comment);
+        config.key_frames = vec![KeyFrame::key(KeyNotation::Camelot)];
+        config.analysis_window_secs = Some(60.0);
+
+        for file in ["ai.json", "ai.toml"] {
+            let path = dir.path().join(file);
+            config.save(&path).unwrap();
+            let loaded = AIConfig::load(&path).unwrap();
+            assert_eq!(serde_json::to_value(&loaded).unwrap(), serde_json::to_value(&config).unwrap());
+        }
+
+        // Old / partial files get defaults
+        let path = dir.path().join("partial.json");
+        std::fs::write(&path, r#"{"confidenceThreshold": 0.9, "apiConfig": {"provider": "ollama"}}"#).unwrap();
+        let loaded = AIConfig::load(&path).unwrap();
+        assert_eq!(loaded.confidence_threshold, 0.9);
+        assert_eq!(loaded.api_config.provider, APIProvider::Ollama);
+        assert_eq!(loaded.api_config.rate_limit, 15);
+        assert_eq!(loaded.max_tags_per_track, 5);
+    }
+
+    #[test]
+    fn test_api_key_env_fallback() {
+        let env = |vars: &'static [(&'static str, &'static str)]| move |name: &str| {
+            vars.iter().find(|(n, _)| *n == name).map(|(_, v)| v.to_string())
+        };
+        let mut config = APIConfig { provider: APIProvider::Groq, ..Default::default() };
+        assert_eq!(config.resolve_api_key(env(&[])), None);
+        assert_eq!(config.resolve_api_key(env(&[(API_KEY_ENV, "generic")])).as_deref(), Some("generic"));
+        assert_eq!(config.resolve_api_key(env(&[(API_KEY_ENV, "generic"), ("GROQ_API_KEY", "groq")])).as_deref(), Some("groq"));
+        // Other provider's variable is ignored
+        assert_eq!(config.resolve_api_key(env(&[("OPENAI_API_KEY", "openai")])), None);
+
+        // Explicit config wins
+        config.api_key = Some("config".to_string());
+        assert_eq!(config.resolve_api_key(env(&[(API_KEY_ENV, "generic"), ("GROQ_API_KEY", "groq")])).as_deref(), Some("config"));
+
+        let config = APIConfig { provider: APIProvider::TogetherAI, ..Default::default() };
+        assert!(!config.is_configured_with_env(env(&[])));
+        assert!(config.is_configured_with_env(env(&[("TOGETHER_API_KEY", "together")])));
+    }
+
+    #[test]
+    fn test_validate() {
+        // Independent of keys in the environment
+        let no_env = |_: &str| None::<String>;
+        let mut config = AIConfig::default();
+        config.api_config.api_key = Some("key".to_string());
+        assert!(config.validate_with_env(no_env).is_ok());
+
+        config.confidence_threshold = 5.0;
+        config.batch_size = 0;
+        assert_eq!(config.validate_with_env(no_env).unwrap_err(), vec![
+            ConfigError::OutOfRange { field: "confidenceThreshold", value: 5.0 },
+            ConfigError::Zero { field: "batchSize" },
+        ]);
+
+        let mut config = AIConfig::default();
+        config.api_config.provider = APIProvider::OpenRouter;
+        config.api_config.api_key = None;
+        config.max_threads = 0;
+        config.quality_strictness = -0.1;
+        let errors = config.validate_with_env(no_env).unwrap_err();
+        assert_eq!(errors.len(), 3);
+        assert_eq!(errors[2], ConfigError::MissingApiKey(APIProvider::OpenRouter));
+        assert!(errors[2].to_string().contains("OPENROUTER_API_KEY"));
+
+        // Disabled or local provider doesn't need a key
+        config.max_threads = 1;
+        config.quality_strictness = 0.5;
+        config.enabled = false;
+        assert!(config.validate_with_env(no_env).is_ok());
+        config.enabled = true;
+        config.api_config.provider = APIProvider::Ollama;
+        assert!(config.validate_with_env(no_env).is_ok());
+    }
 }
@@ -0,0 +1,671 @@
+//! DSP Module
+//!
+//! STFT and spectral feature computations used by the feature extractor
+
+use std::f32::consts::PI;
+
+/// STFT frame size
+pub const FFT_SIZE: usize = 2048;
+/// STFT hop size
+pub const HOP_SIZE: usize = 512;
+/// Number of mel filters used for MFCCs
+const MEL_BANDS: usize = 40;
+/// Number of MFCC coefficients
+pub const MFCC_COUNT: usize = 13;
+/// Spectral rolloff energy ratio
+const ROLLOFF_RATIO: f32 = 0.85;
+/// Onsets are onset envelope peaks this many standard deviations above its mean
+const ONSET_THRESHOLD_STD: f32 = 1.5;
+/// Minimum time between two onsets (seconds)
+const MIN_ONSET_GAP_SECS: f32 = 0.1;
+/// Minimum inter-onset intervals needed to estimate tempo stability
+const MIN_ONSET_INTERVALS: usize = 3;
+/// Tempo range searched by beat tracking (BPM)
+const TEMPO_RANGE: std::ops::RangeInclusive<f32> = 60.0..=200.0;
+/// Center of the log-Gaussian tempo prior (BPM)
+const TEMPO_PRIOR_BPM: f32 = 120.0;
+/// Minimum normalized autocorrelation of the onset envelope at the beat period, weaker means no stable beat
+const MIN_BEAT_PERIODICITY: f32 = 0.3;
+/// Penalty of beat intervals deviating from the period in dynamic programming beat tracking
+const BEAT_TIGHTNESS: f32 = 100.0;
+/// Median filter length (frames / bins) of harmonic-percussive separation
+const HPSS_KERNEL: usize = 17;
+/// Vocal formant band (Hz)
+const VOCAL_BAND: (f32, f32) = (300.0, 3400.0);
+/// Minimum share of frame energy in the vocal band for a voiced frame
+const VOCAL_BAND_RATIO: f32 = 0.5;
+/// Maximum spectral flatness of the vocal band for a voiced frame (harmonic rather than noise)
+const VOCAL_MAX_FLATNESS: f32 = 0.3;
+/// Relative frame to frame change of the vocal band amplitude considered fully syllabic
+const VOCAL_MODULATION: f32 = 0.05;
+
+/// EBU R128 gating block length (seconds), blocks overlap by 75%
+const LOUDNESS_BLOCK_SECS: f64 = 0.4;
+/// EBU R128 absolute gate (LUFS)
+const LOUDNESS_ABSOLUTE_GATE: f64 = -70.0;
+/// EBU R128 relative gate (LU below the absolutely gated loudness)
+const LOUDNESS_RELATIVE_GATE: f64 = 10.0;
+
+/// Magnitude spectrogram
+#[derive(Debug, Clone)]
+pub struct Spectrogram {
+    /// Magnitudes per frame (`fft_size / 2 + 1` bins)
+    pub frames: Vec<Vec<f32>>,
+    pub sample_rate: u32,
+    pub fft_size: usize,
+}
+
+impl Spectrogram {
+    /// Hann windowed STFT of mono samples
+    pub fn new(samples: &[f32], sample_rate: u32, fft_size: usize, hop_size: usize) -> Spectrogram {
+        assert!(fft_size.is_power_of_two(), "FFT size must be a power of two");
+        let window = hann(fft_size);
+        let mut frames = Vec::new();
+        let mut start = 0;
+        while start + fft_size <= samples.len() {
+            let mut re: Vec<f32> = samples[start..start + fft_size].iter().zip(&window).map(|(s, w)| s * w).collect();
+            let mut im = vec![0.0; fft_size];
+            fft(&mut re, &mut im);
+            frames.push((0..=fft_size / 2).map(|i| (re[i] * re[i] + im[i] * im[i]).sqrt()).collect());
+            start += hop_size;
+        }
+        Spectrogram { frames, sample_rate, fft_size }
+    }
+
+    /// Center frequency of bin
+    pub fn bin_frequency(&self, bin: usize) -> f32 {
+        bin as f32 * self.sample_rate as f32 / self.fft_size as f32
+    }
+
+    /// Mean spectral centroid (Hz) over non-silent frames
+    pub fn centroid(&self) -> f32 {
+        mean(self.frames.iter().filter_map(|frame| {
+            let total: f32 = frame.iter().sum();
+            if total <= 0.0 {
+                return None;
+            }
+            Some(frame.iter().enumerate().map(|(i, m)| self.bin_frequency(i) * m).sum::<f32>() / total)
+        }))
+    }
+
+    /// Mean frequency (Hz) below which 85% of the spectral energy lies
+    pub fn rolloff(&self) -> f32 {
+        mean(self.frames.iter().filter_map(|frame| {
+            let total: f32 = frame.iter().map(|m| m * m).sum();
+            if total <= 0.0 {
+                return None;
+            }
+            let mut energy = 0.0;
+            let bin = frame.iter().position(|m| {
+                energy += m * m;
+                energy >= total * ROLLOFF_RATIO
+            })?;
+            Some(self.bin_frequency(bin))
+        }))
+    }
+
+    /// Mean positive magnitude change between frames, relative to frame magnitude (0-1)
+    pub fn flux(&self) -> f32 {
+        mean(self.frames.windows(2).filter_map(|pair| {
+            let total: f32 = pair[1].iter().sum();
+            if total <= 0.0 {
+                return None;
+            }
+            Some(pair[1].iter().zip(&pair[0]).map(|(b, a)| (b - a).max(0.0)).sum::<f32>() / total)
+        }))
+    }
+
+    /// Positive spectral flux per frame relative to the louder of the two frames (0-1), 0 for silence.
+    /// First value belongs to the second frame
+    pub fn onset_envelope(&self) -> Vec<f32> {
+        self.frames.windows(2).map(|pair| {
+            let total: f32 = pair[1].iter().zip(&pair[0]).map(|(b, a)| b.max(*a)).sum();
+            if total <= 0.0 {
+                return 0.0;
+            }
+            pair[1].iter().zip(&pair[0]).map(|(b, a)| (b - a).max(0.0)).sum::<f32>() / total
+        }).collect()
+    }
+
+    /// Share of non-silent frames (0-1) where a harmonic (low spectral flatness) vocal formant band carries most
+    /// of the energy, scaled down for steady tones as voices keep changing from syllable to syllable
+    pub fn vocal_presence(&self) -> f32 {
+        let bins = self.fft_size / 2 + 1;
+        let low = (VOCAL_BAND.0 * self.fft_size as f32 / self.sample_rate as f32).ceil() as usize;
+        let high = ((VOCAL_BAND.1 * self.fft_size as f32 / self.sample_rate as f32) as usize).min(bins - 1);
+        if low >= high {
+            return 0.0;
+        }
+
+        // (voiced, vocal band amplitude) of each non-silent frame
+        let frames: Vec<(bool, f32)> = self.frames.iter().filter_map(|frame| {
+            let total: f32 = frame.iter().map(|m| m * m).sum();
+            if total <= 0.0 {
+                return None;
+            }
+            let band = &frame[low..=high];
+            let energy: f32 = band.iter().map(|m| m * m).sum();
+            let log_mean = band.iter().map(|m| (m * m + 1e-12).ln()).sum::<f32>() / band.len() as f32;
+            let flatness = log_mean.exp() / (energy / band.len() as f32).max(1e-12);
+            Some((energy / total > VOCAL_BAND_RATIO && flatness < VOCAL_MAX_FLATNESS, energy.sqrt()))
+        }).collect();
+        if frames.is_empty() {
+            return 0.0;
+        }
+        let voiced = frames.iter().filter(|(v, _)| *v).count() as f32 / frames.len() as f32;
+
+        let pairs: Vec<(f32, f32)> = frames.windows(2).filter(|p| p[0].0 && p[1].0).map(|p| (p[0].1, p[1].1)).collect();
+        let level = mean(pairs.iter().map(|(_, b)| *b));
+        if level <= 0.0 {
+            return 0.0;
+        }
+        let modulation = mean(pairs.iter().map(|(a, b)| (b - a).abs())) / level;
+        voiced * (modulation / VOCAL_MODULATION).min(1.0)
+    }
+
+    /// Mean MFCCs (mel filterbank + log + DCT-II)
+    pub fn mfccs(&self) -> Vec<f32> {
+        let filterbank = mel_filterbank(MEL_BANDS, self.fft_size, self.sample_rate);
+        let mut result = vec![0.0; MFCC_COUNT];
+        if self.frames.is_empty() {
+            return result;
+        }
+
+        for frame in &self.frames {
+            let log_energies: Vec<f32> = filterbank.iter()
+                .map(|filter| (filter.iter().zip(frame).map(|(w, m)| w * m * m).sum::<f32>() + 1e-10).ln())
+                .collect();
+            for (k, coefficient) in result.iter_mut().enumerate() {
+                *coefficient += log_energies.iter().enumerate()
+                    .map(|(n, e)| e * (PI * k as f32 * (n as f32 + 0.5) / MEL_BANDS as f32).cos())
+                    .sum::<f32>();
+            }
+        }
+        result.iter().map(|c| c / self.frames.len() as f32).collect()
+    }
+
+    /// Chroma vector folding spectral energy into 12 pitch classes (C = 0), normalized to max 1.0
+    pub fn chroma(&self) -> Vec<f32> {
+        let mut chroma = vec![0.0f32; 12];
+        for frame in self.frame_chroma() {
+            chroma.iter_mut().zip(frame).for_each(|(c, f)| *c += f);
+        }
+        let max = chroma.iter().cloned().fold(0.0, f32::max);
+        if max > 0.0 {
+            chroma.iter_mut().for_each(|c| *c /= max);
+        }
+        chroma
+    }
+
+    /// Unnormalized chroma energy of each frame
+    pub fn frame_chroma(&self) -> Vec<[f32; 12]> {
+        self.frames.iter().map(|frame| {
+            let mut chroma = [0.0f32; 12];
+            for (bin, magnitude) in frame.iter().enumerate() {
+                let frequency = self.bin_frequency(bin);
+                // A0 - ~C8
+                if !(27.5..=4200.0).contains(&frequency) {
+                    continue;
+                }
+                let midi = (69.0 + 12.0 * (frequency / 440.0).log2()).round() as i32;
+                chroma[midi.rem_euclid(12) as usize] += magnitude * magnitude;
+            }
+            chroma
+        }).collect()
+    }
+}
+
+/// Harmonic-percussive source separation by median filtering (Fitzgerald 2010), returns (harmonic, percussive).
+/// Magnitudes smoothed along time keep sustained tones, along frequency keep transients; soft masks of the two
+/// are applied to the STFT and resynthesized by overlap-add. Samples after the last full frame are silent
+pub fn hpss(samples: &[f32]) -> (Vec<f32>, Vec<f32>) {
+    let window = hann(FFT_SIZE);
+    let bins = FFT_SIZE / 2 + 1;
+    let mut spectra = Vec::new();
+    let mut start = 0;
+    while start + FFT_SIZE <= samples.len() {
+        let mut re: Vec<f32> = samples[start..start + FFT_SIZE].iter().zip(&window).map(|(s, w)| s * w).collect();
+        let mut im = vec![0.0; FFT_SIZE];
+        fft(&mut re, &mut im);
+        spectra.push((re, im));
+        start += HOP_SIZE;
+    }
+    let magnitudes: Vec<Vec<f32>> = spectra.iter()
+        .map(|(re, im)| (0..bins).map(|i| (re[i] * re[i] + im[i] * im[i]).sqrt()).collect())
+        .collect();
+
+    let half = HPSS_KERNEL / 2;
+    let mut buffer = Vec::with_capacity(HPSS_KERNEL);
+    let mut median = |values: &mut dyn Iterator<Item = f32>| {
+        buffer.clear();
+        buffer.extend(values);
+        let middle = buffer.len() / 2;
+        *buffer.select_nth_unstable_by(middle, f32::total_cmp).1
+    };
+
+    let mut harmonic = vec![0.0; samples.len()];
+    let mut percussive = vec![0.0; samples.len()];
+    let mut norm = vec![0.0; samples.len()];
+    for (frame, (re, im)) in spectra.iter().enumerate() {
+        let frames = frame.saturating_sub(half)..(frame + half + 1).min(magnitudes.len());
+        let mask: Vec<f32> = (0..bins).map(|bin| {
+            let h = median(&mut magnitudes[frames.clone()].iter().map(|m| m[bin]));
+            let p = median(&mut magnitudes[frame][bin.saturating_sub(half)..(bin + half + 1).min(bins)].iter().copied());
+            match h * h + p * p {
+                total if total > 0.0 => h * h / total,
+                _ => 0.5,
+            }
+        }).collect();
+
+        // Mirror the mask onto negative frequencies, inverse FFT = conjugated forward FFT
+        let offset = frame * HOP_SIZE;
+        let inverse: Vec<f32> = mask.iter().map(|m| 1.0 - m).collect();
+        for (mask, output) in [(&mask, &mut harmonic), (&inverse, &mut percussive)] {
+            let scale = |i: usize| mask[if i < bins { i } else { FFT_SIZE - i }];
+            let mut re: Vec<f32> = re.iter().enumerate().map(|(i, v)| v * scale(i)).collect();
+            let mut im: Vec<f32> = im.iter().enumerate().map(|(i, v)| -v * scale(i)).collect();
+            fft(&mut re, &mut im);
+            for (i, (value, w)) in re.iter().zip(&window).enumerate() {
+                output[offset + i] += value / FFT_SIZE as f32 * w;
+            }
+        }
+        for (i, w) in window.iter().enumerate() {
+            norm[offset + i] += w * w;
+        }
+    }
+
+    for (i, n) in norm.iter().enumerate() {
+        if *n > 1e-3 {
+            harmonic[i] /= n;
+            percussive[i] /= n;
+        }
+    }
+    (harmonic, percussive)
+}
+
+/// Zero crossings per sample
+pub fn zero_crossing_rate(samples: &[f32]) -> f32 {
+    if samples.len() < 2 {
+        return 0.0;
+    }
+    let crossings = samples.windows(2).filter(|w| (w[0] >= 0.0) != (w[1] >= 0.0)).count();
+    crossings as f32 / (samples.len() - 1) as f32
+}
+
+/// Root mean square of samples
+pub fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    (samples.iter().map(|s| (*s as f64).powi(2)).sum::<f64>() / samples.len() as f64).sqrt() as f32
+}
+
+/// RMS of consecutive non-overlapping frames, the last frame may be shorter
+pub fn frame_rms(samples: &[f32], frame_size: usize) -> Vec<f32> {
+    samples.chunks(frame_size.max(1)).map(rms).collect()
+}
+
+/// Linear interpolation resampling
+pub fn resample(samples: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to || samples.is_empty() {
+        return samples.to_vec();
+    }
+    let ratio = from as f64 / to as f64;
+    let len = (samples.len() as f64 / ratio).round() as usize;
+    (0..len).map(|i| {
+        let position = i as f64 * ratio;
+        let index = position as usize;
+        let fraction = (position - index as f64) as f32;
+        let a = samples[index.min(samples.len() - 1)];
+        let b = samples[(index + 1).min(samples.len() - 1)];
+        a + (b - a) * fraction
+    }).collect()
+}
+
+/// Hann window
+fn hann(size: usize) -> Vec<f32> {
+    (0..size).map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / size as f32).cos()).collect()
+}
+
+/// In-place iterative radix-2 FFT
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    // Bit reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (w_im, w_re) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Triangular mel filters over FFT bins
+fn mel_filterbank(bands: usize, fft_size: usize, sample_rate: u32) -> Vec<Vec<f32>> {
+    let to_mel = |f: f32| 2595.0 * (1.0 + f / 700.0).log10();
+    let to_hz = |m: f32| 700.0 * (10f32.powf(m / 2595.0) - 1.0);
+    let max_mel = to_mel(sample_rate as f32 / 2.0);
+    let bins = fft_size / 2 + 1;
+    // Filter edges in (fractional) bins
+    let edges: Vec<f32> = (0..bands + 2)
+        .map(|i| to_hz(max_mel * i as f32 / (bands + 1) as f32) * fft_size as f32 / sample_rate as f32)
+        .collect();
+
+    (0..bands).map(|b| {
+        let (left, center, right) = (edges[b], edges[b + 1], edges[b + 2]);
+        (0..bins).map(|i| {
+            let i = i as f32;
+            if i <= left || i >= right {
+                0.0
+            } else if i <= center {
+                (i - left) / (center - left)
+            } else {
+                (right - i) / (right - center)
+            }
+        }).collect()
+    }).collect()
+}
+
+/// Onset times (seconds) picked as peaks of the onset envelope, `frame_secs` is the hop length
+pub fn onset_times(envelope: &[f32], frame_secs: f32) -> Vec<f32> {
+    let mean_value = mean(envelope.iter().copied());
+    let std = mean(envelope.iter().map(|e| (e - mean_value).powi(2))).sqrt();
+    let threshold = mean_value + ONSET_THRESHOLD_STD * std;
+    let mut onsets: Vec<f32> = vec![];
+    for i in 0..envelope.len() {
+        let previous = i.checked_sub(1).map(|p| envelope[p]).unwrap_or(0.0);
+        let next = envelope.get(i + 1).copied().unwrap_or(0.0);
+        if envelope[i] <= threshold || envelope[i] < previous || envelope[i] < next {
+            continue;
+        }
+        let time = i as f32 * frame_secs;
+        if onsets.last().map(|last| time - last >= MIN_ONSET_GAP_SECS).unwrap_or(true) {
+            onsets.push(time);
+        }
+    }
+    onsets
+}
+
+/// 1 - normalized variance (variance / mean²) of inter-onset intervals (0-1), 0 without enough onsets
+pub fn tempo_stability(onsets: &[f32]) -> f32 {
+    let intervals: Vec<f32> = onsets.windows(2).map(|w| w[1] - w[0]).collect();
+    if intervals.len() < MIN_ONSET_INTERVALS {
+        return 0.0;
+    }
+    let mean_interval = mean(intervals.iter().copied());
+    let variance = mean(intervals.iter().map(|i| (i - mean_interval).powi(2)));
+    (1.0 - variance / (mean_interval * mean_interval)).clamp(0.0, 1.0)
+}
+
+/// Beat period (frames) of the onset envelope from its autocorrelation weighted by a tempo prior,
+/// None if the envelope isn't periodic enough
+pub fn beat_period(envelope: &[f32], frame_secs: f32) -> Option<usize> {
+    let mean_value = mean(envelope.iter().copied());
+    let centered: Vec<f32> = envelope.iter().map(|e| e - mean_value).collect();
+    let energy: f32 = centered.iter().map(|e| e * e).sum();
+    if energy <= 0.0 {
+        return None;
+    }
+
+    let min_lag = (60.0 / TEMPO_RANGE.end() / frame_secs).floor().max(1.0) as usize;
+    let max_lag = ((60.0 / TEMPO_RANGE.start() / frame_secs).ceil() as usize).min(centered.len() / 2);
+    let (lag, periodicity, _) = (min_lag..=max_lag)
+        .map(|lag| {
+            let periodicity = centered.iter().zip(&centered[lag..]).map(|(a, b)| a * b).sum::<f32>() / energy;
+            let bpm = 60.0 / (lag as f32 * frame_secs);
+            let prior = (-0.5 * (bpm / TEMPO_PRIOR_BPM).log2().powi(2)).exp();
+            (lag, periodicity, periodicity * prior)
+        })
+        .max_by(|a, b| a.2.total_cmp(&b.2))?;
+    (periodicity >= MIN_BEAT_PERIODICITY).then_some(lag)
+}
+
+/// Beat times (seconds) by dynamic programming beat tracking (Ellis 2007) over the onset envelope.
+/// `bpm` (e.g. from tags) is used instead of the estimated tempo when given. Empty without a stable beat
+pub fn track_beats(envelope: &[f32], frame_secs: f32, bpm: Option<f32>) -> Vec<f32> {
+    let Some(estimated) = beat_period(envelope, frame_secs) else { return vec![] };
+    let period = bpm.filter(|b| TEMPO_RANGE.contains(b))
+        .map(|b| 60.0 / b / frame_secs)
+        .unwrap_or(estimated as f32);
+
+    // Normalized onset envelope is the local score
+    let mean_value = mean(envelope.iter().copied());
+    let std = mean(envelope.iter().map(|e| (e - mean_value).powi(2))).sqrt().max(f32::EPSILON);
+    let mut score: Vec<f32> = envelope.iter().map(|e| e / std).collect();
+    let mut backlink: Vec<Option<usize>> = vec![None; envelope.len()];
+    let (min_gap, max_gap) = ((period / 2.0).round().max(1.0) as usize, (period * 2.0).round() as usize);
+    for t in min_gap..score.len() {
+        let best = (t.saturating_sub(max_gap)..=t - min_gap)
+            .map(|previous| (previous, score[previous] - BEAT_TIGHTNESS * ((t - previous) as f32 / period).ln().powi(2)))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        // Only extend chains worth following, otherwise a new one starts here (no beats before the first onset)
+        if let Some((previous, value)) = best.filter(|(_, value)| *value > 0.0) {
+            score[t] += value;
+            backlink[t] = Some(previous);
+        }
+    }
+
+    // Backtrack from the best beat within the last period
+    let start = score.len().saturating_sub(period.round() as usize);
+    let Some(mut beat) = (start..score.len()).max_by(|a, b| score[*a].total_cmp(&score[*b])) else { return vec![] };
+    let mut beats = vec![beat as f32 * frame_secs];
+    while let Some(previous) = backlink[beat] {
+        beat = previous;
+        beats.push(beat as f32 * frame_secs);
+    }
+    beats.reverse();
+    beats
+}
+
+/// Integrated loudness (LUFS) of mono samples per ITU BS.1770 / EBU R128: K-weighting, 400ms blocks,
+/// absolute and relative gating. None if shorter than one block or silent
+pub fn integrated_loudness(samples: &[f32], sample_rate: u32) -> Option<f32> {
+    let weighted = k_weighting(samples, sample_rate as f64);
+    let block = (LOUDNESS_BLOCK_SECS * sample_rate as f64) as usize;
+    let step = (block / 4).max(1);
+    if block == 0 || weighted.len() < block {
+        return None;
+    }
+
+    // Mean square of each block, from a running sum
+    let mut cumulative = Vec::with_capacity(weighted.len() + 1);
+    cumulative.push(0.0f64);
+    for sample in &weighted {
+        cumulative.push(cumulative.last().unwrap() + sample * sample);
+    }
+    let blocks: Vec<f64> = (0..=weighted.len() - block).step_by(step)
+        .map(|start| (cumulative[start + block] - cumulative[start]) / block as f64)
+        .collect();
+
+    let loudness = |power: f64| -0.691 + 10.0 * power.log10();
+    let gated_mean = |threshold: f64| {
+        let gated: Vec<f64> = blocks.iter().copied().filter(|p| *p > 0.0 && loudness(*p) > threshold).collect();
+        (!gated.is_empty()).then(|| gated.iter().sum::<f64>() / gated.len() as f64)
+    };
+    let relative_gate = loudness(gated_mean(LOUDNESS_ABSOLUTE_GATE)?) - LOUDNESS_RELATIVE_GATE;
+    Some(loudness(gated_mean(relative_gate.max(LOUDNESS_ABSOLUTE_GATE))?) as f32)
+}
+
+/// BS.1770 K-weighting (high shelf pre-filter + RLB high-pass) for any sample rate
+fn k_weighting(samples: &[f32], sample_rate: f64) -> Vec<f64> {
+    // High shelf
+    let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI as f64 * f0 / sample_rate).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    };
+    // High-pass
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI as f64 * f0 / sample_rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let highpass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    };
+    highpass.filter(&shelf.filter(&samples.iter().map(|s| *s as f64).collect::<Vec<_>>()))
+}
+
+/// Second order IIR filter, normalized (a0 = 1)
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+}
+
+impl Biquad {
+    /// Direct form I
+    fn filter(&self, input: &[f64]) -> Vec<f64> {
+        let (mut x1, mut x2, mut y1, mut y2) = (0.0, 0.0, 0.0, 0.0);
+        input.iter().map(|x| {
+            let y = self.b[0] * x + self.b[1] * x1 + self.b[2] * x2 - self.a[0] * y1 - self.a[1] * y2;
+            (x2, x1, y2, y1) = (x1, *x, y1, y);
+            y
+        }).collect()
+    }
+}
+
+/// Mean of values, 0.0 if empty
+fn mean(values: impl Iterator<Item = f32>) -> f32 {
+    let (sum, count) = values.fold((0.0, 0), |(s, c), v| (s + v, c + 1));
+    if count == 0 { 0.0 } else { sum / count as f32 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f32, seconds: f32) -> Vec<f32> {
+        (0..(44100.0 * seconds) as usize)
+            .map(|i| (i as f32 * frequency * 2.0 * PI / 44100.0).sin() * 0.5)
+            .collect()
+    }
+
+    #[test]
+    fn test_sine_spectral_features() {
+        let spectrogram = Spectrogram::new(&sine(1000.0, 1.0), 44100, FFT_SIZE, HOP_SIZE);
+        assert!((spectrogram.centroid() - 1000.0).abs() < 50.0);
+        assert!((spectrogram.rolloff() - 1000.0).abs() < 50.0);
+        // Stationary signal
+        assert!(spectrogram.flux() < 0.05);
+        let mfccs = spectrogram.mfccs();
+        assert_eq!(mfccs.len(), MFCC_COUNT);
+        assert!(mfccs.iter().all(|c| c.is_finite()));
+    }
+
+    #[test]
+    fn test_sine_chroma() {
+        // A5
+        let chroma = Spectrogram::new(&sine(880.0, 1.0), 44100, FFT_SIZE, HOP_SIZE).chroma();
+        let peak = chroma.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1)).unwrap().0;
+        assert_eq!(peak, 9);
+    }
+
+    #[test]
+    fn test_vocal_presence() {
+        let spectrogram = |samples: &[f32]| Spectrogram::new(samples, 44100, FFT_SIZE, HOP_SIZE);
+        let vocal = crate::test_utils::vocal_samples(2.0);
+        let drums = crate::test_utils::drum_samples(2.0);
+        assert!(spectrogram(&vocal).vocal_presence() > 0.8);
+        assert!(spectrogram(&drums).vocal_presence() < 0.1);
+        // Vocal over drums
+        let mix: Vec<f32> = vocal.iter().zip(&drums).map(|(v, d)| v + d * 0.5).collect();
+        assert!(spectrogram(&mix).vocal_presence() > 0.5);
+        // Steady tone in the vocal band
+        assert!(spectrogram(&sine(440.0, 2.0)).vocal_presence() < 0.1);
+    }
+
+    #[test]
+    fn test_hpss() {
+        // Tone with a click in the middle
+        let mut samples = sine(440.0, 1.0);
+        samples[22050] += 1.0;
+        let (harmonic, percussive) = hpss(&samples);
+        assert_eq!(harmonic.len(), samples.len());
+
+        // Components add up to the input (away from the edges)
+        let error = (4096..samples.len() - 4096).map(|i| (harmonic[i] + percussive[i] - samples[i]).abs()).fold(0.0, f32::max);
+        assert!(error < 1e-3, "{}", error);
+        // Tone goes to the harmonic part, click to the percussive part
+        assert!(rms(&harmonic[4096..16384]) > 0.3);
+        assert!(rms(&percussive[4096..16384]) < 0.05);
+        let click = percussive[22040..22060].iter().cloned().fold(0.0, f32::max);
+        assert!(click > 0.3, "{}", click);
+    }
+
+    #[test]
+    fn test_tempo_stability() {
+        assert!((tempo_stability(&[0.0, 0.5, 1.0, 1.5, 2.0]) - 1.0).abs() < 1e-6);
+        assert!(tempo_stability(&[0.0, 0.2, 1.0, 1.3, 2.3]) < 0.75);
+        assert_eq!(tempo_stability(&[0.0, 0.5]), 0.0);
+    }
+
+    #[test]
+    fn test_beat_period() {
+        // Impulse every 20 frames
+        let envelope: Vec<f32> = (0..400).map(|i| if i % 20 == 0 { 1.0 } else { 0.0 }).collect();
+        assert_eq!(beat_period(&envelope, 0.025), Some(20));
+        assert_eq!(beat_period(&[0.0; 400], 0.025), None);
+    }
+
+    #[test]
+    fn test_integrated_loudness() {
+        // 1kHz sine at -20 dBFS peak = -23 LUFS (mono)
+        let amplitude = 10f32.powf(-20.0 / 20.0);
+        let samples: Vec<f32> = (0..48000 * 5).map(|i| (i as f32 * 1000.0 * 2.0 * PI / 48000.0).sin() * amplitude).collect();
+        let lufs = integrated_loudness(&samples, 48000).unwrap();
+        assert!((lufs + 23.0).abs() <= 1.0, "{}", lufs);
+        // Silence is gated out
+        assert_eq!(integrated_loudness(&vec![0.0; 48000], 48000), None);
+    }
+
+    #[test]
+    fn test_zero_crossing_rate() {
+        let samples = sine(441.0, 1.0);
+        // 2 crossings per period
+        assert!((zero_crossing_rate(&samples) - 882.0 / 44100.0).abs() < 0.001);
+        assert!((rms(&samples) - 0.5 / 2f32.sqrt()).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_frame_rms() {
+        let frames = frame_rms(&[0.5; 250], 100);
+        assert_eq!(frames.len(), 3);
+        assert!(frames.iter().all(|f| (f - 0.5).abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_resample() {
+        let samples = sine(441.0, 1.0);
+        let resampled = resample(&samples, 44100, 22050);
+        assert_eq!(resampled.len(), 22050);
+        // Frequency is preserved
+        assert!((zero_crossing_rate(&resampled) - 882.0 / 22050.0).abs() < 0.001);
+    }
+}
@@ -3,37 +3,369 @@
 //! Finds duplicate and similar tracks using audio fingerprinting
 
 use anyhow::{Error, Result};
-use std::path::PathBuf;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use serde::{Serialize, Deserialize};
+use crate::acoustid::{self, AcoustIdMatch};
+use crate::api::APIClient;
+use crate::cache::FeatureCache;
+use crate::config::AcoustIdConfig;
+use crate::features::AudioProperties;
+use crate::fingerprint::Fingerprint;
+
+/// FeatureCache kind for fingerprints
+pub const FINGERPRINT_CACHE_KIND: &str = "fingerprint";
+/// Similarity at or above which the audio is considered identical (when properties are unknown)
+const EXACT_SIMILARITY: f32 = 0.98;
+/// Length of regions compared for remaster detection (frames, ~2s)
+const REGION_FRAMES: usize = 16;
+/// Maximum duration difference of the same recording (seconds)
+const DURATION_TOLERANCE: f32 = 0.5;
+/// Maximum bitrate difference of the same encoding (kbps)
+const BITRATE_TOLERANCE: u32 = 8;
+/// Number of files from which only pairs sharing an LSH bucket are compared
+const LSH_MIN_FILES: usize = 1000;
+
+/// Progress callback (files fingerprinted, total files)
+pub type ProgressCallback<'a> = &'a (dyn Fn(usize, usize) + Sync);
 
 /// Duplicate detector
 pub struct DuplicateDetector {
     threshold: f32,
+    cache: Option<FeatureCache>,
+    /// Compare only LSH candidates from this many files, all pairs below
+    lsh_min_files: usize,
+    /// AcoustID service used by `identify`
+    acoustid: Option<AcoustIdConfig>,
+    /// Pairs never reported as duplicates (sorted paths)
+    ignored: HashSet<(PathBuf, PathBuf)>,
+    /// File `ignored` is persisted to
+    ignore_file: Option<PathBuf>,
 }
 
 impl DuplicateDetector {
     pub fn new(threshold: f32) -> Self {
-        Self { threshold }
+        Self { threshold, cache: None, lsh_min_files: LSH_MIN_FILES, acoustid: None, ignored: HashSet::new(), ignore_file: None }
     }
 
-    /// Find duplicates in a list of files
-    pub fn find_duplicates(&self, files: &[PathBuf]) -> Result<Vec<DuplicateMatch>> {
-        let mut duplicates = Vec::new();
+    /// Number of files from which only likely duplicates (sharing an LSH bucket) are compared
+    pub fn with_lsh_min_files(mut self, files: usize) -> Self {
+        self.lsh_min_files = files;
+        self
+    }
 
-        // TODO: Implement chromaprint-based fingerprinting
-        // For now, return empty list
+    /// Persist fingerprints between runs
+    pub fn with_cache(mut self, cache: Option<FeatureCache>) -> Self {
+        self.cache = cache;
+        self
+    }
 
+    /// Identify files with AcoustID (see `APIConfig::acoustid`)
+    pub fn with_acoustid(mut self, config: AcoustIdConfig) -> Self {
+        self.acoustid = Some(config);
+        self
+    }
+
+    /// Load ignored pairs from file (empty if it doesn't exist yet), `ignore_pair` / `unignore_pair` write it back
+    pub fn with_ignore_file(mut self, path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if path.exists() {
+            let pairs: Vec<(PathBuf, PathBuf)> = serde_json::from_slice(&std::fs::read(&path)?)?;
+            self.ignored = pairs.into_iter().map(|(a, b)| Self::pair_key(&a, &b)).collect();
+        }
+        self.ignore_file = Some(path);
+        Ok(self)
+    }
+
+    /// Don't report this pair as duplicates anymore (eg. radio edit and extended mix)
+    pub fn ignore_pair(&mut self, file1: &Path, file2: &Path) -> Result<()> {
+        if self.ignored.insert(Self::pair_key(file1, file2)) {
+            self.save_ignored()?;
+        }
+        Ok(())
+    }
+
+    /// Report this pair again, returns whether it was ignored
+    pub fn unignore_pair(&mut self, file1: &Path, file2: &Path) -> Result<bool> {
+        let removed = self.ignored.remove(&Self::pair_key(file1, file2));
+        if removed {
+            self.save_ignored()?;
+        }
+        Ok(removed)
+    }
+
+    pub fn is_ignored(&self, file1: &Path, file2: &Path) -> bool {
+        self.ignored.contains(&Self::pair_key(file1, file2))
+    }
+
+    /// Order independent key of pair
+    fn pair_key(file1: &Path, file2: &Path) -> (PathBuf, PathBuf) {
+        match file1 <= file2 {
+            true => (file1.to_owned(), file2.to_owned()),
+            false => (file2.to_owned(), file1.to_owned()),
+        }
+    }
+
+    /// Write ignored pairs to the ignore file (if any)
+    fn save_ignored(&self) -> Result<()> {
+        let Some(path) = &self.ignore_file else { return Ok(()) };
+        let mut pairs: Vec<&(PathBuf, PathBuf)> = self.ignored.iter().collect();
+        pairs.sort();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_vec(&pairs)?)?;
+        Ok(())
+    }
+
+    /// Look up the file's Chromaprint fingerprint in AcoustID, `None` if no recording is known.
+    /// Needs an AcoustID API key (`with_acoustid`) and the `fingerprinting` feature
+    pub async fn identify(&self, file: &Path) -> Result<Option<AcoustIdMatch>> {
+        let config = self.acoustid.as_ref().ok_or_else(|| anyhow!("AcoustID not configured"))?;
+        // Decoding is CPU bound, keep it off the async workers
+        let path = file.to_owned();
+        let (fingerprint, fingerprinted) = tokio::task::spawn_blocking(move || acoustid::chromaprint(&path)).await??;
+        // Lookup needs the full duration, only the beginning is fingerprinted
+        let duration = AudioProperties::probe(file).map(|p| p.duration).unwrap_or(fingerprinted);
+        acoustid::lookup(&APIClient::default_http_client()?, config, &fingerprint, duration).await
+    }
+
+    /// Find duplicates in a list of files, except ignored pairs.
+    /// When `cancel` is set, fingerprinting stops and only matches among already fingerprinted files are returned
+    pub fn find_duplicates(&self, files: &[PathBuf], progress: Option<ProgressCallback>, cancel: Option<Arc<AtomicBool>>) -> Result<Vec<DuplicateMatch>> {
         info!("Scanning {} files for duplicates", files.len());
         debug!("Using similarity threshold: {}", self.threshold);
 
+        let (scanned, _) = self.scan(files, progress, cancel.as_deref());
+        let (duplicates, comparisons) = self.match_scanned(&scanned);
+        info!("Found {} duplicate pairs in {} comparisons", duplicates.len(), comparisons);
         Ok(duplicates)
     }
 
+    /// Find duplicates involving files not in `previously_seen`, matches among previously seen files aren't reported again.
+    /// Only files missing from the fingerprint cache (`with_cache`) are fingerprinted
+    pub fn find_duplicates_incremental(&self, files: &[PathBuf], previously_seen: &[PathBuf]) -> Result<IncrementalScan> {
+        let seen: HashSet<&PathBuf> = previously_seen.iter().collect();
+        let new: Vec<bool> = files.iter().map(|f| !seen.contains(f)).collect();
+        info!("Scanning {} new of {} files for duplicates", new.iter().filter(|n| **n).count(), files.len());
+
+        let (scanned, fingerprinted) = self.scan(files, None, None);
+        let pairs: Vec<(usize, usize)> = self.pairs(&scanned).into_iter().filter(|(i, j)| new[*i] || new[*j]).collect();
+        let matches = self.match_pairs(&scanned, &pairs);
+        let processed: Vec<PathBuf> = files.iter().zip(fingerprinted).filter(|(_, f)| *f).map(|(p, _)| p.clone()).collect();
+        info!("Fingerprinted {} files, found {} duplicate pairs in {} comparisons", processed.len(), matches.len(), pairs.len());
+        Ok(IncrementalScan { matches, processed })
+    }
+
+    /// Fingerprint and probe every file once, also returns which files weren't in the cache.
+    /// When `cancel` is set, the remaining files are skipped
+    fn scan<'a>(&self, files: &'a [PathBuf], progress: Option<ProgressCallback>, cancel: Option<&AtomicBool>) -> (Vec<Option<Scanned<'a>>>, Vec<bool>) {
+        let cancelled = || cancel.map(|c| c.load(Ordering::SeqCst)).unwrap_or(false);
+        let done = AtomicUsize::new(0);
+        let (scanned, fingerprinted) = files.par_iter()
+            .map(|path| {
+                if cancelled() {
+                    return (None, false);
+                }
+                let scanned = match self.fingerprint_cached(path) {
+                    Ok((fingerprint, fingerprinted)) => (Some(Scanned { path, fingerprint, properties: AudioProperties::probe(path).ok() }), fingerprinted),
+                    Err(e) => {
+                        warn!("Failed to fingerprint {}: {}", path.display(), e);
+                        (None, true)
+                    }
+                };
+                let done = done.fetch_add(1, Ordering::SeqCst) + 1;
+                if let Some(progress) = progress {
+                    progress(done, files.len());
+                }
+                scanned
+            })
+            .unzip();
+        if cancelled() {
+            info!("Duplicate scan cancelled after {} of {} files", done.load(Ordering::SeqCst), files.len());
+        }
+        (scanned, fingerprinted)
+    }
+
+    /// Compare fingerprinted files (all pairs or LSH candidates), returns matches in input order and number of comparisons
+    fn match_scanned(&self, scanned: &[Option<Scanned>]) -> (Vec<DuplicateMatch>, usize) {
+        let pairs = self.pairs(scanned);
+        (self.match_pairs(scanned, &pairs), pairs.len())
+    }
+
+    /// Pairs worth comparing: all of them or LSH candidates for large sets
+    fn pairs(&self, scanned: &[Option<Scanned>]) -> Vec<(usize, usize)> {
+        match scanned.len() >= self.lsh_min_files {
+            true => Self::candidate_pairs(scanned),
+            false => (0..scanned.len()).flat_map(|i| ((i + 1)..scanned.len()).map(move |j| (i, j))).collect(),
+        }
+    }
+
+    /// Compare pairs of fingerprinted files, skipping ignored ones
+    fn match_pairs(&self, scanned: &[Option<Scanned>], pairs: &[(usize, usize)]) -> Vec<DuplicateMatch> {
+        pairs.par_iter()
+            .filter_map(|(i, j)| {
+                let (Some(a), Some(b)) = (&scanned[*i], &scanned[*j]) else { return None };
+                if self.is_ignored(a.path, b.path) {
+                    return None;
+                }
+                self.compare(a, b)
+            })
+            .collect()
+    }
+
+    /// Sorted pairs of files sharing at least one LSH bucket
+    fn candidate_pairs(scanned: &[Option<Scanned>]) -> Vec<(usize, usize)> {
+        let keys: Vec<Vec<u64>> = scanned.par_iter()
+            .map(|s| s.as_ref().map(|s| s.fingerprint.lsh_keys()).unwrap_or_default())
+            .collect();
+        let mut buckets: HashMap<u64, Vec<usize>> = HashMap::new();
+        for (i, keys) in keys.iter().enumerate() {
+            for key in keys {
+                buckets.entry(*key).or_default().push(i);
+            }
+        }
+        let mut pairs = HashSet::new();
+        for members in buckets.values() {
+            for (n, a) in members.iter().enumerate() {
+                pairs.extend(members[n + 1..].iter().map(|b| (*a, *b)));
+            }
+        }
+        let mut pairs: Vec<(usize, usize)> = pairs.into_iter().collect();
+        pairs.sort();
+        debug!("LSH: {} candidate pairs in {} buckets", pairs.len(), buckets.len());
+        pairs
+    }
+
+    /// Group transitively similar files (A~B, B~C), every file ends up in exactly one group
+    pub fn find_duplicate_groups(&self, files: &[PathBuf]) -> Result<Vec<DuplicateGroup>> {
+        let matches = self.find_duplicates(files, None, None)?;
+
+        // Union-find over the similarity graph
+        let index: HashMap<&PathBuf, usize> = files.iter().enumerate().map(|(i, f)| (f, i)).collect();
+        let mut parent: Vec<usize> = (0..files.len()).collect();
+        fn root(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+        for m in &matches {
+            let (a, b) = (root(&mut parent, index[&m.file1]), root(&mut parent, index[&m.file2]));
+            parent[a.max(b)] = a.min(b);
+        }
+
+        // Components in input order
+        let mut groups: HashMap<usize, usize> = HashMap::new();
+        let mut components: Vec<Vec<PathBuf>> = vec![];
+        for (i, file) in files.iter().enumerate() {
+            let r = root(&mut parent, i);
+            match groups.get(&r) {
+                Some(group) => components[*group].push(file.clone()),
+                None => {
+                    groups.insert(r, components.len());
+                    components.push(vec![file.clone()]);
+                }
+            }
+        }
+
+        let properties: HashMap<&PathBuf, &AudioProperties> = matches.iter()
+            .flat_map(|m| [(&m.file1, m.properties1.as_ref()), (&m.file2, m.properties2.as_ref())])
+            .filter_map(|(f, p)| Some((f, p?)))
+            .collect();
+        Ok(components.into_iter().map(|files| DuplicateGroup {
+            keeper: Self::keeper(&files, &properties),
+            files,
+        }).collect())
+    }
+
+    /// Best quality file of a group (highest bitrate, then longest)
+    fn keeper(files: &[PathBuf], properties: &HashMap<&PathBuf, &AudioProperties>) -> PathBuf {
+        files.iter()
+            .map(|f| (f, properties.get(f).map(|p| (p.bitrate, p.duration)).unwrap_or((0, 0.0))))
+            // First of the best on ties
+            .min_by(|(_, a), (_, b)| b.0.cmp(&a.0).then(b.1.total_cmp(&a.1)))
+            .map(|(f, _)| f.clone())
+            .unwrap_or_default()
+    }
+
     /// Check if two files are duplicates
     pub fn are_duplicates(&self, file1: &PathBuf, file2: &PathBuf) -> Result<bool> {
-        // TODO: Compare audio fingerprints
-        Ok(false)
+        let a = Scanned { path: file1, fingerprint: self.fingerprint(file1)?, properties: None };
+        let b = Scanned { path: file2, fingerprint: self.fingerprint(file2)?, properties: None };
+        Ok(self.compare(&a, &b).is_some())
+    }
+
+    /// Fingerprint of file, from cache if available
+    pub fn fingerprint(&self, path: &Path) -> Result<Fingerprint, Error> {
+        self.fingerprint_cached(path).map(|(fingerprint, _)| fingerprint)
+    }
+
+    /// Fingerprint of file and whether it had to be computed (wasn't cached)
+    fn fingerprint_cached(&self, path: &Path) -> Result<(Fingerprint, bool), Error> {
+        if let Some(fingerprint) = self.cache.as_ref().and_then(|c| c.get::<Fingerprint>(path, FINGERPRINT_CACHE_KIND)) {
+            return Ok((fingerprint, false));
+        }
+        let fingerprint = Fingerprint::from_file(path)?;
+        if let Some(cache) = &self.cache {
+            if let Err(e) = cache.put(path, FINGERPRINT_CACHE_KIND, &fingerprint) {
+                warn!("Failed to cache fingerprint of {}: {}", path.display(), e);
+            }
+        }
+        Ok((fingerprint, true))
+    }
+
+    /// Match if fingerprints are similar enough
+    fn compare(&self, a: &Scanned, b: &Scanned) -> Option<DuplicateMatch> {
+        let similarity = a.fingerprint.compare(&b.fingerprint);
+        if similarity < self.threshold {
+            return None;
+        }
+        let differing_regions = a.fingerprint.region_similarities(&b.fingerprint, REGION_FRAMES)
+            .into_iter()
+            .filter(|s| *s < self.threshold)
+            .count();
+        Some(DuplicateMatch {
+            file1: a.path.to_owned(),
+            file2: b.path.to_owned(),
+            similarity,
+            match_type: Self::match_type(similarity, differing_regions, a.properties.as_ref(), b.properties.as_ref()),
+            properties1: a.properties.clone(),
+            properties2: b.properties.clone(),
+            differing_regions,
+        })
     }
+
+    /// Classify matching audio by comparing audio properties
+    fn match_type(similarity: f32, differing_regions: usize, a: Option<&AudioProperties>, b: Option<&AudioProperties>) -> DuplicateType {
+        let (Some(a), Some(b)) = (a, b) else {
+            return match similarity >= EXACT_SIMILARITY {
+                true => DuplicateType::Exact,
+                false => DuplicateType::Similar,
+            };
+        };
+        if (a.duration - b.duration).abs() > DURATION_TOLERANCE {
+            return DuplicateType::Similar;
+        }
+        if differing_regions > 0 {
+            return DuplicateType::DifferentMaster;
+        }
+        match a.codec == b.codec && a.bitrate.abs_diff(b.bitrate) <= BITRATE_TOLERANCE {
+            true => DuplicateType::Exact,
+            false => DuplicateType::QualityVariant,
+        }
+    }
+}
+
+/// Fingerprinted file
+struct Scanned<'a> {
+    path: &'a Path,
+    fingerprint: Fingerprint,
+    properties: Option<AudioProperties>,
 }
 
 /// A duplicate match result
@@ -43,6 +375,31 @@ pub struct DuplicateMatch {
     pub file2: PathBuf,
     pub similarity: f32,
     pub match_type: DuplicateType,
+    /// Compared audio properties of `file1` and `file2`
+    #[serde(default)]
+    pub properties1: Option<AudioProperties>,
+    #[serde(default)]
+    pub properties2: Option<AudioProperties>,
+    /// Number of ~2s regions where the fingerprints differ
+    #[serde(default)]
+    pub differing_regions: usize,
+}
+
+/// Result of `find_duplicates_incremental`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IncrementalScan {
+    /// Matches involving at least one new file
+    pub matches: Vec<DuplicateMatch>,
+    /// Files which had to be fingerprinted (not cached)
+    pub processed: Vec<PathBuf>,
+}
+
+/// Files which are duplicates of each other
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateGroup {
+    pub files: Vec<PathBuf>,
+    /// Recommended file to keep
+    pub keeper: PathBuf,
 }
 
 /// Type of duplicate
@@ -63,9 +420,214 @@ pub enum DuplicateType {
 mod tests {
     use super::*;
 
+    use crate::test_utils::{write_wav, MockServer, MockResponse};
+
+    /// Melody of notes (with 2nd harmonic), 0.5s each
+    fn melody(notes: &[f32], sample_rate: u32) -> Vec<f32> {
+        let note_len = sample_rate as usize / 2;
+        (0..note_len * notes.len())
+            .map(|i| {
+                let t = i as f32 * 2.0 * std::f32::consts::PI / sample_rate as f32;
+                let frequency = notes[i / note_len];
+                (t * frequency).sin() * 0.5 + (t * frequency * 2.0).sin() * 0.2
+            })
+            .collect()
+    }
+
+    const MELODY_A: [f32; 16] = [262.0, 294.0, 330.0, 349.0, 392.0, 440.0, 494.0, 523.0, 494.0, 440.0, 392.0, 349.0, 330.0, 294.0, 262.0, 392.0];
+    const MELODY_B: [f32; 16] = [277.0, 311.0, 370.0, 415.0, 466.0, 370.0, 311.0, 277.0, 466.0, 415.0, 370.0, 311.0, 277.0, 415.0, 466.0, 311.0];
+
     #[test]
     fn test_duplicate_detector() {
         let detector = DuplicateDetector::new(0.85);
         assert_eq!(detector.threshold, 0.85);
     }
+
+    #[test]
+    fn test_find_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        // Same audio at 1411 and 353 kbps, unrelated melody
+        let files: Vec<PathBuf> = ["high.wav", "low.wav", "other.wav"].iter().map(|f| dir.path().join(f)).collect();
+        write_wav(&files[0], &melody(&MELODY_A, 44100), 44100);
+        write_wav(&files[1], &melody(&MELODY_A, 22050), 22050);
+        write_wav(&files[2], &melody(&MELODY_B, 44100), 44100);
+
+        let detector = DuplicateDetector::new(0.85);
+        let duplicates = detector.find_duplicates(&files, None, None).unwrap();
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!((&duplicates[0].file1, &duplicates[0].file2), (&files[0], &files[1]));
+        assert!(detector.are_duplicates(&files[0], &files[1]).unwrap());
+        assert!(!detector.are_duplicates(&files[0], &files[2]).unwrap());
+    }
+
+    /// Classify a pair of fixtures
+    fn match_type(a: &[f32], a_rate: u32, b: &[f32], b_rate: u32) -> Option<DuplicateType> {
+        let dir = tempfile::tempdir().unwrap();
+        let files = vec![dir.path().join("a.wav"), dir.path().join("b.wav")];
+        write_wav(&files[0], a, a_rate);
+        write_wav(&files[1], b, b_rate);
+        DuplicateDetector::new(0.85).find_duplicates(&files, None, None).unwrap().first().map(|m| m.match_type)
+    }
+
+    #[test]
+    fn test_duplicate_types() {
+        // Not repeating, so the only alignment is at the start
+        let long: Vec<f32> = MELODY_A.iter().chain(MELODY_B.iter()).copied().collect();
+        let original = melody(&long, 44100);
+        assert_eq!(match_type(&original, 44100, &original, 44100), Some(DuplicateType::Exact));
+        // Same audio at lower sample rate
+        assert_eq!(match_type(&original, 44100, &melody(&long, 22050), 22050), Some(DuplicateType::QualityVariant));
+        // Same length, 2s in the middle differ
+        let mut remaster = long.clone();
+        remaster[16..20].copy_from_slice(&[185.0, 208.0, 233.0, 277.0]);
+        assert_eq!(match_type(&original, 44100, &melody(&remaster, 44100), 44100), Some(DuplicateType::DifferentMaster));
+        // Same audio, shorter edit
+        assert_eq!(match_type(&original, 44100, &melody(&long[..24], 44100), 44100), Some(DuplicateType::Similar));
+    }
+
+    #[test]
+    fn test_cancel_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        let files: Vec<PathBuf> = (0..6).map(|i| dir.path().join(format!("{}.wav", i))).collect();
+        for (i, file) in files.iter().enumerate() {
+            write_wav(file, &melody(if i < 2 { &MELODY_A } else { &MELODY_B }, 44100), 44100);
+        }
+
+        // Cancel after the first two files
+        let cancel = Arc::new(AtomicBool::new(false));
+        let calls = AtomicUsize::new(0);
+        let progress = |done: usize, total: usize| {
+            assert_eq!(total, 6);
+            calls.fetch_add(1, Ordering::SeqCst);
+            if done == 2 {
+                cancel.store(true, Ordering::SeqCst);
+            }
+        };
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+        let duplicates = pool.install(|| DuplicateDetector::new(0.85).find_duplicates(&files, Some(&progress), Some(cancel.clone()))).unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!((&duplicates[0].file1, &duplicates[0].file2), (&files[0], &files[1]));
+    }
+
+    #[test]
+    fn test_lsh_candidates() {
+        // Random fingerprints, the last 5 planted again with 2% of bits flipped
+        let mut state = 42u64;
+        let mut random = move || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 32) as u32
+        };
+        let mut fingerprints: Vec<Fingerprint> = (0..400)
+            .map(|_| Fingerprint { codes: (0..600).map(|_| random()).collect(), duration: 75.0 })
+            .collect();
+        for i in 395..400 {
+            let codes = fingerprints[i].codes.iter()
+                .map(|c| (0..32).filter(|_| random() % 50 == 0).fold(*c, |c, bit| c ^ (1 << bit)))
+                .collect();
+            fingerprints.push(Fingerprint { codes, duration: 75.0 });
+        }
+        let paths: Vec<PathBuf> = (0..fingerprints.len()).map(|i| PathBuf::from(format!("{}.mp3", i))).collect();
+        let scanned: Vec<Option<Scanned>> = paths.iter().zip(fingerprints)
+            .map(|(path, fingerprint)| Some(Scanned { path, fingerprint, properties: None }))
+            .collect();
+
+        let (duplicates, comparisons) = DuplicateDetector::new(0.85).with_lsh_min_files(100).match_scanned(&scanned);
+        let pairs: Vec<(&PathBuf, &PathBuf)> = duplicates.iter().map(|m| (&m.file1, &m.file2)).collect();
+        let expected: Vec<(&PathBuf, &PathBuf)> = (395..400).map(|i| (&paths[i], &paths[i + 5])).collect();
+        assert_eq!(pairs, expected);
+        let n = scanned.len();
+        assert!(comparisons < n * n / 100, "{} comparisons", comparisons);
+
+        // Small sets compare all pairs
+        let (duplicates, comparisons) = DuplicateDetector::new(0.85).match_scanned(&scanned[395..]);
+        assert_eq!(comparisons, 45);
+        assert_eq!(duplicates.len(), 5);
+    }
+
+    #[test]
+    fn test_duplicate_groups() {
+        let dir = tempfile::tempdir().unwrap();
+        let files: Vec<PathBuf> = ["mid.wav", "high.wav", "low.wav", "other.wav"].iter().map(|f| dir.path().join(f)).collect();
+        write_wav(&files[0], &melody(&MELODY_A, 32000), 32000);
+        write_wav(&files[1], &melody(&MELODY_A, 44100), 44100);
+        write_wav(&files[2], &melody(&MELODY_A, 22050), 22050);
+        write_wav(&files[3], &melody(&MELODY_B, 44100), 44100);
+
+        let groups = DuplicateDetector::new(0.85).find_duplicate_groups(&files).unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].files, files[0..3].to_vec());
+        assert_eq!(groups[0].keeper, files[1]);
+        assert_eq!(groups[1].files, vec![files[3].clone()]);
+        assert_eq!(groups[1].keeper, files[3]);
+    }
+
+    #[tokio::test]
+    async fn test_identify() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("track.wav");
+        write_wav(&file, &melody(&MELODY_A, 44100), 44100);
+        let server = MockServer::start(vec![MockResponse::new(200, r#"{"status": "ok", "results": [
+            {"id": "9ff43b6a-4f16-427c-93c2-92307ca505e0", "score": 0.9, "recordings": [{"id": "cd2e7c47-16f5-46c6-a37c-a1eb7bf599ff"}]}
+        ]}"#)]).await;
+
+        assert!(DuplicateDetector::new(0.85).identify(&file).await.is_err());
+        let config = AcoustIdConfig { api_key: Some("key".to_string()), endpoint: server.url.clone() };
+        let detector = DuplicateDetector::new(0.85).with_acoustid(config);
+        if !cfg!(feature = "fingerprinting") {
+            assert!(detector.identify(&file).await.is_err());
+            return;
+        }
+        let result = detector.identify(&file).await.unwrap().unwrap();
+        assert_eq!(result.recording_id, "cd2e7c47-16f5-46c6-a37c-a1eb7bf599ff");
+        assert!(server.requests()[0].contains("fingerprint="));
+    }
+
+    #[test]
+    fn test_ignore_pair() {
+        let dir = tempfile::tempdir().unwrap();
+        let files: Vec<PathBuf> = ["radio.wav", "extended.wav"].iter().map(|f| dir.path().join(f)).collect();
+        write_wav(&files[0], &melody(&MELODY_A, 44100), 44100);
+        write_wav(&files[1], &melody(&MELODY_A, 22050), 22050);
+        let ignore_file = dir.path().join("ignored.json");
+
+        let mut detector = DuplicateDetector::new(0.85).with_ignore_file(&ignore_file).unwrap();
+        assert_eq!(detector.find_duplicates(&files, None, None).unwrap().len(), 1);
+        detector.ignore_pair(&files[1], &files[0]).unwrap();
+        assert!(detector.is_ignored(&files[0], &files[1]));
+
+        // Persisted for the next scan
+        let mut detector = DuplicateDetector::new(0.85).with_ignore_file(&ignore_file).unwrap();
+        assert!(detector.find_duplicates(&files, None, None).unwrap().is_empty());
+
+        assert!(detector.unignore_pair(&files[0], &files[1]).unwrap());
+        assert!(!detector.unignore_pair(&files[0], &files[1]).unwrap());
+        let detector = DuplicateDetector::new(0.85).with_ignore_file(&ignore_file).unwrap();
+        assert_eq!(detector.find_duplicates(&files, None, None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_incremental() {
+        let dir = tempfile::tempdir().unwrap();
+        let files: Vec<PathBuf> = ["a.wav", "b.wav", "a_copy.wav"].iter().map(|f| dir.path().join(f)).collect();
+        write_wav(&files[0], &melody(&MELODY_A, 44100), 44100);
+        write_wav(&files[1], &melody(&MELODY_B, 44100), 44100);
+        write_wav(&files[2], &melody(&MELODY_A, 22050), 22050);
+        let detector = DuplicateDetector::new(0.85).with_cache(Some(FeatureCache::new(dir.path().join("cache"))));
+
+        let scan = detector.find_duplicates_incremental(&files[..2], &[]).unwrap();
+        assert_eq!(scan.processed, files[..2].to_vec());
+        assert!(scan.matches.is_empty());
+
+        // Only the added file is fingerprinted and compared against the rest
+        let scan = detector.find_duplicates_incremental(&files, &files[..2]).unwrap();
+        assert_eq!(scan.processed, vec![files[2].clone()]);
+        assert_eq!(scan.matches.len(), 1);
+        assert_eq!((&scan.matches[0].file1, &scan.matches[0].file2), (&files[0], &files[2]));
+
+        // Nothing new
+        let scan = detector.find_duplicates_incremental(&files, &files).unwrap();
+        assert!(scan.processed.is_empty());
+        assert!(scan.matches.is_empty());
+    }
 }
@@ -3,15 +3,75 @@
 //! Generates embeddings for better track matching
 
 use anyhow::{Error, Result};
+use crate::camelot;
+use crate::config::AIConfig;
 use crate::features::AudioFeatures;
+use crate::index::EmbeddingIndex;
 use onetagger_tagger::Track;
+use parking_lot::Mutex;
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[cfg(feature = "onnx")]
+use tract_onnx::prelude::*;
+
+/// Max tokens (including [CLS] and [SEP]) fed to the text model
+#[cfg(feature = "onnx")]
+const MAX_TOKENS: usize = 128;
+/// Dimensions of the character trigram embedding used without a text model
+const NGRAM_DIMENSIONS: usize = 256;
+/// Weights of genre overlap, BPM proximity, key compatibility and text similarity in recommendations
+const RECOMMEND_WEIGHTS: [f32; 4] = [0.4, 0.25, 0.15, 0.2];
+/// BPM difference at which BPM proximity drops to 0
+const RECOMMEND_BPM_RANGE: f32 = 20.0;
+
+/// Text to embedding function
+type TextEmbedder<'a> = Box<dyn Fn(&str) -> Vec<f32> + 'a>;
+
+lazy_static! {
+    /// Loaded text models by directory, shared between generators
+    static ref TEXT_MODELS: Mutex<HashMap<PathBuf, Arc<TextModel>>> = Mutex::new(HashMap::new());
+}
+
+/// Weights of the audio and text parts of a combined embedding
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingWeights {
+    pub audio: f32,
+    pub text: f32,
+}
+
+impl Default for EmbeddingWeights {
+    fn default() -> Self {
+        Self { audio: 0.6, text: 0.4 }
+    }
+}
 
 /// Embedding generator for semantic matching
-pub struct EmbeddingGenerator {}
+pub struct EmbeddingGenerator {
+    /// Directory with sentence-transformer `model.onnx` and `vocab.txt`
+    text_model: PathBuf,
+}
 
 impl EmbeddingGenerator {
     pub fn new() -> Self {
-        Self {}
+        Self { text_model: TextModel::default_dir() }
+    }
+
+    /// Generator using the configured text model
+    pub fn from_config(config: &AIConfig) -> Self {
+        match &config.text_model {
+            Some(dir) => Self::new().with_text_model(dir),
+            None => Self::new(),
+        }
+    }
+
+    /// Use text model from directory
+    pub fn with_text_model(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.text_model = dir.into();
+        self
     }
 
     /// Generate embedding from audio features
@@ -29,20 +89,46 @@ impl EmbeddingGenerator {
         Ok(embedding)
     }
 
-    /// Generate text embedding from title/artist
+    /// Generate text embedding (384 dims for all-MiniLM-L6-v2) from title/artist, the model is loaded on first use
     pub fn generate_text_embedding(&self, text: &str) -> Result<Vec<f32>> {
-        // TODO: Use sentence transformer model
-        // For now, simple hash-based embedding
-        let mut embedding = vec![0.0; 384];  // Standard sentence embedding size
-
-        for (i, ch) in text.chars().enumerate().take(384) {
-            embedding[i] = (ch as u32 % 256) as f32 / 255.0;
-        }
+        let model = {
+            let mut models = TEXT_MODELS.lock();
+            match models.get(&self.text_model) {
+                Some(model) => model.clone(),
+                None => {
+                    let model = Arc::new(TextModel::load(&self.text_model)?);
+                    models.insert(self.text_model.clone(), model.clone());
+                    model
+                }
+            }
+        };
+        model.embed(text)
+    }
 
-        Ok(embedding)
+    /// Audio and text (artists + title) embeddings fused into one normalized vector
+    pub fn generate_combined(&self, track: &Track, features: &AudioFeatures, weights: EmbeddingWeights) -> Result<Vec<f32>> {
+        let audio = self.generate_audio_embedding(features)?;
+        let text = self.generate_text_embedding(&track_text(track))?;
+        Ok(combine(audio, text, weights))
     }
 }
 
+/// Concatenate normalized parts scaled by sqrt of their weights,
+/// so cosine similarity of combined vectors is the weighted mean of the parts' similarities
+fn combine(audio: Vec<f32>, text: Vec<f32>, weights: EmbeddingWeights) -> Vec<f32> {
+    let total = weights.audio.max(0.0) + weights.text.max(0.0);
+    let (audio_weight, text_weight) = match total > 0.0 {
+        true => (weights.audio.max(0.0) / total, weights.text.max(0.0) / total),
+        false => (0.5, 0.5),
+    };
+    let scale = |part: Vec<f32>, weight: f32| {
+        let norm = part.iter().map(|v| v * v).sum::<f32>().sqrt();
+        let factor = if norm > 0.0 { weight.sqrt() / norm } else { 0.0 };
+        part.into_iter().map(move |v| v * factor)
+    };
+    scale(audio, audio_weight).chain(scale(text, text_weight)).collect()
+}
+
 impl Default for EmbeddingGenerator {
     fn default() -> Self {
         Self::new()
@@ -52,25 +138,333 @@ impl Default for EmbeddingGenerator {
 /// Semantic matcher using embeddings
 pub struct SemanticMatcher {
     generator: EmbeddingGenerator,
+    index: EmbeddingIndex,
+    weights: EmbeddingWeights,
 }
 
 impl SemanticMatcher {
     pub fn new() -> Self {
         Self {
             generator: EmbeddingGenerator::new(),
+            index: EmbeddingIndex::new(),
+            weights: EmbeddingWeights::default(),
         }
     }
 
-    /// Calculate similarity between two tracks
-    pub fn calculate_similarity(&self, track1: &Track, track2: &Track) -> Result<f32> {
-        let text1 = format!("{} {}", track1.artists.join(" "), track1.title);
-        let text2 = format!("{} {}", track2.artists.join(" "), track2.title);
+    /// Weights used for combined audio+text similarity
+    pub fn with_weights(mut self, weights: EmbeddingWeights) -> Self {
+        self.weights = weights;
+        self
+    }
 
-        let emb1 = self.generator.generate_text_embedding(&text1)?;
-        let emb2 = self.generator.generate_text_embedding(&text2)?;
+    /// Use custom generator (text model)
+    pub fn with_generator(mut self, generator: EmbeddingGenerator) -> Self {
+        self.generator = generator;
+        self
+    }
+
+    /// Use text model from directory
+    pub fn with_text_model(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.generator = self.generator.with_text_model(dir);
+        self
+    }
+
+    /// Search tracks in an existing (persisted) index
+    pub fn with_index(mut self, index: EmbeddingIndex) -> Self {
+        self.index = index;
+        self
+    }
+
+    pub fn index(&self) -> &EmbeddingIndex {
+        &self.index
+    }
+
+    /// Calculate similarity between two tracks, character trigrams when the text model isn't available
+    pub fn calculate_similarity(&self, track1: &Track, track2: &Track) -> Result<f32> {
+        let embed = self.text_embedder(&track_text(track1));
+        let emb1 = embed(&track_text(track1));
+        let emb2 = embed(&track_text(track2));
 
         Ok(cosine_similarity(&emb1, &emb2))
     }
+
+    /// Calculate similarity using combined audio+text embeddings when features of both tracks are available
+    pub fn calculate_similarity_with_features(
+        &self,
+        track1: &Track,
+        features1: Option<&AudioFeatures>,
+        track2: &Track,
+        features2: Option<&AudioFeatures>,
+    ) -> Result<f32> {
+        match (features1, features2) {
+            (Some(features1), Some(features2)) => {
+                let embed = self.text_embedder(&track_text(track1));
+                let emb1 = combine(self.generator.generate_audio_embedding(features1)?, embed(&track_text(track1)), self.weights);
+                let emb2 = combine(self.generator.generate_audio_embedding(features2)?, embed(&track_text(track2)), self.weights);
+                Ok(cosine_similarity(&emb1, &emb2))
+            },
+            _ => self.calculate_similarity(track1, track2),
+        }
+    }
+
+    /// Add track to the index
+    pub fn index_track(&mut self, id: impl Into<String>, track: &Track) -> Result<()> {
+        let text = track_text(track);
+        let embedding = self.text_embedder(&text)(&text);
+        self.index.add(id, embedding)
+    }
+
+    /// `k` most similar indexed tracks as (id, similarity)
+    pub fn find_similar(&self, track: &Track, k: usize) -> Result<Vec<(String, f32)>> {
+        let text = track_text(track);
+        let embedding = self.text_embedder(&text)(&text);
+        Ok(self.index.query(&embedding, k))
+    }
+
+    /// Rank autotagger candidates by title / artist similarity to the query, as (candidate index, score) best first.
+    /// Character trigram embeddings are used when the text model isn't available
+    pub fn rank_matches(&self, query: &Track, candidates: &[Track]) -> Vec<(usize, f32)> {
+        let embed = self.text_embedder(&query.title);
+        let title = embed(&query.title);
+        let artists = (!query.artists.is_empty()).then(|| embed(&query.artists.join(" ")));
+        let mut ranked: Vec<(usize, f32)> = candidates.iter().enumerate().map(|(i, candidate)| {
+            let title_score = cosine_similarity(&title, &embed(&candidate.title));
+            let score = match (&artists, candidate.artists.is_empty()) {
+                (Some(artists), false) => (title_score + cosine_similarity(artists, &embed(&candidate.artists.join(" ")))) / 2.0,
+                _ => title_score,
+            };
+            (i, score)
+        }).collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranked
+    }
+
+    /// `k` library tracks most similar to the seed for set building, as (library index, score 0-1) best first.
+    /// Score combines genre overlap, BPM proximity (half / double tempo included), Camelot key compatibility and text similarity.
+    /// The seed itself (same instance or URL) is excluded
+    pub fn recommend(&self, seed: &Track, library: &[Track], k: usize) -> Vec<(usize, f32)> {
+        let embed = self.text_embedder(&track_text(seed));
+        let seed_text = embed(&track_text(seed));
+        let mut ranked: Vec<(usize, f32)> = library.iter().enumerate()
+            .filter(|(_, track)| !std::ptr::eq(*track, seed) && (seed.url.is_empty() || track.url != seed.url))
+            .map(|(i, track)| {
+                let scores = [
+                    genre_overlap(seed, track),
+                    bpm_proximity(seed.bpm, track.bpm),
+                    key_compatibility(seed.key.as_deref(), track.key.as_deref()),
+                    cosine_similarity(&seed_text, &embed(&track_text(track))),
+                ];
+                (i, scores.iter().zip(RECOMMEND_WEIGHTS).map(|(s, w)| s * w).sum())
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        ranked.truncate(k);
+        ranked
+    }
+
+    /// Text embedding function, character trigrams when the text model isn't available
+    fn text_embedder(&self, probe: &str) -> TextEmbedder<'_> {
+        match self.generator.generate_text_embedding(probe) {
+            Ok(_) => Box::new(|text: &str| self.generator.generate_text_embedding(text).unwrap_or_else(|_| ngram_embedding(text))),
+            Err(e) => {
+                debug!("Text model unavailable ({}), using character trigrams", e);
+                Box::new(ngram_embedding)
+            }
+        }
+    }
+}
+
+/// Jaccard similarity of the genres (case-insensitive)
+fn genre_overlap(a: &Track, b: &Track) -> f32 {
+    let genres = |track: &Track| track.genres.iter().map(|g| g.to_lowercase()).collect::<std::collections::HashSet<_>>();
+    let (a, b) = (genres(a), genres(b));
+    match a.union(&b).count() {
+        0 => 0.0,
+        union => a.intersection(&b).count() as f32 / union as f32,
+    }
+}
+
+/// 1 for the same BPM down to 0 at `RECOMMEND_BPM_RANGE` difference, half / double tempo count as the same
+fn bpm_proximity(a: Option<i64>, b: Option<i64>) -> f32 {
+    let (Some(a), Some(b)) = (a, b) else { return 0.0 };
+    let (a, b) = (a as f32, b as f32);
+    let difference = [b, b * 2.0, b / 2.0].iter().map(|b| (a - b).abs()).fold(f32::INFINITY, f32::min);
+    (1.0 - difference / RECOMMEND_BPM_RANGE).max(0.0)
+}
+
+/// 1 for the same key, 0.5 for harmonically compatible keys
+fn key_compatibility(a: Option<&str>, b: Option<&str>) -> f32 {
+    let (Some(a), Some(b)) = (a.and_then(camelot::to_camelot), b.and_then(camelot::to_camelot)) else { return 0.0 };
+    match (a == b, camelot::compatible(a, b)) {
+        (true, _) => 1.0,
+        (false, true) => 0.5,
+        _ => 0.0,
+    }
+}
+
+/// Hashed, normalized counts of lowercase character trigrams
+fn ngram_embedding(text: &str) -> Vec<f32> {
+    let chars: Vec<char> = format!("  {} ", text.to_lowercase()).chars().collect();
+    let mut embedding = vec![0.0; NGRAM_DIMENSIONS];
+    for trigram in chars.windows(3) {
+        // FNV-1a
+        let hash = trigram.iter().fold(0xcbf29ce484222325u64, |h, c| (h ^ *c as u64).wrapping_mul(0x100000001b3));
+        embedding[(hash % NGRAM_DIMENSIONS as u64) as usize] += 1.0;
+    }
+    let norm = embedding.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        embedding.iter_mut().for_each(|v| *v /= norm);
+    }
+    embedding
+}
+
+/// Text used for track embedding
+fn track_text(track: &Track) -> String {
+    format!("{} {}", track.artists.join(" "), track.title)
+}
+
+/// Sentence-transformer (BERT) ONNX model with mean pooling
+pub struct TextModel {
+    #[cfg(feature = "onnx")]
+    tokenizer: WordPiece,
+    #[cfg(feature = "onnx")]
+    plan: TypedRunnableModel<TypedModel>,
+}
+
+impl TextModel {
+    /// Default model location (all-MiniLM-L6-v2 ONNX export)
+    pub fn default_dir() -> PathBuf {
+        onetagger_shared::Settings::get_folder()
+            .unwrap_or_else(|_| PathBuf::from("."))
+            .join("models")
+            .join("all-MiniLM-L6-v2")
+    }
+
+    /// Load `model.onnx` and `vocab.txt` from directory
+    pub fn load(dir: &Path) -> Result<TextModel> {
+        for file in ["model.onnx", "vocab.txt"] {
+            if !dir.join(file).is_file() {
+                return Err(anyhow!("Text embedding model file missing: {} (download the all-MiniLM-L6-v2 ONNX export)", dir.join(file).display()));
+            }
+        }
+        Self::load_model(dir)
+    }
+
+    #[cfg(feature = "onnx")]
+    fn load_model(dir: &Path) -> Result<TextModel> {
+        let tokenizer = WordPiece::new(&std::fs::read_to_string(dir.join("vocab.txt"))?)?;
+        let plan = tract_onnx::onnx()
+            .model_for_path(dir.join("model.onnx"))?
+            .into_optimized()?
+            .into_runnable()?;
+        info!("Loaded text embedding model from {}", dir.display());
+        Ok(TextModel { tokenizer, plan })
+    }
+
+    #[cfg(not(feature = "onnx"))]
+    fn load_model(dir: &Path) -> Result<TextModel> {
+        Err(anyhow!("Can't load {}: ONNX support not enabled, build with the `onnx` feature", dir.display()))
+    }
+
+    /// Mean pooled, L2 normalized token embeddings
+    #[cfg(feature = "onnx")]
+    pub fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        let ids = self.tokenizer.encode(text);
+        let n = ids.len();
+        let inputs = [ids, vec![1i64; n], vec![0i64; n]];
+        // Some exports don't have token_type_ids
+        let inputs: TVec<TValue> = inputs.into_iter()
+            .take(self.plan.model().inputs.len())
+            .map(|input| Ok(Tensor::from(tract_ndarray::Array2::from_shape_vec((1, n), input)?).into()))
+            .collect::<Result<_>>()?;
+        let output = self.plan.run(inputs)?;
+        let hidden = output[0].to_array_view::<f32>()?;
+        let shape = hidden.shape().to_vec();
+        if shape.len() != 3 || shape[1] != n {
+            return Err(anyhow!("Unexpected text model output shape {:?}", shape));
+        }
+
+        let mut embedding = vec![0.0f32; shape[2]];
+        for token in 0..n {
+            for (i, value) in embedding.iter_mut().enumerate() {
+                *value += hidden[[0, token, i]] / n as f32;
+            }
+        }
+        let norm = embedding.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            embedding.iter_mut().for_each(|v| *v /= norm);
+        }
+        Ok(embedding)
+    }
+
+    #[cfg(not(feature = "onnx"))]
+    pub fn embed(&self, _text: &str) -> Result<Vec<f32>> {
+        Err(anyhow!("ONNX support not enabled, build with the `onnx` feature"))
+    }
+}
+
+/// BERT uncased WordPiece tokenizer
+#[cfg(feature = "onnx")]
+struct WordPiece {
+    vocab: HashMap<String, i64>,
+    unknown: i64,
+    cls: i64,
+    sep: i64,
+}
+
+#[cfg(feature = "onnx")]
+impl WordPiece {
+    /// Vocabulary with one token per line (line number = ID)
+    fn new(vocab: &str) -> Result<WordPiece> {
+        let vocab: HashMap<String, i64> = vocab.lines().enumerate().map(|(i, t)| (t.trim().to_string(), i as i64)).collect();
+        let id = |token: &str| vocab.get(token).copied().ok_or(anyhow!("Vocabulary is missing {}", token));
+        Ok(WordPiece { unknown: id("[UNK]")?, cls: id("[CLS]")?, sep: id("[SEP]")?, vocab })
+    }
+
+    /// Token IDs including [CLS] and [SEP]
+    fn encode(&self, text: &str) -> Vec<i64> {
+        let mut ids = vec![self.cls];
+        // Split on whitespace and punctuation, punctuation is its own word
+        let lowercase = text.to_lowercase();
+        let words = lowercase
+            .split(|c: char| c.is_whitespace())
+            .flat_map(|w| w.split_inclusive(|c: char| c.is_ascii_punctuation()))
+            .flat_map(|w| match w.char_indices().last() {
+                Some((i, c)) if c.is_ascii_punctuation() && i > 0 => vec![&w[..i], &w[i..]],
+                _ => vec![w],
+            })
+            .filter(|w| !w.is_empty());
+        for word in words {
+            ids.extend(self.word_pieces(word));
+        }
+        ids.truncate(MAX_TOKENS - 1);
+        ids.push(self.sep);
+        ids
+    }
+
+    /// Greedy longest-match-first subwords of a word
+    fn word_pieces(&self, word: &str) -> Vec<i64> {
+        let mut pieces = vec![];
+        let mut start = 0;
+        while start < word.len() {
+            let piece = word[start..].char_indices().map(|(i, c)| start + i + c.len_utf8()).rev()
+                .find_map(|end| {
+                    let piece = match start {
+                        0 => word[start..end].to_string(),
+                        _ => format!("##{}", &word[start..end]),
+                    };
+                    Some((end, *self.vocab.get(&piece)?))
+                });
+            match piece {
+                Some((end, id)) => {
+                    pieces.push(id);
+                    start = end;
+                },
+                None => return vec![self.unknown],
+            }
+        }
+        pieces
+    }
 }
 
 impl Default for SemanticMatcher {
@@ -79,13 +473,47 @@ impl Default for SemanticMatcher {
     }
 }
 
-/// Calculate cosine similarity between two vectors
-fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+/// Vector comparison metric
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Metric {
+    Cosine,
+    Euclidean,
+    DotProduct,
+}
+
+/// Similarity of two vectors using metric (higher = more similar), Euclidean distance is mapped to 0-1
+pub fn similarity(a: &[f32], b: &[f32], metric: Metric) -> f32 {
+    match metric {
+        Metric::Cosine => cosine_similarity(a, b),
+        Metric::Euclidean => 1.0 / (1.0 + euclidean_distance(a, b)),
+        Metric::DotProduct => dot_product(a, b),
+    }
+}
+
+/// Euclidean distance between two vectors, infinite for mismatched lengths
+pub fn euclidean_distance(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return f32::INFINITY;
+    }
+    a.iter().zip(b.iter()).map(|(x, y)| (x - y) * (x - y)).sum::<f32>().sqrt()
+}
+
+/// Dot product of two vectors, 0 for mismatched lengths
+pub fn dot_product(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    a.iter().zip(b.iter()).map(|(x, y)| x * y).sum()
+}
+
+/// Calculate cosine similarity between two vectors (clamped to 0-1, 0 for mismatched lengths)
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
     if a.len() != b.len() {
         return 0.0;
     }
 
-    let dot_product: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+    let dot = dot_product(a, b);
     let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
     let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
 
@@ -93,13 +521,124 @@ fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
         return 0.0;
     }
 
-    (dot_product / (norm_a * norm_b)).clamp(0.0, 1.0)
+    (dot / (norm_a * norm_b)).clamp(0.0, 1.0)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_recommend() {
+        let track = |title: &str, genre: &str, bpm: i64, key: &str| Track {
+            title: title.to_string(),
+            url: format!("/music/{}.mp3", title),
+            genres: vec![genre.to_string()],
+            bpm: Some(bpm),
+            key: Some(key.to_string()),
+            ..Default::default()
+        };
+        let library = vec![
+            track("Seed", "Techno", 128, "8A"),
+            track("Ballad", "Pop", 80, "3B"),
+            track("Warehouse", "techno", 127, "9A"),
+            track("Anthem", "Trance", 138, "2B"),
+            track("Basement", "Techno", 130, "5B"),
+        ];
+
+        let dir = tempfile::tempdir().unwrap();
+        let matcher = SemanticMatcher::new().with_text_model(dir.path());
+        let recommended = matcher.recommend(&library[0], &library, 10);
+        let order: Vec<usize> = recommended.iter().map(|(i, _)| *i).collect();
+        assert_eq!(order.len(), 4);
+        assert!(!order.contains(&0));
+        assert_eq!(&order[..2], &[2, 4]);
+        assert!(recommended.windows(2).all(|w| w[0].1 >= w[1].1));
+
+        assert_eq!(matcher.recommend(&library[0], &library, 1).len(), 1);
+        assert_eq!(bpm_proximity(Some(70), Some(140)), 1.0);
+        assert_eq!(key_compatibility(Some("Am"), Some("C")), 0.5);
+    }
+
+    #[cfg(feature = "onnx")]
+    #[test]
+    fn test_word_piece() {
+        let vocab = ["[PAD]", "[UNK]", "[CLS]", "[SEP]", "daft", "punk", "one", "more", "time", "har", "##der", "!"].join("\n");
+        let tokenizer = WordPiece::new(&vocab).unwrap();
+        assert_eq!(tokenizer.encode("Daft Punk  HARDER!"), vec![2, 4, 5, 9, 10, 11, 3]);
+        assert_eq!(tokenizer.encode("xyz time"), vec![2, 1, 8, 3]);
+    }
+
+    #[test]
+    fn test_missing_text_model() {
+        let dir = tempfile::tempdir().unwrap();
+        let generator = EmbeddingGenerator::new().with_text_model(dir.path());
+        let error = generator.generate_text_embedding("Daft Punk One More Time").unwrap_err();
+        assert!(error.to_string().contains("model.onnx"));
+    }
+
+    /// Needs the all-MiniLM-L6-v2 ONNX export (`model.onnx` + `vocab.txt`) in `ONETAGGER_TEXT_MODEL`
+    #[cfg(feature = "onnx")]
+    #[test]
+    #[ignore]
+    fn test_text_embedding_similarity() {
+        let generator = EmbeddingGenerator::new().with_text_model(std::env::var("ONETAGGER_TEXT_MODEL").unwrap());
+        let daft = generator.generate_text_embedding("Daft Punk One More Time").unwrap();
+        let harder = generator.generate_text_embedding("Daft Punk Harder Better").unwrap();
+        let bach = generator.generate_text_embedding("Bach Cello Suite").unwrap();
+        assert_eq!(daft.len(), 384);
+        assert!(cosine_similarity(&daft, &harder) > cosine_similarity(&daft, &bach));
+    }
+
+    #[test]
+    fn test_similarity_without_text_model() {
+        let track = |artist: &str, title: &str| Track { artists: vec![artist.to_string()], title: title.to_string(), ..Default::default() };
+        let (daft, harder, bach) = (track("Daft Punk", "One More Time"), track("Daft Punk", "Harder Better"), track("Bach", "Cello Suite"));
+        let dir = tempfile::tempdir().unwrap();
+        let mut matcher = SemanticMatcher::new().with_text_model(dir.path());
+        assert!(matcher.calculate_similarity(&daft, &harder).unwrap() > matcher.calculate_similarity(&daft, &bach).unwrap());
+
+        matcher.index_track("harder", &harder).unwrap();
+        matcher.index_track("bach", &bach).unwrap();
+        let similar = matcher.find_similar(&daft, 1).unwrap();
+        assert_eq!(similar.len(), 1);
+        assert_eq!(similar[0].0, "harder");
+    }
+
+    #[test]
+    fn test_combined_embedding() {
+        let generator = EmbeddingGenerator::new();
+        let features = AudioFeatures { bpm: Some(128.0), spectral_centroid: 2500.0, rms_energy: 0.3, mfccs: vec![1.0, -2.0, 0.5], ..Default::default() };
+        let other = AudioFeatures { bpm: Some(90.0), spectral_centroid: 800.0, rms_energy: 0.05, mfccs: vec![-3.0, 1.0, 2.0], ..Default::default() };
+        let audio = generator.generate_audio_embedding(&features).unwrap();
+        // Unrelated titles
+        let (text1, text2) = (vec![1.0, 0.0, 0.0], vec![0.0, 0.0, 1.0]);
+
+        let weights = EmbeddingWeights::default();
+        let a = combine(audio.clone(), text1.clone(), weights);
+        let b = combine(audio.clone(), text2, weights);
+        let c = combine(generator.generate_audio_embedding(&other).unwrap(), text1, weights);
+        assert!((a.iter().map(|v| v * v).sum::<f32>() - 1.0).abs() < 1e-4);
+        assert!((cosine_similarity(&a, &b) - 0.6).abs() < 1e-4);
+        // Identical audio outweighs identical title
+        assert!(cosine_similarity(&a, &b) > cosine_similarity(&a, &c));
+    }
+
+    #[test]
+    fn test_rank_matches() {
+        let track = |artist: &str, title: &str| Track { artists: vec![artist.to_string()], title: title.to_string(), ..Default::default() };
+        let query = track("Daft Punk", "One More Time");
+        let candidates = vec![
+            track("Johann Sebastian Bach", "Cello Suite No. 1"),
+            track("Daft Pnuk", "One Mor Time (Radio Edit)"),
+        ];
+        let dir = tempfile::tempdir().unwrap();
+        let ranked = SemanticMatcher::new().with_text_model(dir.path()).rank_matches(&query, &candidates);
+        assert_eq!(ranked.len(), 2);
+        assert_eq!(ranked[0].0, 1);
+        assert!(ranked[0].1 > ranked[1].1);
+    }
+
     #[test]
     fn test_cosine_similarity() {
         let a = vec![1.0, 0.0, 0.0];
@@ -110,4 +649,22 @@ mod tests {
         let d = vec![0.0, 1.0];
         assert_eq!(cosine_similarity(&c, &d), 0.0);
     }
+
+    #[test]
+    fn test_similarity_metrics() {
+        let a = vec![1.0, 2.0, 3.0];
+        let b = vec![2.0, 1.0, 0.5];
+        assert_eq!(similarity(&a, &b, Metric::Cosine), cosine_similarity(&a, &b));
+        assert_eq!(dot_product(&a, &b), 5.5);
+        assert_eq!(euclidean_distance(&a, &a), 0.0);
+        assert_eq!(similarity(&a, &a, Metric::Euclidean), 1.0);
+        assert!(similarity(&a, &b, Metric::Euclidean) < 1.0);
+
+        // Mismatched lengths
+        let c = vec![1.0, 2.0];
+        assert_eq!(cosine_similarity(&a, &c), 0.0);
+        assert_eq!(dot_product(&a, &c), 0.0);
+        assert_eq!(euclidean_distance(&a, &c), f32::INFINITY);
+        assert_eq!(similarity(&a, &c, Metric::Euclidean), 0.0);
+    }
 }
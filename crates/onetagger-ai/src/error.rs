@@ -0,0 +1,97 @@
+//! Error Module
+//!
+//! Typed errors returned by the public API, internals use anyhow and are converted at the boundary
+
+use std::time::Duration;
+use crate::api::HttpError;
+use crate::config::{APIProvider, ConfigError};
+use crate::features::UnsupportedAudio;
+use crate::Cancelled;
+
+/// Error of the AI module
+#[derive(Debug)]
+pub enum AiError {
+    /// Provider requires an API key but none is configured
+    MissingApiKey(APIProvider),
+    /// Provider kept rate limiting (HTTP 429) after retries
+    RateLimited { retry_after: Option<Duration> },
+    /// Non-success response from the provider
+    ProviderError { status: u16, body: String },
+    /// Audio can't be decoded (DRM / unsupported codec)
+    Decode(UnsupportedAudio),
+    /// Aborted through a `CancellationToken`
+    Cancelled,
+    /// Invalid configuration
+    Config(Vec<ConfigError>),
+    /// IO, network, parsing...
+    Other(anyhow::Error),
+}
+
+impl std::fmt::Display for AiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AiError::MissingApiKey(provider) => ConfigError::MissingApiKey(*provider).fmt(f),
+            AiError::RateLimited { retry_after: Some(retry_after) } => write!(f, "Rate limited, retry after {}s", retry_after.as_secs()),
+            AiError::RateLimited { retry_after: None } => write!(f, "Rate limited"),
+            AiError::ProviderError { status, body } => write!(f, "API error ({}): {}", status, body),
+            AiError::Decode(unsupported) => unsupported.fmt(f),
+            AiError::Cancelled => Cancelled.fmt(f),
+            AiError::Config(errors) => {
+                let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "Invalid AI config: {}", errors.join("; "))
+            },
+            AiError::Other(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for AiError {}
+
+impl From<anyhow::Error> for AiError {
+    /// Recover the typed variants from internal errors
+    fn from(e: anyhow::Error) -> Self {
+        let e = match e.downcast::<HttpError>() {
+            Ok(http) if http.status == 429 => return AiError::RateLimited { retry_after: http.retry_after },
+            Ok(http) => return AiError::ProviderError { status: http.status, body: http.body },
+            Err(e) => e,
+        };
+        let e = match e.downcast::<ConfigError>() {
+            Ok(ConfigError::MissingApiKey(provider)) => return AiError::MissingApiKey(provider),
+            Ok(config) => return AiError::Config(vec![config]),
+            Err(e) => e,
+        };
+        let e = match e.downcast::<UnsupportedAudio>() {
+            Ok(unsupported) => return AiError::Decode(unsupported),
+            Err(e) => e,
+        };
+        match e.is::<Cancelled>() {
+            true => AiError::Cancelled,
+            false => AiError::Other(e),
+        }
+    }
+}
+
+impl From<std::io::Error> for AiError {
+    fn from(e: std::io::Error) -> Self {
+        AiError::Other(e.into())
+    }
+}
+
+impl From<Vec<ConfigError>> for AiError {
+    fn from(errors: Vec<ConfigError>) -> Self {
+        AiError::Config(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_anyhow() {
+        let error = AiError::from(anyhow::Error::from(HttpError { status: 503, retry_after: None, body: "down".to_string() }));
+        assert!(matches!(error, AiError::ProviderError { status: 503, .. }));
+        assert!(matches!(AiError::from(anyhow::Error::from(Cancelled)), AiError::Cancelled));
+        assert!(matches!(AiError::from(anyhow!("io")), AiError::Other(_)));
+    }
+}
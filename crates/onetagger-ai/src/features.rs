@@ -3,8 +3,45 @@
 //! Extracts audio features for AI analysis
 
 use anyhow::Result;
-use std::path::Path;
+use crate::cache::{Cache, FeatureCache};
+use crate::config::AIConfig;
+use crate::error::AiError;
+use crate::dsp::{self, Spectrogram};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::Read;
+use std::panic::AssertUnwindSafe;
+use rayon::prelude::*;
+use std::path::{Path, PathBuf};
 use serde::{Serialize, Deserialize};
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, SeekMode, SeekTo};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use symphonia::core::units::Time;
+
+/// Pitch class names (sharps) starting from C
+const PITCH_CLASSES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+/// Krumhansl-Kessler key profiles (starting from tonic)
+const MAJOR_PROFILE: [f32; 12] = [6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88];
+const MINOR_PROFILE: [f32; 12] = [6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17];
+
+/// Samples at or above this level are considered full scale
+const CLIP_LEVEL: f32 = 0.999;
+/// Minimum consecutive full scale samples to count as clipping (single peaks hitting the ceiling are fine)
+const MIN_CLIP_RUN: usize = 3;
+
+/// Length of the frames in `AudioFeatures::rms_frames` (seconds)
+pub const RMS_FRAME_SECS: f32 = 1.0;
+
+/// Chroma analysis range (MIDI notes C2 - B6)
+const CHROMA_MIDI_RANGE: std::ops::Range<u8> = 36..96;
+/// Chroma analysis frame size (at the decimated sample rate)
+const CHROMA_FRAME_SIZE: usize = 4096;
 
 /// Complete audio feature set extracted from a track
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -12,7 +49,17 @@ use serde::{Serialize, Deserialize};
 pub struct AudioFeatures {
     pub bpm: Option<f32>,
     pub key: Option<String>,
+    /// Correlation of the chroma with the detected key's profile (0-1), `None` for keys read from tags
+    #[serde(default)]
+    pub key_confidence: Option<f32>,
+    /// Key in Camelot notation (e.g. `8A`)
+    #[serde(default)]
+    pub camelot: Option<String>,
+    /// Full track duration (seconds)
     pub duration: f32,
+    /// Duration of the audio the features were computed from (seconds)
+    #[serde(default)]
+    pub analyzed_duration: f32,
     pub spectral_centroid: f32,
     pub spectral_rolloff: f32,
     pub spectral_flux: f32,
@@ -22,6 +69,27 @@ pub struct AudioFeatures {
     pub chroma: Vec<f32>,
     pub onset_strength: f32,
     pub tempo_stability: f32,
+    /// RMS per `RMS_FRAME_SECS` frame of the analyzed audio
+    #[serde(default)]
+    pub rms_frames: Vec<f32>,
+    /// Detected beat times (seconds from the start of the analyzed audio), empty without a stable beat
+    #[serde(default)]
+    pub beat_positions: Vec<f32>,
+    /// Beat grid alignment / consistency of the onsets
+    #[serde(default)]
+    pub rhythm: Option<RhythmStats>,
+    /// Peak / clipping / crest factor measurements
+    #[serde(default)]
+    pub dynamics: Option<DynamicsStats>,
+    /// EBU R128 integrated loudness of the mono downmix (LUFS)
+    #[serde(default)]
+    pub lufs: Option<f32>,
+    /// Share of the audio with vocals (0-1), see `Spectrogram::vocal_presence`
+    #[serde(default)]
+    pub vocal_presence: Option<f32>,
+    /// Spectral values are stubbed defaults rather than measured
+    #[serde(default)]
+    pub is_placeholder: bool,
 }
 
 impl Default for AudioFeatures {
@@ -29,7 +97,10 @@ impl Default for AudioFeatures {
         Self {
             bpm: None,
             key: None,
+            key_confidence: None,
+            camelot: None,
             duration: 0.0,
+            analyzed_duration: 0.0,
             spectral_centroid: 0.0,
             spectral_rolloff: 0.0,
             spectral_flux: 0.0,
@@ -39,25 +110,195 @@ impl Default for AudioFeatures {
             chroma: vec![0.0; 12],
             onset_strength: 0.0,
             tempo_stability: 0.0,
+            rms_frames: vec![],
+            beat_positions: vec![],
+            rhythm: None,
+            dynamics: None,
+            lufs: None,
+            vocal_presence: None,
+            is_placeholder: false,
+        }
+    }
+}
+
+/// Onset strength within this distance of a beat is on the beat grid (seconds)
+const BEAT_WINDOW_SECS: f32 = 0.05;
+
+/// Regularity of the onsets against the tracked beats
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RhythmStats {
+    /// Share of the onset strength within `BEAT_WINDOW_SECS` of a beat (0-1)
+    pub beat_alignment: f32,
+    /// Evenness of the beat strengths, 1 - mean deviation from the median relative to the median (0-1)
+    pub beat_consistency: f32,
+}
+
+impl RhythmStats {
+    /// Measure onset envelope (frames of `frame_secs`) against beat times, zeros without beats
+    pub fn analyze(envelope: &[f32], frame_secs: f32, beats: &[f32]) -> RhythmStats {
+        let total: f32 = envelope.iter().sum();
+        if beats.len() < 2 || total <= 0.0 {
+            return RhythmStats { beat_alignment: 0.0, beat_consistency: 0.0 };
+        }
+
+        // Strength of each beat = envelope peak around it
+        let window = (BEAT_WINDOW_SECS / frame_secs).round() as usize;
+        let mut on_grid = vec![false; envelope.len()];
+        let mut strengths: Vec<f32> = beats.iter().map(|beat| {
+            let frame = (beat / frame_secs).round() as usize;
+            let range = frame.saturating_sub(window).min(envelope.len())..(frame + window + 1).min(envelope.len());
+            on_grid[range.clone()].iter_mut().for_each(|f| *f = true);
+            envelope[range].iter().copied().fold(0.0, f32::max)
+        }).collect();
+        let aligned: f32 = envelope.iter().zip(&on_grid).filter(|(_, on)| **on).map(|(e, _)| e).sum();
+
+        strengths.sort_by(f32::total_cmp);
+        let median = strengths[strengths.len() / 2];
+        let beat_consistency = match median > 0.0 {
+            true => 1.0 - strengths.iter().map(|s| (s - median).abs()).sum::<f32>() / strengths.len() as f32 / median,
+            false => 0.0,
+        };
+        RhythmStats {
+            beat_alignment: (aligned / total).clamp(0.0, 1.0),
+            beat_consistency: beat_consistency.clamp(0.0, 1.0),
+        }
+    }
+}
+
+/// Loudness / clipping measurements of the decoded audio
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DynamicsStats {
+    /// Sample peak (0.0 - 1.0)
+    pub peak: f32,
+    /// Peak to RMS ratio in dB
+    pub crest_factor_db: f32,
+    /// Number of sustained full scale runs
+    pub clip_runs: usize,
+    /// Ratio of samples inside sustained full scale runs
+    pub clipped_ratio: f32,
+}
+
+impl DynamicsStats {
+    /// Measure dynamics of mono samples
+    pub fn analyze(samples: &[f32]) -> DynamicsStats {
+        let mut peak = 0.0f32;
+        let mut sum_squares = 0.0f64;
+        let (mut clip_runs, mut clipped, mut run) = (0, 0, 0);
+        for sample in samples {
+            let abs = sample.abs();
+            peak = peak.max(abs);
+            sum_squares += (abs as f64).powi(2);
+
+            if abs >= CLIP_LEVEL {
+                run += 1;
+                continue;
+            }
+            if run >= MIN_CLIP_RUN {
+                clip_runs += 1;
+                clipped += run;
+            }
+            run = 0;
+        }
+        if run >= MIN_CLIP_RUN {
+            clip_runs += 1;
+            clipped += run;
+        }
+
+        let rms = (sum_squares / samples.len().max(1) as f64).sqrt() as f32;
+        DynamicsStats {
+            peak,
+            crest_factor_db: if rms > 0.0 { 20.0 * (peak / rms).log10() } else { 0.0 },
+            clip_runs,
+            clipped_ratio: clipped as f32 / samples.len().max(1) as f32,
         }
     }
 }
 
 /// Feature extractor
 pub struct FeatureExtractor {
-    _sample_rate: u32,
+    sample_rate: u32,
+    /// Analyze only this many seconds
+    window_secs: Option<f32>,
+    /// Skip this many seconds at the start (intro)
+    offset_secs: f32,
+    /// Threads used for batch extraction
+    threads: usize,
+    /// Used by `extract_cached` when no cache is passed
+    cache: Option<FeatureCache>,
+    /// Harmonic-percussive separation before analysis
+    hpss: bool,
 }
 
 impl FeatureExtractor {
     pub fn new() -> Self {
         Self {
-            _sample_rate: 44100,
+            sample_rate: 44100,
+            window_secs: None,
+            offset_secs: 0.0,
+            threads: num_cpus::get(),
+            cache: None,
+            hpss: false,
         }
     }
 
+    /// Extractor with analysis window from config
+    pub fn from_config(config: &AIConfig) -> Self {
+        Self::new()
+            .with_window(config.analysis_window_secs, config.analysis_offset_secs)
+            .with_threads(config.max_threads)
+            .with_hpss(config.hpss)
+    }
+
+    /// Number of threads for `extract_batch`
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Decode and analyze only `window_secs` seconds starting at `offset_secs`
+    pub fn with_window(mut self, window_secs: Option<f32>, offset_secs: f32) -> Self {
+        self.window_secs = window_secs.filter(|w| *w > 0.0);
+        self.offset_secs = offset_secs.max(0.0);
+        self
+    }
+
+    /// Analyze chroma and key on the harmonic component, onsets and beats on the percussive one
+    pub fn with_hpss(mut self, hpss: bool) -> Self {
+        self.hpss = hpss;
+        self
+    }
+
     /// Extract features from an audio file
-    pub fn extract(&self, path: &Path) -> Result<AudioFeatures> {
+    pub fn extract(&self, path: &Path) -> Result<AudioFeatures, AiError> {
+        Ok(self.extract_file(path)?)
+    }
+
+    /// Extract features from already decoded mono samples
+    pub fn extract_from_samples(&self, samples: &[f32], sample_rate: u32) -> Result<AudioFeatures, AiError> {
+        Ok(self.features_from_samples(samples, sample_rate, AudioFeatures::default())?)
+    }
+
+    /// Run the feature pipeline on mono samples, `features` can be prefilled with tagged BPM / key
+    fn features_from_samples(&self, samples: &[f32], sample_rate: u32, mut features: AudioFeatures) -> Result<AudioFeatures> {
+        if samples.is_empty() || sample_rate == 0 {
+            return Err(anyhow!("No audio samples to analyze"));
+        }
+        let samples = match sample_rate == self.sample_rate {
+            true => Cow::Borrowed(samples),
+            false => Cow::Owned(dsp::resample(samples, sample_rate, self.sample_rate)),
+        };
+        self.analyze_samples(&samples, self.sample_rate, &mut features);
+        features.camelot = features.key.as_deref().and_then(key_to_camelot);
+        Ok(features)
+    }
+
+    fn extract_file(&self, path: &Path) -> Result<AudioFeatures> {
         info!("Extracting features from: {}", path.display());
+        if !path.is_file() {
+            return Err(anyhow!("File not found: {}", path.display()));
+        }
 
         let mut features = AudioFeatures::default();
 
@@ -84,21 +325,31 @@ impl FeatureExtractor {
             }
         }
 
-        // Use symphonia to read basic audio properties
-        // Note: Full audio analysis (BPM detection, key detection, spectral analysis)
-        // is complex and better left to specialized tools like MixedInKey.
-        // The AI tagger focuses on LLM-based tag suggestions.
 
-        // Set placeholder values for spectral features
-        // These would require complex DSP analysis to compute accurately
-        // For now, we use sensible defaults that won't break the LLM prompts
-        features.spectral_centroid = 1500.0;
-        features.spectral_rolloff = 3000.0;
-        features.spectral_flux = 0.5;
-        features.zero_crossing_rate = 0.3;
-        features.rms_energy = 0.7;
-        features.onset_strength = 0.6;
-        features.tempo_stability = 0.8;
+        // Spectral features + key estimation from the audio itself
+        match self.decode(path) {
+            Ok(decoded) => {
+                features = self.features_from_samples(&decoded.samples, decoded.sample_rate, features)?;
+                if let Some(duration) = decoded.duration {
+                    features.duration = duration;
+                }
+            },
+            // Can't be analyzed at all, let the caller skip it
+            Err(e) if e.is::<UnsupportedAudio>() => return Err(e),
+            Err(e) => {
+                warn!("Failed decoding {}: {}. Using placeholder audio features.", path.display(), e);
+                // Sensible defaults that won't break the LLM prompts
+                features.spectral_centroid = 1500.0;
+                features.spectral_rolloff = 3000.0;
+                features.spectral_flux = 0.5;
+                features.zero_crossing_rate = 0.3;
+                features.rms_energy = 0.7;
+                features.onset_strength = 0.6;
+                features.tempo_stability = 0.8;
+                features.is_placeholder = true;
+                features.camelot = features.key.as_deref().and_then(key_to_camelot);
+            },
+        }
 
         // Note: If BPM/key are not found in tags, that's OK
         // The LLM can still suggest tags based on genre/mood/context
@@ -113,6 +364,418 @@ impl FeatureExtractor {
     }
 }
 
+/// File which can't be decoded because of DRM or an unsupported codec, should be skipped rather than treated as failure
+#[derive(Debug, Clone)]
+pub struct UnsupportedAudio {
+    pub path: PathBuf,
+    /// Codec / format name
+    pub codec: String,
+    /// Copy protected (e.g. iTunes FairPlay)
+    pub drm: bool,
+}
+
+impl std::fmt::Display for UnsupportedAudio {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.drm {
+            true => write!(f, "{} is DRM protected ({})", self.path.display(), self.codec),
+            false => write!(f, "{} uses an unsupported codec ({})", self.path.display(), self.codec),
+        }
+    }
+}
+
+impl std::error::Error for UnsupportedAudio {}
+
+impl UnsupportedAudio {
+    fn new(path: &Path, codec: impl Into<String>) -> Self {
+        Self { path: path.to_owned(), codec: codec.into(), drm: false }
+    }
+
+    /// DRM protected file, detected by extension or protection atoms in the MP4 header
+    fn detect_drm(path: &Path) -> Option<UnsupportedAudio> {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_lowercase();
+        if extension == "m4p" {
+            return Some(Self { drm: true, ..Self::new(path, "AAC (FairPlay)") });
+        }
+        if !["m4a", "mp4", "m4b"].contains(&extension.as_str()) {
+            return None;
+        }
+        let mut header = vec![];
+        File::open(path).ok()?.take(64 * 1024).read_to_end(&mut header).ok()?;
+        match header.windows(4).any(|w| w == b"drms" || w == b"sinf") {
+            true => Some(Self { drm: true, ..Self::new(path, "AAC (FairPlay)") }),
+            false => None,
+        }
+    }
+}
+
+/// Name of the codec in a WAV header (symphonia only supports PCM / ADPCM / float / A-law / mu-law)
+fn wav_codec_name(path: &Path) -> Option<String> {
+    let mut header = vec![];
+    File::open(path).ok()?.take(4096).read_to_end(&mut header).ok()?;
+    if header.len() < 12 || &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        return None;
+    }
+    let fmt = header.windows(4).position(|w| w == b"fmt ")?;
+    let tag = u16::from_le_bytes(header.get(fmt + 8..fmt + 10)?.try_into().ok()?);
+    Some(match tag {
+        0x0055 => "MP3".to_string(),
+        0x0160..=0x0163 => "WMA".to_string(),
+        0x0092 => "Dolby AC3".to_string(),
+        0x00FF | 0x1610 => "AAC".to_string(),
+        0x0031 => "GSM 6.10".to_string(),
+        tag => format!("WAV format 0x{:04X}", tag),
+    })
+}
+
+/// Container / codec level properties of an audio file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioProperties {
+    pub codec: String,
+    pub sample_rate: u32,
+    pub channels: Option<usize>,
+    /// Duration (seconds)
+    pub duration: f32,
+    /// Average bitrate (kbps) estimated from file size
+    pub bitrate: u32,
+}
+
+impl AudioProperties {
+    /// Read properties from the container without decoding
+    pub fn probe(path: &Path) -> Result<AudioProperties> {
+        let size = std::fs::metadata(path)?.len();
+        let mss = MediaSourceStream::new(Box::new(File::open(path)?), Default::default());
+        let mut hint = Hint::new();
+        if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+            hint.with_extension(ext);
+        }
+        let probed = symphonia::default::get_probe().format(&hint, mss, &FormatOptions::default(), &MetadataOptions::default())?;
+        let track = probed.format.default_track().ok_or(anyhow!("No audio track found"))?;
+        let params = &track.codec_params;
+        let sample_rate = params.sample_rate.ok_or(anyhow!("Unknown sample rate"))?;
+        let duration = params.n_frames.map(|n| n as f32 / sample_rate as f32).unwrap_or(0.0);
+        let codec = symphonia::default::get_codecs().get_codec(params.codec)
+            .map(|d| d.short_name.to_string())
+            .unwrap_or(format!("{:?}", params.codec));
+
+        Ok(AudioProperties {
+            codec,
+            sample_rate,
+            channels: params.channels.map(|c| c.count()),
+            duration,
+            bitrate: match duration > 0.0 {
+                true => (size as f32 * 8.0 / duration / 1000.0).round() as u32,
+                false => 0,
+            },
+        })
+    }
+}
+
+/// Decoded mono audio
+struct Decoded {
+    samples: Vec<f32>,
+    sample_rate: u32,
+    /// Full duration of the file, if known from the container
+    duration: Option<f32>,
+}
+
+/// Decode audio file into mono samples at the file's sample rate, optionally only a window (offset, length) in seconds
+fn decode_mono(path: &Path, window: Option<(f32, f32)>) -> Result<Decoded> {
+    if let Some(drm) = UnsupportedAudio::detect_drm(path) {
+        return Err(drm.into());
+    }
+
+    let mss = MediaSourceStream::new(Box::new(File::open(path)?), Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+    let probed = match symphonia::default::get_probe().format(&hint, mss, &FormatOptions::default(), &MetadataOptions::default()) {
+        Ok(probed) => probed,
+        Err(SymphoniaError::Unsupported(e)) => {
+            return Err(UnsupportedAudio::new(path, wav_codec_name(path).unwrap_or(e.to_string())).into())
+        },
+        Err(e) => return Err(e.into()),
+    };
+    let mut format = probed.format;
+    let track = format.default_track().ok_or(anyhow!("No audio track found"))?;
+    let track_id = track.id;
+    let sample_rate = track.codec_params.sample_rate.ok_or(anyhow!("Unknown sample rate"))?;
+    let codecs = symphonia::default::get_codecs();
+    let mut decoder = match codecs.make(&track.codec_params, &DecoderOptions::default()) {
+        Ok(decoder) => decoder,
+        Err(SymphoniaError::Unsupported(_)) => {
+            let codec = codecs.get_codec(track.codec_params.codec)
+                .map(|d| d.short_name.to_string())
+                .unwrap_or(format!("{:?}", track.codec_params.codec));
+            return Err(UnsupportedAudio::new(path, codec).into());
+        },
+        Err(e) => return Err(e.into()),
+    };
+    let duration = track.codec_params.n_frames.map(|n| n as f32 / sample_rate as f32);
+
+    // Seek past the offset rather than decoding it
+    let mut skip = 0;
+    let mut max_samples = usize::MAX;
+    if let Some((offset, length)) = window {
+        max_samples = (length * sample_rate as f32) as usize;
+        if offset > 0.0 {
+            let time = Time::new(offset.trunc() as u64, offset.fract() as f64);
+            match format.seek(SeekMode::Accurate, SeekTo::Time { time, track_id: Some(track_id) }) {
+                Ok(seeked) => {
+                    skip = seeked.required_ts.saturating_sub(seeked.actual_ts) as usize;
+                    decoder.reset();
+                },
+                Err(e) => {
+                    debug!("Seeking failed: {}, decoding from start", e);
+                    skip = (offset * sample_rate as f32) as usize;
+                }
+            }
+        }
+    }
+
+    let mut samples = Vec::new();
+    while samples.len() < max_samples {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        match decoder.decode(&packet) {
+            Ok(decoded) => {
+                let spec = *decoded.spec();
+                let channels = spec.channels.count().max(1);
+                let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+                buffer.copy_interleaved_ref(decoded);
+                let mono = buffer.samples().chunks(channels).map(|f| f.iter().sum::<f32>() / channels as f32);
+                let skipped = skip.min(buffer.samples().len() / channels);
+                skip -= skipped;
+                samples.extend(mono.skip(skipped));
+            },
+            // Skip corrupted packets
+            Err(SymphoniaError::DecodeError(e)) => warn!("Decode error: {}", e),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    samples.truncate(max_samples);
+    Ok(Decoded { samples, sample_rate, duration })
+}
+
+/// Compute 12 bin chroma vector (normalized to max 1.0) using Goertzel filters at note frequencies
+/// Higher low-frequency resolution than the STFT chroma, used for key detection
+pub fn compute_chroma(samples: &[f32], sample_rate: u32) -> Vec<f32> {
+    let mut chroma = vec![0.0f32; 12];
+
+    // Decimate, notes above ~2kHz aren't needed
+    let factor = (sample_rate as usize / 11025).max(1);
+    let rate = sample_rate as f32 / factor as f32;
+    let decimated: Vec<f32> = samples.chunks(factor).map(|c| c.iter().sum::<f32>() / c.len() as f32).collect();
+
+    let window: Vec<f32> = (0..CHROMA_FRAME_SIZE)
+        .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / CHROMA_FRAME_SIZE as f32).cos())
+        .collect();
+    let coefficients: Vec<(usize, f32)> = CHROMA_MIDI_RANGE
+        .map(|midi| {
+            let frequency = 440.0 * 2.0f32.powf((midi as f32 - 69.0) / 12.0);
+            (midi as usize % 12, 2.0 * (2.0 * std::f32::consts::PI * frequency / rate).cos())
+        })
+        .collect();
+
+    for frame in decimated.chunks_exact(CHROMA_FRAME_SIZE) {
+        for (pitch_class, coeff) in &coefficients {
+            let (mut s1, mut s2) = (0.0f32, 0.0f32);
+            for (sample, w) in frame.iter().zip(&window) {
+                let s0 = sample * w + coeff * s1 - s2;
+                s2 = s1;
+                s1 = s0;
+            }
+            chroma[*pitch_class] += (s1 * s1 + s2 * s2 - coeff * s1 * s2).max(0.0);
+        }
+    }
+
+    let max = chroma.iter().cloned().fold(0.0, f32::max);
+    if max > 0.0 {
+        chroma.iter_mut().for_each(|c| *c /= max);
+    }
+    chroma
+}
+
+/// Estimate key from chroma using Krumhansl-Schmuckler profiles, returns e.g. `Am` or `F#`
+pub fn detect_key(chroma: &[f32]) -> Option<String> {
+    detect_key_confidence(chroma).map(|(key, _)| key)
+}
+
+/// Detect key and how well the chroma correlates with its profile (0-1)
+pub fn detect_key_confidence(chroma: &[f32]) -> Option<(String, f32)> {
+    if chroma.len() != 12 || chroma.iter().all(|c| *c <= 0.0) {
+        return None;
+    }
+
+    let mut best: Option<(f32, String)> = None;
+    for tonic in 0..12 {
+        for (profile, suffix) in [(&MAJOR_PROFILE, ""), (&MINOR_PROFILE, "m")] {
+            let rotated: Vec<f32> = (0..12).map(|i| profile[(i + 12 - tonic) % 12]).collect();
+            let score = correlation(chroma, &rotated);
+            if best.as_ref().map(|(s, _)| score > *s).unwrap_or(true) {
+                best = Some((score, format!("{}{}", PITCH_CLASSES[tonic], suffix)));
+            }
+        }
+    }
+    best.map(|(score, key)| (key, score.clamp(0.0, 1.0)))
+}
+
+/// Convert key (any common notation) to Camelot
+pub fn key_to_camelot(key: &str) -> Option<String> {
+    crate::camelot::to_camelot(key).map(|c| c.to_string())
+}
+
+/// Pearson correlation coefficient
+fn correlation(a: &[f32], b: &[f32]) -> f32 {
+    let n = a.len() as f32;
+    let (mean_a, mean_b) = (a.iter().sum::<f32>() / n, b.iter().sum::<f32>() / n);
+    let (mut cov, mut var_a, mut var_b) = (0.0, 0.0, 0.0);
+    for (x, y) in a.iter().zip(b) {
+        cov += (x - mean_a) * (y - mean_b);
+        var_a += (x - mean_a).powi(2);
+        var_b += (y - mean_b).powi(2);
+    }
+    match var_a * var_b {
+        v if v > 0.0 => cov / v.sqrt(),
+        _ => 0.0,
+    }
+}
+
+impl FeatureExtractor {
+    /// Extract features of multiple files in parallel, results are in input order.
+    /// Cached features are reused (`with_cache`)
+    pub fn extract_batch(&self, paths: &[PathBuf]) -> Vec<Result<AudioFeatures>> {
+        // Don't let a panic on one broken file take down the whole batch
+        let extract = |path: &PathBuf| {
+            std::panic::catch_unwind(AssertUnwindSafe(|| self.extract_cached(path, None)))
+                .unwrap_or_else(|_| Err(anyhow!("Feature extraction panicked on {}", path.display())))
+        };
+
+        match rayon::ThreadPoolBuilder::new().num_threads(self.threads).build() {
+            Ok(pool) => pool.install(|| paths.par_iter().map(extract).collect()),
+            Err(e) => {
+                warn!("Failed creating thread pool: {}, extracting sequentially", e);
+                paths.iter().map(extract).collect()
+            }
+        }
+    }
+
+    /// Cache kind identifying features extracted with these settings
+    pub(crate) fn cache_kind(&self) -> String {
+        format!("features:{}:{:?}:{}:{}", self.sample_rate, self.window_secs, self.offset_secs, self.hpss)
+    }
+
+    /// Cache features in custom storage
+    pub fn with_cache(mut self, cache: Box<dyn Cache>) -> Self {
+        self.cache = Some(FeatureCache::with_backend(cache));
+        self
+    }
+
+    /// Cache features in a feature cache (see `crate::feature_cache`)
+    pub(crate) fn with_feature_cache(mut self, cache: Option<FeatureCache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Extract features, reusing cached ones if the file didn't change.
+    /// Falls back to the extractor's own cache (`with_cache`)
+    pub fn extract_cached(&self, path: &Path, cache: Option<&FeatureCache>) -> Result<AudioFeatures> {
+        let cache = cache.or(self.cache.as_ref());
+        let kind = self.cache_kind();
+        if let Some(features) = cache.and_then(|c| c.get(path, &kind)) {
+            debug!("Using cached features for {}", path.display());
+            return Ok(features);
+        }
+        let features = self.extract_file(path)?;
+        if let Some(cache) = cache {
+            if let Err(e) = cache.put(path, &kind, &features) {
+                warn!("Failed caching features: {}", e);
+            }
+        }
+        Ok(features)
+    }
+
+    /// Decode any supported audio file into mono samples resampled to the extractor's sample rate
+    pub fn decode_samples(&self, path: &Path) -> Result<(Vec<f32>, u32)> {
+        let decoded = self.decode(path)?;
+        Ok((decoded.samples, decoded.sample_rate))
+    }
+
+    /// Decode (the analysis window of) a file and resample
+    fn decode(&self, path: &Path) -> Result<Decoded> {
+        let window = self.window_secs.map(|w| (self.offset_secs, w));
+        let decoded = decode_mono(path, window).map_err(|e| match e.is::<UnsupportedAudio>() {
+            true => e,
+            false => anyhow!("Corrupt or unreadable audio file {}: {}", path.display(), e),
+        })?;
+        if decoded.samples.is_empty() {
+            return Err(anyhow!("No audio decoded from {}", path.display()));
+        }
+        Ok(Decoded {
+            samples: dsp::resample(&decoded.samples, decoded.sample_rate, self.sample_rate),
+            sample_rate: self.sample_rate,
+            duration: decoded.duration,
+        })
+    }
+
+    /// Fill features measured from decoded mono samples
+    pub(crate) fn analyze_samples(&self, samples: &[f32], sample_rate: u32, features: &mut AudioFeatures) {
+        features.duration = samples.len() as f32 / sample_rate as f32;
+        features.analyzed_duration = features.duration;
+        let separated = self.hpss.then(|| dsp::hpss(samples));
+        self.extract_spectral_features(samples, separated.as_ref(), sample_rate, features);
+        features.dynamics = Some(DynamicsStats::analyze(samples));
+        features.lufs = dsp::integrated_loudness(samples, sample_rate);
+        if features.key.is_none() {
+            let harmonic = separated.as_ref().map(|(h, _)| h.as_slice()).unwrap_or(samples);
+            let detected = detect_key_confidence(&compute_chroma(harmonic, sample_rate));
+            features.key_confidence = detected.as_ref().map(|(_, confidence)| *confidence);
+            features.key = detected.map(|(key, _)| key);
+            debug!("Detected key: {:?}", features.key);
+        }
+    }
+
+    /// Fill STFT based spectral features, MFCCs and chroma.
+    /// With separated (harmonic, percussive) components chroma comes from the harmonic one, flux and onsets from the percussive one
+    fn extract_spectral_features(&self, samples: &[f32], separated: Option<&(Vec<f32>, Vec<f32>)>, sample_rate: u32, features: &mut AudioFeatures) {
+        let spectrogram = Spectrogram::new(samples, sample_rate, dsp::FFT_SIZE, dsp::HOP_SIZE);
+        let (harmonic, percussive) = match separated {
+            Some((harmonic, percussive)) => (
+                Some(Spectrogram::new(harmonic, sample_rate, dsp::FFT_SIZE, dsp::HOP_SIZE)),
+                Some(Spectrogram::new(percussive, sample_rate, dsp::FFT_SIZE, dsp::HOP_SIZE)),
+            ),
+            None => (None, None),
+        };
+        let rhythm = percussive.as_ref().unwrap_or(&spectrogram);
+        features.spectral_centroid = spectrogram.centroid();
+        features.spectral_rolloff = spectrogram.rolloff();
+        features.spectral_flux = rhythm.flux();
+        features.mfccs = spectrogram.mfccs();
+        features.vocal_presence = Some(spectrogram.vocal_presence());
+        features.chroma = harmonic.as_ref().unwrap_or(&spectrogram).chroma();
+        features.zero_crossing_rate = dsp::zero_crossing_rate(samples);
+        features.rms_energy = dsp::rms(samples);
+        features.rms_frames = dsp::frame_rms(samples, (sample_rate as f32 * RMS_FRAME_SECS) as usize);
+
+        // Onset strength = mean of the onset envelope, stability from the regularity of onsets
+        let envelope = rhythm.onset_envelope();
+        features.onset_strength = if envelope.is_empty() { 0.0 } else { envelope.iter().sum::<f32>() / envelope.len() as f32 };
+        let frame_secs = dsp::HOP_SIZE as f32 / sample_rate as f32;
+        features.tempo_stability = dsp::tempo_stability(&dsp::onset_times(&envelope, frame_secs));
+        features.beat_positions = dsp::track_beats(&envelope, frame_secs, features.bpm);
+        features.rhythm = Some(RhythmStats::analyze(&envelope, frame_secs, &features.beat_positions));
+        debug!("Spectral features: centroid={:.0}Hz, rolloff={:.0}Hz, flux={:.3}, onset strength={:.2}, tempo stability={:.2}",
+            features.spectral_centroid, features.spectral_rolloff, features.spectral_flux, features.onset_strength, features.tempo_stability);
+    }
+}
+
 impl Default for FeatureExtractor {
     fn default() -> Self {
         Self::new()
@@ -126,7 +789,265 @@ mod tests {
     #[test]
     fn test_feature_extractor_creation() {
         let extractor = FeatureExtractor::new();
-        assert_eq!(extractor._sample_rate, 44100);
+        assert_eq!(extractor.sample_rate, 44100);
+    }
+
+    /// A minor triad tone complex (A3, C4, E4)
+    fn a_minor_samples() -> Vec<f32> {
+        (0..44100 * 3).map(|i| {
+            let t = i as f32 / 44100.0;
+            [220.0f32, 261.63, 329.63].iter()
+                .map(|f| (t * f * 2.0 * std::f32::consts::PI).sin() / 3.0)
+                .sum()
+        }).collect()
+    }
+
+    #[test]
+    fn test_detect_key() {
+        let chroma = compute_chroma(&a_minor_samples(), 44100);
+        // A, C and E dominate
+        assert!(chroma[9] > 0.9 && chroma[0] > 0.9 && chroma[4] > 0.9);
+        assert!(chroma[1] < 0.1);
+        assert_eq!(detect_key(&chroma).unwrap(), "Am");
+        assert!(detect_key(&[0.0; 12]).is_none());
+        assert!(detect_key_confidence(&chroma).unwrap().1 > 0.5);
+
+        assert_eq!(key_to_camelot("Am").unwrap(), "8A");
+        assert_eq!(key_to_camelot("F#").unwrap(), "2B");
+        assert!(key_to_camelot("unknown").is_none());
+    }
+
+    #[test]
+    fn test_extract_key_from_audio() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("am.wav");
+        crate::test_utils::write_wav(&path, &a_minor_samples(), 44100);
+
+        let features = FeatureExtractor::new().extract(&path).unwrap();
+        assert_eq!(features.key.as_deref(), Some("Am"));
+        assert_eq!(features.camelot.as_deref(), Some("8A"));
+        assert!((features.duration - 3.0).abs() < 0.01);
+        assert!(!features.is_placeholder);
+        assert!(features.spectral_centroid > 200.0 && features.spectral_centroid < 400.0);
+    }
+
+    #[test]
+    fn test_dynamics_clipping() {
+        // Decaying 100Hz "kicks" twice per second
+        let clean: Vec<f32> = (0..44100 * 2).map(|i| {
+            let t = (i % 22050) as f32 / 44100.0;
+            (t * 100.0 * 2.0 * std::f32::consts::PI).sin() * (-t * 20.0).exp() * 0.9
+        }).collect();
+        let clipped: Vec<f32> = clean.iter().map(|s| (s * 4.0).clamp(-1.0, 1.0)).collect();
+
+        let clean = DynamicsStats::analyze(&clean);
+        assert_eq!(clean.clip_runs, 0);
+        assert!(clean.crest_factor_db > 10.0);
+
+        let clipped = DynamicsStats::analyze(&clipped);
+        assert!(clipped.clip_runs > 0);
+        assert!(clipped.clipped_ratio > 0.01);
+        assert!(clipped.crest_factor_db < clean.crest_factor_db);
+    }
+
+    #[test]
+    fn test_decode_wav() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stereo.wav");
+        let spec = hound::WavSpec { channels: 2, sample_rate: 48000, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for _ in 0..48000 * 2 {
+            writer.write_sample((0.4 * i16::MAX as f32) as i16).unwrap();
+            writer.write_sample((0.2 * i16::MAX as f32) as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let (samples, sample_rate) = FeatureExtractor::new().decode_samples(&path).unwrap();
+        assert_eq!(sample_rate, 44100);
+        assert!((samples.len() as i64 - 88200).abs() <= 1);
+        // Downmixed
+        assert!(samples.iter().all(|s| (s - 0.3).abs() < 0.01));
+    }
+
+    #[test]
+    fn test_decode_mp3() {
+        // 100 silent MPEG-1 Layer III frames (128kbps, 44.1kHz, mono), 1152 samples each
+        let mut frame = vec![0u8; 417];
+        frame[..4].copy_from_slice(&[0xFF, 0xFB, 0x90, 0xC0]);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("silence.mp3");
+        std::fs::write(&path, frame.repeat(100)).unwrap();
+
+        let (samples, _) = FeatureExtractor::new().decode_samples(&path).unwrap();
+        let duration = samples.len() as f32 / 44100.0;
+        assert!((duration - 115200.0 / 44100.0).abs() < 0.1);
+
+        // Corrupt file
+        let bad = dir.path().join("bad.mp3");
+        std::fs::write(&bad, b"not really an mp3").unwrap();
+        let error = FeatureExtractor::new().decode_samples(&bad).unwrap_err();
+        assert!(error.to_string().contains("bad.mp3"));
+    }
+
+    #[test]
+    fn test_unsupported_codec() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // WAV header with WMA format tag
+        let path = dir.path().join("wma.wav");
+        let mut data = b"RIFF\x24\x10\x00\x00WAVEfmt \x10\x00\x00\x00".to_vec();
+        data.extend_from_slice(&[0x61, 0x01, 0x02, 0x00, 0x44, 0xAC, 0x00, 0x00, 0x10, 0xB1, 0x02, 0x00, 0x04, 0x00, 0x10, 0x00]);
+        data.extend_from_slice(b"data\x00\x10\x00\x00");
+        data.extend(vec![0u8; 4096]);
+        std::fs::write(&path, data).unwrap();
+
+        let error = FeatureExtractor::new().extract(&path).unwrap_err();
+        let AiError::Decode(unsupported) = error else { panic!("Expected decode error, got {:?}", error) };
+        assert_eq!(unsupported.codec, "WMA");
+        assert!(!unsupported.drm);
+
+        // iTunes protected file
+        let path = dir.path().join("purchase.m4p");
+        std::fs::write(&path, b"\x00\x00\x00\x20ftypM4P ").unwrap();
+        let error = FeatureExtractor::new().decode_samples(&path).unwrap_err();
+        assert!(error.downcast_ref::<UnsupportedAudio>().unwrap().drm);
+    }
+
+    #[test]
+    fn test_audio_properties() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sine.wav");
+        crate::test_utils::write_sine_wav(&path, 440.0, 2.0);
+        let properties = AudioProperties::probe(&path).unwrap();
+        assert_eq!((properties.sample_rate, properties.channels), (44100, Some(1)));
+        assert!((properties.duration - 2.0).abs() < 0.01);
+        // 16-bit mono 44.1kHz = 705.6 kbps (+ header)
+        assert!((705..=710).contains(&properties.bitrate));
+    }
+
+    #[test]
+    fn test_analysis_window() {
+        // 30s silence followed by 30s tone
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("long.wav");
+        let samples: Vec<f32> = (0..44100 * 60)
+            .map(|i| if i < 44100 * 30 { 0.0 } else { (i as f32 * 440.0 * 2.0 * std::f32::consts::PI / 44100.0).sin() * 0.5 })
+            .collect();
+        crate::test_utils::write_wav(&path, &samples, 44100);
+
+        let features = FeatureExtractor::new().with_window(Some(10.0), 0.0).extract(&path).unwrap();
+        assert!((features.analyzed_duration - 10.0).abs() < 0.01);
+        assert!((features.duration - 60.0).abs() < 0.01);
+        assert!(features.rms_energy < 0.01);
+
+        // Skip the intro
+        let features = FeatureExtractor::new().with_window(Some(10.0), 30.0).extract(&path).unwrap();
+        assert!((features.analyzed_duration - 10.0).abs() < 0.01);
+        assert!((features.duration - 60.0).abs() < 0.01);
+        assert!(features.rms_energy > 0.3);
+    }
+
+    #[test]
+    fn test_extract_batch() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut paths = vec![];
+        for i in 1..=3 {
+            let path = dir.path().join(format!("{}.wav", i));
+            crate::test_utils::write_sine_wav(&path, 440.0, i as f32 * 0.5);
+            paths.push(path);
+        }
+        paths.insert(1, dir.path().join("missing.wav"));
+
+        let results = FeatureExtractor::new().with_threads(2).extract_batch(&paths);
+        assert_eq!(results.len(), 4);
+        assert!(results[1].is_err());
+        let durations: Vec<f32> = [0, 2, 3].iter().map(|i| results[*i].as_ref().unwrap().duration).collect();
+        assert!((durations[0] - 0.5).abs() < 0.01);
+        assert!((durations[1] - 1.0).abs() < 0.01);
+        assert!((durations[2] - 1.5).abs() < 0.01);
+
+        // Reuses cached features
+        let cache = FeatureCache::new(dir.path().join("features"));
+        let extractor = FeatureExtractor::new().with_feature_cache(Some(cache.clone()));
+        extractor.extract_batch(&paths[..1]);
+        assert!(cache.get::<AudioFeatures>(&paths[0], &extractor.cache_kind()).is_some());
+    }
+
+    #[test]
+    fn test_hpss_chroma() {
+        // A4 tone with noise bursts 4 times per second
+        let mut noise = 1u32;
+        let samples: Vec<f32> = (0..44100 * 3).map(|i| {
+            let tone = (i as f32 * 440.0 * 2.0 * std::f32::consts::PI / 44100.0).sin() * 0.3;
+            noise = noise.wrapping_mul(1664525).wrapping_add(1013904223);
+            let burst = if i % 11025 < 128 { (noise >> 8) as f32 / (1u32 << 23) as f32 - 1.0 } else { 0.0 };
+            tone + burst
+        }).collect();
+        // Share of the A pitch class
+        let purity = |features: &AudioFeatures| features.chroma[9] / features.chroma.iter().sum::<f32>();
+
+        let mixed = FeatureExtractor::new().extract_from_samples(&samples, 44100).unwrap();
+        let separated = FeatureExtractor::new().with_hpss(true).extract_from_samples(&samples, 44100).unwrap();
+        assert!(purity(&separated) > purity(&mixed), "{} <= {}", purity(&separated), purity(&mixed));
+        assert_eq!(separated.key.as_deref().map(|k| &k[..1]), Some("A"));
+        assert_ne!(FeatureExtractor::new().with_hpss(true).cache_kind(), FeatureExtractor::new().cache_kind());
+    }
+
+    #[test]
+    fn test_tempo_stability() {
+        let extractor = FeatureExtractor::new();
+        let mut metronome = AudioFeatures::default();
+        let times: Vec<f32> = (0..20).map(|i| i as f32 * 0.5).collect();
+        extractor.analyze_samples(&crate::test_utils::click_samples(&times, 10.5), 22050, &mut metronome);
+        assert!(metronome.tempo_stability > 0.95);
+        assert!(metronome.onset_strength > 0.0);
+
+        // Rubato, intervals between 0.2s and 1s
+        let mut rubato = AudioFeatures::default();
+        let times: Vec<f32> = [0.2, 0.8, 0.3, 1.0, 0.25, 0.6].iter().cycle().take(18)
+            .scan(0.0, |time, interval| { *time += interval; Some(*time) })
+            .collect();
+        extractor.analyze_samples(&crate::test_utils::click_samples(&times, 11.0), 22050, &mut rubato);
+        assert!(rubato.tempo_stability < 0.75);
+    }
+
+    #[test]
+    fn test_beat_positions() {
+        // 120 BPM
+        let mut features = AudioFeatures::default();
+        let times: Vec<f32> = (0..30).map(|i| i as f32 * 0.5).collect();
+        FeatureExtractor::new().analyze_samples(&crate::test_utils::click_samples(&times, 15.0), 22050, &mut features);
+        let beats = &features.beat_positions;
+        assert!(beats.len() >= 25);
+        assert!(beats.windows(2).all(|w| ((w[1] - w[0]) - 0.5).abs() < 0.05));
+
+        let mut silence = AudioFeatures::default();
+        FeatureExtractor::new().analyze_samples(&vec![0.0; 22050 * 5], 22050, &mut silence);
+        assert!(silence.beat_positions.is_empty());
+    }
+
+    #[test]
+    fn test_extract_from_samples() {
+        let extractor = FeatureExtractor::new();
+        // Pure 1kHz tone at 22050Hz, resampled to the extractor's rate
+        let tone: Vec<f32> = (0..22050 * 3).map(|i| (i as f32 / 22050.0 * 1000.0 * 2.0 * std::f32::consts::PI).sin() * 0.5).collect();
+        let features = extractor.extract_from_samples(&tone, 22050).unwrap();
+        assert!((features.spectral_centroid - 1000.0).abs() < 150.0);
+        assert!((features.rms_energy - 0.5 / 2f32.sqrt()).abs() < 0.02);
+        assert!((features.duration - 3.0).abs() < 0.01);
+        assert!(!features.is_placeholder);
+
+        // Noise is brighter and louder than the tone
+        let mut seed = 1u32;
+        let noise: Vec<f32> = (0..44100 * 3).map(|_| {
+            seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            (seed >> 8) as f32 / (1u32 << 24) as f32 * 2.0 - 1.0
+        }).collect();
+        let noisy = extractor.extract_from_samples(&noise, 44100).unwrap();
+        assert!(noisy.spectral_centroid > features.spectral_centroid * 3.0);
+        assert!(noisy.zero_crossing_rate > features.zero_crossing_rate);
+
+        assert!(extractor.extract_from_samples(&[], 44100).is_err());
     }
 
     #[test]
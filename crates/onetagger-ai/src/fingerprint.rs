@@ -0,0 +1,201 @@
+//! Fingerprint Module
+//!
+//! Chromaprint-style acoustic fingerprints: 32-bit sub-fingerprints derived from
+//! chroma features, compared by bit error rate
+
+use anyhow::{Error, Result};
+use serde::{Serialize, Deserialize};
+use std::collections::HashSet;
+use std::path::Path;
+use crate::dsp::{self, Spectrogram};
+use crate::features::FeatureExtractor;
+
+/// Sample rate audio is downsampled to before fingerprinting
+const FINGERPRINT_SAMPLE_RATE: u32 = 11025;
+/// STFT frame size at `FINGERPRINT_SAMPLE_RATE`
+const FRAME_SIZE: usize = 4096;
+/// STFT hop size (2/3 overlap, ~8 frames per second)
+const HOP_SIZE: usize = FRAME_SIZE / 3;
+/// Moving average length (frames) applied to chroma before deriving bits
+const SMOOTHING_FRAMES: usize = 5;
+/// Pitch classes below this fraction of the strongest one are treated as silent (noise floor)
+const CHROMA_FLOOR: f32 = 0.05;
+/// Maximum alignment offset (frames, ~10s) searched when comparing
+const MAX_OFFSET: usize = 80;
+/// Only the beginning of the track is fingerprinted (seconds)
+pub const FINGERPRINT_SECS: f32 = 120.0;
+/// MinHash bands of the LSH keys, fingerprints sharing any band are duplicate candidates
+const LSH_BANDS: usize = 32;
+/// MinHash values per band
+const LSH_ROWS: usize = 2;
+
+/// Acoustic fingerprint of a track
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Fingerprint {
+    /// One sub-fingerprint per frame
+    pub codes: Vec<u32>,
+    /// Duration of the fingerprinted audio (seconds)
+    pub duration: f32,
+}
+
+impl Fingerprint {
+    /// Decode and fingerprint the beginning of a file
+    pub fn from_file(path: &Path) -> Result<Fingerprint, Error> {
+        let (samples, sample_rate) = FeatureExtractor::new()
+            .with_window(Some(FINGERPRINT_SECS), 0.0)
+            .decode_samples(path)?;
+        Ok(Fingerprint::from_samples(&samples, sample_rate))
+    }
+
+    /// Fingerprint mono samples
+    pub fn from_samples(samples: &[f32], sample_rate: u32) -> Fingerprint {
+        let duration = samples.len() as f32 / sample_rate as f32;
+        let samples = dsp::resample(samples, sample_rate, FINGERPRINT_SAMPLE_RATE);
+        let chroma = Spectrogram::new(&samples, FINGERPRINT_SAMPLE_RATE, FRAME_SIZE, HOP_SIZE).frame_chroma();
+
+        // Normalize each frame, so the fingerprint doesn't depend on loudness
+        let chroma: Vec<[f32; 12]> = chroma.into_iter().map(|mut frame| {
+            let norm = frame.iter().map(|c| c * c).sum::<f32>().sqrt();
+            if norm > 0.0 {
+                frame.iter_mut().for_each(|c| *c /= norm);
+            }
+            frame
+        }).collect();
+
+        let codes = (0..chroma.len()).map(|i| {
+            let start = i.saturating_sub(SMOOTHING_FRAMES / 2);
+            let end = (i + SMOOTHING_FRAMES / 2 + 1).min(chroma.len());
+            let mut smoothed = [0.0f32; 12];
+            for frame in &chroma[start..end] {
+                smoothed.iter_mut().zip(frame).for_each(|(s, c)| *s += c);
+            }
+            // Comparing noise would make bits random between encodings
+            let floor = smoothed.iter().cloned().fold(0.0, f32::max) * CHROMA_FLOOR;
+            smoothed.iter_mut().filter(|c| **c < floor).for_each(|c| *c = 0.0);
+            encode(&smoothed)
+        }).collect();
+
+        Fingerprint { codes, duration }
+    }
+
+    /// Similarity (0-1) at the best alignment, 1.0 = identical, ~0 = unrelated
+    pub fn compare(&self, other: &Fingerprint) -> f32 {
+        self.best_offset(other).map(|(_, ber)| similarity(ber)).unwrap_or(0.0)
+    }
+
+    /// Similarity of consecutive regions of `region_frames` frames at the best alignment
+    pub fn region_similarities(&self, other: &Fingerprint, region_frames: usize) -> Vec<f32> {
+        let Some((offset, _)) = self.best_offset(other) else { return vec![] };
+        let (a, b) = aligned(&self.codes, &other.codes, offset).unwrap_or_default();
+        a.chunks(region_frames.max(1)).zip(b.chunks(region_frames.max(1)))
+            .filter_map(|(a, b)| bit_error_rate(a, b, 0, 1))
+            .map(similarity)
+            .collect()
+    }
+
+    /// Locality sensitive hash keys, one per band of a MinHash signature over the 16-bit halves of the codes.
+    /// Fingerprints of the same audio share a key with high probability regardless of alignment, unrelated ones rarely do
+    pub fn lsh_keys(&self) -> Vec<u64> {
+        let tokens: HashSet<u64> = self.codes.iter()
+            .flat_map(|c| [(c >> 16) as u64, (c & 0xffff) as u64 | 1 << 16])
+            .collect();
+        if tokens.is_empty() {
+            return vec![];
+        }
+        let signature: Vec<u64> = (0..LSH_BANDS * LSH_ROWS)
+            .map(|i| {
+                let seed = mix(i as u64 + 1);
+                tokens.iter().map(|t| mix(t ^ seed)).min().unwrap_or_default()
+            })
+            .collect();
+        signature.chunks(LSH_ROWS).enumerate()
+            .map(|(band, rows)| rows.iter().fold(mix(band as u64), |key, row| mix(key ^ row)))
+            .collect()
+    }
+
+    /// Offset (frames) with the lowest bit error rate
+    fn best_offset(&self, other: &Fingerprint) -> Option<(isize, f32)> {
+        let min_overlap = (self.codes.len().min(other.codes.len()) / 2).max(1);
+        (-(MAX_OFFSET as isize)..=MAX_OFFSET as isize)
+            .filter_map(|offset| Some((offset, bit_error_rate(&self.codes, &other.codes, offset, min_overlap)?)))
+            .fold(None, |best: Option<(isize, f32)>, (offset, ber)| match best {
+                Some((_, best_ber)) if best_ber <= ber => best,
+                _ => Some((offset, ber)),
+            })
+    }
+}
+
+/// SplitMix64 finalizer
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+/// Bit error rate to similarity (random bits = 0.5 BER = 0.0)
+fn similarity(ber: f32) -> f32 {
+    (1.0 - 2.0 * ber).clamp(0.0, 1.0)
+}
+
+/// Derive 32 bits from a chroma vector by comparing pitch classes and groups of them
+fn encode(chroma: &[f32; 12]) -> u32 {
+    let mut code = 0u32;
+    for b in 0..12 {
+        // Neighbouring semitone and minor third
+        if chroma[b] > chroma[(b + 1) % 12] {
+            code |= 1 << b;
+        }
+        if chroma[b] > chroma[(b + 3) % 12] {
+            code |= 1 << (12 + b);
+        }
+    }
+    // Energy of 4 consecutive pitch classes vs the following 4
+    let group = |start: usize| (start..start + 4).map(|b| chroma[b % 12]).sum::<f32>();
+    for k in 0..8 {
+        if group(k) > group(k + 4) {
+            code |= 1 << (24 + k);
+        }
+    }
+    code
+}
+
+/// Overlapping parts of `a` and `b` shifted by `offset` frames
+fn aligned<'a>(a: &'a [u32], b: &'a [u32], offset: isize) -> Option<(&'a [u32], &'a [u32])> {
+    let (a, b) = match offset >= 0 {
+        true => (a.get(offset as usize..)?, b),
+        false => (a, b.get((-offset) as usize..)?),
+    };
+    let overlap = a.len().min(b.len());
+    Some((&a[..overlap], &b[..overlap]))
+}
+
+/// Bit error rate of `b` shifted by `offset` frames against `a`, `None` if they overlap too little
+fn bit_error_rate(a: &[u32], b: &[u32], offset: isize, min_overlap: usize) -> Option<f32> {
+    let (a, b) = aligned(a, b, offset)?;
+    if a.is_empty() || a.len() < min_overlap {
+        return None;
+    }
+    let errors: u32 = a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum();
+    Some(errors as f32 / (a.len() * 32) as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint_offset() {
+        // Same signal, second one starting 8 hops later
+        let samples: Vec<f32> = (0..44100 * 10)
+            .map(|i| {
+                let frequency = [262.0, 330.0, 392.0, 494.0][(i / 22050) % 4];
+                (i as f32 * frequency * 2.0 * std::f32::consts::PI / 44100.0).sin()
+            })
+            .collect();
+        let a = Fingerprint::from_samples(&samples, 44100);
+        let b = Fingerprint::from_samples(&samples[HOP_SIZE * 4 * 8..], 44100);
+        assert!(a.compare(&b) > 0.9);
+        assert_eq!(a.compare(&a), 1.0);
+    }
+}
@@ -0,0 +1,247 @@
+//! Embedding Index Module
+//!
+//! Persistent nearest-neighbor search over track embeddings (cosine similarity),
+//! exact by default or approximate using an inverted file (IVF) for large libraries
+
+use anyhow::{Error, Result};
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// k-means iterations when building IVF lists
+const KMEANS_ITERATIONS: usize = 10;
+
+/// Embedding index
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingIndex {
+    ids: Vec<String>,
+    /// L2 normalized vectors
+    vectors: Vec<Vec<f32>>,
+    /// IVF centroids and the vectors assigned to each
+    #[serde(default)]
+    ivf: Option<Ivf>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Ivf {
+    centroids: Vec<Vec<f32>>,
+    lists: Vec<Vec<usize>>,
+    /// Number of nearest lists searched per query
+    probes: usize,
+}
+
+impl EmbeddingIndex {
+    /// In-memory index
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load index from file (empty if it doesn't exist yet), `save` writes it back
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, Error> {
+        let path = path.into();
+        let mut index = match path.exists() {
+            true => serde_json::from_slice::<EmbeddingIndex>(&std::fs::read(&path)?)?,
+            false => EmbeddingIndex::default(),
+        };
+        index.path = Some(path);
+        Ok(index)
+    }
+
+    /// Write index to the file it was opened from
+    pub fn save(&self) -> Result<()> {
+        let path = self.path.as_ref().ok_or(anyhow!("Index is in-memory only"))?;
+        self.save_to(path)
+    }
+
+    /// Write index to file
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Add or replace embedding of id
+    pub fn add(&mut self, id: impl Into<String>, vector: Vec<f32>) -> Result<()> {
+        if let Some(dim) = self.vectors.first().map(|v| v.len()) {
+            if vector.len() != dim {
+                return Err(anyhow!("Embedding has {} dimensions, index has {}", vector.len(), dim));
+            }
+        }
+        let id = id.into();
+        let vector = normalize(vector);
+        match self.ids.iter().position(|i| i == &id) {
+            Some(position) => {
+                self.vectors[position] = vector;
+                if let Some(ivf) = &mut self.ivf {
+                    ivf.lists.iter_mut().for_each(|l| l.retain(|i| *i != position));
+                    ivf.assign(position, &self.vectors[position]);
+                }
+            },
+            None => {
+                self.ids.push(id);
+                self.vectors.push(vector);
+                let position = self.vectors.len() - 1;
+                if let Some(ivf) = &mut self.ivf {
+                    ivf.assign(position, &self.vectors[position]);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Top `k` (id, cosine similarity) most similar to vector, best first
+    pub fn query(&self, vector: &[f32], k: usize) -> Vec<(String, f32)> {
+        let query = normalize(vector.to_vec());
+        let candidates: Vec<usize> = match &self.ivf {
+            Some(ivf) => ivf.candidates(&query),
+            None => (0..self.vectors.len()).collect(),
+        };
+        let mut scored: Vec<(usize, f32)> = candidates.into_iter()
+            .filter(|i| self.vectors[*i].len() == query.len())
+            .map(|i| (i, dot(&self.vectors[i], &query)))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored.into_iter().take(k).map(|(i, s)| (self.ids[i].to_string(), s)).collect()
+    }
+
+    /// Cluster vectors into `lists` inverted lists (k-means), queries then only search the `probes` closest lists
+    pub fn build_ivf(&mut self, lists: usize, probes: usize) {
+        if self.vectors.is_empty() {
+            return;
+        }
+        let lists = lists.clamp(1, self.vectors.len().max(1));
+        // Deterministic init: evenly spaced vectors
+        let mut centroids: Vec<Vec<f32>> = (0..lists).map(|i| self.vectors[i * self.vectors.len() / lists].clone()).collect();
+        let mut assignment = vec![0; self.vectors.len()];
+        for _ in 0..KMEANS_ITERATIONS {
+            for (i, vector) in self.vectors.iter().enumerate() {
+                assignment[i] = nearest(&centroids, vector);
+            }
+            let mut sums: HashMap<usize, (Vec<f32>, usize)> = HashMap::new();
+            for (i, vector) in self.vectors.iter().enumerate() {
+                let (sum, count) = sums.entry(assignment[i]).or_insert((vec![0.0; vector.len()], 0));
+                sum.iter_mut().zip(vector).for_each(|(s, v)| *s += v);
+                *count += 1;
+            }
+            for (list, (sum, _)) in sums {
+                centroids[list] = normalize(sum);
+            }
+        }
+
+        let mut ivf = Ivf { centroids, lists: vec![vec![]; lists], probes: probes.clamp(1, lists) };
+        for (i, vector) in self.vectors.iter().enumerate() {
+            ivf.assign(i, vector);
+        }
+        debug!("Built IVF index with {} lists over {} vectors", lists, self.vectors.len());
+        self.ivf = Some(ivf);
+    }
+}
+
+impl Ivf {
+    fn assign(&mut self, position: usize, vector: &[f32]) {
+        let list = nearest(&self.centroids, vector);
+        self.lists[list].push(position);
+    }
+
+    /// Vectors in the lists closest to the query
+    fn candidates(&self, query: &[f32]) -> Vec<usize> {
+        let mut lists: Vec<(usize, f32)> = self.centroids.iter().enumerate().map(|(i, c)| (i, dot(c, query))).collect();
+        lists.sort_by(|a, b| b.1.total_cmp(&a.1));
+        lists.into_iter().take(self.probes).flat_map(|(i, _)| self.lists[i].iter().copied()).collect()
+    }
+}
+
+/// Index of the most similar centroid
+fn nearest(centroids: &[Vec<f32>], vector: &[f32]) -> usize {
+    centroids.iter().enumerate()
+        .max_by(|a, b| dot(a.1, vector).total_cmp(&dot(b.1, vector)))
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn normalize(mut vector: Vec<f32>) -> Vec<f32> {
+    let norm = dot(&vector, &vector).sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+    vector
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic pseudo-random vectors (LCG)
+    fn random_vectors(count: usize, dim: usize) -> Vec<Vec<f32>> {
+        let mut state: u64 = 42;
+        (0..count).map(|_| (0..dim).map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 33) as f32 / (1u64 << 31) as f32 - 0.5
+        }).collect()).collect()
+    }
+
+    /// Brute force nearest neighbor
+    fn exact_nearest(vectors: &[Vec<f32>], query: &[f32]) -> usize {
+        let query = normalize(query.to_vec());
+        (0..vectors.len()).max_by(|a, b| {
+            dot(&normalize(vectors[*a].clone()), &query).total_cmp(&dot(&normalize(vectors[*b].clone()), &query))
+        }).unwrap()
+    }
+
+    #[test]
+    fn test_index_query() {
+        let vectors = random_vectors(300, 32);
+        let mut index = EmbeddingIndex::new();
+        for (i, vector) in vectors.iter().enumerate() {
+            index.add(format!("track{}", i), vector.clone()).unwrap();
+        }
+        for query in random_vectors(320, 32).iter().skip(300) {
+            let expected = format!("track{}", exact_nearest(&vectors, query));
+            let result = index.query(query, 5);
+            assert_eq!(result.len(), 5);
+            assert_eq!(result[0].0, expected);
+            assert!(result.windows(2).all(|w| w[0].1 >= w[1].1));
+        }
+        // Stored vector is its own nearest neighbor
+        assert_eq!(index.query(&vectors[123], 1)[0].0, "track123");
+        assert!(index.add("bad", vec![1.0; 3]).is_err());
+    }
+
+    #[test]
+    fn test_index_ivf_and_persistence() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.json");
+        let vectors = random_vectors(400, 16);
+        let mut index = EmbeddingIndex::open(&path).unwrap();
+        for (i, vector) in vectors.iter().enumerate() {
+            index.add(i.to_string(), vector.clone()).unwrap();
+        }
+        // Searching all lists is exact
+        index.build_ivf(8, 8);
+        index.save().unwrap();
+
+        let index = EmbeddingIndex::open(&path).unwrap();
+        assert_eq!(index.len(), 400);
+        for i in [0, 57, 399] {
+            assert_eq!(index.query(&vectors[i], 1)[0].0, i.to_string());
+        }
+    }
+}
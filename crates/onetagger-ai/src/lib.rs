@@ -14,35 +14,85 @@
 
 use anyhow::Error;
 use serde::{Serialize, Deserialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use futures_util::stream::{self, Stream, StreamExt};
+use api::CacheLookups;
 
 // Public modules
 pub mod config;
+pub mod error;
 pub mod api;
 pub mod tagger;
 pub mod features;
+pub mod dsp;
 pub mod classifier;
 pub mod embeddings;
+pub mod index;
 pub mod duplicates;
+pub mod fingerprint;
 pub mod quality;
 pub mod playlist;
+pub mod archive;
+pub mod cache;
+pub mod mapping;
+pub mod batch;
+pub mod bench;
+pub mod lyrics;
+pub mod camelot;
+pub mod acoustid;
+
+#[cfg(test)]
+mod test_utils;
 
 // Re-exports
-pub use config::{AIConfig, CustomTagConfig, APIConfig, APIProvider};
-pub use api::APIClient;
+pub use config::{AIConfig, CustomTagConfig, APIConfig, APIProvider, AzureConfig, AcoustIdConfig, DescriptionStyle, MergeStrategy, Preset, ConfidenceWeights, SourceWeights, TagTarget, ConfigError};
+pub use api::{APIClient, UsageStats};
+pub use cache::{Cache, FileCache, InMemoryCache};
+pub use error::AiError;
 pub use tagger::{AITagger, AIBuilder};
-pub use features::{AudioFeatures, FeatureExtractor};
-pub use classifier::{GenreClassifier, MoodDetector, EnergyAnalyzer};
-pub use embeddings::{EmbeddingGenerator, SemanticMatcher};
-pub use duplicates::{DuplicateDetector, DuplicateMatch};
-pub use quality::{QualityControl, ValidationResult};
+pub use features::{AudioFeatures, AudioProperties, FeatureExtractor};
+pub use classifier::{GenreClassifier, GenreHierarchy, VocalDetector, MoodDetector, MoodPoint, EnergyAnalyzer, EnergyTimeline};
+pub use embeddings::{EmbeddingGenerator, EmbeddingWeights, SemanticMatcher, Metric};
+pub use index::EmbeddingIndex;
+pub use duplicates::{DuplicateDetector, DuplicateGroup, DuplicateMatch, IncrementalScan};
+pub use acoustid::AcoustIdMatch;
+pub use fingerprint::Fingerprint;
+pub use quality::{QualityControl, ValidationResult, AppliedFix, LibraryReport};
 pub use playlist::PlaylistGenerator;
+pub use mapping::TagMapper;
+pub use batch::{BatchAnalyzer, BatchSummary, PrewarmStats, ScanEstimate, prewarm};
 pub use config::PlaylistConfig;
 
 /// AI Module version
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// Shared flag, analysis is aborted with [`Cancelled`] once it is set
+pub type CancellationToken = Arc<AtomicBool>;
+
+lazy_static! {
+    /// Set when the user stops tagging, default token of `AITagger`
+    pub static ref STOP_ANALYSIS: CancellationToken = Arc::new(AtomicBool::new(false));
+}
+
+/// Analysis was aborted through a [`CancellationToken`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Analysis cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Overall confidence multiplier when audio features are placeholders
+const PLACEHOLDER_CONFIDENCE_PENALTY: f32 = 0.5;
+
 /// AI Analysis Result for a single track
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -65,6 +115,22 @@ pub struct AIAnalysisResult {
     /// Aggression/intensity (0-100)
     pub aggression: Option<f32>,
 
+    /// Energy curve over time
+    #[serde(default)]
+    pub energy_timeline: Option<EnergyTimeline>,
+
+    /// Mix-in cue, start of the first sustained energy (seconds from the start of the analyzed audio)
+    #[serde(default)]
+    pub mix_in: Option<f32>,
+
+    /// Mix-out cue, end of the last sustained energy before the outro (seconds from the start of the analyzed audio)
+    #[serde(default)]
+    pub mix_out: Option<f32>,
+
+    /// Valence/arousal position used for mood detection
+    #[serde(default)]
+    pub mood_point: Option<MoodPoint>,
+
     /// Overall confidence score (0-1)
     pub confidence: f32,
 
@@ -76,6 +142,71 @@ pub struct AIAnalysisResult {
 
     /// Suggested custom tags from LLM
     pub llm_suggestions: Vec<String>,
+
+    /// Explicit content, from the file's advisory tag or lyrics (None = unknown)
+    #[serde(default)]
+    pub explicit: Option<bool>,
+
+    /// Track has no vocals (None = unknown)
+    #[serde(default)]
+    pub is_instrumental: Option<bool>,
+}
+
+/// Extension appended to the audio file name for sidecar files
+const SIDECAR_EXTENSION: &str = ".ai.json";
+
+impl AIAnalysisResult {
+    /// Sidecar file of audio file (`track.mp3.ai.json`)
+    pub fn sidecar_path(audio_path: &Path) -> PathBuf {
+        let mut path = audio_path.as_os_str().to_owned();
+        path.push(SIDECAR_EXTENSION);
+        PathBuf::from(path)
+    }
+
+    /// Save the result as JSON next to the audio file, returns the sidecar path
+    pub fn write_sidecar(&self, audio_path: &Path) -> Result<PathBuf, AiError> {
+        let path = Self::sidecar_path(audio_path);
+        std::fs::write(&path, serde_json::to_vec_pretty(self).map_err(Error::from)?)?;
+        Ok(path)
+    }
+
+    /// Load result from the audio file's sidecar
+    pub fn read_sidecar(audio_path: &Path) -> Result<AIAnalysisResult, AiError> {
+        let data = std::fs::read(Self::sidecar_path(audio_path))?;
+        Ok(serde_json::from_slice(&data).map_err(Error::from)?)
+    }
+
+    /// Sidecar result, if it was written after the audio file was last modified
+    pub fn fresh_sidecar(audio_path: &Path) -> Option<AIAnalysisResult> {
+        let modified = |p: &Path| std::fs::metadata(p).and_then(|m| m.modified()).ok();
+        if modified(&Self::sidecar_path(audio_path))? < modified(audio_path)? {
+            return None;
+        }
+        match Self::read_sidecar(audio_path) {
+            Ok(result) => Some(result),
+            Err(e) => {
+                warn!("Invalid sidecar of {}: {}", audio_path.display(), e);
+                None
+            }
+        }
+    }
+}
+
+/// Number of genres / moods shown in the summary
+const SUMMARY_TAGS: usize = 3;
+
+/// Compact one line summary: `genres: techno 90%, house 60% | moods: dark 70% | energy 85, danceability 70 | 2 suggestions`
+impl std::fmt::Display for AIAnalysisResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let tags = |tags: &[TagWithConfidence]| match tags.is_empty() {
+            true => "-".to_string(),
+            false => tags.iter().take(SUMMARY_TAGS).map(|t| format!("{} {:.0}%", t.tag, t.confidence * 100.0)).collect::<Vec<_>>().join(", "),
+        };
+        let score = |score: Option<f32>| score.map(|s| format!("{:.0}", s)).unwrap_or("-".to_string());
+        write!(f, "genres: {} | moods: {} | energy {}, danceability {} | {} suggestion{}",
+            tags(&self.genres), tags(&self.moods), score(self.energy_level), score(self.danceability),
+            self.llm_suggestions.len(), if self.llm_suggestions.len() == 1 { "" } else { "s" })
+    }
 }
 
 /// A tag with its confidence score
@@ -83,6 +214,12 @@ pub struct AIAnalysisResult {
 pub struct TagWithConfidence {
     pub tag: String,
     pub confidence: f32,
+    /// What produced the detection
+    #[serde(default)]
+    pub source: DetectionSource,
+    /// Custom collection the tag was mapped into
+    #[serde(default)]
+    pub collection: Option<String>,
 }
 
 impl TagWithConfidence {
@@ -90,21 +227,92 @@ impl TagWithConfidence {
         Self {
             tag: tag.into(),
             confidence: confidence.clamp(0.0, 1.0),
+            source: DetectionSource::Rule,
+            collection: None,
+        }
+    }
+
+    /// Set the detection source
+    pub fn with_source(mut self, source: DetectionSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Set the custom collection
+    pub fn with_collection(mut self, collection: impl Into<String>) -> Self {
+        self.collection = Some(collection.into());
+        self
+    }
+}
+
+/// Source of a tag detection
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum DetectionSource {
+    /// Rule-based classifiers
+    #[default]
+    Rule,
+    /// LLM suggestions
+    Llm,
+    /// ONNX model inference
+    Onnx,
+    /// LLM analysis of lyrics
+    Lyrics,
+}
+
+/// Merge same-tag detections (case-insensitive) applying source weights.
+/// Weighted confidences are combined as independent evidence, so agreeing sources boost each other
+pub fn merge_detections(tags: Vec<TagWithConfidence>, weights: &SourceWeights) -> Vec<TagWithConfidence> {
+    merge_tags(apply_weights(tags, weights), MergeStrategy::Combine)
+}
+
+/// Scale confidences by the weight of their source
+fn apply_weights(tags: Vec<TagWithConfidence>, weights: &SourceWeights) -> Vec<TagWithConfidence> {
+    tags.into_iter()
+        .map(|t| TagWithConfidence { confidence: (t.confidence * weights.weight(t.source)).clamp(0.0, 1.0), ..t })
+        .collect()
+}
+
+/// Collapse case-insensitive duplicate tags, combining their confidences by `strategy`
+pub fn merge_tags(tags: Vec<TagWithConfidence>, strategy: MergeStrategy) -> Vec<TagWithConfidence> {
+    let mut merged: Vec<(TagWithConfidence, Vec<f32>)> = vec![];
+    for tag in tags {
+        match merged.iter_mut().find(|(m, _)| m.tag.eq_ignore_ascii_case(&tag.tag)) {
+            Some((strongest, confidences)) => {
+                confidences.push(tag.confidence);
+                // Keep the source (and spelling) of the strongest detection
+                if tag.confidence > strongest.confidence {
+                    *strongest = tag;
+                }
+            },
+            None => {
+                let confidence = tag.confidence;
+                merged.push((tag, vec![confidence]));
+            },
         }
     }
+    merged.into_iter().map(|(tag, confidences)| {
+        let confidence = match strategy {
+            MergeStrategy::Combine => 1.0 - confidences.iter().map(|c| 1.0 - c).product::<f32>(),
+            MergeStrategy::Max => tag.confidence,
+            MergeStrategy::Mean => confidences.iter().sum::<f32>() / confidences.len() as f32,
+        };
+        TagWithConfidence { confidence, ..tag }
+    }).collect()
+}
+
+/// Sort tags by confidence (descending), ties are broken by tag name so the order is reproducible
+pub fn sort_tags(tags: &mut [TagWithConfidence]) {
+    tags.sort_by(|a, b| b.confidence.total_cmp(&a.confidence).then_with(|| a.tag.cmp(&b.tag)));
 }
 
 /// Initialize the AI module
-pub fn initialize(config: &AIConfig) -> Result<(), Error> {
+pub fn initialize(config: &AIConfig) -> Result<(), AiError> {
     info!("Initializing OneTagger AI module v{} (Cloud API Edition)", VERSION);
     info!("Using provider: {}", config.api_config.provider.display_name());
     info!("Free tier: {}", config.api_config.provider.free_tier_info());
 
-    if config.api_config.api_key.is_none() {
-        warn!("No API key configured. Please set your {} API key in settings.",
-              config.api_config.provider.display_name());
-        warn!("Get a free API key at: {}", get_api_key_url(&config.api_config.provider));
-    }
+    config.validate()?;
 
     // Create cache directory
     if let Some(ref cache_dir) = config.cache_dir {
@@ -116,26 +324,380 @@ pub fn initialize(config: &AIConfig) -> Result<(), Error> {
 }
 
 /// Get URL for obtaining API key
-fn get_api_key_url(provider: &APIProvider) -> &'static str {
+pub(crate) fn get_api_key_url(provider: &APIProvider) -> &'static str {
     match provider {
         APIProvider::Gemini => "https://aistudio.google.com/app/apikey",
         APIProvider::OpenRouter => "https://openrouter.ai/keys",
         APIProvider::Groq => "https://console.groq.com/keys",
         APIProvider::TogetherAI => "https://api.together.xyz/settings/api-keys",
         APIProvider::OpenAI => "https://platform.openai.com/api-keys",
+        APIProvider::AzureOpenAI => "https://portal.azure.com",
+        APIProvider::Ollama => "https://ollama.com/download",
         APIProvider::Custom => "Contact your provider",
     }
 }
 
 /// Analyze a single audio file and return AI-generated tags
-pub async fn analyze_track(path: &PathBuf, config: &AIConfig) -> Result<AIAnalysisResult, Error> {
+/// Paths pointing inside a zip archive (`pack.zip/folder/track.mp3`) are supported.
+/// With `reuse_sidecars`, a fresh sidecar is returned instead (applies to all `analyze_*` functions)
+pub async fn analyze_track(path: &PathBuf, config: &AIConfig) -> Result<AIAnalysisResult, AiError> {
+    let client = llm_client(config)?;
+    Ok(analyze_path(path, config, client.as_ref(), None, None).await?)
+}
+
+/// Analyze a single audio file using the given lyrics for `lyrics_themes` instead of the ones in its tags.
+/// Lyrics analysis is skipped for empty lyrics
+pub async fn analyze_track_with_lyrics(path: &Path, config: &AIConfig, lyrics: &str) -> Result<AIAnalysisResult, AiError> {
+    let client = llm_client(config)?;
+    Ok(analyze_path(path, config, client.as_ref(), Some(lyrics), None).await?)
+}
+
+/// Same as `analyze_track`, but returns [`Cancelled`] once `cancel` is set.
+/// The token is checked before feature extraction and before each LLM call
+pub async fn analyze_track_cancellable(path: &Path, config: &AIConfig, cancel: &CancellationToken) -> Result<AIAnalysisResult, AiError> {
+    check_cancelled(Some(cancel.as_ref()))?;
+    let client = llm_client(config)?;
+    Ok(analyze_path(path, config, client.as_ref(), None, Some(cancel.as_ref())).await?)
+}
+
+/// Same as `analyze_track`, using an existing client for the LLM calls (shared cache, usage and concurrency limit)
+pub async fn analyze_track_with_client(path: &Path, config: &AIConfig, client: &APIClient) -> Result<AIAnalysisResult, AiError> {
+    Ok(analyze_path(path, config, Some(client), None, None).await?)
+}
+
+/// Analyze multiple files, results are in input order and a failing file doesn't abort the batch.
+/// Features are extracted in parallel (`max_threads`), LLM calls share one client and are spaced by `rate_limit`
+pub async fn analyze_batch(paths: &[PathBuf], config: &AIConfig) -> Vec<Result<AIAnalysisResult, AiError>> {
+    let client = match llm_client(config) {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("Failed creating API client: {}. Continuing with rule-based tags.", e);
+            None
+        }
+    };
+
+    // Archive entries (not on disk) are extracted and analyzed one by one later.
+    // Decoding is CPU bound, keep it off the async workers
+    let mut sidecars: Vec<Option<AIAnalysisResult>> = paths.iter().map(|p| reused_sidecar(p, config)).collect();
+    let on_disk: Vec<bool> = paths.iter().zip(&sidecars).map(|(p, s)| s.is_none() && p.exists()).collect();
+    let files: Vec<PathBuf> = paths.iter().zip(&on_disk).filter(|(_, d)| **d).map(|(p, _)| p.clone()).collect();
+    let owned = config.clone();
+    let extracted = tokio::task::spawn_blocking(move || {
+        FeatureExtractor::from_config(&owned).with_feature_cache(feature_cache(&owned)).extract_batch(&files)
+    }).await.unwrap_or_else(|e| {
+        warn!("Feature extraction failed: {}", e);
+        vec![]
+    });
+    let mut extracted = extracted.into_iter();
+    let features: Vec<Option<Result<AudioFeatures, Error>>> = on_disk.iter()
+        .map(|d| d.then(|| extracted.next().unwrap_or_else(|| Err(anyhow!("Feature extraction failed")))))
+        .collect();
+
+    let interval = rate_limit_interval(config);
+    let mut last_call: Option<Instant> = None;
+    let mut results = Vec::with_capacity(paths.len());
+    for ((path, features), sidecar) in paths.iter().zip(features).zip(sidecars.iter_mut()) {
+        if let Some(result) = sidecar.take() {
+            results.push(Ok(result));
+            continue;
+        }
+        if let (Some(_), Some(last_call)) = (&client, last_call) {
+            if let Some(wait) = interval.checked_sub(last_call.elapsed()) {
+                tokio::time::sleep(wait).await;
+            }
+        }
+        let tokens = client.as_ref().map(|c| c.tokens_used()).unwrap_or(0);
+        let result = match features {
+            Some(Ok(features)) => analyze_extracted(path, features, config, client.as_ref()).await,
+            Some(Err(e)) => Err(e),
+            None => analyze_path(path, config, client.as_ref(), None, None).await,
+        };
+        if let Err(e) = &result {
+            warn!("Analysis of {} failed: {}", path.display(), e);
+        }
+        // Cached responses don't count against the rate limit
+        if client.as_ref().map(|c| c.tokens_used()).unwrap_or(0) > tokens {
+            last_call = Some(Instant::now());
+        }
+        results.push(result.map_err(AiError::from));
+    }
+    results
+}
+
+/// Analyze files concurrently (`max_threads`), yielding each result as soon as it's done, in no particular order.
+/// LLM analyses share one client and are started at most once per `rate_limit` interval
+pub fn analyze_library(paths: Vec<PathBuf>, config: AIConfig) -> impl Stream<Item = (PathBuf, Result<AIAnalysisResult, AiError>)> {
+    let client = match llm_client(&config) {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("Failed creating API client: {}. Continuing with rule-based tags.", e);
+            None
+        }
+    };
+    let concurrency = config.max_threads.max(1);
+    let interval = rate_limit_interval(&config);
+    let config = Arc::new(config);
+    let client = Arc::new(client);
+    let last_call = Arc::new(tokio::sync::Mutex::new(None::<Instant>));
+
+    stream::iter(paths)
+        .map(move |path| {
+            let (config, client, last_call) = (config.clone(), client.clone(), last_call.clone());
+            async move {
+                let result = analyze_streamed(&path, &config, (*client).as_ref(), &last_call, interval).await;
+                if let Err(e) = &result {
+                    warn!("Analysis of {} failed: {}", path.display(), e);
+                }
+                (path, result.map_err(AiError::from))
+            }
+        })
+        .buffer_unordered(concurrency)
+}
+
+/// Single file of `analyze_library`
+async fn analyze_streamed(path: &Path, config: &Arc<AIConfig>, client: Option<&APIClient>, last_call: &tokio::sync::Mutex<Option<Instant>>, interval: Duration) -> Result<AIAnalysisResult, Error> {
+    if let Some(result) = reused_sidecar(path, config) {
+        return Ok(result);
+    }
+    // Decoding is CPU bound, keep it off the async workers
+    let features = match path.exists() {
+        true => {
+            let (path, config) = (path.to_owned(), config.clone());
+            Some(tokio::task::spawn_blocking(move || FeatureExtractor::from_config(&config).extract_cached(&path, feature_cache(&config).as_ref())).await??)
+        },
+        false => None,
+    };
+
+    // Reserve the next LLM slot
+    if client.is_some() {
+        let mut last_call = last_call.lock().await;
+        if let Some(wait) = last_call.and_then(|l| interval.checked_sub(l.elapsed())) {
+            tokio::time::sleep(wait).await;
+        }
+        *last_call = Some(Instant::now());
+    }
+
+    match features {
+        Some(features) => analyze_extracted(path, features, config, client).await,
+        None => analyze_path(path, config, client, None, None).await,
+    }
+}
+
+/// Analyze features of file on disk, lyrics are read from its tags
+async fn analyze_extracted(path: &Path, features: AudioFeatures, config: &AIConfig, client: Option<&APIClient>) -> Result<AIAnalysisResult, Error> {
+    let lyrics = config.lyrics_themes.then(|| lyrics::read_lyrics(path)).flatten();
+    let mut result = analyze_features(features, config, client, lyrics.as_deref(), None).await?;
+    apply_explicit_tag(&mut result, path);
+    Ok(result)
+}
+
+/// Minimum time between LLM calls
+fn rate_limit_interval(config: &AIConfig) -> Duration {
+    match config.api_config.rate_limit {
+        0 => Duration::ZERO,
+        rate_limit => Duration::from_secs_f64(60.0 / rate_limit as f64),
+    }
+}
+
+/// Estimate work of `analyze_batch` without decoding audio or calling the LLM.
+/// Prompts can only be checked against the response cache for files with cached features, other files count as live calls
+pub async fn analyze_batch_estimate(paths: &[PathBuf], config: &AIConfig) -> ScanEstimate {
+    let mut estimate = ScanEstimate { files: paths.len(), ..Default::default() };
+    let lookups = Arc::new(parking_lot::Mutex::new(CacheLookups::default()));
+    let client = match llm_client(config) {
+        Ok(Some(client)) => client.cache_only(lookups.clone()),
+        Ok(None) => return estimate,
+        Err(e) => {
+            warn!("Failed creating API client: {}. No LLM calls expected.", e);
+            return estimate;
+        }
+    };
+
+    let kind = FeatureExtractor::from_config(config).cache_kind();
+    let cache = feature_cache(config);
+    for path in paths {
+        let lyrics = config.lyrics_themes.then(|| lyrics::read_lyrics(path)).flatten();
+        match cache.as_ref().and_then(|c| c.get::<AudioFeatures>(path, &kind)) {
+            // Replay the analysis against the cache only
+            Some(features) => {
+                estimate.cached_features += 1;
+                if let Err(e) = analyze_features(features, config, Some(&client), lyrics.as_deref(), None).await {
+                    warn!("Estimate of {} failed: {}", path.display(), e);
+                }
+            },
+            None => {
+                let genre_call = config.enable_genre_classification && config.llm_genre_classification;
+                let lyrics_call = lyrics.is_some();
+                lookups.lock().misses += 1 + genre_call as usize + lyrics_call as usize;
+            },
+        }
+    }
+
+    let lookups = *lookups.lock();
+    estimate.cached_calls = lookups.hits;
+    estimate.live_calls = lookups.misses;
+    estimate.llm_calls = lookups.hits + lookups.misses;
+    if config.api_config.rate_limit > 0 {
+        estimate.eta_secs = (estimate.live_calls as f64 * 60.0 / config.api_config.rate_limit as f64).ceil() as u64;
+    }
+    info!("Estimate: {} files, {} LLM calls ({} cached), ~{}s", estimate.files, estimate.llm_calls, estimate.cached_calls, estimate.eta_secs);
+    estimate
+}
+
+/// Analyze an audio file stored inside a zip archive
+pub async fn analyze_archive_entry(archive: &Path, entry: &str, config: &AIConfig) -> Result<AIAnalysisResult, AiError> {
+    let client = llm_client(config)?;
+    Ok(analyze_entry(archive, entry, config, client.as_ref(), None, None).await?)
+}
+
+/// API client for LLM suggestions, if configured
+pub(crate) fn llm_client(config: &AIConfig) -> Result<Option<APIClient>, Error> {
+    llm_client_shared(config, &APIClient::default_http_client()?)
+}
+
+/// API client for LLM suggestions using a shared HTTP client, if configured
+pub(crate) fn llm_client_shared(config: &AIConfig, http_client: &reqwest::Client) -> Result<Option<APIClient>, Error> {
+    if !config.llm_enabled || !config.api_config.is_configured() {
+        return Ok(None);
+    }
+    Ok(Some(APIClient::new_with_client(config.api_config.clone(), http_client.clone())?.with_cache_dir(config.cache_dir.clone())))
+}
+
+/// Feature / embedding cache in the configured cache directory
+pub(crate) fn feature_cache(config: &AIConfig) -> Option<cache::FeatureCache> {
+    config.cache_dir.as_ref().map(|dir| cache::FeatureCache::new(dir.join("features")))
+}
+
+/// Analyze file or archive entry, LLM suggestions are skipped without client.
+/// Lyrics are read from the tags when not given
+pub(crate) async fn analyze_path(path: &Path, config: &AIConfig, client: Option<&APIClient>, lyrics: Option<&str>, cancel: Option<&AtomicBool>) -> Result<AIAnalysisResult, Error> {
+    if let Some(result) = reused_sidecar(path, config) {
+        return Ok(result);
+    }
+    if !path.exists() {
+        if let Some((archive, entry)) = archive::split_archive_path(path) {
+            return analyze_entry(&archive, &entry, config, client, lyrics, cancel).await;
+        }
+    }
+    analyze_file(path, config, client, lyrics, cancel).await
+}
+
+/// Fresh sidecar of the file, if enabled by `reuse_sidecars`
+fn reused_sidecar(path: &Path, config: &AIConfig) -> Option<AIAnalysisResult> {
+    if !config.reuse_sidecars {
+        return None;
+    }
+    let result = AIAnalysisResult::fresh_sidecar(path)?;
+    debug!("Using sidecar of {}", path.display());
+    Some(result)
+}
+
+async fn analyze_entry(archive: &Path, entry: &str, config: &AIConfig, client: Option<&APIClient>, lyrics: Option<&str>, cancel: Option<&AtomicBool>) -> Result<AIAnalysisResult, Error> {
+    info!("Analyzing {} from archive {}", entry, archive.display());
+    // Temporary file is removed once `extracted` is dropped
+    let extracted = archive::extract_entry(archive, entry)?;
+    analyze_file(&extracted.path, config, client, lyrics, cancel).await
+}
+
+/// Analyze audio file on disk
+async fn analyze_file(path: &Path, config: &AIConfig, client: Option<&APIClient>, lyrics: Option<&str>, cancel: Option<&AtomicBool>) -> Result<AIAnalysisResult, Error> {
     info!("Analyzing track: {}", path.display());
+    check_cancelled(cancel)?;
 
     // Extract audio features
-    let extractor = FeatureExtractor::new();
-    let audio_features = extractor.extract(path)?;
+    let extractor = FeatureExtractor::from_config(config);
+    let audio_features = extractor.extract_cached(path, feature_cache(config).as_ref())?;
+    let tag_lyrics = match lyrics {
+        None if config.lyrics_themes => lyrics::read_lyrics(path),
+        _ => None,
+    };
+    let mut result = analyze_features(audio_features, config, client, lyrics.or(tag_lyrics.as_deref()), cancel).await?;
+    apply_explicit_tag(&mut result, path);
+    Ok(result)
+}
+
+/// Existing advisory tag takes precedence over the detected one
+fn apply_explicit_tag(result: &mut AIAnalysisResult, path: &Path) {
+    if let Some(explicit) = lyrics::read_explicit(path) {
+        result.explicit = Some(explicit);
+    }
+}
+
+/// Analyze already extracted features
+async fn analyze_features(audio_features: AudioFeatures, config: &AIConfig, client: Option<&APIClient>, lyrics: Option<&str>, cancel: Option<&AtomicBool>) -> Result<AIAnalysisResult, Error> {
     debug!("Extracted audio features: BPM={:?}, Key={:?}", audio_features.bpm, audio_features.key);
 
+    let mut result = classify(&audio_features, config)?;
+
+    // LLM genre classification replaces the rule-based genres
+    if config.enable_genre_classification && config.llm_genre_classification {
+        if let Some(client) = client {
+            check_cancelled(cancel)?;
+            let genres = GenreClassifier::new(config)?.classify_with_llm(client, &audio_features).await?;
+            result.genres = finalize_tags(genres, config.genre_threshold(), config);
+            debug!("LLM classified {} genres", result.genres.len());
+        }
+    }
+    result.genres = add_parent_genres(std::mem::take(&mut result.genres), config);
+
+    // Thematic moods from lyrics alongside the audio-derived ones, explicit content
+    if config.lyrics_themes {
+        if let (Some(client), Some(lyrics)) = (client, lyrics.filter(|l| !l.trim().is_empty())) {
+            check_cancelled(cancel)?;
+            match lyrics::analyze_lyrics(client, lyrics).await {
+                Ok(analysis) => {
+                    result.explicit = analysis.explicit;
+                    if config.enable_mood_detection {
+                        debug!("Lyrics added {} moods / themes", analysis.moods.len());
+                        let mut moods = std::mem::take(&mut result.moods);
+                        moods.extend(analysis.moods);
+                        result.moods = finalize_tags(moods, config.mood_threshold(), config);
+                    }
+                }
+                Err(e) => warn!("Lyrics analysis failed: {}. Continuing with audio moods.", e),
+            }
+        }
+    }
+
+    // LLM-based tag suggestions (using FREE Gemini API)
+    if let Some(client) = client {
+        check_cancelled(cancel)?;
+        match get_llm_suggestions(client, &audio_features, &result, config).await {
+            Ok((description, suggestions)) => {
+                result.description = description;
+                result.llm_suggestions = suggestions;
+                debug!("LLM generated {} custom tag suggestions", result.llm_suggestions.len());
+            }
+            Err(e) => {
+                warn!("LLM tag suggestions failed: {}. Continuing with rule-based tags.", e);
+            }
+        }
+    }
+
+    // Map to custom tags if user has defined collections
+    if !config.custom_tags.genres.is_empty() {
+        result.custom_tags = finalize_tags(map_to_custom_tags(&result, &config.custom_tags)?, config.custom_tag_threshold(), config);
+    }
+
+    // Calculate overall confidence
+    result.confidence = calculate_overall_confidence(&result, &config.confidence_weights);
+    apply_placeholder_penalty(&mut result);
+
+    info!("Analysis complete: {} | {} custom tags", result, result.custom_tags.len());
+
+    Ok(result)
+}
+
+/// Error if the cancellation token is set
+fn check_cancelled(cancel: Option<&AtomicBool>) -> Result<(), Error> {
+    match cancel {
+        Some(cancel) if cancel.load(Ordering::SeqCst) => Err(Cancelled.into()),
+        _ => Ok(())
+    }
+}
+
+/// Rule-based genre, mood and energy analysis of extracted features
+pub(crate) fn classify(audio_features: &AudioFeatures, config: &AIConfig) -> Result<AIAnalysisResult, Error> {
     let mut result = AIAnalysisResult {
         genres: Vec::new(),
         moods: Vec::new(),
@@ -143,87 +705,132 @@ pub async fn analyze_track(path: &PathBuf, config: &AIConfig) -> Result<AIAnalys
         energy_level: None,
         danceability: None,
         aggression: None,
+        energy_timeline: None,
+        mix_in: None,
+        mix_out: None,
+        mood_point: None,
         confidence: 0.0,
         audio_features: Some(audio_features.clone()),
         description: None,
         llm_suggestions: Vec::new(),
+        explicit: None,
+        is_instrumental: None,
     };
 
     // Genre classification (rule-based + API)
     if config.enable_genre_classification {
         let classifier = GenreClassifier::new(config)?;
-        let genres = classifier.classify(&audio_features)?;
-        result.genres = genres.into_iter()
-            .filter(|g| g.confidence >= config.confidence_threshold)
-            .collect();
+        result.genres = finalize_tags(classifier.classify(audio_features)?, config.genre_threshold(), config);
         debug!("Detected {} genres", result.genres.len());
     }
 
     // Mood detection (rule-based + API)
     if config.enable_mood_detection {
         let detector = MoodDetector::new(config)?;
-        let moods = detector.detect(&audio_features)?;
-        result.moods = moods.into_iter()
-            .filter(|m| m.confidence >= config.confidence_threshold)
-            .collect();
+        result.moods = finalize_tags(detector.detect(audio_features)?, config.mood_threshold(), config);
+        result.mood_point = Some(detector.mood_point(audio_features));
         debug!("Detected {} moods", result.moods.len());
     }
 
     // Energy analysis
     if config.enable_energy_analysis {
         let analyzer = EnergyAnalyzer::new(config)?;
-        let energy_result = analyzer.analyze(&audio_features)?;
+        let energy_result = analyzer.analyze(audio_features)?;
         result.energy_level = Some(energy_result.energy_level);
         result.danceability = Some(energy_result.danceability);
         result.aggression = Some(energy_result.aggression);
+        result.energy_timeline = analyzer.analyze_segments(audio_features, &audio_features.rms_frames);
+        (result.mix_in, result.mix_out) = analyzer.cue_points(audio_features);
         debug!("Energy: {}, Danceability: {}, Aggression: {}",
                energy_result.energy_level, energy_result.danceability, energy_result.aggression);
     }
 
-    // LLM-based tag suggestions (using FREE Gemini API)
-    if config.api_config.api_key.is_some() {
-        match get_llm_suggestions(&audio_features, &result, config).await {
-            Ok((description, suggestions)) => {
-                result.description = Some(description);
-                result.llm_suggestions = suggestions;
-                debug!("LLM generated {} custom tag suggestions", result.llm_suggestions.len());
-            }
-            Err(e) => {
-                warn!("LLM tag suggestions failed: {}. Continuing with rule-based tags.", e);
-            }
-        }
-    }
-
-    // Map to custom tags if user has defined collections
-    if !config.custom_tags.genres.is_empty() {
-        result.custom_tags = map_to_custom_tags(&result, &config.custom_tags)?;
-    }
+    result.is_instrumental = VocalDetector::new(config)?.is_instrumental(audio_features);
 
-    // Calculate overall confidence
-    result.confidence = calculate_overall_confidence(&result);
+    Ok(result)
+}
 
-    info!("Analysis complete: {} genres, {} moods, {} custom tags, energy={:?}",
-          result.genres.len(), result.moods.len(), result.custom_tags.len(), result.energy_level);
+/// Merge detections, drop ones below the threshold, sort and limit to max tags per track
+fn finalize_tags(tags: Vec<TagWithConfidence>, threshold: f32, config: &AIConfig) -> Vec<TagWithConfidence> {
+    let mut tags: Vec<TagWithConfidence> = merge_tags(apply_weights(tags, &config.source_weights), config.merge_strategy)
+        .into_iter()
+        .filter(|t| t.confidence >= threshold)
+        .collect();
+    sort_tags(&mut tags);
+    tags.truncate(config.max_tags_per_track);
+    tags
+}
 
-    Ok(result)
+/// Add parents of detected sub-genres (`AIConfig::genre_hierarchy`), keeping the tag limit
+fn add_parent_genres(genres: Vec<TagWithConfidence>, config: &AIConfig) -> Vec<TagWithConfidence> {
+    let mut genres: Vec<TagWithConfidence> = config.genre_hierarchy.expand(genres)
+        .into_iter()
+        .filter(|t| t.confidence >= config.genre_threshold())
+        .collect();
+    sort_tags(&mut genres);
+    genres.truncate(config.max_tags_per_track);
+    genres
 }
 
 /// Get LLM tag suggestions using API
 async fn get_llm_suggestions(
+    api_client: &APIClient,
     features: &AudioFeatures,
     result: &AIAnalysisResult,
     config: &AIConfig,
-) -> Result<(String, Vec<String>), Error> {
+) -> Result<(Option<String>, Vec<String>), Error> {
     let prompt = create_llm_prompt(features, result, config);
-
-    let api_client = APIClient::new(config.api_config.clone())?;
     let response = api_client.generate(&prompt).await?;
 
-    parse_llm_response(&response)
+    parse_llm_response(&response, config.description_style)
 }
 
 /// Create a prompt for the LLM to suggest custom tags
 fn create_llm_prompt(features: &AudioFeatures, result: &AIAnalysisResult, config: &AIConfig) -> String {
+    let mut prompt = match &config.prompt_template {
+        Some(template) => render_prompt_template(template, features, result, config),
+        None => default_llm_prompt(features, result, config),
+    };
+
+    match config.description_style.instruction() {
+        Some(instruction) => {
+            prompt.push_str(&format!("Also write {}.\n", instruction));
+            if let Some(language) = config.description_language.as_deref().map(str::trim).filter(|l| !l.is_empty()) {
+                prompt.push_str(&format!("Write the description in the language with BCP-47 code \"{}\", keep the tags and the \"Description:\" / \"Tags:\" labels in English.\n", language));
+            }
+            prompt.push_str("\nAnswer in exactly this format:\nDescription: <description>\nTags: <comma-separated tags>\n");
+        }
+        None => prompt.push_str("Tags: "),
+    }
+
+    prompt
+}
+
+/// Fill `{placeholder}`s of the user's prompt template, unknown values are written as `unknown`
+fn render_prompt_template(template: &str, features: &AudioFeatures, result: &AIAnalysisResult, config: &AIConfig) -> String {
+    let join = |tags: &[TagWithConfidence]| match tags.is_empty() {
+        true => "unknown".to_string(),
+        false => tags.iter().take(3).map(|t| t.tag.as_str()).collect::<Vec<_>>().join(", "),
+    };
+    let values = [
+        ("{bpm}", features.bpm.map(|b| format!("{:.1}", b))),
+        ("{key}", features.key.clone()),
+        ("{genres}", Some(join(&result.genres))),
+        ("{moods}", Some(join(&result.moods))),
+        ("{energy}", result.energy_level.map(|e| format!("{:.0}", e))),
+        ("{custom_genres}", (!config.custom_tags.genres.is_empty()).then(|| config.custom_tags.genres.join(", "))),
+    ];
+    let mut prompt = values.into_iter().fold(template.to_string(), |prompt, (placeholder, value)| {
+        prompt.replace(placeholder, value.as_deref().unwrap_or("unknown"))
+    });
+    if !prompt.ends_with('\n') {
+        prompt.push_str("\n\n");
+    }
+    prompt
+}
+
+/// Built-in prompt for DJs tagging electronic music
+fn default_llm_prompt(features: &AudioFeatures, result: &AIAnalysisResult, config: &AIConfig) -> String {
     let mut prompt = String::from("You are a DJ assistant analyzing electronic music tracks. Based on the following audio characteristics, suggest 3-5 custom tags that a DJ would use for categorization.\n\n");
 
     prompt.push_str("Audio Features:\n");
@@ -255,18 +862,24 @@ fn create_llm_prompt(features: &AudioFeatures, result: &AIAnalysisResult, config
     }
 
     prompt.push_str("\nProvide 3-5 tags (comma-separated) that describe the vibe, context, or sub-genre. Focus on tags a DJ would use to find this track later (e.g., 'peak-time', 'warehouse-vibe', 'hypnotic', 'melodic-progressive').\n\n");
-    prompt.push_str("Tags: ");
-
     prompt
 }
 
 /// Parse LLM response to extract description and tag suggestions
-fn parse_llm_response(response: &str) -> Result<(String, Vec<String>), Error> {
+fn parse_llm_response(response: &str, style: DescriptionStyle) -> Result<(Option<String>, Vec<String>), Error> {
     // Clean up response
     let cleaned = response.trim();
 
+    // Structured answer (`Description: ...` then `Tags: ...`), ASCII lowercase keeps byte offsets
+    let lower = cleaned.to_ascii_lowercase();
+    let (description, tags_text) = match (lower.find("description:"), lower.rfind("tags:")) {
+        (Some(d), Some(t)) if d < t => (Some(cleaned[d + 12..t].trim().to_string()), &cleaned[t + 5..]),
+        (None, Some(t)) => (None, &cleaned[t + 5..]),
+        _ => (cleaned.lines().next().map(String::from), cleaned),
+    };
+
     // Extract tags (comma or newline separated)
-    let tags: Vec<String> = cleaned
+    let tags: Vec<String> = tags_text
         .split(&[',', '\n', ';'][..])
         .map(|s| s.trim())
         .filter(|s| !s.is_empty())
@@ -275,62 +888,82 @@ fn parse_llm_response(response: &str) -> Result<(String, Vec<String>), Error> {
         .take(10)  // Max 10 tags
         .collect();
 
-    let description = cleaned.lines().next().unwrap_or("").to_string();
+    let description = match style {
+        DescriptionStyle::None => None,
+        _ => description.filter(|d| !d.is_empty()),
+    };
 
     Ok((description, tags))
 }
 
 /// Map AI results to user's custom tag collections
 fn map_to_custom_tags(result: &AIAnalysisResult, custom_config: &CustomTagConfig) -> Result<Vec<TagWithConfidence>, Error> {
-    let mut custom_tags = Vec::new();
-
-    // Map genres to custom genre taxonomy
-    for genre in &result.genres {
-        if custom_config.genres.iter().any(|g| g.eq_ignore_ascii_case(&genre.tag)) {
-            custom_tags.push(genre.clone());
-        }
-    }
-
-    // Map moods to custom mood taxonomy
-    for mood in &result.moods {
-        if custom_config.moods.iter().any(|m| m.eq_ignore_ascii_case(&mood.tag)) {
-            custom_tags.push(mood.clone());
-        }
-    }
+    Ok(TagMapper::new(custom_config).map(result))
+}
 
-    // Check LLM suggestions against custom collections
-    for suggestion in &result.llm_suggestions {
-        // Check all custom collections
-        for (_name, tags) in &custom_config.custom_collections {
-            if tags.iter().any(|t| t.eq_ignore_ascii_case(suggestion)) {
-                custom_tags.push(TagWithConfidence::new(suggestion.clone(), 0.8));
-                break;
-            }
-        }
+/// Lower confidence if classification was based on stubbed audio features
+fn apply_placeholder_penalty(result: &mut AIAnalysisResult) {
+    if result.audio_features.as_ref().map(|f| f.is_placeholder).unwrap_or(false) {
+        warn!("Audio features are placeholder values, classification is unreliable. Lowering confidence.");
+        result.confidence *= PLACEHOLDER_CONFIDENCE_PENALTY;
     }
-
-    Ok(custom_tags)
 }
 
-/// Calculate overall confidence score from all detections
-fn calculate_overall_confidence(result: &AIAnalysisResult) -> f32 {
-    let mut scores = Vec::new();
+/// Calculate overall confidence score from all detections, weighted by category
+fn calculate_overall_confidence(result: &AIAnalysisResult, weights: &ConfidenceWeights) -> f32 {
+    let mut scores: Vec<(f32, f32)> = Vec::new();
 
-    scores.extend(result.genres.iter().map(|g| g.confidence));
-    scores.extend(result.moods.iter().map(|m| m.confidence));
-    scores.extend(result.custom_tags.iter().map(|c| c.confidence));
+    scores.extend(result.genres.iter().map(|g| (g.confidence, weights.genre_weight)));
+    scores.extend(result.moods.iter().map(|m| (m.confidence, weights.mood_weight)));
+    scores.extend(result.custom_tags.iter().map(|c| (c.confidence, weights.custom_tag_weight)));
+    if let Some(features) = result.audio_features.as_ref().filter(|f| !f.is_placeholder) {
+        scores.push((features.tempo_stability, weights.feature_weight));
+    }
 
-    if scores.is_empty() {
+    let total_weight: f32 = scores.iter().map(|(_, w)| w.max(0.0)).sum();
+    if total_weight <= 0.0 {
         return 0.5;
     }
 
-    scores.iter().sum::<f32>() / scores.len() as f32
+    scores.iter().map(|(c, w)| c * w.max(0.0)).sum::<f32>() / total_weight
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_task_thresholds() {
+        let mut config = AIConfig::default();
+        let tags = || vec![TagWithConfidence::new("a", 0.9), TagWithConfidence::new("b", 0.75), TagWithConfidence::new("c", 0.65)];
+        assert_eq!(finalize_tags(tags(), config.genre_threshold(), &config).len(), 2);
+        assert_eq!(finalize_tags(tags(), config.mood_threshold(), &config).len(), 2);
+
+        config.genre_threshold = Some(0.8);
+        config.mood_threshold = Some(0.6);
+        assert_eq!(config.custom_tag_threshold(), 0.7);
+        assert_eq!(finalize_tags(tags(), config.genre_threshold(), &config).len(), 1);
+        assert_eq!(finalize_tags(tags(), config.mood_threshold(), &config).len(), 3);
+
+        config.mood_threshold = Some(1.5);
+        assert_eq!(config.validate().unwrap_err()[0], ConfigError::OutOfRange { field: "moodThreshold", value: 1.5 });
+    }
+
+    #[test]
+    fn test_add_parent_genres() {
+        let mut config = AIConfig::default();
+        config.confidence_threshold = 0.5;
+        let genres = add_parent_genres(vec![TagWithConfidence::new("deep-house", 0.9)], &config);
+        assert_eq!(genres.len(), 2);
+        assert_eq!(genres[0].tag, "deep-house");
+        assert_eq!(genres[1].tag, "house");
+        assert!(genres[1].confidence < genres[0].confidence);
+
+        config.max_tags_per_track = 1;
+        let genres = add_parent_genres(vec![TagWithConfidence::new("deep-house", 0.9)], &config);
+        assert_eq!(genres.len(), 1);
+    }
+
     #[test]
     fn test_tag_with_confidence() {
         let tag = TagWithConfidence::new("techno", 0.95);
@@ -338,6 +971,70 @@ mod tests {
         assert_eq!(tag.confidence, 0.95);
     }
 
+    #[test]
+    fn test_sort_tags_tie_break() {
+        let mut tags = vec![
+            TagWithConfidence::new("minimal", 0.8),
+            TagWithConfidence::new("techno", 0.9),
+            TagWithConfidence::new("house", 0.8),
+            TagWithConfidence::new("acid", 0.8),
+        ];
+        sort_tags(&mut tags);
+        let order: Vec<String> = tags.iter().map(|t| t.tag.clone()).collect();
+        assert_eq!(order, vec!["techno", "acid", "house", "minimal"]);
+
+        // Same result regardless of input order
+        tags.reverse();
+        sort_tags(&mut tags);
+        assert_eq!(tags.iter().map(|t| t.tag.clone()).collect::<Vec<_>>(), order);
+    }
+
+    #[test]
+    fn test_source_weights() {
+        let detections = vec![
+            TagWithConfidence::new("techno", 0.8),
+            TagWithConfidence::new("Techno", 0.6).with_source(DetectionSource::Llm),
+            TagWithConfidence::new("wedding", 0.8).with_source(DetectionSource::Llm),
+        ];
+
+        // Agreement boosts confidence
+        let merged = merge_detections(detections.clone(), &SourceWeights::default());
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].tag, "techno");
+        assert_eq!(merged[0].source, DetectionSource::Rule);
+        assert!((merged[0].confidence - 0.92).abs() < 0.001);
+        assert!((merged[1].confidence - 0.8).abs() < 0.001);
+
+        // Downweighted LLM
+        let weights = SourceWeights { llm_weight: 0.5, ..Default::default() };
+        let merged = merge_detections(detections.clone(), &weights);
+        assert!((merged[0].confidence - 0.86).abs() < 0.001);
+        assert!((merged[1].confidence - 0.4).abs() < 0.001);
+
+        // LLM ignored
+        let weights = SourceWeights { llm_weight: 0.0, ..Default::default() };
+        let merged = merge_detections(detections, &weights);
+        assert!((merged[0].confidence - 0.8).abs() < 0.001);
+        assert_eq!(merged[1].confidence, 0.0);
+    }
+
+    #[test]
+    fn test_merge_tags() {
+        let tags = vec![
+            TagWithConfidence::new("techno", 0.8),
+            TagWithConfidence::new("Techno", 0.9).with_source(DetectionSource::Llm),
+            TagWithConfidence::new("house", 0.5),
+        ];
+        let merged = merge_tags(tags.clone(), MergeStrategy::Max);
+        assert_eq!(merged.len(), 2);
+        assert_eq!((merged[0].tag.as_str(), merged[0].confidence), ("Techno", 0.9));
+        assert_eq!(merged[0].source, DetectionSource::Llm);
+        assert_eq!(merged[1].confidence, 0.5);
+
+        let merged = merge_tags(tags, MergeStrategy::Mean);
+        assert!((merged[0].confidence - 0.85).abs() < 0.001);
+    }
+
     #[test]
     fn test_llm_prompt_generation() {
         let mut features = AudioFeatures::default();
@@ -359,6 +1056,386 @@ mod tests {
         assert!(prompt.contains("techno"));
     }
 
+    #[tokio::test]
+    async fn test_analyze_track_in_archive() {
+        use std::io::Write;
+
+        // Short sine WAV
+        let dir = tempfile::tempdir().unwrap();
+        let wav_path = dir.path().join("track.wav");
+        test_utils::write_sine_wav(&wav_path, 440.0, 1.0);
+
+        let archive_path = dir.path().join("promos.zip");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&archive_path).unwrap());
+        zip.start_file("pack/vol1/track.wav", zip::write::SimpleFileOptions::default()).unwrap();
+        zip.write_all(&std::fs::read(&wav_path).unwrap()).unwrap();
+        zip.finish().unwrap();
+
+        let mut config = AIConfig::default();
+        config.cache_dir = None;
+        let result = analyze_track(&archive_path.join("pack").join("vol1").join("track.wav"), &config).await;
+        assert!(result.is_ok());
+        assert!(analyze_track(&archive_path.join("pack").join("missing.wav"), &config).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_analyze_batch() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths: Vec<PathBuf> = ["a.wav", "missing.wav", "b.wav", "c.wav"].iter().map(|f| dir.path().join(f)).collect();
+        test_utils::write_sine_wav(&paths[0], 440.0, 0.5);
+        test_utils::write_sine_wav(&paths[2], 440.0, 1.0);
+        test_utils::write_sine_wav(&paths[3], 440.0, 1.5);
+
+        let mut config = AIConfig::default();
+        config.cache_dir = None;
+        config.api_config.api_key = None;
+        config.max_threads = 2;
+        let results = analyze_batch(&paths, &config).await;
+
+        assert_eq!(results.len(), 4);
+        assert!(results[1].is_err());
+        let durations: Vec<f32> = [0, 2, 3].iter()
+            .map(|i| results[*i].as_ref().unwrap().audio_features.as_ref().unwrap().duration)
+            .collect();
+        assert!((durations[0] - 0.5).abs() < 0.05);
+        assert!((durations[1] - 1.0).abs() < 0.05);
+        assert!((durations[2] - 1.5).abs() < 0.05);
+    }
+
+    #[tokio::test]
+    async fn test_batch_estimate() {
+        let server = test_utils::MockServer::start(vec![test_utils::MockResponse::new(200, test_utils::gemini_response("techno, dark"))]).await;
+        let dir = tempfile::tempdir().unwrap();
+        let paths: Vec<PathBuf> = [(440.0, "a.wav"), (660.0, "b.wav"), (880.0, "c.wav")].iter()
+            .map(|(freq, file)| {
+                let path = dir.path().join(file);
+                test_utils::write_sine_wav(&path, *freq, 0.5);
+                path
+            })
+            .collect();
+
+        let mut config = test_utils::llm_config(&server);
+        config.cache_dir = Some(dir.path().join("cache"));
+        config.api_config.rate_limit = 0;
+        // Warm the cache with 2 of 3 files
+        analyze_batch(&paths[..2], &config).await;
+        let hits = server.hits();
+
+        config.api_config.rate_limit = 30;
+        let estimate = analyze_batch_estimate(&paths, &config).await;
+        assert_eq!(server.hits(), hits);
+        assert_eq!(estimate.files, 3);
+        assert_eq!(estimate.cached_features, 2);
+        assert_eq!((estimate.llm_calls, estimate.cached_calls, estimate.live_calls), (3, 2, 1));
+        assert_eq!(estimate.eta_secs, 2);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_before_llm() {
+        let server = test_utils::MockServer::start(vec![test_utils::MockResponse::new(200, test_utils::gemini_response("techno, dark"))]).await;
+        let config = test_utils::llm_config(&server);
+        let client = APIClient::new(config.api_config.clone()).unwrap();
+
+        let cancel: CancellationToken = Arc::new(AtomicBool::new(true));
+        let result = analyze_features(AudioFeatures::default(), &config, Some(&client), None, Some(cancel.as_ref())).await;
+        assert!(result.unwrap_err().is::<Cancelled>());
+        assert_eq!(server.hits(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_lyrics_themes() {
+        let response = r#"{"mood": {"tag": "romantic", "confidence": 0.9}, "themes": [{"tag": "love", "confidence": 0.8}]}"#;
+        let server = test_utils::MockServer::start(vec![test_utils::MockResponse::new(200, test_utils::gemini_response(response))]).await;
+        let mut config = test_utils::llm_config(&server);
+        config.lyrics_themes = true;
+        config.confidence_threshold = 0.0;
+        config.max_tags_per_track = 10;
+        let client = APIClient::new(config.api_config.clone()).unwrap();
+
+        let mut features = AudioFeatures::default();
+        features.key = Some("C".to_string());
+        features.rms_energy = 0.9;
+        features.onset_strength = 0.9;
+        features.spectral_centroid = 3000.0;
+        let result = analyze_features(features.clone(), &config, Some(&client), Some("Hold me close tonight"), None).await.unwrap();
+        let mood = |tag: &str| result.moods.iter().find(|m| m.tag == tag).map(|m| m.source);
+        assert_eq!(mood("euphoric"), Some(DetectionSource::Rule));
+        assert_eq!(mood("romantic"), Some(DetectionSource::Lyrics));
+        assert_eq!(mood("love"), Some(DetectionSource::Lyrics));
+        assert!(server.requests()[0].contains("Hold me close tonight"));
+
+        // Instrumental, only the suggestions request is made
+        let hits = server.hits();
+        let result = analyze_features(features, &config, Some(&client), Some(""), None).await.unwrap();
+        assert!(result.moods.iter().all(|m| m.source != DetectionSource::Lyrics));
+        assert_eq!(server.hits(), hits + 1);
+    }
+
+    #[tokio::test]
+    async fn test_explicit_from_lyrics() {
+        let response = r#"{"mood": {"tag": "aggressive", "confidence": 0.9}, "themes": [], "explicit": true}"#;
+        let server = test_utils::MockServer::start(vec![test_utils::MockResponse::new(200, test_utils::gemini_response(response))]).await;
+        let mut config = test_utils::llm_config(&server);
+        config.lyrics_themes = true;
+        let client = APIClient::new(config.api_config.clone()).unwrap();
+
+        let result = analyze_features(AudioFeatures::default(), &config, Some(&client), Some("F*** the police"), None).await.unwrap();
+        assert_eq!(result.explicit, Some(true));
+        let result = analyze_features(AudioFeatures::default(), &config, Some(&client), Some(""), None).await.unwrap();
+        assert_eq!(result.explicit, None);
+    }
+
+    #[test]
+    fn test_explicit_advisory_tag() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("clean.wav");
+        test_utils::write_sine_wav(&path, 440.0, 0.5);
+        let mut result = AIAnalysisResult { explicit: Some(true), ..Default::default() };
+        apply_explicit_tag(&mut result, &path);
+        assert_eq!(result.explicit, Some(true));
+
+        let mut tag = onetagger_tag::Tag::load_file(&path, false).unwrap();
+        tag.tag_mut().set_explicit(false);
+        tag.tag_mut().save_file(&path).unwrap();
+        apply_explicit_tag(&mut result, &path);
+        assert_eq!(result.explicit, Some(false));
+    }
+
+    #[test]
+    fn test_placeholder_confidence_penalty() {
+        let mut features = AudioFeatures::default();
+        let mut result = AIAnalysisResult {
+            genres: vec![TagWithConfidence::new("techno", 0.9)],
+            audio_features: Some(features.clone()),
+            ..Default::default()
+        };
+        result.confidence = calculate_overall_confidence(&result, &ConfidenceWeights::default());
+        apply_placeholder_penalty(&mut result);
+        let measured = result.confidence;
+
+        features.is_placeholder = true;
+        result.audio_features = Some(features);
+        result.confidence = calculate_overall_confidence(&result, &ConfidenceWeights::default());
+        apply_placeholder_penalty(&mut result);
+        assert!(result.confidence < measured);
+        assert_eq!(result.confidence, measured * PLACEHOLDER_CONFIDENCE_PENALTY);
+    }
+
+    #[test]
+    fn test_weighted_confidence() {
+        let result = AIAnalysisResult {
+            genres: vec![TagWithConfidence::new("techno", 0.9)],
+            moods: vec![TagWithConfidence::new("dark", 0.3)],
+            ..Default::default()
+        };
+        let flat = ConfidenceWeights { genre_weight: 1.0, ..Default::default() };
+        assert!((calculate_overall_confidence(&result, &flat) - 0.6).abs() < 0.001);
+        assert!((calculate_overall_confidence(&result, &ConfidenceWeights::default()) - 0.7).abs() < 0.001);
+
+        // Unstable tempo lowers confidence when features are weighted
+        let result = AIAnalysisResult { audio_features: Some(AudioFeatures { tempo_stability: 0.2, ..Default::default() }), ..result };
+        let weights = ConfidenceWeights { feature_weight: 1.0, ..Default::default() };
+        assert!(calculate_overall_confidence(&result, &weights) < 0.7);
+    }
+
+    #[test]
+    fn test_prompt_template() {
+        let features = AudioFeatures { bpm: Some(92.0), key: Some("Bb".to_string()), ..Default::default() };
+        let result = AIAnalysisResult {
+            genres: vec![TagWithConfidence::new("hard-bop", 0.9), TagWithConfidence::new("cool-jazz", 0.6)],
+            energy_level: Some(42.4),
+            ..Default::default()
+        };
+        let mut config = AIConfig::default();
+        config.custom_tags.genres = vec!["Bebop".to_string(), "Swing".to_string()];
+        config.description_style = DescriptionStyle::None;
+        config.prompt_template = Some("You are a jazz archivist. {bpm} BPM in {key}, {genres} ({moods}), energy {energy}. Use: {custom_genres}. {unknown}".to_string());
+
+        let prompt = create_llm_prompt(&features, &result, &config);
+        assert!(prompt.starts_with("You are a jazz archivist. 92.0 BPM in Bb, hard-bop, cool-jazz (unknown), energy 42. Use: Bebop, Swing. {unknown}\n\n"));
+        assert!(!prompt.contains("DJ"));
+        // Answer format is still requested
+        assert!(prompt.ends_with("Tags: "));
+    }
+
+    #[test]
+    fn test_description_language() {
+        let mut config = AIConfig::default();
+        let prompt = create_llm_prompt(&AudioFeatures::default(), &AIAnalysisResult::default(), &config);
+        assert!(!prompt.contains("BCP-47"));
+
+        config.description_language = Some("pt-BR".to_string());
+        let prompt = create_llm_prompt(&AudioFeatures::default(), &AIAnalysisResult::default(), &config);
+        assert!(prompt.contains("Write the description in the language with BCP-47 code \"pt-BR\""));
+        assert!(prompt.ends_with("Answer in exactly this format:\nDescription: <description>\nTags: <comma-separated tags>\n"));
+
+        // Only the description is translated
+        config.description_style = DescriptionStyle::None;
+        let prompt = create_llm_prompt(&AudioFeatures::default(), &AIAnalysisResult::default(), &config);
+        assert!(!prompt.contains("pt-BR"));
+
+        let response = "Description: Faixa hipnótica para o pico da noite.\nTags: hypnotic, peak-time";
+        let (description, _) = parse_llm_response(response, DescriptionStyle::OneLiner).unwrap();
+        assert_eq!(description.unwrap(), "Faixa hipnótica para o pico da noite.");
+    }
+
+    #[tokio::test]
+    async fn test_analyze_track_mock_client() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("track.wav");
+        test_utils::write_sine_wav(&path, 440.0, 1.0);
+        let mut config = AIConfig::default();
+        config.cache_dir = None;
+        let client = APIClient::mock(config.api_config.clone()).unwrap();
+
+        let result = analyze_track_with_client(&path, &config, &client).await.unwrap();
+        assert_eq!(result.llm_suggestions.len(), 3);
+        assert!(result.description.as_deref().unwrap().starts_with("Mock description"));
+
+        // Same prompt, same answer
+        let again = analyze_track_with_client(&path, &config, &client).await.unwrap();
+        assert_eq!(again.llm_suggestions, result.llm_suggestions);
+        assert_eq!(again.description, result.description);
+    }
+
+    #[test]
+    fn test_description_style_none() {
+        let mut config = AIConfig::default();
+        config.description_style = DescriptionStyle::None;
+        let prompt = create_llm_prompt(&AudioFeatures::default(), &AIAnalysisResult::default(), &config);
+        assert!(!prompt.contains("Description"));
+
+        let (description, tags) = parse_llm_response("hypnotic, warehouse, peak-time", DescriptionStyle::None).unwrap();
+        assert!(description.is_none());
+        assert_eq!(tags, vec!["hypnotic", "warehouse", "peak-time"]);
+    }
+
+    #[test]
+    fn test_description_style_set_notes() {
+        let mut config = AIConfig::default();
+        config.description_style = DescriptionStyle::SetNotes;
+        let prompt = create_llm_prompt(&AudioFeatures::default(), &AIAnalysisResult::default(), &config);
+        assert!(prompt.contains("DJ set notes"));
+
+        let response = "Description: Peak-time roller. Mix in over the breakdown.\nTags: hypnotic, Warehouse Vibe";
+        let (description, tags) = parse_llm_response(response, DescriptionStyle::SetNotes).unwrap();
+        assert_eq!(description.unwrap(), "Peak-time roller. Mix in over the breakdown.");
+        assert_eq!(tags, vec!["hypnotic", "warehouse-vibe"]);
+    }
+
+    #[test]
+    fn test_sidecar_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let audio = dir.path().join("track.mp3");
+        let result = AIAnalysisResult {
+            genres: vec![TagWithConfidence::new("techno", 0.9).with_source(DetectionSource::Onnx)],
+            moods: vec![TagWithConfidence::new("dark", 0.7)],
+            custom_tags: vec![TagWithConfidence::new("peak-time", 0.6).with_source(DetectionSource::Llm).with_collection("Set")],
+            energy_level: Some(85.0),
+            danceability: Some(70.0),
+            aggression: Some(40.0),
+            energy_timeline: Some(EnergyTimeline { segment_secs: 8.0, energy: vec![20.0, 80.0], peaks: vec![8.0] }),
+            mix_in: Some(16.0),
+            mix_out: Some(320.0),
+            mood_point: Some(MoodPoint { valence: 0.3, arousal: 0.8 }),
+            confidence: 0.75,
+            audio_features: Some(AudioFeatures { bpm: Some(128.0), key: Some("Am".to_string()), ..Default::default() }),
+            description: Some("Driving warehouse roller".to_string()),
+            llm_suggestions: vec!["hypnotic".to_string()],
+            explicit: Some(false),
+            is_instrumental: Some(true),
+        };
+
+        assert!(AIAnalysisResult::read_sidecar(&audio).is_err());
+        let path = result.write_sidecar(&audio).unwrap();
+        assert_eq!(path, dir.path().join("track.mp3.ai.json"));
+        let read = AIAnalysisResult::read_sidecar(&audio).unwrap();
+        assert_eq!(serde_json::to_value(&read).unwrap(), serde_json::to_value(&result).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_empty_lyrics_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let audio = dir.path().join("track.wav");
+        test_utils::write_sine_wav(&audio, 440.0, 1.0);
+        let server = test_utils::MockServer::start(vec![test_utils::MockResponse::new(200, test_utils::gemini_response("Description: Test."))]).await;
+        let mut config = test_utils::llm_config(&server);
+        config.lyrics_themes = true;
+
+        let result = analyze_track_with_lyrics(&audio, &config, " \n").await.unwrap();
+        assert!(server.hits() > 0);
+        assert!(server.requests().iter().all(|r| !r.contains("Lyrics:")));
+        // Vocal detection is up to the audio
+        assert_eq!(result.is_instrumental, analyze_track(&audio, &config).await.unwrap().is_instrumental);
+    }
+
+    #[tokio::test]
+    async fn test_reuse_sidecar() {
+        let dir = tempfile::tempdir().unwrap();
+        let audio = dir.path().join("track.wav");
+        test_utils::write_sine_wav(&audio, 440.0, 1.0);
+        let result = AIAnalysisResult { description: Some("from sidecar".to_string()), ..Default::default() };
+        result.write_sidecar(&audio).unwrap();
+
+        let mut config = AIConfig::default();
+        config.llm_enabled = false;
+        config.cache_dir = None;
+        assert!(analyze_track(&audio, &config).await.unwrap().description.is_none());
+        config.reuse_sidecars = true;
+        assert_eq!(analyze_track(&audio, &config).await.unwrap().description.unwrap(), "from sidecar");
+
+        // Audio changed after the sidecar was written
+        let sidecar = AIAnalysisResult::sidecar_path(&audio);
+        let old = std::fs::metadata(&audio).unwrap().modified().unwrap() - Duration::from_secs(60);
+        std::fs::File::options().write(true).open(&sidecar).unwrap().set_modified(old).unwrap();
+        assert!(analyze_track(&audio, &config).await.unwrap().description.is_none());
+
+        // Batch and library analysis reuse it too
+        result.write_sidecar(&audio).unwrap();
+        let batch = analyze_batch(&[audio.clone()], &config).await;
+        assert_eq!(batch[0].as_ref().unwrap().description.as_deref(), Some("from sidecar"));
+        let library: Vec<_> = analyze_library(vec![audio.clone()], config.clone()).collect().await;
+        assert_eq!(library[0].1.as_ref().unwrap().description.as_deref(), Some("from sidecar"));
+    }
+
+    #[tokio::test]
+    async fn test_analyze_library() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut paths: Vec<PathBuf> = (0..4).map(|i| dir.path().join(format!("{}.wav", i))).collect();
+        for (i, path) in paths.iter().enumerate() {
+            test_utils::write_sine_wav(path, 220.0 * (i + 1) as f32, 1.0);
+        }
+        paths.push(dir.path().join("missing.wav"));
+        let mut config = AIConfig::default();
+        config.llm_enabled = false;
+        config.cache_dir = None;
+        config.max_threads = 2;
+
+        let results: Vec<(PathBuf, Result<AIAnalysisResult, AiError>)> = analyze_library(paths.clone(), config).collect().await;
+        let mut seen: Vec<&PathBuf> = results.iter().map(|(p, _)| p).collect();
+        seen.sort();
+        let mut expected: Vec<&PathBuf> = paths.iter().collect();
+        expected.sort();
+        assert_eq!(seen, expected);
+        for (path, result) in &results {
+            assert_eq!(result.is_err(), path.ends_with("missing.wav"), "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn test_result_summary() {
+        let result = AIAnalysisResult {
+            genres: vec![TagWithConfidence::new("techno", 0.9), TagWithConfidence::new("house", 0.6)],
+            moods: vec![TagWithConfidence::new("dark", 0.7)],
+            energy_level: Some(85.0),
+            llm_suggestions: vec!["hypnotic".to_string(), "warehouse".to_string()],
+            ..Default::default()
+        };
+        let summary = result.to_string();
+        assert_eq!(summary, "genres: techno 90%, house 60% | moods: dark 70% | energy 85, danceability - | 2 suggestions");
+        assert_eq!(AIAnalysisResult::default().to_string(), "genres: - | moods: - | energy -, danceability - | 0 suggestions");
+    }
+
     #[test]
     fn test_api_key_urls() {
         assert!(get_api_key_url(&APIProvider::Gemini).contains("aistudio.google.com"));
@@ -375,10 +1452,16 @@ impl Default for AIAnalysisResult {
             energy_level: None,
             danceability: None,
             aggression: None,
+            energy_timeline: None,
+            mix_in: None,
+            mix_out: None,
+            mood_point: None,
             confidence: 0.0,
             audio_features: None,
             description: None,
             llm_suggestions: Vec::new(),
+            explicit: None,
+            is_instrumental: None,
         }
     }
 }
@@ -0,0 +1,113 @@
+//! Lyrics Module
+//!
+//! Thematic moods (love, protest, party...) and explicit content detected in lyrics by the LLM,
+//! catches what audio features alone miss on vocal tracks
+
+use anyhow::Result;
+use serde::Deserialize;
+use std::path::Path;
+use crate::api::APIClient;
+use crate::{DetectionSource, TagWithConfidence};
+
+/// Tags lyrics are stored in (ID3, Vorbis / FLAC, MP4)
+const LYRICS_TAGS: [&str; 4] = ["USLT", "LYRICS", "UNSYNCEDLYRICS", "©lyr"];
+/// Lyrics are cut to this many characters before prompting
+const MAX_LYRICS_CHARS: usize = 4000;
+/// Max themes requested from the LLM
+const MAX_THEMES: usize = 3;
+
+/// Read unsynchronized lyrics from the file's tags
+pub fn read_lyrics(path: &Path) -> Option<String> {
+    let tag = onetagger_tag::Tag::load_file(path, false).ok()?;
+    let tag = tag.tag();
+    LYRICS_TAGS.iter()
+        .filter_map(|t| tag.get_raw(t))
+        .map(|v| v.join("\n"))
+        .find(|l| !is_instrumental(l))
+}
+
+/// Explicit advisory already in the file's tags
+pub fn read_explicit(path: &Path) -> Option<bool> {
+    onetagger_tag::Tag::load_file(path, false).ok()?.tag().get_explicit()
+}
+
+/// Result of lyrics analysis
+#[derive(Debug, Clone, Default)]
+pub struct LyricsAnalysis {
+    /// Overall mood followed by themes
+    pub moods: Vec<TagWithConfidence>,
+    /// Explicit content, None if the LLM didn't tell
+    pub explicit: Option<bool>,
+}
+
+/// Empty or placeholder lyrics
+pub fn is_instrumental(lyrics: &str) -> bool {
+    let lyrics = lyrics.trim().trim_matches(|c| c == '[' || c == ']' || c == '(' || c == ')');
+    lyrics.is_empty() || lyrics.eq_ignore_ascii_case("instrumental")
+}
+
+/// Ask the LLM for the overall mood, themes and explicitness of lyrics, empty for instrumental tracks
+pub async fn analyze_lyrics(client: &APIClient, lyrics: &str) -> Result<LyricsAnalysis> {
+    if is_instrumental(lyrics) {
+        debug!("No lyrics, skipping lyrics analysis");
+        return Ok(LyricsAnalysis::default());
+    }
+    let response = client.generate(&prompt(lyrics)).await?;
+    parse_response(&response)
+}
+
+/// Prompt asking for mood, themes and explicitness in JSON
+fn prompt(lyrics: &str) -> String {
+    let lyrics: String = lyrics.chars().take(MAX_LYRICS_CHARS).collect();
+    format!("You are a DJ assistant tagging a vocal track by its lyrics.\n\nLyrics:\n{}\n\n\
+        Answer only with JSON containing the overall mood, up to {} themes (single lowercase words) and whether the lyrics \
+        are explicit (profanity, sexual or violent content), e.g. \
+        {{\"mood\": {{\"tag\": \"melancholic\", \"confidence\": 0.8}}, \"themes\": [{{\"tag\": \"love\", \"confidence\": 0.9}}], \"explicit\": false}}\n",
+        lyrics.trim(), MAX_THEMES)
+}
+
+/// Parse JSON answer
+fn parse_response(response: &str) -> Result<LyricsAnalysis> {
+    #[derive(Deserialize)]
+    struct LlmTag {
+        tag: String,
+        confidence: f32,
+    }
+    #[derive(Deserialize)]
+    struct LlmLyrics {
+        mood: Option<LlmTag>,
+        #[serde(default)]
+        themes: Vec<LlmTag>,
+        explicit: Option<bool>,
+    }
+
+    // Strip markdown fences / surrounding text
+    let start = response.find('{').ok_or(anyhow!("No JSON object in response"))?;
+    let end = response.rfind('}').ok_or(anyhow!("No JSON object in response"))?;
+    let parsed: LlmLyrics = serde_json::from_str(response.get(start..=end).unwrap_or_default())?;
+
+    let moods = parsed.mood.into_iter()
+        .chain(parsed.themes.into_iter().take(MAX_THEMES))
+        .filter(|t| !t.tag.trim().is_empty())
+        .map(|t| TagWithConfidence::new(t.tag.trim().to_lowercase(), t.confidence.clamp(0.0, 1.0)).with_source(DetectionSource::Lyrics))
+        .collect();
+    Ok(LyricsAnalysis { moods, explicit: parsed.explicit })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lyrics_response() {
+        let analysis = parse_response("```json\n{\"mood\": {\"tag\": \"Euphoric\", \"confidence\": 0.8}, \"themes\": [{\"tag\": \"party\", \"confidence\": 1.4}]}\n```").unwrap();
+        assert_eq!(analysis.explicit, None);
+        let tags = analysis.moods;
+        assert_eq!(tags.len(), 2);
+        assert_eq!((tags[0].tag.as_str(), tags[0].confidence), ("euphoric", 0.8));
+        assert_eq!((tags[1].tag.as_str(), tags[1].confidence), ("party", 1.0));
+        assert!(tags.iter().all(|t| t.source == DetectionSource::Lyrics));
+        assert!(is_instrumental(" [Instrumental] "));
+        assert!(is_instrumental(""));
+    }
+}
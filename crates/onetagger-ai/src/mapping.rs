@@ -0,0 +1,184 @@
+//! Custom Tag Mapping Module
+//!
+//! Maps detected tags onto the user's custom taxonomy using precomputed lookups,
+//! so mapping cost doesn't grow with the taxonomy size
+
+use std::collections::HashMap;
+use crate::config::CustomTagConfig;
+use crate::quality::edit_distance;
+use crate::{AIAnalysisResult, DetectionSource, TagWithConfidence};
+
+/// Confidence assigned to LLM suggestions matching a custom collection
+const LLM_SUGGESTION_CONFIDENCE: f32 = 0.8;
+
+/// Normalized tag -> collection lookups built once per config, reusable across tracks
+#[derive(Debug, Clone)]
+pub struct TagMapper {
+    genres: HashMap<String, String>,
+    moods: HashMap<String, String>,
+    collections: HashMap<String, (String, String)>,
+    /// Collection tags without separators, (tag, collection)
+    compact: HashMap<String, (String, String)>,
+    fuzzy_threshold: f32,
+}
+
+impl TagMapper {
+    /// Precompute lookups for the config
+    pub fn new(custom: &CustomTagConfig) -> TagMapper {
+        let mut mapper = TagMapper {
+            genres: HashMap::new(),
+            moods: HashMap::new(),
+            collections: HashMap::new(),
+            compact: HashMap::new(),
+            fuzzy_threshold: custom.fuzzy_threshold,
+        };
+        // Same precedence as `CustomTagConfig::collection_for`, vibes aren't mapped
+        for (name, tags) in custom.collections() {
+            for tag in tags {
+                match name {
+                    "genres" => { mapper.genres.entry(normalize(tag)).or_insert_with(|| name.to_string()); },
+                    "moods" => { mapper.moods.entry(normalize(tag)).or_insert_with(|| name.to_string()); },
+                    "vibes" => {},
+                    _ => {
+                        let entry = (tag.to_string(), name.to_string());
+                        mapper.collections.entry(normalize(tag)).or_insert_with(|| entry.clone());
+                        mapper.compact.entry(compact(tag)).or_insert(entry);
+                    },
+                }
+            }
+        }
+        mapper
+    }
+
+    /// Collection tag (canonical spelling) and collection of an LLM suggestion.
+    /// Exact (case-insensitive) matches first, then ignoring separators, then the most similar tag above the fuzzy threshold
+    pub fn resolve(&self, suggestion: &str) -> Option<(&str, &str)> {
+        let found = self.collections.get(&normalize(suggestion))
+            .or_else(|| self.compact.get(&compact(suggestion)))
+            .or_else(|| self.closest(suggestion));
+        found.map(|(tag, collection)| (tag.as_str(), collection.as_str()))
+    }
+
+    /// Most similar collection tag by normalized edit distance of the compact forms
+    fn closest(&self, suggestion: &str) -> Option<&(String, String)> {
+        if self.fuzzy_threshold >= 1.0 {
+            return None;
+        }
+        let suggestion: Vec<char> = compact(suggestion).chars().collect();
+        // Max edit distance allowed for the longer of the two is at least the length difference
+        let max_ratio = 1.0 - self.fuzzy_threshold.max(0.0);
+        let mut best: Option<(&(String, String), f32)> = None;
+        for (tag, entry) in &self.compact {
+            let length = tag.chars().count().max(suggestion.len());
+            if length == 0 || (tag.chars().count() as f32 - suggestion.len() as f32).abs() > max_ratio * length as f32 {
+                continue;
+            }
+            let tag: Vec<char> = tag.chars().collect();
+            let similarity = 1.0 - edit_distance(&suggestion, &tag) as f32 / length as f32;
+            // Ties go to the alphabetically first tag so the result doesn't depend on hash order
+            let better = match best {
+                None => true,
+                Some((current, s)) => similarity > s || (similarity == s && entry.0 < current.0),
+            };
+            if similarity >= self.fuzzy_threshold && better {
+                best = Some((entry, similarity));
+            }
+        }
+        best.map(|(entry, _)| entry)
+    }
+
+    /// Map AI results to the custom tag collections
+    pub fn map(&self, result: &AIAnalysisResult) -> Vec<TagWithConfidence> {
+        let mut custom_tags = Vec::new();
+
+        // Map genres to custom genre taxonomy
+        for genre in &result.genres {
+            if let Some(collection) = self.genres.get(&normalize(&genre.tag)) {
+                custom_tags.push(genre.clone().with_collection(collection));
+            }
+        }
+
+        // Map moods to custom mood taxonomy
+        for mood in &result.moods {
+            if let Some(collection) = self.moods.get(&normalize(&mood.tag)) {
+                custom_tags.push(mood.clone().with_collection(collection));
+            }
+        }
+
+        // Check LLM suggestions against custom collections
+        for suggestion in &result.llm_suggestions {
+            if let Some((tag, collection)) = self.resolve(suggestion) {
+                custom_tags.push(TagWithConfidence::new(tag, LLM_SUGGESTION_CONFIDENCE)
+                    .with_source(DetectionSource::Llm)
+                    .with_collection(collection));
+            }
+        }
+
+        custom_tags
+    }
+}
+
+/// Case-insensitive lookup key
+fn normalize(tag: &str) -> String {
+    tag.to_ascii_lowercase()
+}
+
+/// Lowercase alphanumerics only ("Deep House" = "deep-house")
+fn compact(tag: &str) -> String {
+    tag.chars().filter(|c| c.is_alphanumeric()).flat_map(|c| c.to_lowercase()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn test_large_taxonomy() {
+        let mut custom = CustomTagConfig::default();
+        for c in 0..100 {
+            let tags = (0..1000).map(|i| format!("collection{}-tag{}", c, i)).collect();
+            custom.custom_collections.insert(format!("collection{}", c), tags);
+        }
+        custom.genres.extend((0..10000).map(|i| format!("genre-{}", i)));
+        // Exact matching only, "collection99-tag1000" would be a near miss of "collection99-tag100"
+        custom.fuzzy_threshold = 1.0;
+
+        let result = AIAnalysisResult {
+            genres: vec![TagWithConfidence::new("Genre-9999", 0.9), TagWithConfidence::new("not-a-genre", 0.9)],
+            moods: vec![TagWithConfidence::new("DARK", 0.8)],
+            llm_suggestions: (0..1000).map(|i| format!("collection99-tag{}", i * 2)).collect(),
+            ..Default::default()
+        };
+
+        let mapper = TagMapper::new(&custom);
+        let start = Instant::now();
+        let mapped = mapper.map(&result);
+        let elapsed = start.elapsed();
+
+        // 1 genre + 1 mood + 500 suggestions (tags only go up to 999)
+        assert_eq!(mapped.len(), 502);
+        assert_eq!(mapped[0].tag, "Genre-9999");
+        assert_eq!(mapped[1].tag, "DARK");
+        assert!(mapped[2..].iter().all(|t| t.confidence == LLM_SUGGESTION_CONFIDENCE && t.source == DetectionSource::Llm));
+        assert_eq!(mapped[0].collection.as_deref(), Some("genres"));
+        assert_eq!(mapped[2].collection.as_deref(), Some("collection99"));
+        // Linear scans would do ~10^8 comparisons here
+        assert!(elapsed.as_millis() < 500, "Mapping took {:?}", elapsed);
+    }
+
+    #[test]
+    fn test_fuzzy_suggestions() {
+        let mut custom = CustomTagConfig::default();
+        custom.custom_collections.insert("styles".to_string(), vec!["deep-house".to_string(), "peak-time".to_string()]);
+        let mapper = TagMapper::new(&custom);
+        for suggestion in ["deep house", "deephouse", "Deep-House", "deep-hous"] {
+            assert_eq!(mapper.resolve(suggestion), Some(("deep-house", "styles")), "{}", suggestion);
+        }
+        assert_eq!(mapper.resolve("peaktime"), Some(("peak-time", "styles")));
+        assert_eq!(mapper.resolve("polka"), None);
+
+        custom.fuzzy_threshold = 1.0;
+        assert_eq!(TagMapper::new(&custom).resolve("deep-hous"), None);
+    }
+}
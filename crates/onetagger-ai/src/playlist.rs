@@ -7,8 +7,19 @@
 
 use anyhow::{Error, Result};
 use serde::{Serialize, Deserialize};
+use std::path::Path;
 use onetagger_tagger::Track;
 use crate::config::{PlaylistConfig, EnergyCurve};
+use crate::camelot;
+
+/// Tag written by the AI tagger with energy level (0-100)
+const ENERGY_FRAME: &str = "AI_ENERGY";
+/// Maximum BPM difference from the first track when BPM changes are disabled
+const BPM_TOLERANCE: f32 = 1.0;
+/// Energy points a 1 BPM jump costs when ranking candidates
+const BPM_SMOOTHING: f32 = 0.5;
+/// Energy points a Camelot wheel step costs once harmonic mixing is relaxed
+const HARMONIC_PENALTY: f32 = 10.0;
 
 /// Playlist generator
 pub struct PlaylistGenerator {
@@ -23,36 +34,151 @@ impl PlaylistGenerator {
     /// Generate a playlist from a track library
     pub fn generate(&self, library: &[Track]) -> Result<GeneratedPlaylist> {
         info!("Generating playlist with {} tracks in library", library.len());
+        let duration_secs = self.config.duration_minutes as u64 * 60;
 
-        let mut tracks = Vec::new();
-
-        // TODO: Implement smart track selection based on:
-        // - Energy curve
-        // - BPM transitions
-        // - Harmonic mixing
-        // - Mood progression
+        // Tracks with unknown length can't be planned, unknown energy is skipped
+        let mut candidates: Vec<(&Track, f32)> = library.iter()
+            .filter(|t| !t.duration.is_zero())
+            .filter_map(|t| Some((t, track_energy(t)?)))
+            .collect();
+        debug!("{} of {} tracks usable for playlist", candidates.len(), library.len());
 
-        // For now, just take tracks that match criteria
-        let duration_secs = self.config.duration_minutes * 60;
+        // Greedily follow the energy curve
+        let mut tracks: Vec<&Track> = vec![];
+        let mut elapsed = 0;
+        let mut artist_gap_relaxed = false;
+        while elapsed < duration_secs && !candidates.is_empty() {
+            let position = elapsed as f32 / duration_secs as f32;
+            let target = target_energy(self.config.energy_curve, position);
+            // Shrink the artist gap if the library is too small
+            let Some((index, gap)) = (0..=self.config.min_artist_gap).rev()
+                .find_map(|gap| Some((self.pick(&candidates, target, &tracks, position, gap)?, gap))) else {
+                warn!("No track within BPM range left, playlist is shorter than requested");
+                break;
+            };
+            if gap < self.config.min_artist_gap && !artist_gap_relaxed {
+                warn!("Not enough artists for a gap of {} tracks, relaxing to {}", self.config.min_artist_gap, gap);
+                artist_gap_relaxed = true;
+            }
+            let (track, _) = candidates.remove(index);
+            elapsed += track.duration.as_secs();
+            tracks.push(track);
+        }
 
         let playlist = GeneratedPlaylist {
             name: "AI Generated Playlist".to_string(),
-            tracks,
-            total_duration: 0,
-            energy_curve: vec![],
-            bpm_progression: vec![],
+            tracks: tracks.iter().map(|t| track_ref(t)).collect(),
+            total_duration: elapsed as usize,
+            energy_curve: self.calculate_energy_curve(&tracks),
+            bpm_progression: tracks.iter().map(|t| t.bpm.unwrap_or(0) as f32).collect(),
+            entries: tracks.iter().map(|t| PlaylistEntry::from_track(t)).collect(),
+            artist_gap_relaxed,
         };
+        info!("Generated playlist with {} tracks ({}s)", playlist.tracks.len(), playlist.total_duration);
 
         Ok(playlist)
     }
 
+    /// Index of the candidate closest to the target energy (with smallest BPM jump), moving in the direction of the curve
+    /// and harmonically compatible with the previous track (if enabled). BPM limits are never relaxed, the artist gap is relaxed by the caller.
+    /// Once harmonic mixing is relaxed, candidates closer on the Camelot wheel are preferred.
+    fn pick(&self, candidates: &[(&Track, f32)], target: f32, selected: &[&Track], position: f32, artist_gap: usize) -> Option<usize> {
+        let previous = selected.last().copied();
+        let recent = &selected[selected.len().saturating_sub(artist_gap)..];
+        let artist_ok = |track: &Track| !track.artists.iter()
+            .any(|a| recent.iter().any(|r| r.artists.iter().any(|b| a.eq_ignore_ascii_case(b))));
+        // Direction of the curve since the previous track
+        let rising = target_energy(self.config.energy_curve, position) - target_energy(self.config.energy_curve, (position - 0.05).max(0.0));
+        let previous_energy = previous.and_then(track_energy);
+        let direction_ok = |energy: f32| match previous_energy {
+            Some(previous) if rising > f32::EPSILON => energy >= previous,
+            Some(previous) if rising < -f32::EPSILON => energy <= previous,
+            _ => true,
+        };
+        let previous_key = previous.and_then(|p| p.key.as_ref()).filter(|_| self.config.harmonic_mixing);
+        let harmonic_ok = |track: &Track| match previous_key {
+            Some(previous) => track.key.as_ref().map(|k| camelot_compatible(previous, k)).unwrap_or(false),
+            None => true,
+        };
+        // Unknown keys are as far as it gets
+        let harmonic_penalty = |track: &Track| match previous_key {
+            Some(previous) => track.key.as_ref().and_then(|k| camelot::key_distance(previous, k)).unwrap_or(7) as f32 * HARMONIC_PENALTY,
+            None => 0.0,
+        };
+        // BPM range relative to previous track, or the first one if BPM has to stay the same
+        let (reference, max_difference) = match self.config.allow_bpm_changes {
+            true => (previous.and_then(|t| t.bpm), self.config.max_bpm_difference),
+            false => (selected.first().and_then(|t| t.bpm), BPM_TOLERANCE),
+        };
+        let bpm_ok = |track: &Track| match (reference, track.bpm) {
+            (Some(reference), Some(bpm)) => ((bpm - reference) as f32).abs() <= max_difference,
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        let score = |track: &Track, energy: f32| {
+            let jump = match (previous.and_then(|t| t.bpm), track.bpm) {
+                (Some(a), Some(b)) => (a - b).abs() as f32,
+                _ => 0.0,
+            };
+            (energy - target).abs() + jump * BPM_SMOOTHING
+        };
+        let closest = |filter: &dyn Fn(&Track, f32) -> bool, penalty: &dyn Fn(&Track) -> f32| candidates.iter().enumerate()
+            .filter(|(_, (t, e))| bpm_ok(t) && artist_ok(t) && filter(t, *e))
+            .min_by(|(_, (ta, a)), (_, (tb, b))| (score(ta, *a) + penalty(ta)).total_cmp(&(score(tb, *b) + penalty(tb))))
+            .map(|(i, _)| i);
+
+        // Relax direction, then harmonic mixing if nothing fits
+        let none = |_: &Track| 0.0;
+        closest(&|t, e| harmonic_ok(t) && direction_ok(e), &none)
+            .or_else(|| closest(&|t, _| harmonic_ok(t), &none))
+            .or_else(|| closest(&|_, e| direction_ok(e), &harmonic_penalty))
+            .or_else(|| closest(&|_, _| true, &harmonic_penalty))
+    }
+
     /// Calculate energy curve for a playlist
     fn calculate_energy_curve(&self, tracks: &[&Track]) -> Vec<f32> {
-        // TODO: Calculate actual energy progression
-        vec![]
+        tracks.iter().map(|t| track_energy(t).unwrap_or(0.0)).collect()
+    }
+}
+
+/// Whether keys (any notation) can be mixed (`camelot::compatible`), false for unknown keys
+pub fn camelot_compatible(a: &str, b: &str) -> bool {
+    match (camelot::to_camelot(a), camelot::to_camelot(b)) {
+        (Some(a), Some(b)) => camelot::compatible(a, b),
+        _ => false,
+    }
+}
+
+/// Target energy (0-100) at position (0-1) of the playlist
+fn target_energy(curve: EnergyCurve, position: f32) -> f32 {
+    let position = position.clamp(0.0, 1.0);
+    match curve {
+        EnergyCurve::GradualBuild | EnergyCurve::Custom => 30.0 + 70.0 * position,
+        EnergyCurve::QuickPeak => (40.0 + 200.0 * position).min(90.0),
+        EnergyCurve::Constant => 70.0,
+        EnergyCurve::Wave => 60.0 + 30.0 * (position * 4.0 * std::f32::consts::PI).sin(),
+    }
+}
+
+/// Energy (0-100) from AI tags, estimated from BPM if missing
+fn track_energy(track: &Track) -> Option<f32> {
+    let tagged = track.other.iter()
+        .find(|(frame, _)| frame.id3 == ENERGY_FRAME)
+        .and_then(|(_, values)| values.first()?.trim().parse::<f32>().ok());
+    match tagged {
+        Some(energy) => Some(energy.clamp(0.0, 100.0)),
+        None => track.bpm.map(|bpm| ((bpm as f32 - 80.0) * 1.25).clamp(0.0, 100.0)),
     }
 }
 
+/// Path / URL of track, falling back to ID and title
+fn track_ref(track: &Track) -> String {
+    if !track.url.is_empty() {
+        return track.url.to_string();
+    }
+    track.track_id.clone().unwrap_or_else(|| track.full_title())
+}
+
 /// Generated playlist result
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -62,16 +188,286 @@ pub struct GeneratedPlaylist {
     pub total_duration: usize,  // seconds
     pub energy_curve: Vec<f32>,
     pub bpm_progression: Vec<f32>,
+    /// Track details for export
+    #[serde(default)]
+    pub entries: Vec<PlaylistEntry>,
+    /// Library was too small to keep `min_artist_gap`
+    #[serde(default)]
+    pub artist_gap_relaxed: bool,
+}
+
+impl GeneratedPlaylist {
+    /// Extended M3U, paths relative to `base_dir` if they are inside it
+    pub fn to_m3u8(&self, base_dir: Option<&Path>) -> String {
+        let mut m3u = "#EXTM3U\r\n".to_string();
+        for entry in &self.entries {
+            let path = Path::new(&entry.path);
+            let path = base_dir.and_then(|base| path.strip_prefix(base).ok()).unwrap_or(path);
+            m3u.push_str(&format!("#EXTINF:{},{}\r\n{}\r\n", entry.duration, entry.display_title(), path.to_string_lossy()));
+        }
+        m3u
+    }
+
+    /// Rekordbox XML collection with this playlist
+    pub fn to_rekordbox_xml(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<DJ_PLAYLISTS Version=\"1.0.0\">\n");
+        xml.push_str(&format!("  <PRODUCT Name=\"OneTagger\" Version=\"{}\" Company=\"OneTagger\"/>\n", crate::VERSION));
+        xml.push_str(&format!("  <COLLECTION Entries=\"{}\">\n", self.entries.len()));
+        for (i, entry) in self.entries.iter().enumerate() {
+            xml.push_str(&format!(
+                "    <TRACK TrackID=\"{}\" Name=\"{}\" Artist=\"{}\" TotalTime=\"{}\" AverageBpm=\"{:.2}\" Tonality=\"{}\" Location=\"{}\"/>\n",
+                i + 1,
+                xml_escape(&entry.title),
+                xml_escape(&entry.artists.join(", ")),
+                entry.duration,
+                entry.bpm.unwrap_or(0.0),
+                xml_escape(&entry.key.as_deref().map(|k| crate::tagger::normalize_key(k).unwrap_or(k.to_string())).unwrap_or_default()),
+                xml_escape(&file_location(&entry.path)),
+            ));
+        }
+        xml.push_str("  </COLLECTION>\n  <PLAYLISTS>\n    <NODE Type=\"0\" Name=\"ROOT\" Count=\"1\">\n");
+        xml.push_str(&format!("      <NODE Name=\"{}\" Type=\"1\" KeyType=\"0\" Entries=\"{}\">\n", xml_escape(&self.name), self.entries.len()));
+        for i in 0..self.entries.len() {
+            xml.push_str(&format!("        <TRACK Key=\"{}\"/>\n", i + 1));
+        }
+        xml.push_str("      </NODE>\n    </NODE>\n  </PLAYLISTS>\n</DJ_PLAYLISTS>\n");
+        xml
+    }
+}
+
+/// Playlist track details
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistEntry {
+    /// Path / URL (see `tracks`)
+    pub path: String,
+    pub title: String,
+    pub artists: Vec<String>,
+    /// Seconds
+    pub duration: u64,
+    pub bpm: Option<f32>,
+    pub key: Option<String>,
+}
+
+impl PlaylistEntry {
+    fn from_track(track: &Track) -> PlaylistEntry {
+        PlaylistEntry {
+            path: track_ref(track),
+            title: track.full_title(),
+            artists: track.artists.clone(),
+            duration: track.duration.as_secs(),
+            bpm: track.bpm.map(|b| b as f32),
+            key: track.key.clone(),
+        }
+    }
+
+    /// `Artist - Title`
+    fn display_title(&self) -> String {
+        match self.artists.is_empty() {
+            true => self.title.to_string(),
+            false => format!("{} - {}", self.artists.join(", "), self.title),
+        }
+    }
+}
+
+/// Rekordbox style file URI
+fn file_location(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let encoded: Vec<String> = path.trim_start_matches('/').split('/')
+        .map(|segment| urlencoding::encode(segment).replace("%3A", ":"))
+        .collect();
+    format!("file://localhost/{}", encoded.join("/"))
+}
+
+/// Escape XML attribute value
+fn xml_escape(value: &str) -> String {
+    value.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::Duration;
+
+    /// Library track with energy tag
+    fn track(name: &str, energy: f32, bpm: i64, key: &str, minutes: u64) -> Track {
+        Track {
+            title: name.to_string(),
+            url: format!("/music/{}.mp3", name),
+            bpm: Some(bpm),
+            key: Some(key.to_string()),
+            duration: Duration::from_secs(minutes * 60),
+            other: vec![(onetagger_tag::FrameName::same(ENERGY_FRAME), vec![format!("{:.0}", energy)])],
+            ..Default::default()
+        }
+    }
+
+    fn library() -> Vec<Track> {
+        (0..30).map(|i| track(&format!("track{}", i), ((i * 37) % 100) as f32, 124 + (i % 5), "8A", 5 + (i as u64 % 3))).collect()
+    }
 
     #[test]
     fn test_playlist_generator() {
-        let config = PlaylistConfig::default();
-        let generator = PlaylistGenerator::new(config);
-        // Test will be added when implementation is complete
+        let generator = PlaylistGenerator::new(PlaylistConfig::default());
+        let playlist = generator.generate(&library()).unwrap();
+        assert!(!playlist.tracks.is_empty());
+        assert_eq!(playlist.tracks.len(), playlist.energy_curve.len());
+        assert_eq!(playlist.tracks.len(), playlist.bpm_progression.len());
+    }
+
+    #[test]
+    fn test_camelot_compatible() {
+        assert!(camelot_compatible("8A", "9A"));
+        assert!(camelot_compatible("12A", "1A"));
+        assert!(camelot_compatible("8A", "8B"));
+        assert!(camelot_compatible("Am", "C"));
+        // Energy boost
+        assert!(camelot_compatible("8A", "10A"));
+        assert!(!camelot_compatible("8A", "11A"));
+        assert!(!camelot_compatible("8A", "9B"));
+        assert!(!camelot_compatible("8A", "unknown"));
+    }
+
+    #[test]
+    fn test_harmonic_mixing() {
+        // Compatible 8A/9A tracks and incompatible ones
+        let mut library: Vec<Track> = (0..20)
+            .map(|i| track(&format!("track{}", i), 20.0 + i as f32 * 4.0, 126, ["8A", "9A"][i % 2], 5))
+            .collect();
+        library.extend((0..6).map(|i| track(&format!("other{}", i), 50.0 + i as f32 * 10.0, 126, ["3B", "5B"][i % 2], 5)));
+        let mut config = PlaylistConfig::default();
+        config.harmonic_mixing = true;
+        config.duration_minutes = 50;
+        let playlist = PlaylistGenerator::new(config).generate(&library).unwrap();
+
+        let keys: Vec<&str> = playlist.tracks.iter()
+            .map(|r| library.iter().find(|t| &t.url == r).unwrap().key.as_deref().unwrap())
+            .collect();
+        assert_eq!(keys.len(), 10);
+        assert!(keys.windows(2).all(|w| camelot_compatible(w[0], w[1])), "{:?}", keys);
+    }
+
+    #[test]
+    fn test_relaxed_harmonic_mixing() {
+        // Nothing compatible with 8A, the closer key wins over the closer energy
+        let library = vec![track("first", 30.0, 126, "8A", 5), track("near", 50.0, 126, "11A", 5), track("far", 60.0, 126, "2B", 5)];
+        let mut config = PlaylistConfig::default();
+        config.harmonic_mixing = true;
+        config.duration_minutes = 10;
+        let playlist = PlaylistGenerator::new(config).generate(&library).unwrap();
+        assert_eq!(playlist.tracks, vec!["/music/first.mp3".to_string(), "/music/near.mp3".to_string()]);
+    }
+
+    #[test]
+    fn test_max_bpm_difference() {
+        let library: Vec<Track> = (0..40)
+            .map(|i| track(&format!("track{}", i), ((i * 37) % 100) as f32, 90 + ((i * 13) % 85) as i64, "8A", 5))
+            .collect();
+        let mut config = PlaylistConfig::default();
+        config.harmonic_mixing = false;
+        config.allow_bpm_changes = true;
+        config.max_bpm_difference = 6.0;
+        let playlist = PlaylistGenerator::new(config.clone()).generate(&library).unwrap();
+        assert!(playlist.tracks.len() > 1);
+        assert!(playlist.bpm_progression.windows(2).all(|w| (w[1] - w[0]).abs() <= 6.0), "{:?}", playlist.bpm_progression);
+
+        // Single BPM
+        config.allow_bpm_changes = false;
+        let playlist = PlaylistGenerator::new(config).generate(&library).unwrap();
+        assert!(playlist.bpm_progression.iter().all(|b| (b - playlist.bpm_progression[0]).abs() <= BPM_TOLERANCE));
+    }
+
+    #[test]
+    fn test_export_m3u8() {
+        let playlist = PlaylistGenerator::new(PlaylistConfig::default()).generate(&library()).unwrap();
+        let m3u = playlist.to_m3u8(Some(Path::new("/music")));
+        assert!(m3u.starts_with("#EXTM3U"));
+        assert_eq!(m3u.matches("#EXTINF:").count(), playlist.tracks.len());
+
+        let files = onetagger_playlist::get_files_from_m3u(&m3u, Some("/music".into()));
+        let expected: Vec<std::path::PathBuf> = playlist.tracks.iter().map(|t| t.into()).collect();
+        assert_eq!(files, expected);
+    }
+
+    #[test]
+    fn test_export_rekordbox() {
+        let mut library = library();
+        library.iter_mut().for_each(|t| t.title = format!("{} & \"Friends\" <Edit>", t.title));
+        let playlist = PlaylistGenerator::new(PlaylistConfig::default()).generate(&library).unwrap();
+        let xml = playlist.to_rekordbox_xml();
+
+        // Well-formed: every opened element is closed in order
+        let mut stack = vec![];
+        for element in xml.split('<').skip(1).map(|e| &e[..e.find('>').unwrap()]) {
+            if element.starts_with('?') || element.ends_with('/') {
+                continue;
+            }
+            match element.strip_prefix('/') {
+                Some(name) => assert_eq!(stack.pop(), Some(name)),
+                None => stack.push(element.split(' ').next().unwrap()),
+            }
+        }
+        assert!(stack.is_empty());
+        assert!(!xml.contains("& "));
+
+        // Collection + playlist references
+        assert_eq!(xml.matches("<TRACK ").count(), playlist.tracks.len() * 2);
+        assert!(xml.contains("Location=\"file://localhost/music/"));
+        assert!(xml.contains("Tonality=\"Am\""));
+    }
+
+    #[test]
+    fn test_artist_gap() {
+        // Mostly one artist at the target energy
+        let mut library: Vec<Track> = (0..20).map(|i| track(&format!("solo{}", i), 70.0, 126, "8A", 5)).collect();
+        library.iter_mut().for_each(|t| t.artists = vec!["Solo".to_string()]);
+        library.extend((0..10).map(|i| Track {
+            artists: vec![format!("Artist {}", i)],
+            ..track(&format!("other{}", i), 40.0 + i as f32, 126, "8A", 5)
+        }));
+        let mut config = PlaylistConfig::default();
+        config.energy_curve = EnergyCurve::Constant;
+        let playlist = PlaylistGenerator::new(config.clone()).generate(&library).unwrap();
+        let solo: Vec<usize> = playlist.entries.iter().enumerate()
+            .filter(|(_, e)| e.artists == vec!["Solo"])
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(solo, vec![0, 4, 8]);
+        assert!(!playlist.artist_gap_relaxed);
+
+        // Only one artist
+        let playlist = PlaylistGenerator::new(config).generate(&library[..20]).unwrap();
+        assert_eq!(playlist.tracks.len(), 12);
+        assert!(playlist.artist_gap_relaxed);
+    }
+
+    #[test]
+    fn test_gradual_build() {
+        let mut config = PlaylistConfig::default();
+        config.energy_curve = EnergyCurve::GradualBuild;
+        config.duration_minutes = 60;
+        let playlist = PlaylistGenerator::new(config).generate(&library()).unwrap();
+        assert!(playlist.energy_curve.windows(2).all(|w| w[1] >= w[0]), "{:?}", playlist.energy_curve);
+    }
+
+    #[test]
+    fn test_playlist_duration() {
+        let mut config = PlaylistConfig::default();
+        config.duration_minutes = 45;
+        let library = library();
+        let playlist = PlaylistGenerator::new(config).generate(&library).unwrap();
+        // Overshoots by at most one track (7 minutes max)
+        assert!(playlist.total_duration >= 45 * 60);
+        assert!(playlist.total_duration < 45 * 60 + 7 * 60);
+        // Missing energy and BPM is skipped
+        let mut unknown = track("unknown", 0.0, 0, "8A", 5);
+        unknown.other.clear();
+        unknown.bpm = None;
+        let playlist = PlaylistGenerator::new(PlaylistConfig::default()).generate(&[unknown]).unwrap();
+        assert!(playlist.tracks.is_empty());
     }
 }
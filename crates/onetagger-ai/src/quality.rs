@@ -5,16 +5,110 @@
 use anyhow::{Error, Result};
 use serde::{Serialize, Deserialize};
 use onetagger_tagger::Track;
-use crate::features::AudioFeatures;
+use std::collections::HashMap;
+use std::path::Path;
+use crate::camelot;
+use crate::config::AIConfig;
+use crate::features::{AudioFeatures, AudioProperties, FeatureExtractor};
+
+/// Ratio of samples in sustained full scale runs above which the audio is considered clipped
+const MAX_CLIPPED_RATIO: f32 = 0.0005;
+/// Number of lowest scoring tracks listed in a library report
+const WORST_TRACKS: usize = 10;
+/// Fraction of the expected duration which may be missing before a file is considered truncated
+const TRUNCATION_TOLERANCE: f32 = 0.05;
+/// Crest factor (dB) below which the audio is considered over-compressed
+const MIN_CREST_FACTOR_DB: f32 = 6.0;
 
 /// Quality control checker
 pub struct QualityControl {
     strictness: f32,
+    /// Genre spellings used by `auto_fix`
+    taxonomy: Vec<String>,
+    /// Report genres outside the taxonomy
+    check_taxonomy: bool,
 }
 
 impl QualityControl {
     pub fn new(strictness: f32) -> Self {
-        Self { strictness }
+        Self { strictness, taxonomy: vec![], check_taxonomy: false }
+    }
+
+    /// Quality control with strictness and genre taxonomy from config
+    pub fn from_config(config: &AIConfig) -> Self {
+        Self::new(config.quality_strictness)
+            .with_taxonomy(config.custom_tags.genres.clone())
+            .with_taxonomy_check(config.validate_genre_taxonomy)
+    }
+
+    /// Report genres which aren't in the taxonomy, suggesting the closest allowed one
+    pub fn with_taxonomy_check(mut self, enabled: bool) -> Self {
+        self.check_taxonomy = enabled;
+        self
+    }
+
+    /// Normalize genre casing against a custom taxonomy
+    pub fn with_taxonomy(mut self, genres: Vec<String>) -> Self {
+        self.taxonomy = genres;
+        self
+    }
+
+    /// Corrected copy of the track and the fixes which were applied.
+    /// Missing values are filled in, set values are only replaced at maximum strictness
+    /// (trimming and genre casing don't change the value, so they always apply)
+    pub fn auto_fix(&self, track: &Track, features: &AudioFeatures) -> (Track, Vec<AppliedFix>) {
+        let mut fixed = track.clone();
+        let mut fixes = vec![];
+        let overwrite = self.strictness >= 1.0;
+
+        // BPM
+        if let Some(detected) = features.bpm.map(|b| b.round() as i64) {
+            let replace = match fixed.bpm {
+                None => true,
+                Some(bpm) => overwrite && (bpm - detected).abs() > 5,
+            };
+            if replace {
+                fixes.push(AppliedFix::new("BPM", fixed.bpm.map(|b| b.to_string()), detected.to_string()));
+                fixed.bpm = Some(detected);
+            }
+        }
+
+        // Key
+        if let Some(detected) = &features.key {
+            let replace = match &fixed.key {
+                None => true,
+                Some(key) => overwrite && camelot::key_distance(key, detected).map(|d| d > 0).unwrap_or(true),
+            };
+            if replace {
+                fixes.push(AppliedFix::new("Key", fixed.key.clone(), detected.to_string()));
+                fixed.key = Some(detected.to_string());
+            }
+        }
+
+        // Whitespace
+        if fixed.title.trim() != fixed.title {
+            let title = fixed.title.trim().to_string();
+            fixes.push(AppliedFix::new("Title", Some(fixed.title.clone()), title.clone()));
+            fixed.title = title;
+        }
+        for artist in fixed.artists.iter_mut() {
+            if artist.trim() != artist.as_str() {
+                let trimmed = artist.trim().to_string();
+                fixes.push(AppliedFix::new("Artist", Some(artist.clone()), trimmed.clone()));
+                *artist = trimmed;
+            }
+        }
+
+        // Genre casing
+        for genre in fixed.genres.iter_mut() {
+            let spelling = self.taxonomy.iter().find(|t| t.eq_ignore_ascii_case(genre.trim()));
+            if let Some(spelling) = spelling.filter(|s| s.as_str() != genre.as_str()) {
+                fixes.push(AppliedFix::new("Genre", Some(genre.clone()), spelling.to_string()));
+                *genre = spelling.to_string();
+            }
+        }
+
+        (fixed, fixes)
     }
 
     /// Validate a track's metadata
@@ -35,6 +129,43 @@ impl QualityControl {
             }
         }
 
+        // Check key consistency, relative / neighbouring keys are allowed depending on strictness
+        if let (Some(tagged_key), Some(detected_key)) = (&track.key, &features.key) {
+            let tolerance = self.key_tolerance();
+            match camelot::key_distance(tagged_key, detected_key) {
+                Some(distance) if distance as u32 > tolerance => {
+                    issues.push(ValidationIssue {
+                        severity: IssueSeverity::Warning,
+                        field: "Key".to_string(),
+                        message: format!("Tagged key ({}) is incompatible with detected ({})", tagged_key, detected_key),
+                    });
+                    suggestions.push(format!("Consider updating key to {}", detected_key));
+                },
+                _ => {}
+            }
+        }
+
+        // Check for clipping / over-compression
+        if let Some(dynamics) = &features.dynamics {
+            if dynamics.clipped_ratio > MAX_CLIPPED_RATIO {
+                issues.push(ValidationIssue {
+                    severity: IssueSeverity::Warning,
+                    field: "Audio".to_string(),
+                    message: format!("Clipping detected: {} sustained full scale runs ({:.2}% of samples)",
+                        dynamics.clip_runs, dynamics.clipped_ratio * 100.0),
+                });
+            }
+            // Silence has no meaningful crest factor
+            if dynamics.peak > 0.0 && dynamics.crest_factor_db < MIN_CREST_FACTOR_DB {
+                issues.push(ValidationIssue {
+                    severity: IssueSeverity::Warning,
+                    field: "Audio".to_string(),
+                    message: format!("Over-compressed master: crest factor {:.1} dB (peak {:.2})",
+                        dynamics.crest_factor_db, dynamics.peak),
+                });
+            }
+        }
+
         // Check for missing critical tags
         if track.genres.is_empty() {
             issues.push(ValidationIssue {
@@ -44,6 +175,23 @@ impl QualityControl {
             });
         }
 
+        // Check genres against the controlled vocabulary
+        if self.check_taxonomy && !self.taxonomy.is_empty() {
+            for genre in &track.genres {
+                if self.taxonomy.iter().any(|t| t.eq_ignore_ascii_case(genre)) {
+                    continue;
+                }
+                let Some((closest, distance)) = self.closest_genre(genre) else { continue };
+                // Same genre spelled differently is only informational
+                issues.push(ValidationIssue {
+                    severity: if distance == 0 { IssueSeverity::Info } else { IssueSeverity::Warning },
+                    field: "Genre".to_string(),
+                    message: format!("Genre \"{}\" is not in the genre taxonomy", genre),
+                });
+                suggestions.push(format!("Consider replacing genre \"{}\" with \"{}\"", genre, closest));
+            }
+        }
+
         if track.artists.is_empty() {
             issues.push(ValidationIssue {
                 severity: IssueSeverity::Error,
@@ -67,6 +215,92 @@ impl QualityControl {
         })
     }
 
+    /// Check that the file fully decodes: empty, undecodable or truncated files
+    /// (decoded audio much shorter than the container / tagged duration) are reported as errors
+    pub fn check_file(&self, path: &Path, track: &Track) -> Vec<ValidationIssue> {
+        let error = |message: String| vec![ValidationIssue { severity: IssueSeverity::Error, field: "Audio".to_string(), message }];
+        match std::fs::metadata(path) {
+            Ok(metadata) if metadata.len() == 0 => return error("Empty (zero-byte) file".to_string()),
+            Ok(_) => {},
+            Err(e) => return error(format!("File can't be read: {}", e)),
+        }
+
+        let (samples, sample_rate) = match FeatureExtractor::new().decode_samples(path) {
+            Ok(decoded) => decoded,
+            Err(e) => return error(format!("Audio can't be decoded: {}", e)),
+        };
+        let decoded = samples.len() as f32 / sample_rate as f32;
+        let container = AudioProperties::probe(path).map(|p| p.duration).unwrap_or(0.0);
+        let expected = container.max(track.duration.as_secs_f32());
+        if decoded == 0.0 || decoded < expected * (1.0 - TRUNCATION_TOLERANCE) {
+            return error(format!("Truncated or corrupt audio: decodes to {:.1}s, expected {:.1}s", decoded, expected));
+        }
+        vec![]
+    }
+
+    /// Validate all tracks and aggregate the results
+    pub fn validate_library(&self, tracks: &[(Track, AudioFeatures)]) -> Result<LibraryReport> {
+        let mut report = LibraryReport { tracks: tracks.len(), ..Default::default() };
+        let mut missing: Vec<(&'static str, usize)> = vec![];
+        let mut scored = vec![];
+        let (mut completeness, mut quality) = (0.0, 0.0);
+
+        for (track, features) in tracks {
+            let result = self.validate(track, features)?;
+            for issue in &result.issues {
+                match issue.severity {
+                    IssueSeverity::Error => report.errors += 1,
+                    IssueSeverity::Warning => report.warnings += 1,
+                    IssueSeverity::Info => report.infos += 1,
+                }
+            }
+            for field in missing_fields(track) {
+                match missing.iter_mut().find(|(f, _)| *f == field) {
+                    Some((_, count)) => *count += 1,
+                    None => missing.push((field, 1)),
+                }
+            }
+            completeness += result.completeness;
+            quality += result.quality_score;
+            scored.push(TrackQuality {
+                track: format!("{} - {}", track.artists.join(", "), track.full_title()),
+                quality_score: result.quality_score,
+                issues: result.issues.len(),
+            });
+        }
+
+        if !tracks.is_empty() {
+            report.average_completeness = completeness / tracks.len() as f32;
+            report.average_quality = quality / tracks.len() as f32;
+        }
+        // First seen wins ties
+        report.most_common_missing = missing.iter()
+            .fold(None, |best: Option<&(&str, usize)>, m| match best {
+                Some(b) if b.1 >= m.1 => Some(b),
+                _ => Some(m),
+            })
+            .map(|(field, _)| field.to_string());
+        report.missing_fields = missing.into_iter().map(|(f, c)| (f.to_string(), c)).collect();
+        scored.sort_by(|a, b| a.quality_score.total_cmp(&b.quality_score));
+        scored.truncate(WORST_TRACKS);
+        report.worst = scored;
+        Ok(report)
+    }
+
+    /// Taxonomy genre with the smallest edit distance (ignoring case and separators)
+    fn closest_genre(&self, genre: &str) -> Option<(&str, usize)> {
+        let simplify = |s: &str| s.chars().filter(|c| c.is_alphanumeric()).flat_map(|c| c.to_lowercase()).collect::<Vec<char>>();
+        let genre = simplify(genre);
+        self.taxonomy.iter()
+            .map(|t| (t.as_str(), edit_distance(&genre, &simplify(t))))
+            .min_by_key(|(_, distance)| *distance)
+    }
+
+    /// Allowed Camelot wheel steps between tagged and detected key (strict = exact match only)
+    fn key_tolerance(&self) -> u32 {
+        ((1.0 - self.strictness.clamp(0.0, 1.0)) * 2.5).round() as u32
+    }
+
     /// Calculate metadata completeness (0-1)
     fn calculate_completeness(&self, track: &Track) -> f32 {
         let mut score = 0.0;
@@ -103,6 +337,35 @@ impl QualityControl {
     }
 }
 
+/// Fields counted by completeness which the track is missing
+fn missing_fields(track: &Track) -> Vec<&'static str> {
+    let fields = [
+        ("Artist", !track.artists.is_empty()),
+        ("Genre", !track.genres.is_empty()),
+        ("BPM", track.bpm.is_some()),
+        ("Key", track.key.is_some()),
+        ("Label", track.label.is_some()),
+        ("Release date", track.release_date.is_some() || track.release_year.is_some()),
+        ("Artwork", track.art.is_some()),
+    ];
+    fields.into_iter().filter(|(_, present)| !present).map(|(field, _)| field).collect()
+}
+
+/// Levenshtein distance
+pub(crate) fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            current[j + 1] = (previous[j] + (ca != cb) as usize)
+                .min(previous[j + 1] + 1)
+                .min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
 /// Validation result
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -113,6 +376,50 @@ pub struct ValidationResult {
     pub suggestions: Vec<String>,
 }
 
+/// Aggregated quality of a library
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryReport {
+    pub tracks: usize,
+    /// Issue counts by severity
+    pub errors: usize,
+    pub warnings: usize,
+    pub infos: usize,
+    pub average_completeness: f32,
+    pub average_quality: f32,
+    /// Number of tracks missing each field
+    pub missing_fields: HashMap<String, usize>,
+    pub most_common_missing: Option<String>,
+    /// Lowest scoring tracks, worst first
+    pub worst: Vec<TrackQuality>,
+}
+
+/// Quality score of a single track in `LibraryReport`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackQuality {
+    /// Artists - title
+    pub track: String,
+    pub quality_score: f32,
+    pub issues: usize,
+}
+
+/// Change made by `QualityControl::auto_fix`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppliedFix {
+    pub field: String,
+    /// Previous value, `None` if missing
+    pub old: Option<String>,
+    pub new: String,
+}
+
+impl AppliedFix {
+    fn new(field: &str, old: Option<String>, new: String) -> Self {
+        Self { field: field.to_string(), old, new }
+    }
+}
+
 /// A validation issue
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ValidationIssue {
@@ -139,4 +446,185 @@ mod tests {
         let qc = QualityControl::new(0.7);
         assert_eq!(qc.strictness, 0.7);
     }
+
+    #[test]
+    fn test_clipping_warning() {
+        use crate::features::DynamicsStats;
+
+        let qc = QualityControl::new(0.7);
+        let track = Track { genres: vec!["techno".to_string()], artists: vec!["Artist".to_string()], ..Default::default() };
+        let mut features = AudioFeatures::default();
+
+        // Loud but clean master
+        features.dynamics = Some(DynamicsStats { peak: 0.99, crest_factor_db: 9.0, clip_runs: 0, clipped_ratio: 0.0 });
+        assert!(qc.validate(&track, &features).unwrap().issues.is_empty());
+
+        features.dynamics = Some(DynamicsStats { peak: 1.0, crest_factor_db: 4.5, clip_runs: 120, clipped_ratio: 0.01 });
+        let result = qc.validate(&track, &features).unwrap();
+        let audio: Vec<&ValidationIssue> = result.issues.iter().filter(|i| i.field == "Audio").collect();
+        assert_eq!(audio.len(), 2);
+        assert!(audio.iter().all(|i| i.severity == IssueSeverity::Warning));
+        assert!(audio[0].message.contains("120"));
+        assert!(audio[1].message.contains("4.5 dB"));
+    }
+
+    #[test]
+    fn test_key_consistency() {
+        let qc = QualityControl::new(0.6);
+        let mut track = Track { genres: vec!["techno".to_string()], artists: vec!["Artist".to_string()], key: Some("Am".to_string()), ..Default::default() };
+        let mut features = AudioFeatures { key: Some("C".to_string()), ..Default::default() };
+        let key_issues = |track: &Track, features: &AudioFeatures, qc: &QualityControl| {
+            qc.validate(track, features).unwrap().issues.into_iter().filter(|i| i.field == "Key").count()
+        };
+
+        // Relative key
+        assert_eq!(key_issues(&track, &features, &qc), 0);
+        // Enharmonic / Camelot notation
+        features.key = Some("8A".to_string());
+        track.key = Some("A minor".to_string());
+        assert_eq!(key_issues(&track, &features, &qc), 0);
+
+        features.key = Some("F#".to_string());
+        track.key = Some("Am".to_string());
+        assert_eq!(key_issues(&track, &features, &qc), 1);
+        assert!(qc.validate(&track, &features).unwrap().suggestions.iter().any(|s| s.contains("F#")));
+
+        // Strict mode only accepts the same key
+        features.key = Some("C".to_string());
+        assert_eq!(key_issues(&track, &features, &QualityControl::new(1.0)), 1);
+    }
+
+    #[test]
+    fn test_auto_fix() {
+        let qc = QualityControl::new(0.6).with_taxonomy(vec!["Deep-Techno".to_string()]);
+        let features = AudioFeatures { bpm: Some(127.8), key: Some("Am".to_string()), ..Default::default() };
+        let track = Track {
+            title: " Title ".to_string(),
+            artists: vec!["Artist  ".to_string(), "Other".to_string()],
+            genres: vec!["deep-techno".to_string(), "house".to_string()],
+            ..Default::default()
+        };
+        let (fixed, fixes) = qc.auto_fix(&track, &features);
+        assert_eq!(fixed.bpm, Some(128));
+        assert_eq!(fixed.key.as_deref(), Some("Am"));
+        assert_eq!(fixed.title, "Title");
+        assert_eq!(fixed.artists, vec!["Artist", "Other"]);
+        assert_eq!(fixed.genres, vec!["Deep-Techno", "house"]);
+        let fields: Vec<&str> = fixes.iter().map(|f| f.field.as_str()).collect();
+        assert_eq!(fields, vec!["BPM", "Key", "Title", "Artist", "Genre"]);
+        assert_eq!(fixes[0], AppliedFix::new("BPM", None, "128".to_string()));
+
+        // Already good track is unchanged
+        let (again, fixes) = qc.auto_fix(&fixed, &features);
+        assert!(fixes.is_empty());
+        assert_eq!(again.title, fixed.title);
+        assert_eq!(again.genres, fixed.genres);
+    }
+
+    #[test]
+    fn test_auto_fix_keeps_user_values() {
+        let features = AudioFeatures { bpm: Some(140.0), key: Some("F#".to_string()), ..Default::default() };
+        let track = Track { bpm: Some(124), key: Some("Am".to_string()), ..Default::default() };
+        let (fixed, fixes) = QualityControl::new(0.9).auto_fix(&track, &features);
+        assert!(fixes.is_empty());
+        assert_eq!(fixed.bpm, Some(124));
+        assert_eq!(fixed.key.as_deref(), Some("Am"));
+
+        // Maximum strictness trusts the detection
+        let (fixed, fixes) = QualityControl::new(1.0).auto_fix(&track, &features);
+        assert_eq!(fixes.len(), 2);
+        assert_eq!(fixed.bpm, Some(140));
+        assert_eq!(fixed.key.as_deref(), Some("F#"));
+    }
+
+    #[test]
+    fn test_truncated_audio() {
+        let dir = tempfile::tempdir().unwrap();
+        let qc = QualityControl::new(0.6);
+        let track = Track::default();
+
+        let good = dir.path().join("good.wav");
+        crate::test_utils::write_sine_wav(&good, 440.0, 1.0);
+        assert!(qc.check_file(&good, &track).is_empty());
+
+        // Header claims 1s, only half of the samples are there
+        let truncated = dir.path().join("truncated.wav");
+        std::fs::copy(&good, &truncated).unwrap();
+        let file = std::fs::OpenOptions::new().write(true).open(&truncated).unwrap();
+        file.set_len(44 + 44100).unwrap();
+        let issues = qc.check_file(&truncated, &track);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, IssueSeverity::Error);
+        assert!(issues[0].message.contains("Truncated"));
+
+        // Tagged duration longer than the audio
+        let long = Track { duration: std::time::Duration::from_secs(300), ..Default::default() };
+        assert_eq!(qc.check_file(&good, &long).len(), 1);
+
+        let empty = dir.path().join("empty.mp3");
+        std::fs::write(&empty, b"").unwrap();
+        assert!(qc.check_file(&empty, &track)[0].message.contains("Empty"));
+    }
+
+    #[test]
+    fn test_genre_taxonomy() {
+        let mut config = AIConfig::default();
+        config.validate_genre_taxonomy = true;
+        config.custom_tags.genres.push("Techouse".to_string());
+        let qc = QualityControl::from_config(&config);
+        let mut track = Track { genres: vec!["Tech-House".to_string()], artists: vec!["Artist".to_string()], ..Default::default() };
+
+        // Default taxonomy has "tech-house"
+        assert!(qc.validate(&track, &AudioFeatures::default()).unwrap().issues.is_empty());
+
+        config.custom_tags.genres.retain(|g| g != "tech-house");
+        let qc = QualityControl::from_config(&config);
+        let result = qc.validate(&track, &AudioFeatures::default()).unwrap();
+        assert_eq!(result.issues.len(), 1);
+        assert_eq!(result.issues[0].severity, IssueSeverity::Warning);
+        assert!(result.suggestions[0].contains("\"Techouse\""));
+
+        // Separators / case only
+        track.genres = vec!["Deep Techno".to_string()];
+        assert_eq!(qc.validate(&track, &AudioFeatures::default()).unwrap().issues[0].severity, IssueSeverity::Info);
+        assert!(QualityControl::new(0.6).validate(&track, &AudioFeatures::default()).unwrap().issues.is_empty());
+    }
+
+    #[test]
+    fn test_library_report() {
+        let qc = QualityControl::new(0.6);
+        let complete = Track {
+            title: "Complete".to_string(),
+            artists: vec!["Artist".to_string()],
+            genres: vec!["techno".to_string()],
+            bpm: Some(128),
+            key: Some("Am".to_string()),
+            label: Some("Label".to_string()),
+            release_year: Some(2020),
+            art: Some("art.jpg".to_string()),
+            ..Default::default()
+        };
+        let no_genre = Track { title: "No genre".to_string(), artists: vec!["Artist".to_string()], ..Default::default() };
+        let empty = Track { title: "Empty".to_string(), ..Default::default() };
+        let features = AudioFeatures { bpm: Some(140.0), ..Default::default() };
+        let tracks = vec![
+            (complete, features.clone()),
+            (no_genre, AudioFeatures::default()),
+            (empty, AudioFeatures::default()),
+        ];
+
+        let report = qc.validate_library(&tracks).unwrap();
+        assert_eq!(report.tracks, 3);
+        // No genre: 1 error, empty: 2 errors, complete: BPM mismatch warning
+        assert_eq!(report.errors, 3);
+        assert_eq!(report.warnings, 1);
+        assert_eq!(report.infos, 0);
+        assert_eq!(report.missing_fields.get("Genre"), Some(&2));
+        assert_eq!(report.missing_fields.get("Artist"), Some(&1));
+        assert_eq!(report.most_common_missing.as_deref(), Some("Genre"));
+        assert!(report.average_completeness > 0.0 && report.average_completeness < 1.0);
+        assert_eq!(report.worst.len(), 3);
+        assert!(report.worst.windows(2).all(|w| w[0].quality_score <= w[1].quality_score));
+        assert!(report.worst[0].track.ends_with("Empty"));
+    }
 }
@@ -5,22 +5,71 @@
 use anyhow::{Error, Result};
 use onetagger_tagger::{
     AutotaggerSource, AutotaggerSourceBuilder, TaggerConfig, Track, TrackMatch, AudioFileInfo,
-    PlatformInfo, SupportedTag, supported_tags,
+    PlatformInfo, supported_tags,
     PlatformCustomOptions, PlatformCustomOptionValue, ConfigCallbackResponse
 };
-use crate::config::{AIConfig, APIProvider};
-use crate::analyze_track;
+use std::path::PathBuf;
+use crate::config::{AIConfig, Preset, APIProvider, CustomTagConfig, EnergyRating, KeyFrame, KeyNotation, OverwriteGuard, TagTarget};
+use crate::{analyze_path, classify, feature_cache, llm_client_shared, AIAnalysisResult, Cancelled, CancellationToken, STOP_ANALYSIS};
+use crate::camelot;
+use crate::api::APIClient;
+use crate::features::{AudioFeatures, FeatureExtractor};
+use crate::quality::{IssueSeverity, QualityControl};
 use serde_json::Value;
 
+/// Providers offered by the `apiProvider` option, by display name
+const PROVIDER_OPTIONS: [(&str, APIProvider); 7] = [
+    ("Gemini (Google)", APIProvider::Gemini),
+    ("OpenRouter", APIProvider::OpenRouter),
+    ("Groq", APIProvider::Groq),
+    ("Together AI", APIProvider::TogetherAI),
+    ("OpenAI", APIProvider::OpenAI),
+    ("Azure OpenAI", APIProvider::AzureOpenAI),
+    ("Ollama (Local)", APIProvider::Ollama),
+];
+
 /// AI Tagger - implements OneTagger's AutotaggerSource trait
 pub struct AITagger {
     ai_config: AIConfig,
+    /// Shared by all tracks, so connections are reused
+    http_client: reqwest::Client,
+    /// LLM client for all tracks, `None` without LLM
+    client: Option<APIClient>,
+    /// Created on first use, the HTTP client's connections are bound to it
+    runtime: Option<tokio::runtime::Runtime>,
+    /// Aborts running analysis, `STOP_ANALYSIS` by default
+    cancel: CancellationToken,
 }
 
 impl AITagger {
     /// Create new AI tagger with config
     pub fn new_with_config(ai_config: AIConfig) -> Self {
-        Self { ai_config }
+        let http_client = APIClient::default_http_client().unwrap_or_else(|e| {
+            warn!("Failed creating HTTP client: {}, using defaults", e);
+            reqwest::Client::new()
+        });
+        Self::new_with_client(ai_config, http_client)
+    }
+
+    /// Create new AI tagger with a shared HTTP client
+    pub fn new_with_client(ai_config: AIConfig, http_client: reqwest::Client) -> Self {
+        let mut tagger = Self { ai_config, http_client, client: None, runtime: None, cancel: STOP_ANALYSIS.clone() };
+        tagger.build_client();
+        tagger
+    }
+
+    /// (Re)create the LLM client from the current config
+    fn build_client(&mut self) {
+        self.client = llm_client_shared(&self.ai_config, &self.http_client).unwrap_or_else(|e| {
+            warn!("Failed creating API client: {}, LLM suggestions disabled", e);
+            None
+        });
+    }
+
+    /// Use a custom cancellation token instead of `STOP_ANALYSIS`
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
     }
 
     /// Get platform info for UI display
@@ -41,7 +90,7 @@ impl AITagger {
                 <b>Setup:</b> Get your FREE API key at <a href="https://aistudio.google.com/app/apikey">Google AI Studio</a>
             "#.to_string(),
             version: env!("CARGO_PKG_VERSION").to_string(),
-            icon: &[],  // TODO: Add icon
+            icon: include_bytes!("../assets/ai-icon.png"),
             max_threads: 8,
             custom_options: Self::custom_options(),
             supported_tags: supported_tags!(
@@ -51,6 +100,45 @@ impl AITagger {
         }
     }
 
+    /// Update the config from values of `custom_options` entered in the UI, once when the source is created
+    fn apply_custom_options(&mut self, config: &TaggerConfig) {
+        if let Some(custom) = config.custom.0.get("ai") {
+            // Extract API key
+            if let Some(Value::String(api_key)) = custom.get("apiKey") {
+                if !api_key.is_empty() {
+                    self.ai_config.api_config.api_key = Some(api_key.clone());
+                }
+            }
+
+            // Extract API provider
+            if let Some(Value::String(provider)) = custom.get("apiProvider") {
+                self.ai_config.api_config.provider = PROVIDER_OPTIONS.iter()
+                    .find(|(name, _)| name == provider)
+                    .map(|(_, p)| *p)
+                    .unwrap_or(APIProvider::Gemini);
+            }
+
+            // Extract feature toggles
+            if let Some(Value::Bool(enabled)) = custom.get("enableGenreClassification") {
+                self.ai_config.enable_genre_classification = *enabled;
+            }
+            if let Some(Value::Bool(enabled)) = custom.get("enableMoodDetection") {
+                self.ai_config.enable_mood_detection = *enabled;
+            }
+            if let Some(Value::Bool(enabled)) = custom.get("enableEnergyAnalysis") {
+                self.ai_config.enable_energy_analysis = *enabled;
+            }
+
+            // Extract confidence threshold
+            if let Some(Value::Number(threshold)) = custom.get("confidenceThreshold") {
+                if let Some(t) = threshold.as_f64() {
+                    self.ai_config.confidence_threshold = (t / 100.0) as f32;
+                }
+            }
+            self.build_client();
+        }
+    }
+
     /// Custom configuration options for UI
     fn custom_options() -> PlatformCustomOptions {
         PlatformCustomOptions::new()
@@ -62,13 +150,8 @@ impl AITagger {
             .add_tooltip("apiProvider", "API Provider",
                 "Which AI provider to use (Gemini is recommended)",
                 PlatformCustomOptionValue::Option {
-                    values: vec![
-                        "Gemini (Google)".to_string(),
-                        "OpenRouter".to_string(),
-                        "Groq".to_string(),
-                        "Together AI".to_string(),
-                    ],
-                    value: "Gemini (Google)".to_string(),
+                    values: PROVIDER_OPTIONS.iter().map(|(name, _)| name.to_string()).collect(),
+                    value: PROVIDER_OPTIONS[0].0.to_string(),
                 })
             .add("enableGenreClassification", "Genre Classification",
                 PlatformCustomOptionValue::Boolean { value: true })
@@ -91,7 +174,7 @@ impl AutotaggerSource for AITagger {
     fn match_track(
         &mut self,
         info: &AudioFileInfo,
-        _config: &TaggerConfig,
+        config: &TaggerConfig,
     ) -> Result<Vec<TrackMatch>> {
         // Get artist and title using methods
         let artist = info.artist().unwrap_or("Unknown");
@@ -100,10 +183,18 @@ impl AutotaggerSource for AITagger {
         info!("AI analyzing: {} - {}", artist, title);
 
         // Analyze track using AI
-        let analysis = match tokio::runtime::Runtime::new()?.block_on(
-            analyze_track(&info.path, &self.ai_config)
-        ) {
+        let runtime = match self.runtime.take() {
+            Some(runtime) => runtime,
+            None => tokio::runtime::Runtime::new()?,
+        };
+        let analysis = runtime.block_on(analyze_path(&info.path, &self.ai_config, self.client.as_ref(), None, Some(self.cancel.as_ref())));
+        self.runtime = Some(runtime);
+        let analysis = match analysis {
             Ok(a) => a,
+            Err(e) if e.is::<Cancelled>() => {
+                info!("AI analysis of {} cancelled", info.path.display());
+                return Ok(Vec::new());
+            }
             Err(e) => {
                 warn!("AI analysis failed: {}", e);
                 return Ok(Vec::new());
@@ -122,19 +213,31 @@ impl AutotaggerSource for AITagger {
             genres: analysis.genres.iter().map(|g| g.tag.clone()).collect(),
             styles: Vec::new(),
             bpm: analysis.audio_features.as_ref().and_then(|f| f.bpm).map(|b| b as i64),
-            key: analysis.audio_features.as_ref().and_then(|f| f.key.clone()),
-            mood: analysis.moods.first().map(|m| m.tag.clone()),
+            key: analysis.audio_features.as_ref().and_then(|f| f.key.as_deref()).map(|k| key_for_notation(k, config.camelot)),
+            // Mapped moods are written by `collection_frames`
+            mood: match self.ai_config.custom_tags.tag_mapping.contains_key("moods") {
+                true => None,
+                false => analysis.moods.first().map(|m| m.tag.clone()),
+            },
             duration: info.duration.unwrap_or_default(),
             url: String::new(),
             ..Default::default()
         };
+        track.custom.insert(PATH_FIELD.to_string(), info.path.to_string_lossy().to_string());
+
+        // Keep trusted existing values
+        if self.ai_config.overwrite_guard.enabled {
+            apply_overwrite_guard(&mut track, &ExistingTags::from_info(info), &analysis, &self.ai_config.overwrite_guard);
+        }
+
+        // Write key into additional frames / notations
+        if let Some(key) = &track.key {
+            track.other.extend(key_frames(key, &self.ai_config.key_frames));
+        }
 
         // Add AI-specific tags to "other" field
         if let Some(energy) = analysis.energy_level {
-            track.other.push((
-                onetagger_tag::FrameName::same("AI_ENERGY"),
-                vec![format!("{:.0}", energy)]
-            ));
+            apply_energy(&mut track, energy, &self.ai_config.energy_rating);
         }
 
         if let Some(danceability) = analysis.danceability {
@@ -144,6 +247,23 @@ impl AutotaggerSource for AITagger {
             ));
         }
 
+        if let Some(explicit) = analysis.explicit {
+            track.other.push((
+                onetagger_tag::FrameName::same("AI_EXPLICIT"),
+                vec![match explicit { true => "1", false => "0" }.to_string()]
+            ));
+        }
+
+        if let Some(instrumental) = analysis.is_instrumental {
+            track.other.push((
+                onetagger_tag::FrameName::same("AI_INSTRUMENTAL"),
+                vec![match instrumental { true => "1", false => "0" }.to_string()]
+            ));
+        }
+
+        // Write collections into their configured frames
+        track.other.extend(collection_frames(&analysis, &self.ai_config.custom_tags));
+
         // Add custom tags as styles
         for tag in analysis.custom_tags {
             track.styles.push(tag.tag);
@@ -163,66 +283,264 @@ impl AutotaggerSource for AITagger {
         Ok(vec![track_match])
     }
 
-    fn extend_track(&mut self, _track: &mut Track, _config: &TaggerConfig) -> Result<(), Error> {
-        // AI tagger doesn't need extended metadata fetching
-        // All metadata is gathered in match_track
-        Ok(())
+    fn extend_track(&mut self, track: &mut Track, config: &TaggerConfig) -> Result<(), Error> {
+        let path = match track.custom.get(PATH_FIELD) {
+            Some(path) => PathBuf::from(path),
+            None => return Ok(()),
+        };
+        if !needs_extending(track, &self.ai_config) {
+            return Ok(());
+        }
+
+        let features = match FeatureExtractor::from_config(&self.ai_config).extract_cached(&path, feature_cache(&self.ai_config).as_ref()) {
+            Ok(features) => features,
+            Err(e) => {
+                warn!("AI feature extraction failed for {}: {}", path.display(), e);
+                return Ok(());
+            }
+        };
+        extend_with_features(track, &features, &self.ai_config, config.camelot)
     }
 }
 
-/// AI Tagger Builder - for platform registration
-pub struct AIBuilder;
+/// `Track::custom` field with the path of the analyzed file
+const PATH_FIELD: &str = "ai_path";
 
-impl AutotaggerSourceBuilder for AIBuilder {
-    fn new() -> Self {
-        AIBuilder
-    }
+/// Whether the track is missing any value extending could fill
+fn needs_extending(track: &Track, config: &AIConfig) -> bool {
+    track.bpm.is_none()
+        || track.key.is_none()
+        || (config.enable_mood_detection && track.mood.is_none())
+        || (config.enable_energy_analysis && !has_frame(track, "AI_ENERGY"))
+}
 
-    fn get_source(&mut self, config: &TaggerConfig) -> Result<Box<dyn AutotaggerSource>, Error> {
-        // Get AI config from custom settings
-        let mut ai_config = AIConfig::default();
+fn has_frame(track: &Track, name: &str) -> bool {
+    track.other.iter().any(|(frame, _)| frame == &onetagger_tag::FrameName::same(name))
+}
 
-        // Try to load settings from config
-        if let Some(custom) = config.custom.0.get("ai") {
-            // Extract API key
-            if let Some(Value::String(api_key)) = custom.get("apiKey") {
-                if !api_key.is_empty() {
-                    ai_config.api_config.api_key = Some(api_key.clone());
-                }
+/// Fill empty fields from analyzed features, existing values are never overwritten
+fn extend_with_features(track: &mut Track, features: &AudioFeatures, config: &AIConfig, camelot: bool) -> Result<(), Error> {
+    if config.enable_quality_control {
+        let validation = QualityControl::from_config(config).validate(track, features)?;
+        for issue in &validation.issues {
+            match issue.severity {
+                IssueSeverity::Info => debug!("{} ({}): {}", track.title, issue.field, issue.message),
+                _ => warn!("{} ({}): {}", track.title, issue.field, issue.message),
             }
+        }
+    }
 
-            // Extract API provider
-            if let Some(Value::String(provider)) = custom.get("apiProvider") {
-                ai_config.api_config.provider = match provider.as_str() {
-                    "OpenRouter" => APIProvider::OpenRouter,
-                    "Groq" => APIProvider::Groq,
-                    "Together AI" => APIProvider::TogetherAI,
-                    "OpenAI" => APIProvider::OpenAI,
-                    "Custom" => APIProvider::Custom,
-                    _ => APIProvider::Gemini,
-                };
-            }
+    if track.bpm.is_none() {
+        track.bpm = features.bpm.map(|b| b.round() as i64);
+    }
+    if track.key.is_none() {
+        track.key = features.key.as_deref().map(|k| key_for_notation(k, camelot));
+    }
 
-            // Extract feature toggles
-            if let Some(Value::Bool(enabled)) = custom.get("enableGenreClassification") {
-                ai_config.enable_genre_classification = *enabled;
-            }
-            if let Some(Value::Bool(enabled)) = custom.get("enableMoodDetection") {
-                ai_config.enable_mood_detection = *enabled;
-            }
-            if let Some(Value::Bool(enabled)) = custom.get("enableEnergyAnalysis") {
-                ai_config.enable_energy_analysis = *enabled;
-            }
+    let analysis = classify(features, config)?;
+    if track.mood.is_none() {
+        track.mood = analysis.moods.first().map(|m| m.tag.clone());
+    }
+    if !analysis.moods.is_empty() && !has_frame(track, "AI_MOOD") {
+        track.other.push((
+            onetagger_tag::FrameName::same("AI_MOOD"),
+            analysis.moods.iter().map(|m| m.tag.clone()).collect()
+        ));
+    }
+    if let Some(energy) = analysis.energy_level.filter(|_| !has_frame(track, "AI_ENERGY")) {
+        track.other.push((onetagger_tag::FrameName::same("AI_ENERGY"), vec![format!("{:.0}", energy)]));
+    }
+    if let Some(danceability) = analysis.danceability.filter(|_| !has_frame(track, "AI_DANCEABILITY")) {
+        track.other.push((onetagger_tag::FrameName::same("AI_DANCEABILITY"), vec![format!("{:.0}", danceability)]));
+    }
+    Ok(())
+}
 
-            // Extract confidence threshold
-            if let Some(Value::Number(threshold)) = custom.get("confidenceThreshold") {
-                if let Some(t) = threshold.as_f64() {
-                    ai_config.confidence_threshold = (t / 100.0) as f32;
+/// Normalize key to short standard notation (`Am`, `F#`), accepts Camelot and long forms (`A minor`)
+pub fn normalize_key(key: &str) -> Option<String> {
+    camelot::to_camelot(key).map(|c| camelot::from_camelot(c).to_string())
+}
+
+/// Convert key into the configured notation, unknown keys are returned as is
+pub fn key_for_notation(key: &str, camelot: bool) -> String {
+    match camelot::to_camelot(key) {
+        Some(position) if camelot => position.to_string(),
+        Some(position) => camelot::from_camelot(position).to_string(),
+        None => key.to_string(),
+    }
+}
+
+/// Key converted for each configured key frame
+fn key_frames(key: &str, frames: &[KeyFrame]) -> Vec<(onetagger_tag::FrameName, Vec<String>)> {
+    frames.iter()
+        .map(|f| (f.frame.clone(), vec![key_for_notation(key, f.notation == KeyNotation::Camelot)]))
+        .collect()
+}
+
+/// Energy as the raw `AI_ENERGY` frame and / or the track's 1-5 rating
+fn apply_energy(track: &mut Track, energy: f32, rating: &EnergyRating) {
+    if !rating.enabled || rating.keep_raw {
+        track.other.push((
+            onetagger_tag::FrameName::same("AI_ENERGY"),
+            vec![format!("{:.0}", energy)]
+        ));
+    }
+    if rating.enabled {
+        track.rating = Some(rating.rating(energy));
+    }
+}
+
+/// Group detected tags by custom collection, for collections with a target frame or tag mapping
+fn collection_frames(analysis: &AIAnalysisResult, custom: &CustomTagConfig) -> Vec<(onetagger_tag::FrameName, Vec<String>)> {
+    // Most confident first
+    let detected: Vec<&str> = analysis.genres.iter()
+        .chain(analysis.moods.iter())
+        .chain(analysis.custom_tags.iter())
+        .map(|t| t.tag.as_str())
+        .chain(analysis.llm_suggestions.iter().map(|s| s.as_str()))
+        .collect();
+
+    let mut output = vec![];
+    for (name, tags) in custom.collections() {
+        let target = match (custom.tag_mapping.get(name), custom.frames.get(name)) {
+            (Some(target), _) => target.clone(),
+            (None, Some(frame)) => TagTarget::Frame(frame.clone()),
+            (None, None) => continue,
+        };
+        // Use the collection's spelling
+        let mut matched: Vec<String> = vec![];
+        for tag in &detected {
+            if let Some(tag) = tags.iter().find(|t| t.eq_ignore_ascii_case(tag)) {
+                if !matched.contains(tag) {
+                    matched.push(tag.to_string());
                 }
             }
         }
+        if !matched.is_empty() {
+            output.push((target.frame(), target.values(matched)));
+        }
+    }
+    output
+}
+
+/// Genre/key/BPM values already present in a file
+#[derive(Debug, Clone, Default)]
+pub struct ExistingTags {
+    pub genres: Vec<String>,
+    pub key: Option<String>,
+    pub bpm: Option<String>,
+}
+
+impl ExistingTags {
+    /// Existing values from the tags the autotagger already read
+    pub fn from_info(info: &AudioFileInfo) -> ExistingTags {
+        let field = |field: onetagger_tag::Field| {
+            let name = field.by_format(&info.format);
+            info.tags.iter().find(|(k, _)| k.eq_ignore_ascii_case(name)).map(|(_, v)| v.clone()).unwrap_or_default()
+        };
+        ExistingTags {
+            genres: field(onetagger_tag::Field::Genre),
+            key: field(onetagger_tag::Field::Key).into_iter().next(),
+            bpm: field(onetagger_tag::Field::BPM).into_iter().next(),
+        }
+    }
+}
+
+/// Remove AI values which are not confident enough to replace existing tags
+fn apply_overwrite_guard(track: &mut Track, existing: &ExistingTags, analysis: &AIAnalysisResult, guard: &OverwriteGuard) {
+    let genre_confidence = analysis.genres.iter().map(|g| g.confidence).fold(0.0, f32::max);
+    if !track.genres.is_empty() && !guard.allows_overwrite(&existing.genres, genre_confidence) {
+        debug!("Keeping existing genres: {:?}", existing.genres);
+        track.genres.clear();
+    }
+
+    // Keys read from tags and placeholder features have no confidence
+    let features = analysis.audio_features.as_ref().filter(|f| !f.is_placeholder);
+    let key_confidence = features.and_then(|f| f.key_confidence).unwrap_or(0.0);
+    let key: Vec<String> = existing.key.iter().cloned().collect();
+    if track.key.is_some() && !guard.allows_overwrite(&key, key_confidence) {
+        debug!("Keeping existing key: {:?}", existing.key);
+        track.key = None;
+    }
+
+    let bpm_confidence = features.map(|f| f.tempo_stability).unwrap_or(0.0);
+    let bpm: Vec<String> = existing.bpm.iter().cloned().collect();
+    if track.bpm.is_some() && !guard.allows_overwrite(&bpm, bpm_confidence) {
+        debug!("Keeping existing BPM: {:?}", existing.bpm);
+        track.bpm = None;
+    }
+}
+
+/// AI Tagger Builder - for platform registration and fluent configuration
+#[derive(Debug, Clone, Default)]
+pub struct AIBuilder {
+    config: AIConfig,
+}
+
+impl AIBuilder {
+    /// Start from an existing config
+    pub fn with_config(config: AIConfig) -> Self {
+        Self { config }
+    }
+
+    /// Start from a preset (see `AIConfig::preset`)
+    pub fn preset(preset: Preset) -> Self {
+        Self::with_config(AIConfig::preset(preset))
+    }
+
+    /// Set API provider
+    pub fn provider(mut self, provider: APIProvider) -> Self {
+        self.config.api_config.provider = provider;
+        self
+    }
+
+    /// Set API key
+    pub fn api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.config.api_config.api_key = Some(api_key.into());
+        self
+    }
+
+    /// Enable or disable genre classification
+    pub fn enable_genre_classification(mut self, enabled: bool) -> Self {
+        self.config.enable_genre_classification = enabled;
+        self
+    }
+
+    /// Minimum confidence to accept AI predictions (0.0-1.0)
+    pub fn confidence_threshold(mut self, threshold: f32) -> Self {
+        self.config.confidence_threshold = threshold;
+        self
+    }
+
+    /// Get the built config
+    pub fn config(&self) -> &AIConfig {
+        &self.config
+    }
+
+    /// Build the tagger, fails if the provider requires an API key and none is set (in config or environment)
+    pub fn build(self) -> Result<AITagger> {
+        self.build_with_env(crate::config::process_env)
+    }
+
+    /// `build` with API keys looked up in `env` instead of the process environment
+    fn build_with_env(self, env: impl Fn(&str) -> Option<String>) -> Result<AITagger> {
+        let api = &self.config.api_config;
+        if self.config.llm_enabled && !api.is_configured_with_env(env) {
+            return Err(anyhow!("{} requires an API key. Get one at: {}", api.provider.display_name(), crate::get_api_key_url(&api.provider)));
+        }
+        Ok(AITagger::new_with_config(self.config))
+    }
+}
 
-        Ok(Box::new(AITagger::new_with_config(ai_config)))
+impl AutotaggerSourceBuilder for AIBuilder {
+    fn new() -> Self {
+        AIBuilder::default()
+    }
+
+    fn get_source(&mut self, config: &TaggerConfig) -> Result<Box<dyn AutotaggerSource>, Error> {
+        let mut tagger = AITagger::new_with_config(self.config.clone());
+        tagger.apply_custom_options(config);
+        Ok(Box::new(tagger))
     }
 
     fn info(&self) -> PlatformInfo {
@@ -244,6 +562,7 @@ mod tests {
         assert_eq!(info.id, "ai");
         assert!(info.name.contains("AI"));
         assert!(info.requires_auth);  // Requires API key
+        assert!(info.icon.starts_with(b"\x89PNG\r\n\x1a\n"));
     }
 
     #[test]
@@ -252,5 +571,225 @@ mod tests {
         assert!(!options.options.is_empty());
         // Should have API key option
         assert!(options.options.iter().any(|o| o.id == "apiKey"));
+
+        // Every offered provider maps to its own variant
+        let mut config = TaggerConfig::default();
+        for (name, provider) in PROVIDER_OPTIONS {
+            config.custom.0.insert("ai".to_string(), serde_json::json!({ "apiProvider": name }));
+            let mut tagger = AITagger::new_with_config(AIConfig::default());
+            tagger.apply_custom_options(&config);
+            assert_eq!(tagger.ai_config.api_config.provider, provider);
+        }
+    }
+
+    #[test]
+    fn test_apply_custom_options() {
+        let mut config = TaggerConfig::default();
+        config.custom.0.insert("ai".to_string(), serde_json::json!({
+            "apiKey": "key",
+            "apiProvider": "Groq",
+            "enableMoodDetection": false,
+            "confidenceThreshold": 85,
+        }));
+        let mut tagger = AITagger::new_with_config(AIConfig::default());
+        tagger.apply_custom_options(&config);
+        assert_eq!(tagger.ai_config.api_config.provider, APIProvider::Groq);
+        assert_eq!(tagger.ai_config.api_config.api_key.as_deref(), Some("key"));
+        assert!(!tagger.ai_config.enable_mood_detection);
+        assert!(tagger.ai_config.enable_genre_classification);
+        assert!((tagger.ai_config.confidence_threshold - 0.85).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_energy_rating() {
+        let mut raw = Track::default();
+        apply_energy(&mut raw, 80.0, &EnergyRating::default());
+        assert_eq!(raw.rating, None);
+        assert_eq!(raw.other.len(), 1);
+
+        let mut rated = Track::default();
+        apply_energy(&mut rated, 80.0, &EnergyRating { enabled: true, keep_raw: false, ..Default::default() });
+        assert_eq!(rated.rating, Some(EnergyRating::default().rating(80.0)));
+        assert!(rated.other.is_empty());
+    }
+
+    #[test]
+    fn test_key_notation() {
+        assert_eq!(key_for_notation("Am", true), "8A");
+        assert_eq!(key_for_notation("A minor", true), "8A");
+        assert_eq!(key_for_notation("F#", true), "2B");
+        assert_eq!(key_for_notation("8A", false), "Am");
+        assert_eq!(key_for_notation("8a", true), "8A");
+        assert_eq!(key_for_notation("Bbmin", false), "Bbm");
+        assert_eq!(key_for_notation("unknown", true), "unknown");
+        // Multi-byte characters
+        assert_eq!(key_for_notation("A♯", false), "Bb");
+        assert_eq!(key_for_notation("♭", true), "♭");
+        assert_eq!(key_for_notation("1é", false), "1é");
+    }
+
+    #[test]
+    fn test_builder() {
+        let tagger = AIBuilder::default()
+            .provider(APIProvider::Groq)
+            .api_key("key")
+            .enable_genre_classification(false)
+            .confidence_threshold(0.5)
+            .build()
+            .unwrap();
+        assert_eq!(tagger.ai_config.api_config.provider, APIProvider::Groq);
+        assert_eq!(tagger.ai_config.api_config.api_key.as_deref(), Some("key"));
+        assert!(!tagger.ai_config.enable_genre_classification);
+        assert_eq!(tagger.ai_config.confidence_threshold, 0.5);
+
+        // Local provider doesn't need a key
+        assert!(AIBuilder::default().provider(APIProvider::Ollama).build().is_ok());
+    }
+
+    #[test]
+    fn test_builder_preset() {
+        // Presets without LLM don't need a key
+        let tagger = AIBuilder::preset(Preset::FastScan).confidence_threshold(0.5).build().unwrap();
+        assert!(!tagger.ai_config.llm_enabled);
+        assert_eq!(tagger.ai_config.analysis_window_secs, Some(30.0));
+        assert_eq!(tagger.ai_config.confidence_threshold, 0.5);
+
+        let builder = AIBuilder::preset(Preset::TechnoDj).provider(APIProvider::Ollama);
+        assert_eq!(builder.config().genre_threshold, Some(0.8));
+        assert_eq!(builder.config().api_config.provider, APIProvider::Ollama);
+    }
+
+    #[test]
+    fn test_builder_missing_key() {
+        let no_env = |_: &str| None::<String>;
+        let result = AIBuilder::default().provider(APIProvider::Gemini).build_with_env(no_env);
+        assert!(result.is_err());
+        assert!(AIBuilder::default().api_key("  ").build_with_env(no_env).is_err());
+        assert!(AIBuilder::default().provider(APIProvider::Gemini).build_with_env(|_: &str| Some("key".to_string())).is_ok());
+    }
+
+    #[test]
+    fn test_key_frames() {
+        let frames = vec![
+            KeyFrame::key(KeyNotation::Standard),
+            KeyFrame::new(onetagger_tag::FrameName::same("CAMELOT"), KeyNotation::Camelot),
+        ];
+        assert_eq!(key_frames("Am", &frames), vec![
+            (onetagger_tag::FrameName::new("TKEY", "INITIALKEY", "com.apple.iTunes:initialkey"), vec!["Am".to_string()]),
+            (onetagger_tag::FrameName::same("CAMELOT"), vec!["8A".to_string()]),
+        ]);
+        // Already Camelot (e.g. kept existing value)
+        assert_eq!(key_frames("8A", &frames)[0].1, vec!["Am".to_string()]);
+    }
+
+    #[test]
+    fn test_collection_frames() {
+        let mut custom = CustomTagConfig::default();
+        custom.custom_collections.insert("Occasion".to_string(), vec!["Wedding".to_string(), "Funeral".to_string()]);
+        custom.custom_collections.insert("Crowd".to_string(), vec!["underground".to_string()]);
+        custom.frames.insert("Occasion".to_string(), onetagger_tag::FrameName::same("OCCASION"));
+        custom.frames.insert("Crowd".to_string(), onetagger_tag::FrameName::same("CROWD"));
+
+        let analysis = AIAnalysisResult {
+            llm_suggestions: vec!["wedding".to_string(), "underground".to_string(), "dark".to_string()],
+            ..Default::default()
+        };
+        let frames = collection_frames(&analysis, &custom);
+        assert_eq!(frames, vec![
+            (onetagger_tag::FrameName::same("CROWD"), vec!["underground".to_string()]),
+            (onetagger_tag::FrameName::same("OCCASION"), vec!["Wedding".to_string()]),
+        ]);
+    }
+
+    #[test]
+    fn test_tag_mapping() {
+        let mut custom = CustomTagConfig::default();
+        custom.tag_mapping.insert("moods".to_string(), TagTarget::Comment);
+        custom.tag_mapping.insert("vibes".to_string(), TagTarget::Mood);
+
+        let analysis = AIAnalysisResult {
+            moods: vec![crate::TagWithConfidence::new("Dark", 0.9), crate::TagWithConfidence::new("hypnotic", 0.7)],
+            llm_suggestions: vec!["after-hours".to_string(), "warehouse".to_string()],
+            ..Default::default()
+        };
+        let frames = collection_frames(&analysis, &custom);
+        assert_eq!(frames, vec![
+            (TagTarget::Comment.frame(), vec!["dark, hypnotic".to_string()]),
+            (TagTarget::Mood.frame(), vec!["after-hours".to_string()]),
+        ]);
+    }
+
+    #[test]
+    fn test_overwrite_guard_preserves_trusted_tags() {
+        let existing = ExistingTags {
+            genres: vec!["Techno".to_string()],
+            key: Some("8A".to_string()),
+            bpm: Some("0".to_string()),
+        };
+        let mut analysis = AIAnalysisResult {
+            genres: vec![crate::TagWithConfidence::new("house", 0.8)],
+            confidence: 0.99,
+            audio_features: Some(AudioFeatures { key_confidence: Some(0.8), ..Default::default() }),
+            ..Default::default()
+        };
+        let ai_track = Track {
+            genres: vec!["house".to_string()],
+            key: Some("Am".to_string()),
+            bpm: Some(124),
+            ..Default::default()
+        };
+        let guard = OverwriteGuard { enabled: true, ..Default::default() };
+        let mut track = ai_track.clone();
+        apply_overwrite_guard(&mut track, &existing, &analysis, &guard);
+
+        assert!(track.genres.is_empty());
+        assert!(track.key.is_none());
+        // Placeholder BPM can be replaced
+        assert_eq!(track.bpm, Some(124));
+
+        // Key judged by its own confidence
+        analysis.audio_features = Some(AudioFeatures { key_confidence: Some(0.99), ..Default::default() });
+        let mut track = ai_track.clone();
+        apply_overwrite_guard(&mut track, &existing, &analysis, &guard);
+        assert_eq!(track.key.as_deref(), Some("Am"));
+
+        // Disabled by default
+        let mut track = ai_track.clone();
+        apply_overwrite_guard(&mut track, &existing, &analysis, &OverwriteGuard::default());
+        assert_eq!(track, ai_track);
+    }
+
+    #[test]
+    fn test_extend_fills_empty_fields() {
+        let features = AudioFeatures {
+            bpm: Some(127.6),
+            key: Some("A minor".to_string()),
+            rms_energy: 0.3,
+            onset_strength: 0.6,
+            ..Default::default()
+        };
+        let mut track = Track {
+            title: "Track".to_string(),
+            artists: vec!["Artist".to_string()],
+            bpm: Some(124),
+            mood: Some("Happy".to_string()),
+            other: vec![(onetagger_tag::FrameName::same("AI_ENERGY"), vec!["10".to_string()])],
+            ..Default::default()
+        };
+        let config = AIConfig { confidence_threshold: 0.0, ..Default::default() };
+        assert!(needs_extending(&track, &config));
+        extend_with_features(&mut track, &features, &config, true).unwrap();
+
+        // User set values are kept
+        assert_eq!(track.bpm, Some(124));
+        assert_eq!(track.mood.as_deref(), Some("Happy"));
+        let energy: Vec<_> = track.other.iter().filter(|(f, _)| f == &onetagger_tag::FrameName::same("AI_ENERGY")).collect();
+        assert_eq!(energy.len(), 1);
+        assert_eq!(energy[0].1, vec!["10".to_string()]);
+        // Empty ones filled
+        assert_eq!(track.key.as_deref(), Some("8A"));
+        assert!(has_frame(&track, "AI_MOOD"));
+        assert!(has_frame(&track, "AI_DANCEABILITY"));
+        assert!(!needs_extending(&track, &config));
     }
 }
@@ -0,0 +1,192 @@
+//! Test utilities
+//!
+//! Minimal HTTP server for testing API calls without network access
+
+use std::f32::consts::PI;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use parking_lot::Mutex;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// Canned HTTP response
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    pub status: u16,
+    pub body: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl MockResponse {
+    pub fn new(status: u16, body: impl Into<String>) -> Self {
+        Self { status, body: body.into(), headers: Vec::new() }
+    }
+
+    /// Add response header
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+/// Mock HTTP server replying with responses in order (last one is repeated)
+pub struct MockServer {
+    pub url: String,
+    hits: Arc<AtomicUsize>,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockServer {
+    /// Start the server on a random local port
+    pub async fn start(responses: Vec<MockResponse>) -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let hits = Arc::new(AtomicUsize::new(0));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let hits_ref = hits.clone();
+        let requests_ref = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = match listener.accept().await {
+                    Ok(s) => s,
+                    Err(_) => break,
+                };
+                let request = read_request(&mut socket).await;
+                let i = hits_ref.fetch_add(1, Ordering::SeqCst);
+                requests_ref.lock().push(request);
+
+                let response = &responses[i.min(responses.len() - 1)];
+                let mut raw = format!("HTTP/1.1 {} MOCK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+                    response.status, response.body.len());
+                for (name, value) in &response.headers {
+                    raw.push_str(&format!("{}: {}\r\n", name, value));
+                }
+                raw.push_str("\r\n");
+                raw.push_str(&response.body);
+                socket.write_all(raw.as_bytes()).await.ok();
+                socket.shutdown().await.ok();
+            }
+        });
+
+        MockServer { url, hits, requests }
+    }
+
+    /// Number of requests received
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::SeqCst)
+    }
+
+    /// Raw received requests (headers + body)
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().clone()
+    }
+}
+
+/// Read full HTTP request (headers + Content-Length body)
+async fn read_request(socket: &mut tokio::net::TcpStream) -> String {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = match socket.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => n,
+        };
+        data.extend_from_slice(&buf[..n]);
+
+        let text = String::from_utf8_lossy(&data);
+        if let Some(header_end) = text.find("\r\n\r\n") {
+            let content_length = text[..header_end].lines()
+                .find_map(|l| {
+                    let (name, value) = l.split_once(':')?;
+                    match name.trim().eq_ignore_ascii_case("content-length") {
+                        true => value.trim().parse::<usize>().ok(),
+                        false => None,
+                    }
+                })
+                .unwrap_or(0);
+            if data.len() >= header_end + 4 + content_length {
+                break;
+            }
+        }
+    }
+    String::from_utf8_lossy(&data).to_string()
+}
+
+/// Uncached config with the LLM pointed at the mock server
+pub fn llm_config(server: &MockServer) -> crate::AIConfig {
+    let mut config = crate::AIConfig::default();
+    config.cache_dir = None;
+    config.api_config.api_key = Some("test".to_string());
+    config.api_config.endpoint = Some(server.url.clone());
+    config
+}
+
+/// Gemini-shaped JSON response with given text
+pub fn gemini_response(text: &str) -> String {
+    serde_json::json!({
+        "candidates": [{ "content": { "parts": [{ "text": text }] } }]
+    }).to_string()
+}
+
+/// Write mono 16-bit WAV fixture from samples in range -1.0..1.0
+pub fn write_wav(path: &std::path::Path, samples: &[f32], sample_rate: u32) {
+    let spec = hound::WavSpec { channels: 1, sample_rate, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+    let mut writer = hound::WavWriter::create(path, spec).unwrap();
+    for sample in samples {
+        writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).unwrap();
+    }
+    writer.finalize().unwrap();
+}
+
+/// Write mono 16-bit sine WAV fixture (half amplitude)
+pub fn write_sine_wav(path: &std::path::Path, frequency: f32, seconds: f32) {
+    let samples: Vec<f32> = (0..(44100.0 * seconds) as usize)
+        .map(|i| (i as f32 * frequency * 2.0 * std::f32::consts::PI / 44100.0).sin() * 0.5)
+        .collect();
+    write_wav(path, &samples, 44100);
+}
+
+/// Sung vowel: 200 Hz harmonics shaped by formants (700 / 1200 Hz) with vibrato, four syllables per second
+pub fn vocal_samples(seconds: f32) -> Vec<f32> {
+    let mut phase = 0.0f32;
+    (0..(44100.0 * seconds) as usize).map(|i| {
+        let t = i as f32 / 44100.0;
+        phase += 2.0 * PI * (200.0 + 4.0 * (2.0 * PI * 5.0 * t).sin()) / 44100.0;
+        let syllable = (PI * 4.0 * t).sin().powi(2);
+        let voice: f32 = (1..16).map(|h| {
+            let frequency = 200.0 * h as f32;
+            let formants = (-((frequency - 700.0) / 250.0).powi(2)).exp() + 0.7 * (-((frequency - 1200.0) / 300.0).powi(2)).exp() + 0.05;
+            formants * (h as f32 * phase).sin()
+        }).sum();
+        0.2 * syllable * voice
+    }).collect()
+}
+
+/// Kick, hi-hat (noise) and sub bass loop without vocals
+pub fn drum_samples(seconds: f32) -> Vec<f32> {
+    let mut noise = 0x2545f491u32;
+    (0..(44100.0 * seconds) as usize).map(|i| {
+        let t = i as f32 / 44100.0;
+        let (kick_t, hat_t) = (t % 0.5, (t + 0.25) % 0.5);
+        let kick = (2.0 * PI * (50.0 + 100.0 * (-kick_t * 30.0).exp()) * kick_t).sin() * (-kick_t * 8.0).exp();
+        noise ^= noise << 13;
+        noise ^= noise >> 17;
+        noise ^= noise << 5;
+        let hat = (noise as f32 / u32::MAX as f32 * 2.0 - 1.0) * (-hat_t * 40.0).exp() * 0.3;
+        let bass = 0.4 * (2.0 * PI * 55.0 * t).sin();
+        0.4 * (kick + hat + bass)
+    }).collect()
+}
+
+/// Decaying 2 kHz clicks at the given times (22.05 kHz)
+pub fn click_samples(times: &[f32], seconds: f32) -> Vec<f32> {
+    let mut samples = vec![0.0; (22050.0 * seconds) as usize];
+    for time in times {
+        let start = (time * 22050.0) as usize;
+        for i in 0..441.min(samples.len().saturating_sub(start)) {
+            samples[start + i] = (i as f32 * 2000.0 * 2.0 * PI / 22050.0).sin() * (-(i as f32) / 80.0).exp();
+        }
+    }
+    samples
+}
@@ -36,6 +36,7 @@ pub mod audiofeatures;
 
 // Re-exports
 pub use platforms::{AUTOTAGGER_PLATFORMS, AutotaggerPlatforms};
+pub use onetagger_ai::STOP_ANALYSIS;
 
 
 lazy_static::lazy_static! {
@@ -222,6 +223,9 @@ impl TrackImpl for Track {
             for (t, value) in &self.other {
                 tag.set_raw(&t.by_format(&format), value.to_owned(), config.overwrite_tag(SupportedTag::OtherTags));
             }
+            if let Some(rating) = self.rating {
+                tag.set_rating(rating, config.overwrite_tag(SupportedTag::OtherTags));
+            }
         }
         // IDs
         if config.tag_enabled(SupportedTag::TrackId) && self.track_id.is_some() {
@@ -631,6 +635,7 @@ impl Tagger {
     // Returtns progress receiver, and file count
     pub fn tag_files(cfg: &TaggerConfig, mut files: Vec<PathBuf>, finished: Arc<Mutex<Option<TaggerFinishedData>>>) -> Receiver<TaggingStatusWrap> {
         STOP_TAGGING.store(false, Ordering::SeqCst);
+        STOP_ANALYSIS.store(false, Ordering::SeqCst);
 
         // Shuffle so album tag is more "efficient"
         if cfg.album_tagging {
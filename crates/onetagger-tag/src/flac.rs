@@ -281,6 +281,14 @@ impl TagImpl for FLACTag {
         }
     }
 
+    fn get_explicit(&self) -> Option<bool> {
+        let comments = self.get_raw("COMMENT")?;
+        match comments.iter().any(|c| c == "Explicit") {
+            true => Some(true),
+            false => comments.iter().any(|c| c == "Clean").then_some(false),
+        }
+    }
+
     fn get_separator(&self) -> Option<String> {
         self.separator.clone()
     }
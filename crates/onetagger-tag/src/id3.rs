@@ -571,6 +571,14 @@ impl TagImpl for ID3Tag {
         ], true)
     }
 
+    fn get_explicit(&self) -> Option<bool> {
+        match self.get_raw("ITUNESADVISORY")?.first()?.trim() {
+            "1" => Some(true),
+            "0" | "2" => Some(false),
+            _ => None
+        }
+    }
+
     fn get_separator(&self) -> Option<String> {
         Some(self.id3_separator.clone())
     }
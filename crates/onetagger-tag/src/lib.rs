@@ -154,6 +154,11 @@ pub trait TagImpl {
 
     /// Set whether the track is explicit
     fn set_explicit(&mut self, explicit: bool);
+
+    /// Whether the track is marked explicit, None if it has no advisory
+    fn get_explicit(&self) -> Option<bool> {
+        None
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
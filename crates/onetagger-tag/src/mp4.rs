@@ -363,6 +363,10 @@ impl TagImpl for MP4Tag {
         });
     }
 
+    fn get_explicit(&self) -> Option<bool> {
+        self.tag.advisory_rating().map(|r| matches!(r, AdvisoryRating::Explicit))
+    }
+
     fn get_separator(&self) -> Option<String> {
         Some(self.separator.clone())
     }
@@ -263,6 +263,8 @@ pub struct Track {
     pub mood: Option<String>,
     /// None = unknown
     pub explicit: Option<bool>,
+    /// Rating 1-5, written with other tags
+    pub rating: Option<u8>,
 
     pub lyrics: Option<Lyrics>,
     
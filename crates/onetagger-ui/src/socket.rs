@@ -373,6 +373,7 @@ async fn handle_message(text: &str, websocket: &mut WebSocket, context: &mut Soc
         },
         Action::StopTagging => {
             onetagger_autotag::STOP_TAGGING.store(true, Ordering::SeqCst);
+            onetagger_autotag::STOP_ANALYSIS.store(true, Ordering::SeqCst);
         },
         Action::Waveform { path } => {
             let source = AudioSources::from_path(&path)?;